@@ -0,0 +1,37 @@
+// Native methods implemented in Rust by the program embedding the JVM (see README.md). HostMath is a class
+// registered by that program: it is only declared here to compile HostDemo, and has no class file
+class HostDemo {
+    String name;
+
+    HostDemo(String name) {
+        this.name = name;
+    }
+
+    static native int add(int a, int b);
+    static native int sum(int[] values);
+    native String greet(String greeting);
+
+    static int addTwice(int a, int b) {
+        return add(add(a, b), b);
+    }
+
+    static int sumOf(int a, int b, int c) {
+        return sum(new int[] { a, b, c });
+    }
+
+    String welcome() {
+        return greet("Welcome");
+    }
+
+    static int squareOrZero(int a) {
+        try {
+            return HostMath.square(a);
+        } catch (IllegalArgumentException e) {
+            return 0;
+        }
+    }
+}
+
+class HostMath {
+    static native int square(int a);
+}
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::bytecode::get_declaring_class;
+use crate::bytecode_class::get_method_key;
+use crate::java_class::{JavaClass, MethodCallResult, get_argument_descriptors, get_argument_types, get_return_type};
+use crate::jvm::{JavaInstance, StackFrame, Value};
+use crate::classpath::to_internal_name;
+use crate::native_java_classes::{NativeArrayInstance, NativeStringInstance, new_java_exception};
+use crate::vm::{self, Vm, get_class};
+
+// A value passed to or returned by Java code. Strings and arrays are copied, objects are references
+#[derive(Clone, Debug, PartialEq)]
+pub enum JavaValue {
+    // Returned by void methods
+    Void,
+    Null,
+    Boolean(bool),
+    Byte(i8),
+    Char(char),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Array(Vec<JavaValue>),
+    Object(Object)
+}
+
+impl JavaValue {
+    // Whether the value can be passed for an argument of the given descriptor type. Like with reflection,
+    // strings, arrays, objects and null can be passed for any reference
+    fn matches(&self, arg_type: char) -> bool {
+        matches!((arg_type, self),
+            ('Z', JavaValue::Boolean(_)) | ('B', JavaValue::Byte(_)) | ('C', JavaValue::Char(_)) | ('S', JavaValue::Short(_)) |
+            ('I', JavaValue::Int(_)) | ('J', JavaValue::Long(_)) | ('F', JavaValue::Float(_)) | ('D', JavaValue::Double(_)) |
+            ('L', JavaValue::Null | JavaValue::String(_) | JavaValue::Array(_) | JavaValue::Object(_)))
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        match self {
+            JavaValue::Void => panic!("Void cannot be converted into a value"),
+            JavaValue::Null => Value::null(),
+            // Booleans, bytes, chars and shorts are ints in the JVM
+            JavaValue::Boolean(value) => Value::Int(*value as i32),
+            JavaValue::Byte(value) => Value::Int(*value as i32),
+            JavaValue::Char(value) => Value::Int(*value as i32),
+            JavaValue::Short(value) => Value::Int(*value as i32),
+            JavaValue::Int(value) => Value::Int(*value),
+            JavaValue::Long(value) => Value::Long(*value),
+            JavaValue::Float(value) => Value::Float(*value),
+            JavaValue::Double(value) => Value::Double(*value),
+            JavaValue::String(value) => Value::Reference(Arc::new(Mutex::new(NativeStringInstance::new(value.clone())))),
+            JavaValue::Array(values) => {
+                let array: Vec<Arc<Mutex<dyn JavaInstance>>> = values.iter().map(|value| value.to_value().to_object()).collect();
+                Value::Reference(Arc::new(Mutex::new(NativeArrayInstance { values: Arc::new(Mutex::new(array)) })))
+            },
+            JavaValue::Object(object) => Value::Reference(object.object.clone())
+        }
+    }
+
+    // The type descriptor gives the primitive types and the type of array elements. Without one (e.g. for an
+    // Object), boxed values are unboxed and booleans, bytes, chars and shorts are ints
+    pub(crate) fn from_value(vm: &Arc<Vm>, value: Value, type_desc: &str) -> JavaValue {
+        match type_desc.chars().next() {
+            Some('V') => JavaValue::Void,
+            Some('Z') => JavaValue::Boolean(value.get_bool()),
+            Some('B') => JavaValue::Byte(value.get_int() as i8),
+            Some('C') => JavaValue::Char(value.get_char()),
+            Some('S') => JavaValue::Short(value.get_short()),
+            Some('I') => JavaValue::Int(value.get_int()),
+            Some('J') => JavaValue::Long(value.get_long()),
+            Some('F') => JavaValue::Float(value.get_float()),
+            Some('D') => JavaValue::Double(value.get_double()),
+            _ => match value {
+                Value::Int(value) => JavaValue::Int(value),
+                Value::Long(value) => JavaValue::Long(value),
+                Value::Float(value) => JavaValue::Float(value),
+                Value::Double(value) => JavaValue::Double(value),
+                Value::Reference(object) => JavaValue::from_object(vm, object, type_desc),
+                Value::ReturnAddress(address) => panic!("Return address {} cannot be converted into a Java value", address)
+            }
+        }
+    }
+
+    fn from_object(vm: &Arc<Vm>, object: Arc<Mutex<dyn JavaInstance>>, type_desc: &str) -> JavaValue {
+        let (is_null, class_name) = {
+            let instance = object.lock().unwrap();
+            (instance.is_null(), instance.get_class_name())
+        };
+        if is_null { return JavaValue::Null; }
+
+        match &class_name[..] {
+            "java/lang/String" => JavaValue::String(object.lock().unwrap().get_string()),
+            "java/util/Arrays" => {
+                let element_desc = type_desc.strip_prefix("[").unwrap_or("Ljava/lang/Object;");
+                let array = object.lock().unwrap().get_array();
+                let values: Vec<Arc<Mutex<dyn JavaInstance>>> = array.lock().unwrap().clone();
+                JavaValue::Array(values.into_iter().map(|value| JavaValue::from_value(vm, Value::from_object(value), element_desc)).collect())
+            },
+            _ => JavaValue::Object(Object::new(vm, object))
+        }
+    }
+}
+
+impl From<bool> for JavaValue { fn from(value: bool) -> JavaValue { JavaValue::Boolean(value) } }
+impl From<i32> for JavaValue { fn from(value: i32) -> JavaValue { JavaValue::Int(value) } }
+impl From<i64> for JavaValue { fn from(value: i64) -> JavaValue { JavaValue::Long(value) } }
+impl From<f32> for JavaValue { fn from(value: f32) -> JavaValue { JavaValue::Float(value) } }
+impl From<f64> for JavaValue { fn from(value: f64) -> JavaValue { JavaValue::Double(value) } }
+impl From<&str> for JavaValue { fn from(value: &str) -> JavaValue { JavaValue::String(value.to_string()) } }
+impl From<String> for JavaValue { fn from(value: String) -> JavaValue { JavaValue::String(value) } }
+impl From<Object> for JavaValue { fn from(value: Object) -> JavaValue { JavaValue::Object(value) } }
+
+// A class loaded by a VM
+#[derive(Clone)]
+pub struct Class {
+    vm: Arc<Vm>,
+    class: Arc<dyn JavaClass>
+}
+
+impl Class {
+    pub(crate) fn new(vm: &Arc<Vm>, class: Arc<dyn JavaClass>) -> Class {
+        Class { vm: vm.clone(), class }
+    }
+
+    pub fn get_name(&self) -> String {
+        self.class.get_name()
+    }
+
+    // Calls a static method given by its name and descriptor (e.g. "(II)I"), which initializes the class first
+    pub fn invoke_static(&self, method_name: &str, type_desc: &str, args: &[JavaValue]) -> Result<JavaValue, JavaException> {
+        let _entered = self.vm.enter();
+        let method_name = method_name.to_string();
+        let type_desc = type_desc.to_string();
+        let args = to_arguments(&self.vm, &type_desc, args)?;
+        if let Err(e) = self.vm.initialize_class(&get_declaring_class(&self.class, &method_name, &type_desc)) {
+            return Err(JavaException::new(&self.vm, e));
+        }
+
+        let mut sf = StackFrame::new(0, args.len());
+        let nb_args = args.len();
+        for arg in args.into_iter() {
+            sf.push_value(arg);
+        }
+        let result = self.class.execute_static_method(&mut sf, &method_name, &type_desc, nb_args);
+        get_result(&self.vm, &mut sf, result, &type_desc)
+    }
+
+    // Creates an instance with the constructor of the given descriptor (e.g. "(Ljava/lang/String;)V")
+    pub fn new_instance(&self, type_desc: &str, args: &[JavaValue]) -> Result<Object, JavaException> {
+        let _entered = self.vm.enter();
+        let type_desc = type_desc.to_string();
+        let mut args = to_arguments(&self.vm, &type_desc, args)?;
+        args.reverse();
+        if let Err(e) = self.vm.initialize_class(&self.class) {
+            return Err(JavaException::new(&self.vm, e));
+        }
+
+        let object = self.class.new();
+        let mut sf = StackFrame::new(0, 1);
+        match self.class.execute_method(&mut sf, &"<init>".to_string(), &type_desc, object.clone(), args) {
+            MethodCallResult::SUCCESS => Ok(Object::new(&self.vm, object)),
+            MethodCallResult::EXCEPTION(e) => Err(JavaException::new(&self.vm, e))
+        }
+    }
+
+    // Reads a static field of the class or of one of its superclasses, which initializes the class declaring it
+    pub fn get_static_field(&self, field_name: &str) -> Result<JavaValue, JavaException> {
+        let _entered = self.vm.enter();
+        let field_name = field_name.to_string();
+        let (class, descriptor) = match find_field(&self.class, &field_name, true) {
+            Some(field) => field,
+            None => return Err(JavaException::new(&self.vm, new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name))))
+        };
+        if let Err(e) = self.vm.initialize_class(&class) {
+            return Err(JavaException::new(&self.vm, e));
+        }
+
+        match class.get_static_object(&field_name) {
+            Ok(value) => Ok(JavaValue::from_value(&self.vm, Value::from_object(value), &descriptor)),
+            Err(e) => Err(JavaException::new(&self.vm, e))
+        }
+    }
+
+    // Compiles a method into assembly, see assembly.md
+    pub fn convert_to_asm(&self, method_name: &str, type_desc: &str, arch: &str, emit: &str) {
+        let _entered = self.vm.enter();
+        self.class.convert_to_asm(&method_name.to_string(), &type_desc.to_string(), arch, emit);
+    }
+
+    pub fn print(&self) {
+        let _entered = self.vm.enter();
+        self.class.print();
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.get_name())
+    }
+}
+
+// A reference to a Java object, which is kept alive by the reference
+#[derive(Clone)]
+pub struct Object {
+    vm: Arc<Vm>,
+    object: Arc<Mutex<dyn JavaInstance>>
+}
+
+impl Object {
+    pub(crate) fn new(vm: &Arc<Vm>, object: Arc<Mutex<dyn JavaInstance>>) -> Object {
+        Object { vm: vm.clone(), object }
+    }
+
+    pub fn get_class_name(&self) -> String {
+        self.object.lock().unwrap().get_class_name()
+    }
+
+    // Calls a method given by its name and descriptor, which is looked up from the class of the object
+    pub fn invoke(&self, method_name: &str, type_desc: &str, args: &[JavaValue]) -> Result<JavaValue, JavaException> {
+        let _entered = self.vm.enter();
+        let type_desc = type_desc.to_string();
+        // Arguments are in reverse order: args[0] is the last one
+        let mut args = to_arguments(&self.vm, &type_desc, args)?;
+        args.reverse();
+
+        let class = get_class(&self.get_class_name());
+        let mut sf = StackFrame::new(0, 1);
+        let result = class.execute_method(&mut sf, &method_name.to_string(), &type_desc, self.object.clone(), args);
+        get_result(&self.vm, &mut sf, result, &type_desc)
+    }
+
+    // Reads a field of the object, including the ones declared by its superclasses
+    pub fn get_field(&self, field_name: &str) -> Result<JavaValue, JavaException> {
+        let _entered = self.vm.enter();
+        let field_name = field_name.to_string();
+        let value = self.object.lock().unwrap().get_field(&field_name);
+        match value {
+            Ok(value) => {
+                let descriptor = match find_field(&get_class(&self.get_class_name()), &field_name, false) {
+                    Some((_, descriptor)) => descriptor,
+                    None => "Ljava/lang/Object;".to_string()
+                };
+                Ok(JavaValue::from_value(&self.vm, Value::from_object(value), &descriptor))
+            },
+            Err(e) => Err(JavaException::new(&self.vm, e))
+        }
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        Arc::ptr_eq(&self.object, &other.object)
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} object>", self.get_class_name())
+    }
+}
+
+// A Throwable thrown by Java code, or by the VM (e.g. a NoClassDefFoundError when a class is not found)
+#[derive(Clone)]
+pub struct JavaException {
+    object: Object
+}
+
+impl JavaException {
+    pub(crate) fn new(vm: &Arc<Vm>, exception: Arc<Mutex<dyn JavaInstance>>) -> JavaException {
+        JavaException { object: Object::new(vm, exception) }
+    }
+
+    pub fn get_object(&self) -> &Object {
+        &self.object
+    }
+
+    pub fn get_class_name(&self) -> String {
+        self.object.get_class_name()
+    }
+
+    pub fn get_message(&self) -> Option<String> {
+        match self.object.invoke("getMessage", "()Ljava/lang/String;", &[]) {
+            Ok(JavaValue::String(message)) if !message.is_empty() => Some(message),
+            _ => None
+        }
+    }
+
+    pub fn get_cause(&self) -> Option<JavaException> {
+        match self.object.invoke("getCause", "()Ljava/lang/Throwable;", &[]) {
+            Ok(JavaValue::Object(cause)) => Some(JavaException { object: cause }),
+            _ => None
+        }
+    }
+
+    pub fn print_stack_trace(&self) {
+        let _ = self.object.invoke("printStackTrace", "()V", &[]);
+    }
+}
+
+// Like Throwable.toString(): the class name, followed by the message if there is one
+impl fmt::Display for JavaException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let class_name = self.get_class_name().replace("/", ".");
+        match self.get_message() {
+            Some(message) => write!(f, "{}: {}", class_name, message),
+            None => write!(f, "{}", class_name)
+        }
+    }
+}
+
+impl fmt::Debug for JavaException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for JavaException {}
+
+// To throw an exception created by Rust code, e.g. with Class::new_instance()
+impl From<Object> for JavaException {
+    fn from(object: Object) -> JavaException {
+        JavaException { object }
+    }
+}
+
+// A Java method implemented in Rust. It gets the VM, this for instance methods, and the arguments, and returns the
+// result (Void for void methods) or the exception to throw
+pub type NativeFunction = dyn Fn(&Arc<Vm>, Option<&Object>, &[JavaValue]) -> Result<JavaValue, JavaException> + Send + Sync;
+
+// A Java class implemented in Rust, made of static methods (see Vm::register_class())
+pub struct NativeClass {
+    name: String,
+    methods: HashMap<String, Arc<NativeFunction>>
+}
+
+impl NativeClass {
+    pub fn new(class_name: &str) -> NativeClass {
+        NativeClass { name: to_internal_name(class_name), methods: HashMap::new() }
+    }
+
+    pub fn static_method<F>(mut self, method_name: &str, type_desc: &str, function: F) -> NativeClass
+            where F: Fn(&Arc<Vm>, Option<&Object>, &[JavaValue]) -> Result<JavaValue, JavaException> + Send + Sync + 'static {
+        self.methods.insert(get_method_key(&method_name.to_string(), &type_desc.to_string()), Arc::new(function));
+        self
+    }
+}
+
+impl JavaClass for NativeClass {
+    fn get_name(&self) -> String { self.name.clone() }
+    fn get_parent(&self) -> String { "java/lang/Object".to_string() }
+    fn print(&self) { println!("Native {} class", self.name); }
+
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, nb_args: usize) -> MethodCallResult {
+        match self.methods.get(&get_method_key(method_name, type_desc)) {
+            Some(function) => {
+                let mut args: Vec<Value> = (0..nb_args).map(|_| sf.pop_value()).collect();
+                args.reverse();
+                call_native_function(function, sf, type_desc, None, args)
+            },
+            None => MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}{}", self.name, method_name, type_desc)))
+        }
+    }
+}
+
+// Converts the arguments (in order) according to the descriptor, and pushes the result returned by the function
+pub(crate) fn call_native_function(function: &Arc<NativeFunction>, sf: &mut StackFrame, type_desc: &String,
+        this: Option<Arc<Mutex<dyn JavaInstance>>>, args: Vec<Value>) -> MethodCallResult {
+    let vm = vm::current();
+    let this = this.map(|object| Object::new(&vm, object));
+    let args: Vec<JavaValue> = args.into_iter().zip(get_argument_descriptors(type_desc).iter())
+        .map(|(arg, arg_desc)| JavaValue::from_value(&vm, arg, arg_desc)).collect();
+
+    let return_type = get_return_type(type_desc);
+    match function(&vm, this.as_ref(), &args) {
+        Ok(_) if return_type == 'V' => MethodCallResult::SUCCESS,
+        Ok(value) if value.matches(return_type) => {
+            sf.push_value(value.to_value());
+            MethodCallResult::SUCCESS
+        },
+        // A value of the wrong type is the mistake of the embedder, which the Java code sees as a ClassCastException
+        Ok(value) => {
+            let message = format!("native method with descriptor {} returned {:?}", type_desc, value);
+            MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/ClassCastException".to_string(), &message))
+        },
+        Err(e) => MethodCallResult::EXCEPTION(e.object.object.clone())
+    }
+}
+
+// Checks the arguments against the method descriptor, as reflection does
+fn to_arguments(vm: &Arc<Vm>, type_desc: &String, args: &[JavaValue]) -> Result<Vec<Value>, JavaException> {
+    let arg_types = get_argument_types(type_desc);
+    let illegal_argument = |message: String| JavaException::new(vm, new_java_exception(&"java/lang/IllegalArgumentException".to_string(), &message));
+    if arg_types.len() != args.len() {
+        return Err(illegal_argument(format!("wrong number of arguments: {} expected: {}", args.len(), arg_types.len())));
+    }
+    if args.iter().zip(arg_types.iter()).any(|(arg, arg_type)| !arg.matches(*arg_type)) {
+        return Err(illegal_argument("argument type mismatch".to_string()));
+    }
+    Ok(args.iter().map(|arg| arg.to_value()).collect())
+}
+
+// The value returned by the method is on the stack, unless it is void
+fn get_result(vm: &Arc<Vm>, sf: &mut StackFrame, result: MethodCallResult, type_desc: &String) -> Result<JavaValue, JavaException> {
+    match result {
+        MethodCallResult::SUCCESS if get_return_type(type_desc) == 'V' => Ok(JavaValue::Void),
+        MethodCallResult::SUCCESS => {
+            let return_desc = &type_desc[type_desc.find(")").unwrap() + 1..];
+            Ok(JavaValue::from_value(vm, sf.pop_value(), return_desc))
+        },
+        MethodCallResult::EXCEPTION(e) => Err(JavaException::new(vm, e))
+    }
+}
+
+// Fields may be declared by a superclass. Returns the class declaring the field and its descriptor
+fn find_field(class: &Arc<dyn JavaClass>, field_name: &String, is_static: bool) -> Option<(Arc<dyn JavaClass>, String)> {
+    let mut current = class.clone();
+    loop {
+        if let Some(descriptor) = current.get_field_descriptor(field_name, is_static) {
+            return Some((current, descriptor));
+        }
+        let parent = current.get_parent();
+        if parent.is_empty() { return None; }
+        current = get_class(&parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{JavaValue, NativeClass};
+    use crate::classpath::ClassPath;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_invoke_static() {
+        let vm = Vm::new(ClassPath::parse("java"), 0);
+        let class = vm.load_class("Exceptions").unwrap();
+        let operation = |a: i32, b: i32| class.invoke_static("operation", "(II)I", &[a.into(), b.into()]);
+        assert_eq!(operation(10, 5).unwrap(), JavaValue::Int(2));
+
+        let exception = operation(1, 0).unwrap_err();
+        assert_eq!(exception.get_class_name(), "java/lang/ArithmeticException");
+        assert_eq!(exception.to_string(), "java.lang.ArithmeticException: / by zero");
+        assert_eq!(operation(1, 3).unwrap_err().get_message().unwrap(), "C'mon! You can do better than that! (exception not caught)");
+
+        let exception = class.invoke_static("operation", "(II)I", &["1".into(), 2.into()]).unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.IllegalArgumentException: argument type mismatch");
+        assert_eq!(vm.load_class("com.acme.Missing").unwrap_err().get_class_name(), "java/lang/NoClassDefFoundError");
+    }
+
+    #[test]
+    fn test_objects() {
+        let vm = Vm::new(ClassPath::parse("java"), 0);
+        let class = vm.load_class("Person").unwrap();
+        let person = class.new_instance("(Ljava/lang/String;Ljava/lang/String;I)V", &["John".into(), "Smith".into(), 21.into()]).unwrap();
+        assert_eq!(person.get_class_name(), "Person");
+        assert_eq!(person.invoke("fullName", "()Ljava/lang/String;", &[]).unwrap(), JavaValue::String("John Smith".to_string()));
+        assert_eq!(person.get_field("age").unwrap(), JavaValue::Int(21));
+        assert_eq!(person.get_field("lastName").unwrap(), JavaValue::String("Smith".to_string()));
+        assert_eq!(person.get_field("height").unwrap_err().get_class_name(), "java/lang/NoSuchFieldError");
+
+        let age_range = vm.load_class("AgeRange").unwrap().get_static_field("AGE_20_39").unwrap();
+        assert!(matches!(age_range, JavaValue::Object(ref range) if range.get_class_name() == "AgeRange"));
+        assert_eq!(person.get_field("ageRange").unwrap(), age_range);
+    }
+
+    #[test]
+    fn test_native_methods() {
+        let vm = Vm::new(ClassPath::parse("java"), 0);
+        let class = vm.load_class("HostDemo").unwrap();
+        let exception = class.invoke_static("addTwice", "(II)I", &[1.into(), 2.into()]).unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.UnsatisfiedLinkError: HostDemo.add(II)I");
+
+        vm.register_native_method("HostDemo", "add", "(II)I", |_, _, args| match args {
+            [JavaValue::Int(a), JavaValue::Int(b)] => Ok(JavaValue::Int(a + b)),
+            _ => panic!("Unexpected arguments {:?}", args)
+        });
+        vm.register_native_method("HostDemo", "sum", "([I)I", |_, _, args| match &args[0] {
+            JavaValue::Array(values) => Ok(JavaValue::Int(values.iter().map(|value| if let JavaValue::Int(value) = value { *value } else { 0 }).sum())),
+            _ => panic!("Unexpected arguments {:?}", args)
+        });
+        vm.register_native_method("HostDemo", "greet", "(Ljava/lang/String;)Ljava/lang/String;", |_, this, args| {
+            match (this.unwrap().get_field("name")?, &args[0]) {
+                (JavaValue::String(name), JavaValue::String(greeting)) => Ok(JavaValue::String(format!("{} {}", greeting, name))),
+                _ => panic!("Unexpected arguments {:?}", args)
+            }
+        });
+        assert_eq!(class.invoke_static("addTwice", "(II)I", &[1.into(), 2.into()]).unwrap(), JavaValue::Int(5));
+        assert_eq!(class.invoke_static("sumOf", "(III)I", &[1.into(), 2.into(), 3.into()]).unwrap(), JavaValue::Int(6));
+        let demo = class.new_instance("(Ljava/lang/String;)V", &["Alice".into()]).unwrap();
+        assert_eq!(demo.invoke("welcome", "()Ljava/lang/String;", &[]).unwrap(), JavaValue::String("Welcome Alice".to_string()));
+    }
+
+    #[test]
+    fn test_native_class() {
+        let vm = Vm::new(ClassPath::parse("java"), 0);
+        vm.register_class(NativeClass::new("HostMath").static_method("square", "(I)I", |vm, _, args| match args {
+            [JavaValue::Int(a)] if *a < 0 => Err(vm.new_exception("java.lang.IllegalArgumentException", "negative")),
+            [JavaValue::Int(a)] => Ok(JavaValue::Int(a * a)),
+            _ => panic!("Unexpected arguments {:?}", args)
+        }).static_method("isEven", "(I)Z", |_, _, _| Ok(JavaValue::Int(1))));
+
+        let class = vm.load_class("HostDemo").unwrap();
+        assert_eq!(class.invoke_static("squareOrZero", "(I)I", &[7.into()]).unwrap(), JavaValue::Int(49));
+        assert_eq!(class.invoke_static("squareOrZero", "(I)I", &[(-7).into()]).unwrap(), JavaValue::Int(0));
+        let exception = vm.load_class("HostMath").unwrap().invoke_static("square", "(I)I", &[(-1).into()]).unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.IllegalArgumentException: negative");
+        let exception = vm.load_class("HostMath").unwrap().invoke_static("isEven", "(I)Z", &[2.into()]).unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.ClassCastException: native method with descriptor (I)Z returned Int(1)");
+    }
+}
@@ -1,108 +1,946 @@
-use std::{collections::{HashMap, HashSet, hash_map::DefaultHasher}, fs::File, hash::{Hash, Hasher}, io::Write};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, fs::File, io::Write};
 
-use crate::bytecode::ByteCode;
+use crate::bytecode::{ByteCode, get_field_declaring_class};
+use crate::bytecode_class::select_default_method;
+use crate::elf;
+use crate::ir::{self, Instr, Loc};
+use crate::runtime::{self, MATH_ROUTINES, ROUTINES, SYSTEM_ROUTINES};
+use crate::x64::{Alu, Cond, Encoder, Label, Mem, Reg, Shift, Sse, X87, Xmm};
+use crate::vm::{class_exists, get_class};
+use crate::java_class::{get_argument_types, get_return_type};
 
+#[derive(Clone, Copy)]
 pub enum Arch {
     LinuxX64,
     MacosX64
 }
 
-pub fn bytecode_to_asm(class_name: &String, bytecode: &ByteCode, arch: Arch) {
+// Output of the conversion: the assembly source, or an executable assembled from it
+#[derive(Clone, Copy, PartialEq)]
+pub enum Emit {
+    Asm,
+    Exe
+}
+
+// System V calling convention: registers of the integer/reference and float/double arguments
+const INT_ARG_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const FLOAT_ARG_REGISTERS: usize = 8;
+
+// Where an argument is passed: an integer register, an xmm register, or a slot of the stack above the return address
+enum ArgLoc {
+    Int(usize),
+    Float(usize),
+    Stack(usize)
+}
+
+// Locations of the arguments (this being the first one of an instance method), the ones beyond the registers passed
+// on the stack in their order
+fn argument_locations(arg_types: &[char]) -> Vec<ArgLoc> {
+    let mut locations: Vec<ArgLoc> = Vec::new();
+    let (mut int_idx, mut float_idx, mut stack_idx) = (0, 0, 0);
+    for arg_type in arg_types {
+        match arg_type {
+            'F' | 'D' if float_idx < FLOAT_ARG_REGISTERS => { locations.push(ArgLoc::Float(float_idx)); float_idx += 1; },
+            _ if !matches!(arg_type, 'F' | 'D') && int_idx < INT_ARG_REGISTERS.len() => { locations.push(ArgLoc::Int(int_idx)); int_idx += 1; },
+            _ => { locations.push(ArgLoc::Stack(stack_idx)); stack_idx += 1; }
+        }
+    }
+    locations
+}
+
+// Restores rbx (callee-saved, and used as a scratch register) and the frame of the caller
+pub const METHOD_EPILOGUE: &str = "    mov rbx, [rbp-8]\n    leave\n    ret";
+
+// Converts a method, and the methods and routines of the runtime it calls, into one program starting at this method
+pub fn class_to_asm(class_name: &String, method_name: &String, type_desc: &String, arch: Arch, emit: Emit) {
     let mut assembly = Assembly {
+        arch,
+        class_name: String::new(),
+        label: String::new(),
         jumps: HashSet::new(),
-        strings: HashSet::new(),
-        variables: HashMap::new(),
-        register_idx: 7
+        strings: Vec::new(),
+        pending: Vec::new(),
+        requested: HashSet::new(),
+        methods: Vec::new(),
+        routines: HashSet::new(),
+        instantiated: ["java/lang/String", "java/lang/Integer", "java/lang/StringBuilder", "["].iter().map(|name| name.to_string()).collect(),
+        descriptors: BTreeSet::new(),
+        virtual_calls: BTreeMap::new(),
+        initializers: BTreeSet::new(),
+        statics: BTreeSet::new(),
+        nb_labels: 0
+    };
+    for (runtime_class, _, _, _) in runtime::CLASSES.iter() {
+        assembly.add_descriptor(&runtime_class.to_string());
+    }
+    let main_label = assembly.request_method(class_name, method_name, type_desc);
+    let initialization = assembly.initialize_class(class_name);
+
+    // The methods called by the converted methods get converted too, as well as the methods selected by their virtual
+    // calls and the static initializers of the classes they use, which can call more methods
+    let (stubs, guards) = loop {
+        while !assembly.pending.is_empty() {
+            let (class_name, method_name, type_desc) = assembly.pending.remove(0);
+            get_class(&class_name).convert_method_to_asm(&method_name, &type_desc, &mut assembly);
+        }
+        let nb_virtual_calls = assembly.virtual_calls.len();
+        let stubs = assembly.virtual_stubs();
+        let guards = assembly.initializer_guards();
+        if assembly.pending.is_empty() && assembly.virtual_calls.len() == nb_virtual_calls {
+            break (stubs, guards);
+        }
     };
 
-    let mut filename = class_name.clone();
-    filename.push_str(".asm");
+    let mut content: Vec<String> = Vec::new();
 
-    match File::create(filename) {
-        Err(why) => panic!("Couldn't create {}.asm: {}", class_name, why),
-        Ok(mut file) => {
-            let mut asm_instructions: Vec<String> = Vec::new();
+    // The entry point calls the main method with the arguments of the command line, below which are their number and
+    // addresses, and exits once it returns
+    let arguments = format!("    mov rdi, [rsp]\n    mov rsi, rsp\n    add rsi, 8\n{}\n    mov rdi, rax\n", assembly.call_routine("__main_args"));
+    match arch {
+        Arch::LinuxX64 => { content.push(format!("    global    _start\n    section   .text\n_start:\n{}{}    call {}\n    mov rax, 60\n    mov rdi, 0\n    syscall\n", initialization, arguments, main_label)); },
+        Arch::MacosX64 => { content.push(format!("    global    start\n    section   .text\nstart:\n{}{}    call {}\n    mov rax, 0x02000001\n    mov rdi, 0\n    syscall\n", initialization, arguments, main_label)); }
+    };
 
-            for instr in bytecode.instructions.iter() {
-                let asm_instruction = match arch {
-                    Arch::LinuxX64 => instr.convert_to_linux_intel_asm(&mut assembly),
-                    Arch::MacosX64 => instr.convert_to_macos_intel_asm(&mut assembly)
-                };
-                asm_instructions.push(asm_instruction);
-            }
-        
-            let mut content: Vec<String> = Vec::new();
+    content.extend(assembly.methods.iter().cloned());
+    content.extend(stubs);
+    content.extend(guards);
 
-            match arch {
-                Arch::LinuxX64 => { content.push("    global    _start\n    section   .text\n_start:\n".to_string()); },
-                Arch::MacosX64 => { content.push("    global    start\n    section   .text\nstart:\n".to_string()); }
-            };
-        
-            let mut instr_idx: usize = 0;
-            for instr in asm_instructions.iter() {
-                if assembly.jumps.contains(&instr_idx) {
-                    content.push(format!("__branch{}:\n", instr_idx));
-                }
-                content.push(instr.clone());
-                content.push("\n".to_string());
-                instr_idx += 1;
-            }
-        
-            content.push("\n__string_length:\n    xor       rax, rax\n    xor       rcx, rcx\n __string_len:\n    mov       cl, [rdi]\n    cmp       cl, 0\n    jz __string_len_ok\n".to_string());
-            content.push("    inc       rax\n    inc       rdi\n    jmp __string_len\n__string_len_ok:\n    ret\n".to_string());
-        
-            content.push("\n    section   .data\n".to_string());
-            for string in assembly.strings.iter() {
-                content.push(format!("{}: db \"{}\", 0\n", string_label(string), string));
-            }
-            content.push("str_cr: db 0ah, 0\n".to_string());
-
-            let mut final_content: String = content.iter().map(|x| x.clone()).collect();
-            // asm optimization: the JVM is passing data between bytecode instructions through the stack
-            // bypass this by keeping the value in the rax register
-            final_content = final_content.replace("    push rax\n    pop rax\n", "");
-
-            match file.write_all(final_content.as_bytes()) {
-                Err(why) => panic!("Couldn't write to {}.asm: {}", class_name, why),
-                Ok(_) => {
-                    println!("Bytecode class compiled into x64 assembly, Intel notation ({}.asm)", class_name);
+    for (name, _, source) in MATH_ROUTINES.iter().chain(ROUTINES.iter()) {
+        if assembly.routines.contains(name) {
+            content.push(source.to_string());
+        }
+    }
+    for (name, _, _) in SYSTEM_ROUTINES.iter() {
+        if assembly.routines.contains(name) {
+            content.push(runtime::system_routine(name, arch).unwrap().to_string());
+        }
+    }
+
+    content.push("\n    section   .data\n".to_string());
+    for (idx, string) in assembly.strings.iter().enumerate() {
+        content.push(runtime::string_data(&format!("__string_{}", idx), string));
+    }
+    content.push(runtime::runtime_data());
+    for class_name in assembly.descriptors.iter() {
+        let parent = assembly.parent(class_name);
+        let parent_label = if parent.is_empty() { "0".to_string() } else { runtime::class_label(&parent) };
+        let name_label = format!("__name_{}", runtime::sanitize(class_name));
+        let interfaces: Vec<String> = assembly.interfaces(class_name).iter().map(|interface| runtime::class_label(interface)).collect();
+        content.push(format!("{}: dq {}, {}, {}\n", runtime::class_label(class_name), parent_label, name_label,
+            [vec![interfaces.len().to_string()], interfaces].concat().join(", ")));
+        content.push(runtime::string_data(&name_label, &class_name.replace('/', ".")));
+    }
+    for label in assembly.statics.iter() {
+        content.push(format!("{}: dq 0\n", label));
+    }
+    for class_name in assembly.initializers.iter() {
+        content.push(format!("init_{}: dq 0\n", runtime::sanitize(class_name)));
+    }
+
+    let final_content: String = content.concat();
+
+    match (emit, arch) {
+        (Emit::Asm, _) => {
+            let mut filename = class_name.clone();
+            filename.push_str(".asm");
+
+            match File::create(filename) {
+                Err(why) => panic!("Couldn't create {}.asm: {}", class_name, why),
+                Ok(mut file) => {
+                    match file.write_all(final_content.as_bytes()) {
+                        Err(why) => panic!("Couldn't write to {}.asm: {}", class_name, why),
+                        Ok(_) => {
+                            println!("Bytecode class compiled into x64 assembly, Intel notation ({}.asm)", class_name);
+                        }
+                    };
                 }
             };
-        }
-    };
+        },
+        (Emit::Exe, Arch::LinuxX64) => {
+            let (code, data, entry) = assemble(&final_content);
+            elf::write_executable(class_name, &code, &data, entry);
+            println!("Bytecode class compiled into a Linux x64 executable ({})", class_name);
+        },
+        (Emit::Exe, Arch::MacosX64) => panic!("Executables can only be emitted for linux, use nasm and ld for macOS")
+    }
 }
 
-fn string_label(string: &String) -> String {
-    let mut hasher = DefaultHasher::new();
-    string.hash(&mut hasher);
-    format!("str_{}", hasher.finish())
+// Label of a method, e.g. Fib_fib_I_I for Fib.fib(I)I
+pub fn method_label(class_name: &String, method_name: &String, type_desc: &String) -> String {
+    runtime::sanitize(&format!("{}_{}{}", class_name, method_name, type_desc))
 }
 
 pub struct Assembly {
+    arch: Arch,
+    // Class and label of the method being converted, the scope of its branch labels
+    class_name: String,
+    label: String,
     jumps: HashSet<usize>,
-    strings: HashSet<String>,
-    variables: HashMap<u8, u8>,
-    register_idx: u8
+    // Constant strings, labeled by their index
+    strings: Vec<String>,
+    // Methods to convert (class, name and descriptor), and the labels of all the methods requested so far
+    pending: Vec<(String, String, String)>,
+    requested: HashSet<String>,
+    methods: Vec<String>,
+    routines: HashSet<&'static str>,
+    // Classes whose objects get created (arrays are [), and classes with a descriptor
+    instantiated: BTreeSet<String>,
+    descriptors: BTreeSet<String>,
+    // Method of each stub of a virtual call: class, name, descriptor, and whether the class is an interface
+    virtual_calls: BTreeMap<String, (String, String, String, bool)>,
+    // Classes initialized by a guard, and labels of the static fields
+    initializers: BTreeSet<String>,
+    statics: BTreeSet<String>,
+    nb_labels: usize
 }
 
 impl Assembly {
-    pub fn add_jump(&mut self, branch: usize) {
+    pub fn add_jump(&mut self, branch: usize) -> String {
         self.jumps.insert(branch);
+        format!(".branch{}", branch)
+    }
+
+    // Label for a branch inside the conversion of an instruction
+    pub fn new_label(&mut self, name: &str) -> String {
+        self.nb_labels += 1;
+        format!(".{}{}", name, self.nb_labels)
+    }
+
+    // Calls a routine of the runtime (see runtime.rs), which gets added to the program
+    pub fn call_routine(&mut self, name: &str) -> String {
+        self.use_routine(name);
+        format!("    call {}", runtime::routine_label(name))
+    }
+
+    // Adds a routine to the program, with the routines it calls
+    fn use_routine(&mut self, name: &str) {
+        if let Some(method) = name.strip_prefix("virtual ") {
+            let (class_name, method_name, type_desc) = runtime::parse_method(method);
+            self.virtual_call(&class_name, &method_name, &type_desc, false);
+            return;
+        }
+        if self.routines.contains(name) {
+            return;
+        }
+        let (name, routines): (&'static str, &[&str]) = match MATH_ROUTINES.iter().chain(ROUTINES.iter()).find(|(routine, _, _)| *routine == name) {
+            Some((routine, routines, _)) => (routine, routines),
+            None => match SYSTEM_ROUTINES.iter().find(|(routine, _, _)| *routine == name) {
+                Some((routine, _, _)) => (routine, &[]),
+                None => panic!("Unknown runtime routine {}", name)
+            }
+        };
+        self.routines.insert(name);
+        for routine in routines.iter() {
+            self.use_routine(routine);
+        }
     }
- 
+
     pub fn add_string(&mut self, string: &String) -> String {
-        self.strings.insert(string.clone());
-        string_label(&string)
+        let idx = match self.strings.iter().position(|other| other == string) {
+            Some(idx) => idx,
+            None => {
+                self.strings.push(string.clone());
+                self.strings.len() - 1
+            }
+        };
+        format!("__string_{}", idx)
+    }
+
+    // Local variables are in the stack frame, below the saved rbx
+    pub fn variable(&self, var_idx: u8) -> String {
+        format!("qword [rbp-{}]", 16 + 8 * var_idx as usize)
+    }
+
+    // Label of a method, which gets converted unless it already was
+    fn request_method(&mut self, class_name: &String, method_name: &String, type_desc: &String) -> String {
+        let label = method_label(class_name, method_name, type_desc);
+        if self.requested.insert(label.clone()) {
+            self.pending.push((class_name.clone(), method_name.clone(), type_desc.clone()));
+        }
+        label
+    }
+
+    // Converts a method into a function. Instructions are converted with the local variables in the stack frame and
+    // the operand stack on the stack, and both become registers in the intermediate representation
+    pub fn add_method(&mut self, class_name: &String, bytecode: &ByteCode) {
+        self.class_name = class_name.clone();
+        self.label = method_label(class_name, &bytecode.method_name, &bytecode.type_desc);
+        self.jumps.clear();
+
+        // The arguments passed on the stack are above the saved rbp and the return address
+        let mut content: Vec<String> = Vec::new();
+        let mut arg_types = get_argument_types(&bytecode.type_desc);
+        if !bytecode.is_static {
+            arg_types.insert(0, 'L');
+        }
+        let mut var_idx: u8 = 0;
+        for (arg_type, location) in arg_types.iter().zip(argument_locations(&arg_types)) {
+            match location {
+                ArgLoc::Int(int_idx) => content.push(format!("    mov {}, {}\n", self.variable(var_idx), INT_ARG_REGISTERS[int_idx])),
+                ArgLoc::Float(float_idx) => content.push(format!("    movq {}, xmm{}\n", self.variable(var_idx), float_idx)),
+                ArgLoc::Stack(stack_idx) => content.push(format!("    mov rax, qword [rbp+{}]\n    mov {}, rax\n", 16 + 8 * stack_idx, self.variable(var_idx)))
+            }
+            var_idx += if *arg_type == 'J' || *arg_type == 'D' { 2 } else { 1 };
+        }
+
+        let mut asm_instructions: Vec<String> = Vec::new();
+        for instr in bytecode.instructions.iter() {
+            let mut asm_instruction = match self.arch {
+                Arch::LinuxX64 => instr.convert_to_linux_intel_asm(self),
+                Arch::MacosX64 => instr.convert_to_macos_intel_asm(self)
+            };
+            // Pseudo instruction for the stack manipulations of the intermediate representation
+            if instr.pushes_category2() {
+                asm_instruction.push_str("\n    category2");
+            }
+            asm_instructions.push(asm_instruction);
+        }
+
+        for (instr_idx, instr) in asm_instructions.iter().enumerate() {
+            if self.jumps.contains(&instr_idx) {
+                content.push(format!(".branch{}:\n", instr_idx));
+            }
+            content.push(instr.clone());
+            content.push("\n".to_string());
+        }
+
+        let returned = match get_return_type(&bytecode.type_desc) {
+            'V' => None,
+            'F' | 'D' => Some(Loc::Xmm(0)),
+            _ => Some(Loc::Reg(Reg::RAX))
+        };
+        let method = ir::Method::new(&content.concat(), bytecode.max_locals, returned);
+        self.methods.push(method.emit(&self.label));
+    }
+
+    fn parent(&self, class_name: &String) -> String {
+        if class_name.starts_with('[') {
+            return "java/lang/Object".to_string();
+        }
+        match runtime::get_class(class_name) {
+            Some((parent, _, _)) => parent.to_string(),
+            None if class_exists(class_name) => get_class(class_name).get_parent(),
+            None => String::new()
+        }
+    }
+
+    fn is_subclass(&self, class_name: &String, ancestor: &String) -> bool {
+        if ancestor.starts_with('[') {
+            return class_name.starts_with('[');
+        }
+        let mut current = class_name.clone();
+        while !current.is_empty() {
+            if current == *ancestor {
+                return true;
+            }
+            current = self.parent(&current);
+        }
+        false
+    }
+
+    // Method selection (JVMS §5.4.6): the class declaring the method, the given class, its closest superclass or the
+    // interface of a default method, and whether the method is a routine of the runtime
+    fn select_method(&self, class_name: &String, method_name: &String, type_desc: &String) -> Option<(String, bool)> {
+        let mut current = class_name.clone();
+        while !current.is_empty() {
+            if runtime::is_routine(&format!("{}.{}{}", current, method_name, type_desc)) {
+                return Some((current, true));
+            }
+            if !current.starts_with('[') && class_exists(&current) && get_class(&current).resolve_method(method_name, type_desc).is_some() {
+                return Some((current, false));
+            }
+            current = self.parent(&current);
+        }
+        // Default methods of the superinterfaces
+        if !class_name.starts_with('[') && class_exists(class_name) {
+            if let Ok(Some((interface, _))) = select_default_method(&*get_class(class_name), method_name, type_desc) {
+                return Some((interface.get_name(), false));
+            }
+        }
+        None
+    }
+
+    // Label of a selected method, which gets added to the program
+    fn method_target(&mut self, class_name: &String, is_routine: bool, method_name: &String, type_desc: &String) -> String {
+        if is_routine {
+            let name = format!("{}.{}{}", class_name, method_name, type_desc);
+            self.use_routine(&name);
+            runtime::routine_label(&name)
+        } else {
+            self.request_method(class_name, method_name, type_desc)
+        }
+    }
+
+    // Calls a function, with this and the arguments popped into their registers or the stack, which is aligned on 16
+    // bytes at the call
+    fn call(&self, label: &String, type_desc: &String, has_this: bool) -> String {
+        let mut arg_types = get_argument_types(type_desc);
+        if has_this {
+            arg_types.insert(0, 'L');
+        }
+        let locations = argument_locations(&arg_types);
+        // With arguments on the stack, it gets aligned before they are popped into their slots
+        let nb_stacked = locations.iter().filter(|location| matches!(location, ArgLoc::Stack(_))).count();
+        let mut content: Vec<String> = Vec::new();
+        if nb_stacked > 0 {
+            content.push(format!("    mov rbx, rsp\n    and rsp, -16\n    sub rsp, {}\n", 8 * (nb_stacked + nb_stacked % 2)));
+        }
+
+        // The last argument is on top of the stack
+        for location in locations.iter().rev() {
+            match location {
+                ArgLoc::Int(int_idx) => content.push(format!("    pop {}\n", INT_ARG_REGISTERS[*int_idx])),
+                ArgLoc::Float(float_idx) => content.push(format!("    pop rax\n    movq xmm{}, rax\n", float_idx)),
+                ArgLoc::Stack(stack_idx) => content.push(format!("    pop qword [rsp+{}]\n", 8 * stack_idx))
+            }
+        }
+        if nb_stacked == 0 {
+            content.push("    mov rbx, rsp\n    and rsp, -16\n".to_string());
+        }
+        content.push(format!("    call {}\n    mov rsp, rbx", label));
+
+        match get_return_type(type_desc) {
+            'V' => {},
+            'F' | 'D' => content.push("\n    movq rax, xmm0\n    push rax".to_string()),
+            _ => content.push("\n    push rax".to_string())
+        }
+        content.concat()
+    }
+
+    // Calls a static method, after the initialization of its class
+    pub fn call_static_method(&mut self, class_name: &String, method_name: &String, type_desc: &String) -> String {
+        let (owner, is_routine) = match self.select_method(class_name, method_name, type_desc) {
+            Some(selected) => selected,
+            None => panic!("Static method {}.{}{} does not support conversion to assembly", class_name, method_name, type_desc)
+        };
+        let initialization = if is_routine { String::new() } else { self.initialize_class(&owner) };
+        let label = self.method_target(&owner, is_routine, method_name, type_desc);
+        format!("{}{}", initialization, self.call(&label, type_desc, false))
+    }
+
+    // Calls a constructor, a private method or a method of the superclass, without virtual selection
+    pub fn call_special_method(&mut self, class_name: &String, method_name: &String, type_desc: &String) -> String {
+        // The constructor of Object does nothing
+        if class_name == "java/lang/Object" && method_name == "<init>" {
+            return "    pop rax".to_string();
+        }
+        let (owner, is_routine) = match self.select_method(class_name, method_name, type_desc) {
+            Some(selected) => selected,
+            None => panic!("Method {}.{}{} does not support conversion to assembly", class_name, method_name, type_desc)
+        };
+        let label = self.method_target(&owner, is_routine, method_name, type_desc);
+        self.call(&label, type_desc, true)
+    }
+
+    // Calls the method selected by the class of the object, through the stub of the virtual call
+    pub fn call_virtual_method(&mut self, class_name: &String, method_name: &String, type_desc: &String, interface: bool) -> String {
+        // System.out and System.err are their file descriptors
+        if class_name == "java/io/PrintStream" {
+            let name = format!("{}.{}{}", class_name, method_name, type_desc);
+            if !runtime::is_routine(&name) {
+                panic!("Method {} does not support conversion to assembly", name);
+            }
+            self.use_routine(&name);
+            return self.call(&runtime::routine_label(&name), type_desc, true);
+        }
+        if class_name.starts_with('[') && method_name == "clone" {
+            self.use_routine("__array_clone");
+            return self.call(&"__array_clone".to_string(), type_desc, true);
+        }
+        let stub = self.virtual_call(class_name, method_name, type_desc, interface);
+        self.call(&stub, type_desc, true)
     }
 
-    pub fn var_to_reg(&mut self, var_idx: u8) -> u8 {
-        match self.variables.get(&var_idx) {
-            Some(reg) => *reg,
+    fn virtual_call(&mut self, class_name: &String, method_name: &String, type_desc: &String, interface: bool) -> String {
+        let stub = format!("__virtual_{}", method_label(class_name, method_name, type_desc));
+        self.virtual_calls.entry(stub.clone()).or_insert((class_name.clone(), method_name.clone(), type_desc.clone(), interface));
+        stub
+    }
+
+    // Stubs of the virtual calls, which check the object in rdi, and jump to the method selected by its class among
+    // the classes whose objects get created. Without any, the object can only be null
+    fn virtual_stubs(&mut self) -> Vec<String> {
+        self.use_routine("__null_pointer_error");
+        let mut stubs: Vec<String> = Vec::new();
+        for (stub, (class_name, method_name, type_desc, interface)) in self.virtual_calls.clone() {
+            let mut targets: Vec<(String, String)> = Vec::new();
+            for object_class in self.instantiated.clone() {
+                if !interface && !self.is_subclass(&object_class, &class_name) {
+                    continue;
+                }
+                if let Some((owner, is_routine)) = self.select_method(&object_class, &method_name, &type_desc) {
+                    let label = self.method_target(&owner, is_routine, &method_name, &type_desc);
+                    targets.push((runtime::class_label(&object_class), label));
+                }
+            }
+
+            let mut content = format!("\n{}:\n    cmp rdi, 0\n    je __null_pointer_error\n", stub);
+            match targets.split_last() {
+                Some(((_, last), others)) => {
+                    if !others.is_empty() {
+                        content.push_str("    mov rax, [rdi]\n");
+                    }
+                    for (descriptor, label) in others.iter() {
+                        content.push_str(&format!("    mov r11, qword {}\n    cmp rax, r11\n    je {}\n", descriptor, label));
+                    }
+                    content.push_str(&format!("    jmp {}\n", last));
+                },
+                None => content.push_str("    jmp __null_pointer_error\n")
+            }
+            stubs.push(content);
+        }
+        stubs
+    }
+
+    // Whether the class or one of its superclasses has a static initializer
+    fn has_initializer(&self, class_name: &String) -> bool {
+        let mut current = class_name.clone();
+        while !current.is_empty() && !current.starts_with('[') {
+            if runtime::get_class(&current).is_none() && class_exists(&current) && get_class(&current).has_static_init() {
+                return true;
+            }
+            current = self.parent(&current);
+        }
+        false
+    }
+
+    // Initializes a class (JVMS §5.5) before its first use, through its guard. The class of the current method and its
+    // superclasses already are
+    fn initialize_class(&mut self, class_name: &String) -> String {
+        if self.is_subclass(&self.class_name, class_name) || !self.has_initializer(class_name) {
+            return String::new();
+        }
+        self.initializers.insert(class_name.clone());
+        format!("    mov rbx, rsp\n    and rsp, -16\n    call __clinit_{}\n    mov rsp, rbx\n", runtime::sanitize(class_name))
+    }
+
+    // Guards of the class initializations, which initialize the superclass and run the static initializer the first
+    // time they are called
+    fn initializer_guards(&mut self) -> Vec<String> {
+        let mut guards: Vec<String> = Vec::new();
+        let mut done: BTreeSet<String> = BTreeSet::new();
+        while let Some(class_name) = self.initializers.difference(&done).next().cloned() {
+            done.insert(class_name.clone());
+            let label = runtime::sanitize(&class_name);
+            let mut content = format!("\n__clinit_{}:\n    mov rax, qword init_{}\n    cmp qword [rax], 0\n    jne .done\n    mov qword [rax], 1\n", label, label);
+            let parent = self.parent(&class_name);
+            if self.has_initializer(&parent) {
+                self.initializers.insert(parent.clone());
+                content.push_str(&format!("    call __clinit_{}\n", runtime::sanitize(&parent)));
+            }
+            if get_class(&class_name).has_static_init() {
+                let method = self.request_method(&class_name, &"<clinit>".to_string(), &"()V".to_string());
+                content.push_str(&format!("    jmp {}\n", method));
+            }
+            content.push_str(".done:\n    ret\n");
+            guards.push(content);
+        }
+        guards
+    }
+
+    // Interfaces of a class, with their superinterfaces
+    fn interfaces(&self, class_name: &String) -> Vec<String> {
+        let direct: Vec<String> = match runtime::get_class(class_name) {
+            Some((_, _, interfaces)) => interfaces.iter().map(|interface| interface.to_string()).collect(),
+            None if class_exists(class_name) => get_class(class_name).get_interfaces(),
+            None => Vec::new()
+        };
+        let mut interfaces: Vec<String> = Vec::new();
+        for interface in direct.iter() {
+            for interface in [vec![interface.clone()], self.interfaces(interface)].concat() {
+                if !interfaces.contains(&interface) {
+                    interfaces.push(interface);
+                }
+            }
+        }
+        interfaces
+    }
+
+    fn add_descriptor(&mut self, class_name: &String) {
+        if !class_name.starts_with('[') && self.descriptors.insert(class_name.clone()) {
+            let parent = self.parent(class_name);
+            if !parent.is_empty() {
+                self.add_descriptor(&parent);
+            }
+            for interface in self.interfaces(class_name) {
+                self.add_descriptor(&interface);
+            }
+        }
+    }
+
+    // Instance fields of a class, those of its superclass first
+    fn instance_fields(&self, class_name: &String) -> Vec<String> {
+        if class_name.is_empty() {
+            return Vec::new();
+        }
+        let mut fields = self.instance_fields(&self.parent(class_name));
+        match runtime::get_class(class_name) {
+            Some((_, class_fields, _)) => fields.extend(class_fields.iter().map(|field| field.to_string())),
+            None if class_exists(class_name) => fields.extend(get_class(class_name).get_instance_fields()),
+            None => {}
+        }
+        fields
+    }
+
+    // Offset of a field in the objects of a class, after their descriptor
+    pub fn field_offset(&self, class_name: &String, field_name: &String) -> usize {
+        match self.instance_fields(class_name).iter().rposition(|field| field == field_name) {
+            Some(idx) => 8 * (idx + 1),
+            None => panic!("Field {}.{} does not support conversion to assembly", class_name, field_name)
+        }
+    }
+
+    pub fn new_object(&mut self, class_name: &String) -> String {
+        let size = 8 * (self.instance_fields(class_name).len() + 1);
+        self.instantiated.insert(class_name.clone());
+        self.add_descriptor(class_name);
+        let initialization = self.initialize_class(class_name);
+        format!("{}{}\n    mov rcx, qword {}\n    mov [rax], rcx\n    push rax", initialization, self.call_new(size), runtime::class_label(class_name))
+    }
+
+    fn call_new(&mut self, size: usize) -> String {
+        format!("    mov rdi, {}\n{}", size, self.call_routine("__new"))
+    }
+
+    pub fn new_array(&mut self) -> String {
+        format!("    pop rdi\n{}\n    push rax", self.call_routine("__new_array"))
+    }
+
+    // Address of an array element minus 16 in rax, from the array and the index on the stack, which must be in bounds
+    pub fn array_element(&mut self) -> String {
+        let in_bounds = self.new_label("in_bounds");
+        format!("    pop rcx\n    pop rax\n    mov rdx, [rax+8]\n    cmp rcx, rdx\n    jb {}\n    mov rdi, rcx\n    mov rsi, rdx\n{}\n{}:\n    shl rcx, 3\n    add rax, rcx",
+            in_bounds, self.call_routine("__array_index_error"), in_bounds)
+    }
+
+    pub fn instance_of(&mut self, class_name: &String) -> String {
+        self.add_descriptor(class_name);
+        format!("    pop rdi\n    mov rsi, qword {}\n{}\n    push rax", runtime::class_label(class_name), self.call_routine("__instance_of"))
+    }
+
+    fn static_field(&mut self, class_name: &String, field_name: &String) -> String {
+        if class_name.starts_with("java/") {
+            panic!("Static field {}.{} does not support conversion to assembly", class_name, field_name);
+        }
+        // Fields inherited from superclasses and interfaces belong to the class declaring them
+        let class_name = match get_field_declaring_class(&get_class(class_name), field_name) {
+            Ok(Some(declaring)) => declaring.get_name(),
+            _ => class_name.clone()
+        };
+        let label = format!("static_{}", runtime::sanitize(&format!("{}_{}", class_name, field_name)));
+        self.statics.insert(label.clone());
+        format!("{}    mov rax, qword {}\n", self.initialize_class(&class_name), label)
+    }
+
+    pub fn get_static_field(&mut self, class_name: &String, field_name: &String) -> String {
+        // System.out and System.err are their file descriptors
+        match (&class_name[..], &field_name[..]) {
+            ("java/lang/System", "out") => "    push 1".to_string(),
+            ("java/lang/System", "err") => "    push 2".to_string(),
+            _ => format!("{}    push qword [rax]", self.static_field(class_name, field_name))
+        }
+    }
+
+    pub fn put_static_field(&mut self, class_name: &String, field_name: &String) -> String {
+        format!("{}    pop rcx\n    mov [rax], rcx", self.static_field(class_name, field_name))
+    }
+}
+
+enum Operand {
+    Reg(Reg),
+    Xmm(Xmm),
+    // x87 register st0-st7
+    St(u8),
+    Mem(Mem),
+    Imm(i64),
+    Label(String)
+}
+
+// Local labels (starting with a dot) belong to the last non-local label
+fn scoped_label(name: &str, scope: &str) -> String {
+    if name.starts_with('.') { format!("{}{}", scope, name) } else { name.to_string() }
+}
+
+fn encoder_operand(operand: ir::Operand, scope: &str) -> Operand {
+    match operand {
+        ir::Operand::Loc(Loc::Reg(reg)) => Operand::Reg(reg),
+        ir::Operand::Loc(Loc::Xmm(xmm)) => Operand::Xmm(Xmm(xmm)),
+        ir::Operand::Loc(Loc::Virt(virt)) => panic!("Virtual register {} in assembly", virt),
+        ir::Operand::Mem(base, disp) => Operand::Mem(Mem::new(base, disp)),
+        ir::Operand::Imm(imm) => Operand::Imm(imm),
+        ir::Operand::Label(name) => match name.strip_prefix("st").and_then(|idx| idx.parse::<u8>().ok()) {
+            Some(idx) if idx < 8 => Operand::St(idx),
+            _ => Operand::Label(scoped_label(&name, scope))
+        }
+    }
+}
+
+// Bytes of a db directive, e.g. "Hello", 0 or 0ah, 0
+fn parse_bytes(values: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut rest = values.trim();
+    while !rest.is_empty() {
+        if let Some(string) = rest.strip_prefix('"') {
+            let end = string.find('"').unwrap_or_else(|| panic!("Unterminated string in assembly: {}", values));
+            bytes.extend_from_slice(string[..end].as_bytes());
+            rest = &string[end + 1..];
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            match ir::parse_number(rest[..end].trim()) {
+                Some(byte) => bytes.push(byte as u8),
+                None => panic!("Invalid byte in assembly: {}", values)
+            }
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+    }
+    bytes
+}
+
+// Values of a dq directive: numbers, or labels whose address gets filled in once the code is assembled
+fn parse_quads(values: &str, data: &mut Vec<u8>, refs: &mut Vec<(usize, String)>) {
+    for value in values.split(',').map(|value| value.trim()) {
+        match ir::parse_number(value) {
+            Some(number) => data.extend_from_slice(&number.to_le_bytes()),
             None => {
-                self.register_idx += 1;
-                self.variables.insert(var_idx, self.register_idx);
-                self.register_idx
+                refs.push((data.len(), value.to_string()));
+                data.extend_from_slice(&[0; 8]);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn condition(mnemonic: &str) -> Option<Cond> {
+    match mnemonic {
+        "jo" => Some(Cond::O), "jno" => Some(Cond::NO), "jb" => Some(Cond::B), "jae" => Some(Cond::AE),
+        "je" | "jz" => Some(Cond::E), "jne" | "jnz" => Some(Cond::NE), "jbe" => Some(Cond::BE), "ja" => Some(Cond::A),
+        "js" => Some(Cond::S), "jns" => Some(Cond::NS), "jp" => Some(Cond::P), "jnp" => Some(Cond::NP),
+        "jl" => Some(Cond::L), "jge" => Some(Cond::GE), "jle" => Some(Cond::LE), "jg" => Some(Cond::G),
+        _ => None
+    }
+}
+
+fn alu(mnemonic: &str) -> Option<Alu> {
+    match mnemonic {
+        "add" => Some(Alu::Add), "or" => Some(Alu::Or), "adc" => Some(Alu::Adc), "and" => Some(Alu::And),
+        "sub" => Some(Alu::Sub), "xor" => Some(Alu::Xor), "cmp" => Some(Alu::Cmp),
+        _ => None
+    }
+}
+
+fn shift(mnemonic: &str) -> Option<Shift> {
+    match mnemonic {
+        "shl" => Some(Shift::Shl), "shr" => Some(Shift::Shr), "sar" => Some(Shift::Sar),
+        _ => None
+    }
+}
+
+// Scalar SSE instructions: operation and whether it is on doubles
+fn sse(mnemonic: &str) -> Option<(Sse, bool)> {
+    let op = match &mnemonic[..mnemonic.len().saturating_sub(2)] {
+        "sqrt" => Sse::Sqrt, "add" => Sse::Add, "mul" => Sse::Mul, "sub" => Sse::Sub, "div" => Sse::Div,
+        _ => return None
+    };
+    match &mnemonic[mnemonic.len() - 2..] {
+        "ss" => Some((op, false)),
+        "sd" => Some((op, true)),
+        _ => None
+    }
+}
+
+fn x87(mnemonic: &str) -> Option<X87> {
+    match mnemonic {
+        "fchs" => Some(X87::Fchs), "fld1" => Some(X87::Fld1), "fldl2e" => Some(X87::Fldl2e), "fldlg2" => Some(X87::Fldlg2),
+        "fldln2" => Some(X87::Fldln2), "fldpi" => Some(X87::Fldpi), "fldz" => Some(X87::Fldz), "f2xm1" => Some(X87::F2xm1), "fyl2x" => Some(X87::Fyl2x),
+        "fprem" => Some(X87::Fprem), "frndint" => Some(X87::Frndint), "fscale" => Some(X87::Fscale),
+        "fsin" => Some(X87::Fsin), "fcos" => Some(X87::Fcos),
+        _ => None
+    }
+}
+
+fn get_label(labels: &mut HashMap<String, Label>, enc: &mut Encoder, name: &String) -> Label {
+    *labels.entry(name.clone()).or_insert_with(|| enc.new_label())
+}
+
+fn imm32(imm: i64, line: &str) -> i32 {
+    // Like nasm, 32-bit values above i32::MAX are their sign-extended bits
+    if imm >= i32::MIN as i64 && imm <= u32::MAX as i64 {
+        imm as i32
+    } else {
+        panic!("Immediate out of range in assembly: {}", line)
+    }
+}
+
+// Assembles the Linux source written by class_to_asm, which only uses a subset of nasm, into its code, its data,
+// and the offset of the _start entry point in the code. Addresses are resolved for the ELF layout
+fn assemble(source: &str) -> (Vec<u8>, Vec<u8>, usize) {
+    let mut enc = Encoder::new();
+    let mut labels: HashMap<String, Label> = HashMap::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut data_labels: HashMap<String, usize> = HashMap::new();
+    // Positions of the 64-bit immediates in the code, and of the quads in the data, which are addresses of labels
+    let mut data_refs: Vec<(usize, String)> = Vec::new();
+    let mut quad_refs: Vec<(usize, String)> = Vec::new();
+    let mut scope = String::new();
+    let mut in_data = false;
+
+    for line in source.lines() {
+        if in_data {
+            // [label:] db or dq values, where strings can contain colons
+            let (label, directive) = match line.split_once(':') {
+                Some((label, directive)) if !label.contains('"') => (Some(label.trim()), directive.trim()),
+                _ => (None, line.trim())
+            };
+            if let Some(values) = directive.strip_prefix("db ") {
+                if let Some(label) = label { data_labels.insert(label.to_string(), data.len()); }
+                data.extend(parse_bytes(values));
+                continue;
+            }
+            if let Some(values) = directive.strip_prefix("dq ") {
+                if let Some(label) = label { data_labels.insert(label.to_string(), data.len()); }
+                parse_quads(values, &mut data, &mut quad_refs);
+                continue;
+            }
+        }
+
+        let line = line.trim();
+        if line.starts_with("global ") {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix("section ") {
+            in_data = section.trim() == ".data";
+            continue;
+        }
+        let (mnemonic, operands): (String, Vec<Operand>) = match ir::parse_line(line) {
+            None => continue,
+            Some(Instr::Label(name)) => {
+                if !name.starts_with('.') {
+                    scope = name.clone();
+                }
+                let label = get_label(&mut labels, &mut enc, &scoped_label(&name, &scope));
+                enc.bind(label);
+                continue;
+            },
+            Some(Instr::Op(mnemonic, operands)) => (mnemonic, operands.into_iter().map(|operand| encoder_operand(operand, &scope)).collect())
+        };
+
+        match (mnemonic.as_str(), &operands[..]) {
+            ("push", [Operand::Reg(reg)]) => enc.push(*reg),
+            ("push", [Operand::Imm(imm)]) => enc.push_i(imm32(*imm, line)),
+            ("push", [Operand::Mem(mem)]) => enc.push_m(*mem),
+            ("pop", [Operand::Reg(reg)]) => enc.pop(*reg),
+            ("pop", [Operand::Mem(mem)]) => enc.pop_m(*mem),
+            ("mov", [Operand::Reg(dst), Operand::Reg(src)]) => enc.mov_rr(*dst, *src),
+            ("mov", [Operand::Reg(dst), Operand::Imm(imm)]) => enc.mov_ri(*dst, *imm),
+            ("mov", [Operand::Reg(dst), Operand::Label(name)]) => {
+                enc.mov_ri64(*dst, 0);
+                data_refs.push((enc.code.len() - 8, name.clone()));
+            },
+            ("mov", [Operand::Reg(dst), Operand::Mem(mem)]) => enc.mov_rm(*dst, *mem),
+            ("mov", [Operand::Mem(mem), Operand::Reg(src)]) if line.contains("byte [") => enc.mov8_mr(*mem, *src),
+            ("mov", [Operand::Mem(mem), Operand::Reg(src)]) => enc.mov_mr(*mem, *src),
+            ("mov", [Operand::Mem(mem), Operand::Imm(imm)]) => enc.mov_mi(*mem, imm32(*imm, line)),
+            ("movzx", [Operand::Reg(dst), Operand::Mem(mem)]) => enc.movzx8_rm(*dst, *mem),
+            ("movsxd", [Operand::Reg(dst), Operand::Reg(src)]) => enc.movsxd_rr(*dst, *src),
+            ("movsxd", [Operand::Reg(dst), Operand::Mem(mem)]) => enc.movsxd_rm(*dst, *mem),
+            ("movq", [Operand::Xmm(dst), Operand::Reg(src)]) => enc.movq_xr(true, *dst, *src),
+            ("movq", [Operand::Reg(dst), Operand::Xmm(src)]) => enc.movq_rx(true, *dst, *src),
+            ("movq", [Operand::Mem(mem), Operand::Xmm(src)]) => enc.movs_mr(true, *mem, *src),
+            ("movq", [Operand::Xmm(dst), Operand::Mem(mem)]) => enc.movs_rm(true, *dst, *mem),
+            ("movsd", [Operand::Xmm(dst), Operand::Xmm(src)]) => enc.movs_rr(true, *dst, *src),
+            (op, [Operand::Reg(dst), Operand::Reg(src)]) if alu(op).is_some() => enc.alu_rr(alu(op).unwrap(), true, *dst, *src),
+            (op, [Operand::Reg(dst), Operand::Imm(imm)]) if alu(op).is_some() => enc.alu_ri(alu(op).unwrap(), true, *dst, imm32(*imm, line)),
+            (op, [Operand::Reg(dst), Operand::Mem(mem)]) if alu(op).is_some() => enc.alu_rm(alu(op).unwrap(), true, *dst, *mem),
+            (op, [Operand::Mem(mem), Operand::Reg(src)]) if alu(op).is_some() => enc.alu_mr(alu(op).unwrap(), true, *mem, *src),
+            (op, [Operand::Mem(mem), Operand::Imm(imm)]) if alu(op).is_some() => enc.alu_mi(alu(op).unwrap(), true, *mem, imm32(*imm, line)),
+            ("imul", [Operand::Reg(dst), Operand::Reg(src)]) => enc.imul_rr(true, *dst, *src),
+            ("imul", [Operand::Reg(dst), Operand::Mem(mem)]) => enc.imul_rm(true, *dst, *mem),
+            (op, [Operand::Reg(reg), Operand::Imm(count)]) if shift(op).is_some() => enc.shift_ri(shift(op).unwrap(), true, *reg, (*count & 63) as u8),
+            (op, [Operand::Reg(reg), Operand::Reg(Reg::RCX)]) if shift(op).is_some() => enc.shift_cl(shift(op).unwrap(), true, *reg),
+            ("idiv", [Operand::Reg(reg)]) => enc.idiv_r(true, *reg),
+            ("div", [Operand::Reg(reg)]) => enc.div_r(true, *reg),
+            ("mul", [Operand::Reg(reg)]) => enc.mul_r(true, *reg),
+            ("inc", [Operand::Reg(reg)]) => enc.inc_r(true, *reg),
+            ("neg", [Operand::Reg(reg)]) => enc.neg_r(true, *reg),
+            ("cqo", []) => enc.sign_extend_rax(true),
+            ("jmp", [Operand::Label(name)]) => { let target = get_label(&mut labels, &mut enc, name); enc.jmp(target); },
+            ("call", [Operand::Label(name)]) => { let target = get_label(&mut labels, &mut enc, name); enc.call(target); },
+            (op, [Operand::Label(name)]) if condition(op).is_some() => { let target = get_label(&mut labels, &mut enc, name); enc.jcc(condition(op).unwrap(), target); },
+            ("ret", []) => enc.ret(),
+            ("leave", []) => enc.leave(),
+            ("syscall", []) => enc.syscall(),
+            ("nop", []) => enc.nop(),
+            ("ucomiss", [Operand::Xmm(a), Operand::Xmm(b)]) => enc.ucomis_rr(false, *a, *b),
+            ("ucomisd", [Operand::Xmm(a), Operand::Xmm(b)]) => enc.ucomis_rr(true, *a, *b),
+            ("cvtsi2ss", [Operand::Xmm(dst), Operand::Reg(src)]) => enc.cvtsi2s_rr(false, true, *dst, *src),
+            ("cvtsi2sd", [Operand::Xmm(dst), Operand::Reg(src)]) => enc.cvtsi2s_rr(true, true, *dst, *src),
+            ("cvtss2sd", [Operand::Xmm(dst), Operand::Xmm(src)]) => enc.cvts2s_rr(false, *dst, *src),
+            ("cvtsd2ss", [Operand::Xmm(dst), Operand::Xmm(src)]) => enc.cvts2s_rr(true, *dst, *src),
+            ("cvttss2si", [Operand::Reg(dst), Operand::Xmm(src)]) => enc.cvtts2si_rr(false, true, *dst, *src),
+            ("cvttsd2si", [Operand::Reg(dst), Operand::Xmm(src)]) => enc.cvtts2si_rr(true, true, *dst, *src),
+            (op, [Operand::Xmm(dst), Operand::Xmm(src)]) if sse(op).is_some() => {
+                let (op, double) = sse(op).unwrap();
+                enc.sse_op_rr(op, double, *dst, *src);
+            },
+            ("fld", [Operand::Mem(mem)]) if line.contains("dword [") => enc.fld32_m(*mem),
+            ("fld", [Operand::Mem(mem)]) => enc.fld_m(*mem),
+            ("fld", [Operand::St(idx)]) => enc.fld_st(*idx),
+            ("fild", [Operand::Mem(mem)]) => enc.fild_m(*mem),
+            ("fstp", [Operand::Mem(mem)]) if line.contains("dword [") => enc.fstp32_m(*mem),
+            ("fstp", [Operand::Mem(mem)]) => enc.fstp_m(*mem),
+            ("fstp", [Operand::St(idx)]) => enc.fstp_st(*idx),
+            ("fistp", [Operand::Mem(mem)]) => enc.fistp_m(*mem),
+            ("fxch", [Operand::St(idx)]) => enc.fxch(*idx),
+            ("fmul", [Operand::St(0), Operand::St(idx)]) => enc.fmul_st(*idx),
+            ("fsub", [Operand::St(0), Operand::St(idx)]) => enc.fsub_st(*idx),
+            ("fdiv", [Operand::St(0), Operand::St(idx)]) => enc.fdiv_st(*idx),
+            ("faddp", [Operand::St(idx)]) => enc.faddp(*idx),
+            ("fmulp", [Operand::St(idx)]) => enc.fmulp(*idx),
+            ("fucomip", [Operand::St(0), Operand::St(idx)]) => enc.fucomip(*idx),
+            ("fnstsw", [Operand::Label(reg)]) if reg == "ax" => enc.fnstsw_ax(),
+            (op, []) if x87(op).is_some() => enc.x87(x87(op).unwrap()),
+            _ => panic!("Unsupported instruction in assembly: {}", line)
+        }
+    }
+
+    let entry = match labels.get("_start") {
+        Some(label) => enc.label_offset(*label),
+        None => panic!("No _start entry point in assembly")
+    };
+    let code_labels: HashMap<String, usize> = labels.iter().map(|(name, label)| (name.clone(), enc.label_offset(*label))).collect();
+    let mut code = enc.finish();
+
+    let data_address = elf::data_address(code.len());
+    let address = |name: &String| -> u64 {
+        match (data_labels.get(name), code_labels.get(name)) {
+            (Some(offset), _) => data_address + *offset as u64,
+            (None, Some(offset)) => elf::CODE_ADDRESS + *offset as u64,
+            (None, None) => panic!("Unknown label {} in assembly", name)
+        }
+    };
+    for (pos, name) in data_refs.iter() {
+        code[*pos..*pos + 8].copy_from_slice(&address(name).to_le_bytes());
+    }
+    for (pos, name) in quad_refs.iter() {
+        data[*pos..*pos + 8].copy_from_slice(&address(name).to_le_bytes());
+    }
+    (code, data, entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "    section   .text\n_start:\n    push qword [rbp-16]\n    jmp .branch0\n.branch0:\n    mov rsi, qword str_cr    ; \"\\n\"\n    ret\n    section   .data\nstr_cr: db 0ah, 0\n";
+        let (code, data, entry) = assemble(source);
+        let address = elf::data_address(code.len()).to_le_bytes();
+        assert_eq!(entry, 0);
+        assert_eq!(code[..8], [0xff, 0x75, 0xf0, 0xe9, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(code[8..10], [0x48, 0xbe]);
+        assert_eq!(code[10..18], address);
+        assert_eq!(code[18], 0xc3);
+        assert_eq!(data, vec![0x0a, 0x00]);
+    }
+}
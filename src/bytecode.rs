@@ -1,10 +1,10 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use rand::Rng;
-
-use crate::asm::Assembly;
-use crate::{get_class, get_debug};
+use crate::asm::{Assembly, METHOD_EPILOGUE};
+use crate::jit::JitState;
+use crate::vm::{self, get_class, get_debug, class_exists, initialize_class, load_class};
 use crate::bytecode_class::{ConstantField, ConstantFloat, ConstantInteger, ConstantLong, ConstantDouble };
 use crate::bytecode_class::ConstantString;
 use crate::bytecode_class::ConstantStringRef;
@@ -12,21 +12,23 @@ use crate::bytecode_class::ConstantClass;
 use crate::bytecode_class::ConstantMethod;
 use crate::bytecode_class::ConstantNameType;
 use crate::bytecode_class::ConstantInvokeDynamic;
-use crate::StackFrame;
-use crate::bytecode_class::Blob;
-use crate::java_class::{JavaClassInstance, MethodCallResult};
-use crate::java_class::get_nb_arguments;
-use crate::jvm::JavaInstance;
-use crate::native_java_classes::{NativeDoubleInstance, NativeGenericExceptionClass};
-use crate::native_java_classes::NativeFloatInstance;
-use crate::native_java_classes::NativeIntegerInstance;
-use crate::native_java_classes::NativeLongInstance;
+use crate::bytecode_class::{Blob, select_default_method};
+use crate::java_class::{JavaClass, JavaClassInstance, MethodCallResult};
+use crate::java_class::{get_nb_arguments, get_return_type};
+use crate::jvm::{JavaInstance, StackFrame, Value};
+use crate::monitor::{get_object_id, monitor_enter, monitor_exit};
+use crate::native_java_classes::{NativeByteInstance, NativeCharInstance, NativeShortInstance, int_to_char, new_java_exception, null_instance};
 use crate::native_java_classes::NativeStringInstance;
 
-pub trait ByteCodeInstruction {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction;
+pub trait ByteCodeInstruction: Send + Sync {
+    // Instructions with a compact form are executed by their Op, the others implement execute()
+    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction { self.get_op().execute(sf) }
+    fn get_op(&self) -> Op { Op::Instr }
     fn print(&self);
     fn set_branch(&mut self, _address_map: &HashMap<usize, usize>) {}
+    // Whether the value pushed is a long or a double. The conversion to assembly gives them a single slot of the
+    // operand stack, so pop2 and the dup2 family need to know
+    fn pushes_category2(&self) -> bool { false }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { self.print(); panic!("Instruction does not support conversion to x64 assembly"); }
     fn convert_to_macos_intel_asm(&self, assembly: &mut Assembly) -> String { self.convert_to_intel_asm(assembly) }
     fn convert_to_linux_intel_asm(&self, assembly: &mut Assembly) -> String { self.convert_to_intel_asm(assembly) }
@@ -39,10 +41,155 @@ pub enum InstrNextAction {
     EXCEPTION(Arc<Mutex<dyn JavaInstance>>)
 }
 
+// Compact form of the most frequent instructions, which the interpreter loop runs without a virtual call.
+// Branches are instruction indexes, and Instr means the instruction only exists as a ByteCodeInstruction
+#[derive(Clone, Copy)]
+pub enum Op {
+    Nop, AConstNull, IConst(i32), LConst(i64), FConst(f32), DConst(f64),
+    Load(usize), Store(usize), IInc(usize, i32), Pop, Dup,
+    IAdd, LAdd, FAdd, DAdd, ISub, LSub, FSub, DSub, IMul, LMul, FMul, DMul, INeg, LNeg, FNeg, DNeg,
+    IShl, IShr, IAnd, IOr, IXor, LAnd, LOr, LXor,
+    I2L, I2F, I2D, L2I, L2F, L2D, F2I, F2L, F2D, D2I, D2L, D2F,
+    LCmp, FCmpl, FCmpg, DCmpl, DCmpg,
+    IfEq(usize), IfNe(usize), IfLt(usize), IfGe(usize), IfGt(usize), IfLe(usize),
+    IfICmpEq(usize), IfICmpNe(usize), IfICmpLt(usize), IfICmpGe(usize), IfICmpGt(usize), IfICmpLe(usize),
+    IfNull(usize), IfNonNull(usize), Goto(usize),
+    Return, ValueReturn,
+    Instr
+}
+
+impl Op {
+    #[inline(always)]
+    pub fn execute(self, sf: &mut StackFrame) -> InstrNextAction {
+        match self {
+            Op::Nop => {},
+            Op::AConstNull => sf.push_null(),
+            Op::IConst(value) => sf.push_int(value),
+            Op::LConst(value) => sf.push_long(value),
+            Op::FConst(value) => sf.push_float(value),
+            Op::DConst(value) => sf.push_double(value),
+            Op::Load(idx) => sf.variable_to_stack(idx),
+            Op::Store(idx) => sf.stack_to_variable(idx),
+            Op::IInc(idx, count) => {
+                sf.variable_to_stack(idx);
+                let nb = sf.pop_int();
+                sf.push_int(nb.wrapping_add(count));
+                sf.stack_to_variable(idx);
+            },
+            Op::Pop => { sf.pop_value(); },
+            Op::Dup => {
+                let arg = sf.pop_value();
+                sf.push_value(arg.clone());
+                sf.push_value(arg);
+            },
+            Op::IAdd => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2.wrapping_add(nb1)); },
+            Op::LAdd => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2.wrapping_add(nb1)); },
+            Op::FAdd => { let nb1 = sf.pop_float(); let nb2 = sf.pop_float(); sf.push_float(nb2 + nb1); },
+            Op::DAdd => { let nb1 = sf.pop_double(); let nb2 = sf.pop_double(); sf.push_double(nb2 + nb1); },
+            Op::ISub => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2.wrapping_sub(nb1)); },
+            Op::LSub => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2.wrapping_sub(nb1)); },
+            Op::FSub => { let nb1 = sf.pop_float(); let nb2 = sf.pop_float(); sf.push_float(nb2 - nb1); },
+            Op::DSub => { let nb1 = sf.pop_double(); let nb2 = sf.pop_double(); sf.push_double(nb2 - nb1); },
+            Op::IMul => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2.wrapping_mul(nb1)); },
+            Op::LMul => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2.wrapping_mul(nb1)); },
+            Op::FMul => { let nb1 = sf.pop_float(); let nb2 = sf.pop_float(); sf.push_float(nb2 * nb1); },
+            Op::DMul => { let nb1 = sf.pop_double(); let nb2 = sf.pop_double(); sf.push_double(nb2 * nb1); },
+            Op::INeg => { let nb = sf.pop_int(); sf.push_int(nb.wrapping_neg()); },
+            Op::LNeg => { let nb = sf.pop_long(); sf.push_long(nb.wrapping_neg()); },
+            Op::FNeg => { let nb = sf.pop_float(); sf.push_float(-nb); },
+            Op::DNeg => { let nb = sf.pop_double(); sf.push_double(-nb); },
+            Op::IShl => { let value2 = sf.pop_int() & 31; let value1 = sf.pop_int(); sf.push_int(value1 << value2); },
+            Op::IShr => { let value2 = sf.pop_int() & 31; let value1 = sf.pop_int(); sf.push_int(value1 >> value2); },
+            Op::IAnd => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2 & nb1); },
+            Op::IOr => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2 | nb1); },
+            Op::IXor => { let nb1 = sf.pop_int(); let nb2 = sf.pop_int(); sf.push_int(nb2 ^ nb1); },
+            Op::LAnd => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2 & nb1); },
+            Op::LOr => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2 | nb1); },
+            Op::LXor => { let nb1 = sf.pop_long(); let nb2 = sf.pop_long(); sf.push_long(nb2 ^ nb1); },
+            Op::I2L => { let nb = sf.pop_int(); sf.push_long(nb as i64); },
+            Op::I2F => { let nb = sf.pop_int(); sf.push_float(nb as f32); },
+            Op::I2D => { let nb = sf.pop_int(); sf.push_double(nb as f64); },
+            Op::L2I => { let nb = sf.pop_long(); sf.push_int(nb as i32); },
+            Op::L2F => { let nb = sf.pop_long(); sf.push_float(nb as f32); },
+            Op::L2D => { let nb = sf.pop_long(); sf.push_double(nb as f64); },
+            Op::F2I => { let nb = sf.pop_float(); sf.push_int(nb as i32); },
+            Op::F2L => { let nb = sf.pop_float(); sf.push_long(nb as i64); },
+            Op::F2D => { let nb = sf.pop_float(); sf.push_double(nb as f64); },
+            Op::D2I => { let nb = sf.pop_double(); sf.push_int(nb as i32); },
+            Op::D2L => { let nb = sf.pop_double(); sf.push_long(nb as i64); },
+            Op::D2F => { let nb = sf.pop_double(); sf.push_float(nb as f32); },
+            Op::LCmp => { let nb2 = sf.pop_long(); let nb1 = sf.pop_long(); sf.push_int(compare(nb1, nb2)); },
+            Op::FCmpl => { let nb2 = sf.pop_float(); let nb1 = sf.pop_float(); sf.push_int(compare_floats(nb1, nb2, -1)); },
+            Op::FCmpg => { let nb2 = sf.pop_float(); let nb1 = sf.pop_float(); sf.push_int(compare_floats(nb1, nb2, 1)); },
+            Op::DCmpl => { let nb2 = sf.pop_double(); let nb1 = sf.pop_double(); sf.push_int(compare_floats(nb1, nb2, -1)); },
+            Op::DCmpg => { let nb2 = sf.pop_double(); let nb1 = sf.pop_double(); sf.push_int(compare_floats(nb1, nb2, 1)); },
+            Op::IfEq(branch) => if sf.pop_int() == 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfNe(branch) => if sf.pop_int() != 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfLt(branch) => if sf.pop_int() < 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfGe(branch) => if sf.pop_int() >= 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfGt(branch) => if sf.pop_int() > 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfLe(branch) => if sf.pop_int() <= 0 { return InstrNextAction::GOTO(branch); },
+            Op::IfICmpEq(branch) => { let value2 = sf.pop_int(); if sf.pop_int() == value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfICmpNe(branch) => { let value2 = sf.pop_int(); if sf.pop_int() != value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfICmpLt(branch) => { let value2 = sf.pop_int(); if sf.pop_int() < value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfICmpGe(branch) => { let value2 = sf.pop_int(); if sf.pop_int() >= value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfICmpGt(branch) => { let value2 = sf.pop_int(); if sf.pop_int() > value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfICmpLe(branch) => { let value2 = sf.pop_int(); if sf.pop_int() <= value2 { return InstrNextAction::GOTO(branch); } },
+            Op::IfNull(branch) => if sf.pop_isnull() { return InstrNextAction::GOTO(branch); },
+            Op::IfNonNull(branch) => if !sf.pop_isnull() { return InstrNextAction::GOTO(branch); },
+            Op::Goto(branch) => return InstrNextAction::GOTO(branch),
+            Op::Return => return InstrNextAction::RETURN,
+            Op::ValueReturn => {
+                sf.set_return_arg_flag();
+                return InstrNextAction::RETURN;
+            },
+            Op::Instr => panic!("Instruction has no compact form")
+        }
+        InstrNextAction::NEXT
+    }
+}
+
+// 1 if nb1 > nb2, -1 if nb1 < nb2, and 0 otherwise
+fn compare<T: PartialOrd>(nb1: T, nb2: T) -> i32 {
+    if nb1 > nb2 {
+        1
+    } else if nb1 < nb2 {
+        -1
+    } else {
+        0
+    }
+}
+
+// fcmpl and dcmpl give -1 if a value is NaN, fcmpg and dcmpg give 1
+fn compare_floats<T: PartialOrd>(nb1: T, nb2: T, nan: i32) -> i32 {
+    match nb1.partial_cmp(&nb2) {
+        Some(_) => compare(nb1, nb2),
+        None => nan
+    }
+}
+
+// Pushes 1, 0 or -1 from the flags of a comparison, given the jumps taken if the first value is greater or less,
+// and the result for NaN after ucomiss or ucomisd (which sets the parity flag for it)
+fn comparison_to_asm(assembly: &mut Assembly, compare: &str, greater: &str, less: &str, nan: Option<i32>) -> String {
+    let (greater_label, less_label, end_label) = (assembly.new_label("greater"), assembly.new_label("less"), assembly.new_label("end"));
+    let nan_jump = match nan {
+        Some(1) => format!("    jp {}\n", greater_label),
+        Some(_) => format!("    jp {}\n", less_label),
+        None => String::new()
+    };
+    format!("{}\n{}    {} {}\n    {} {}\n    push 0\n    jmp {}\n{}:\n    push 1\n    jmp {}\n{}:\n    push -1\n{}:",
+        compare, nan_jump, greater, greater_label, less, less_label, end_label, greater_label, end_label, less_label, end_label)
+}
+
+fn float_comparison_to_asm(assembly: &mut Assembly, double: bool, nan: i32) -> String {
+    let compare = format!("    pop rax\n    movq xmm15, rax\n    pop rax\n    movq xmm14, rax\n    ucomis{} xmm14, xmm15", if double { "d" } else { "s" });
+    comparison_to_asm(assembly, &compare, "ja", "jb", Some(nan))
+}
+
 #[macro_export]
 macro_rules! exception {
     ( $name:expr, $message:expr ) => {
-        InstrNextAction::EXCEPTION(Arc::new(Mutex::new(NativeGenericExceptionClass::new(&$name.to_string(), &$message.to_string()))))
+        InstrNextAction::EXCEPTION(new_java_exception(&$name.to_string(), &$message.to_string()))
     };
 }
 
@@ -51,27 +198,25 @@ macro_rules! exception {
 
 pub struct InstrNop { }
 impl ByteCodeInstruction for InstrNop {
-    fn execute(&self, _sf: &mut StackFrame) -> InstrNextAction {
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Nop }
     fn print(&self) { println!("      nop"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    nop".to_string()
+    }
 }
 
 pub struct InstrAConstNull { }
 impl ByteCodeInstruction for InstrAConstNull {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_null();
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::AConstNull }
     fn print(&self) { println!("      aconst_null"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    push 0".to_string()
+    }
 }
 
 pub struct InstrIConst { value: i32 }
 impl ByteCodeInstruction for InstrIConst {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_int(self.value);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IConst(self.value) }
     fn print(&self) { println!("      iconst_{}", self.value); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    push {:#x}", self.value )
@@ -80,28 +225,27 @@ impl ByteCodeInstruction for InstrIConst {
 
 pub struct InstrLConst0 { }
 impl ByteCodeInstruction for InstrLConst0 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_long(0);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LConst(0) }
     fn print(&self) { println!("      lconst_0"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    push 0".to_string()
+    }
 }
 
 pub struct InstrLConst1 { }
 impl ByteCodeInstruction for InstrLConst1 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_long(1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LConst(1) }
     fn print(&self) { println!("      lconst_1"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    push 1".to_string()
+    }
 }
 
 pub struct InstrFConst0 { }
 impl ByteCodeInstruction for InstrFConst0 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_float(0.0);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FConst(0.0) }
     fn print(&self) { println!("      fconst_0"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    push __float32__(0.0)")
@@ -110,10 +254,7 @@ impl ByteCodeInstruction for InstrFConst0 {
 
 pub struct InstrFConst1 { }
 impl ByteCodeInstruction for InstrFConst1 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_float(1.0);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FConst(1.0) }
     fn print(&self) { println!("      fconst_1"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    push __float32__(1.0)")
@@ -122,10 +263,7 @@ impl ByteCodeInstruction for InstrFConst1 {
 
 pub struct InstrFConst2 { }
 impl ByteCodeInstruction for InstrFConst2 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_float(2.0);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FConst(2.0) }
     fn print(&self) { println!("      fconst_2"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    push __float32__(2.0)")
@@ -134,122 +272,128 @@ impl ByteCodeInstruction for InstrFConst2 {
 
 pub struct InstrDConst0 { }
 impl ByteCodeInstruction for InstrDConst0 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_double(0.0);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DConst(0.0) }
     fn print(&self) { println!("      dconst_0"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    push 0".to_string()
+    }
 }
 
 pub struct InstrDConst1 { }
 impl ByteCodeInstruction for InstrDConst1 {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_double(1.0);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DConst(1.0) }
     fn print(&self) { println!("      dconst_1"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    mov rax, 0x3ff0000000000000    ; 1.0\n    push rax".to_string()
+    }
 }
 
 ///////////// 0x1
 
 pub struct InstrBiPush { value: u8 }
 impl ByteCodeInstruction for InstrBiPush {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_byte(self.value);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IConst(self.value as i8 as i32) }
     fn print(&self) { println!("      bipush {}", self.value); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    push {:#x}", self.value)
+        format!("    push {}", self.value as i8)
     }
 }
 
 pub struct InstrSiPush { value: i16 }
 impl ByteCodeInstruction for InstrSiPush {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push_short(self.value);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IConst(self.value as i32) }
     fn print(&self) { println!("      sipush {}", self.value); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    push {:#x}", self.value)
+        format!("    push {}", self.value)
     }
 }
 
 pub struct InstrILoad { variable: u8 }
 impl ByteCodeInstruction for InstrILoad {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Load(self.variable as usize) }
     fn print(&self) { println!("      iload {}", self.variable); }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        format!("    push r{}", assembly.var_to_reg(self.variable))
+        format!("    push {}", assembly.variable(self.variable))
     }
 }
 
 pub struct InstrLLoad { variable: u8 }
 impl ByteCodeInstruction for InstrLLoad {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::Load(self.variable as usize) }
     fn print(&self) { println!("      lload {}", self.variable); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    push {}", assembly.variable(self.variable))
+    }
 }
 
 pub struct InstrFLoad { variable: u8 }
 impl ByteCodeInstruction for InstrFLoad {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Load(self.variable as usize) }
     fn print(&self) { println!("      fload {}", self.variable); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    movq rax, xmm{}\n    push rax", self.variable)
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    push {}", assembly.variable(self.variable))
     }
 }
 
 pub struct InstrDLoad { variable: u8 }
 impl ByteCodeInstruction for InstrDLoad {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::Load(self.variable as usize) }
     fn print(&self) { println!("      dload {}", self.variable); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    push {}", assembly.variable(self.variable))
+    }
 }
 
 pub struct InstrALoad { variable: u8 }
 impl ByteCodeInstruction for InstrALoad {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Load(self.variable as usize) }
     fn print(&self) { println!("      aload {}", self.variable); }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        format!("    push r{}", assembly.var_to_reg(self.variable))
+        format!("    push {}", assembly.variable(self.variable))
     }
 }
 
-pub struct InstrLdc { value: Arc<Mutex<dyn JavaInstance>>, instance_type: String }
+pub struct InstrLdc { value: Value, instance_type: String, interned: OnceLock<Arc<Mutex<dyn JavaInstance>>> }
 impl ByteCodeInstruction for InstrLdc {
+    fn pushes_category2(&self) -> bool { self.instance_type == "long" || self.instance_type == "double" }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.push(self.value.clone());
+        // String literals are interned by the VM the first time they are loaded, so that equal ones are the same object
+        if self.instance_type.eq("string") {
+            let string = self.interned.get_or_init(|| vm::current().intern(&self.value.get_string()));
+            sf.push(string.clone());
+            return InstrNextAction::NEXT;
+        }
+        sf.push_value(self.value.clone());
         return InstrNextAction::NEXT;
     }
+    // Strings and classes are shared objects, so only the primitive constants have a compact form
+    fn get_op(&self) -> Op {
+        match self.value {
+            Value::Int(value) => Op::IConst(value),
+            Value::Long(value) => Op::LConst(value),
+            Value::Float(value) => Op::FConst(value),
+            Value::Double(value) => Op::DConst(value),
+            _ => Op::Instr
+        }
+    }
     fn print(&self) {
         print!("      ldc ");
-        self.value.lock().unwrap().print();
+        self.value.print();
         println!();
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
         match &self.instance_type[..] {
             "string" => {
-                let str = self.value.lock().unwrap().get_string();
+                let str = self.value.get_string();
                 let str_label = assembly.add_string(&str);
-                format!("    mov rax, qword {}    ; \"{}\"\n    push rax", str_label, str)
+                format!("    mov rax, qword {}    ; {:?}\n    push rax", str_label, str)
             },
             "float" => {
-                let float = self.value.lock().unwrap().get_float();
+                let float = self.value.get_float();
                 if float.round().eq(&float) {
                     format!("    mov dword eax, __float32__({}.0)    ; \"{}\"\n    push rax", float, float)
                 } else {
@@ -257,8 +401,16 @@ impl ByteCodeInstruction for InstrLdc {
                 }
             },
             "int" => {
-                let int = self.value.lock().unwrap().get_int();
+                let int = self.value.get_int();
                 format!("    mov rax, {}    ; \"{}\"\n    push rax", int, int)
+            },
+            "long" => {
+                let long = self.value.get_long();
+                format!("    mov rax, {}    ; \"{}\"\n    push rax", long, long)
+            },
+            "double" => {
+                let double = self.value.get_double();
+                format!("    mov rax, {:#x}    ; \"{}\"\n    push rax", double.to_bits(), double)
             }
             _ => panic!("ldc instruction conversion to assembly does not support {} type", self.instance_type)
         }
@@ -285,6 +437,7 @@ impl ByteCodeInstruction for InstrILoadN {
 
 pub struct InstrLLoadN { variable: u8 }
 impl ByteCodeInstruction for InstrLLoadN {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         sf.variable_to_stack(self.variable as usize);
         return InstrNextAction::NEXT;
@@ -305,6 +458,7 @@ impl ByteCodeInstruction for InstrFLoadN {
 
 pub struct InstrDLoadN { variable: u8 }
 impl ByteCodeInstruction for InstrDLoadN {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         sf.variable_to_stack(self.variable as usize);
         return InstrNextAction::NEXT;
@@ -321,36 +475,55 @@ impl ByteCodeInstruction for InstrALoadN {
     fn print(&self) { println!("      aload{}", self.variable); }
 }
 
+// Pops the index and the array of an xaload/xastore instruction, or returns the exception to throw
+fn pop_array_index(sf: &mut StackFrame) -> Result<(Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>>, usize), InstrNextAction> {
+    let idx = sf.pop_int();
+    let arg = sf.pop();
+    if arg.lock().unwrap().is_null() {
+        return Err(exception!("java/lang/NullPointerException", "Cannot access an element of a null array"));
+    }
+
+    let array = arg.lock().unwrap().get_array();
+    let length = array.lock().unwrap().len();
+    if idx < 0 || idx as usize >= length {
+        return Err(exception!("java/lang/ArrayIndexOutOfBoundsException", format!("Index {} out of bounds for length {}", idx, length)));
+    }
+    Ok((array, idx as usize))
+}
+
 pub struct InstrIALoad {}
 impl ByteCodeInstruction for InstrIALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      iaload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrLALoad {}
 impl ByteCodeInstruction for InstrLALoad {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      laload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 ///////////// 0x3
@@ -358,150 +531,148 @@ impl ByteCodeInstruction for InstrLALoad {
 pub struct InstrFALoad {}
 impl ByteCodeInstruction for InstrFALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      faload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrDALoad {}
 impl ByteCodeInstruction for InstrDALoad {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      daload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrAALoad {}
 impl ByteCodeInstruction for InstrAALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push(object);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      aaload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrBALoad {}
 impl ByteCodeInstruction for InstrBALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      baload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrCALoad {}
 impl ByteCodeInstruction for InstrCALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      caload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrSALoad {}
 impl ByteCodeInstruction for InstrSALoad {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let idx = sf.pop_int();
-        let arg = sf.pop_array();
-        let array = arg.lock().unwrap();
-        let object: &Arc<Mutex<dyn JavaInstance>> = match array.get(idx as usize) {
-            Some(obj) => obj,
-            _ => panic!("No object in the array at index {}", idx)
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
         };
-        sf.push(object.clone());
+        let object = array.lock().unwrap()[idx].clone();
+        sf.push_value(Value::from_object(object));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      saload"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("{}\n    push qword [rax+16]", assembly.array_element())
+    }
 }
 
 pub struct InstrIStore { variable: u8 }
 impl ByteCodeInstruction for InstrIStore {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.stack_to_variable(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Store(self.variable as usize) }
     fn print(&self) { println!("      istore {}", self.variable); }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        format!("    pop r{}", assembly.var_to_reg(self.variable))
+        format!("    pop {}", assembly.variable(self.variable))
     }
 }
 
 pub struct InstrLStore { variable: u8 }
 impl ByteCodeInstruction for InstrLStore {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.stack_to_variable(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Store(self.variable as usize) }
     fn print(&self) { println!("      lstore {}", self.variable); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop {}", assembly.variable(self.variable))
+    }
 }
 
 pub struct InstrFStore { variable: u8 }
 impl ByteCodeInstruction for InstrFStore {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.stack_to_variable(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Store(self.variable as usize) }
     fn print(&self) { println!("      fstore {}", self.variable); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    pop rax\n    movq xmm{}, rax", self.variable)
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop {}", assembly.variable(self.variable))
     }
 }
 
 pub struct InstrDStore { variable: u8 }
 impl ByteCodeInstruction for InstrDStore {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.stack_to_variable(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Store(self.variable as usize) }
     fn print(&self) { println!("      dstore {}", self.variable); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop {}", assembly.variable(self.variable))
+    }
 }
 
 pub struct InstrAStore { variable: u8 }
 impl ByteCodeInstruction for InstrAStore {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.stack_to_variable(self.variable as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Store(self.variable as usize) }
     fn print(&self) { println!("      astore {}", self.variable); }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        format!("    pop r{}", assembly.var_to_reg(self.variable))
+        format!("    pop {}", assembly.variable(self.variable))
     }
 }
 
@@ -521,8 +692,8 @@ impl ByteCodeInstruction for InstrIStore1 {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      istore_1"); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        "    pop r8".to_string()
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop {}", assembly.variable(1))
     }
 }
 
@@ -622,12 +793,17 @@ pub struct InstrIAStore {}
 impl ByteCodeInstruction for InstrIAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      iastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 ///////////// 0x5
@@ -636,246 +812,346 @@ pub struct InstrLAStore {}
 impl ByteCodeInstruction for InstrLAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      lastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrFAStore {}
 impl ByteCodeInstruction for InstrFAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      fastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrDAStore {}
 impl ByteCodeInstruction for InstrDAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrAAStore {}
 impl ByteCodeInstruction for InstrAAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      aastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrBAStore {}
 impl ByteCodeInstruction for InstrBAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let object: Arc<Mutex<dyn JavaInstance>> = Arc::new(Mutex::new(NativeByteInstance::new(sf.pop_int() as u8)));
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      bastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    shl rbx, 56\n    sar rbx, 56\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrCAStore {}
 impl ByteCodeInstruction for InstrCAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let object: Arc<Mutex<dyn JavaInstance>> = Arc::new(Mutex::new(NativeCharInstance::new(int_to_char(sf.pop_int()))));
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      castore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    shl rbx, 48\n    shr rbx, 48\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrSAStore {}
 impl ByteCodeInstruction for InstrSAStore {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let object = sf.pop();
-        let idx = sf.pop_int();
-        let array = sf.pop_array();
-        array.lock().unwrap()[idx as usize] = object;
+        let object: Arc<Mutex<dyn JavaInstance>> = Arc::new(Mutex::new(NativeShortInstance::new(sf.pop_int() as i16)));
+        let (array, idx) = match pop_array_index(sf) {
+            Ok(array_index) => array_index,
+            Err(e) => return e
+        };
+        array.lock().unwrap()[idx] = object;
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      sastore"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    shl rbx, 48\n    sar rbx, 48\n{}\n    mov [rax+16], rbx", assembly.array_element())
+    }
 }
 
 pub struct InstrPop { }
 impl ByteCodeInstruction for InstrPop {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.pop();
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Pop }
     fn print(&self) { println!("      pop"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax")
+    }
 }
 
 pub struct InstrPop2 { }
 impl ByteCodeInstruction for InstrPop2 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.pop();
-        sf.pop();
+        let value1 = sf.pop_value();
+        if !value1.is_category2() {
+            sf.pop_value();
+        }
         return InstrNextAction::NEXT;
     }
-    fn print(&self) { println!("      pop"); }
+    fn print(&self) { println!("      pop2"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        // A long or double, or two other values, which the intermediate representation tells apart
+        format!("    pop2")
+    }
 }
 
 pub struct InstrDup { }
 impl ByteCodeInstruction for InstrDup {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop();
-        sf.push(arg.clone());
-        sf.push(arg.clone());
-
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::Dup }
     fn print(&self) { println!("      dup"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    push rax\n    push rax")
+    }
 }
 
 pub struct InstrDupX1 { }
 impl ByteCodeInstruction for InstrDupX1 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        sf.push(value1.clone());
-        sf.push(value2.clone());
-        sf.push(value1.clone());
+        let value1 = sf.pop_value();
+        let value2 = sf.pop_value();
+        sf.push_value(value1.clone());
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dup_x1"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    pop rcx\n    push rax\n    push rcx\n    push rax")
+    }
 }
 
 pub struct InstrDupX2 { }
 impl ByteCodeInstruction for InstrDupX2 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        let value3 = sf.pop();
-        sf.push(value1.clone());
-        sf.push(value3.clone());
-        sf.push(value2.clone());
-        sf.push(value1.clone());
+        let value1 = sf.pop_value();
+        let value2 = sf.pop_value();
+        if value2.is_category2() {
+            // Form 2: value2 is a long or double
+            sf.push_value(value1.clone());
+            sf.push_value(value2.clone());
+            sf.push_value(value1.clone());
+            return InstrNextAction::NEXT;
+        }
+
+        let value3 = sf.pop_value();
+        sf.push_value(value1.clone());
+        sf.push_value(value3.clone());
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dup_x2"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        // value2 may be a long or double, which the intermediate representation tells apart
+        format!("    dup_x2")
+    }
 }
 
 pub struct InstrDup2 { }
 impl ByteCodeInstruction for InstrDup2 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        sf.push(value2.clone());
-        sf.push(value1.clone());
-        sf.push(value2.clone());
-        sf.push(value1.clone());
+        let value1 = sf.pop_value();
+        if value1.is_category2() {
+            // Form 2: duplicates a single long or double
+            sf.push_value(value1.clone());
+            sf.push_value(value1.clone());
+            return InstrNextAction::NEXT;
+        }
+
+        let value2 = sf.pop_value();
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dup2"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        // A long or double, or two other values such as an array and an index to read and then write the element
+        format!("    dup2")
+    }
 }
 
 pub struct InstrDup2X1 { }
 impl ByteCodeInstruction for InstrDup2X1 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        let value3 = sf.pop();
-        sf.push(value2.clone());
-        sf.push(value1.clone());
-        sf.push(value3.clone());
-        sf.push(value2.clone());
-        sf.push(value1.clone());
+        let value1 = sf.pop_value();
+        let value2 = sf.pop_value();
+        if value1.is_category2() {
+            // Form 2: value1 is a long or double
+            sf.push_value(value1.clone());
+            sf.push_value(value2.clone());
+            sf.push_value(value1.clone());
+            return InstrNextAction::NEXT;
+        }
+
+        let value3 = sf.pop_value();
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
+        sf.push_value(value3.clone());
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dup2_x1"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    dup2_x1")
+    }
 }
 
 pub struct InstrDup2X2 { }
 impl ByteCodeInstruction for InstrDup2X2 {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        let value3 = sf.pop();
-        let value4 = sf.pop();
-        sf.push(value2.clone());
-        sf.push(value1.clone());
-        sf.push(value4.clone());
-        sf.push(value3.clone());
-        sf.push(value2.clone());
-        sf.push(value1.clone());
+        let value1 = sf.pop_value();
+        let value2 = sf.pop_value();
+
+        if value1.is_category2() {
+            if value2.is_category2() {
+                // Form 4: two longs or doubles
+                sf.push_value(value1.clone());
+                sf.push_value(value2.clone());
+                sf.push_value(value1.clone());
+                return InstrNextAction::NEXT;
+            }
+
+            // Form 2: value1 is a long or double, value2 and value3 are not
+            let value3 = sf.pop_value();
+            sf.push_value(value1.clone());
+            sf.push_value(value3.clone());
+            sf.push_value(value2.clone());
+            sf.push_value(value1.clone());
+            return InstrNextAction::NEXT;
+        }
+
+        let value3 = sf.pop_value();
+        if value3.is_category2() {
+            // Form 3: value3 is a long or double, value1 and value2 are not
+            sf.push_value(value2.clone());
+            sf.push_value(value1.clone());
+            sf.push_value(value3.clone());
+            sf.push_value(value2.clone());
+            sf.push_value(value1.clone());
+            return InstrNextAction::NEXT;
+        }
+
+        let value4 = sf.pop_value();
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
+        sf.push_value(value4.clone());
+        sf.push_value(value3.clone());
+        sf.push_value(value2.clone());
+        sf.push_value(value1.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      dup2_x2"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    dup2_x2")
+    }
 }
 
 pub struct InstrSwap { }
 impl ByteCodeInstruction for InstrSwap {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value1 = sf.pop();
-        let value2 = sf.pop();
-        sf.push(value1.clone());
-        sf.push(value2.clone());
+        let value1 = sf.pop_value();
+        let value2 = sf.pop_value();
+        sf.push_value(value1.clone());
+        sf.push_value(value2.clone());
 
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      swap"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    pop rcx\n    push rax\n    push rcx")
+    }
 }
 
 ///////////// 0x6
 
 pub struct InstrIAdd {}
 impl ByteCodeInstruction for InstrIAdd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 + nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IAdd }
     fn print(&self) { println!("      iadd"); }
+    // Ints are sign extended in the 64-bit registers, and wrap around after the operations which can overflow
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    pop rbx\n    pop rax\n    add rax, rbx\n    push rax")
+        format!("    pop rbx\n    pop rax\n    add rax, rbx\n    movsxd rax, eax\n    push rax")
     }
 }
 
 pub struct InstrLAdd {}
 impl ByteCodeInstruction for InstrLAdd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 + nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LAdd }
     fn print(&self) { println!("      ladd"); }
-}
-
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    add rax, rbx\n    push rax")
+    }
+}
+
 pub struct InstrFAdd {}
 impl ByteCodeInstruction for InstrFAdd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_float();
-        let nb2 = sf.pop_float();
-        sf.push_float(nb2 + nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FAdd }
     fn print(&self) { println!("      fadd"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    addss xmm15, xmm14\n    movq rax, xmm15\n    push rax")
@@ -884,48 +1160,36 @@ impl ByteCodeInstruction for InstrFAdd {
 
 pub struct InstrDAdd {}
 impl ByteCodeInstruction for InstrDAdd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_double();
-        let nb2 = sf.pop_double();
-        sf.push_double(nb2 + nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DAdd }
     fn print(&self) { println!("      dadd"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    addsd xmm15, xmm14\n    movq rax, xmm15\n    push rax")
+    }
 }
 
 pub struct InstrISub {}
 impl ByteCodeInstruction for InstrISub {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 - nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::ISub }
     fn print(&self) { println!("      isub"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    pop rbx\n    pop rax\n    sub rax, rbx\n    push rax")
+        format!("    pop rbx\n    pop rax\n    sub rax, rbx\n    movsxd rax, eax\n    push rax")
     }
 }
 
 pub struct InstrLSub {}
 impl ByteCodeInstruction for InstrLSub {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 - nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LSub }
     fn print(&self) { println!("      lsub"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    sub rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrFSub {}
 impl ByteCodeInstruction for InstrFSub {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_float();
-        let nb2 = sf.pop_float();
-        sf.push_float(nb2 - nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FSub }
     fn print(&self) { println!("      fsub"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    subss xmm15, xmm14\n    movq rax, xmm15\n    push rax")
@@ -934,48 +1198,36 @@ impl ByteCodeInstruction for InstrFSub {
 
 pub struct InstrDSub {}
 impl ByteCodeInstruction for InstrDSub {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_double();
-        let nb2 = sf.pop_double();
-        sf.push_double(nb2 - nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DSub }
     fn print(&self) { println!("      dsub"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    subsd xmm15, xmm14\n    movq rax, xmm15\n    push rax")
+    }
 }
 
 pub struct InstrIMul {}
 impl ByteCodeInstruction for InstrIMul {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 * nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IMul }
     fn print(&self) { println!("      imul"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    pop rdx\n    pop rax\n    mul rax\n    push rax")
+        format!("    pop rbx\n    pop rax\n    imul rax, rbx\n    movsxd rax, eax\n    push rax")
     }
 }
 
 pub struct InstrLMul {}
 impl ByteCodeInstruction for InstrLMul {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 * nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LMul }
     fn print(&self) { println!("      lmul"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    imul rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrFMul {}
 impl ByteCodeInstruction for InstrFMul {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_float();
-        let nb2 = sf.pop_float();
-        sf.push_float(nb2 * nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FMul }
     fn print(&self) { println!("      fmul"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    mulss xmm15, xmm14\n    movq rax, xmm15\n    push rax")
@@ -984,13 +1236,12 @@ impl ByteCodeInstruction for InstrFMul {
 
 pub struct InstrDMul {}
 impl ByteCodeInstruction for InstrDMul {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_double();
-        let nb2 = sf.pop_double();
-        sf.push_double(nb2 * nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DMul }
     fn print(&self) { println!("      dmul"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    mulsd xmm15, xmm14\n    movq rax, xmm15\n    push rax")
+    }
 }
 
 pub struct InstrIDiv {}
@@ -1000,26 +1251,32 @@ impl ByteCodeInstruction for InstrIDiv {
         let nb2 = sf.pop_int();
         if nb1 == 0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
-        sf.push_int(nb2 / nb1);
+        sf.push_int(nb2.wrapping_div(nb1));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      idiv"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        format!("    pop r15\n    xor rdx, rdx\n    pop rax\n    idiv r15\n    push rax")
+        format!("    pop rcx\n    pop rax\n    cqo\n    idiv rcx\n    movsxd rax, eax\n    push rax")
     }
 }
 
 pub struct InstrLDiv {}
 impl ByteCodeInstruction for InstrLDiv {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_long();
         let nb2 = sf.pop_long();
         if nb1 == 0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
-        sf.push_long(nb2 / nb1);
+        sf.push_long(nb2.wrapping_div(nb1));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      ldiv"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        // idiv faults on the overflow of the minimum divided by -1, where Java wraps around
+        let (negate, end) = (assembly.new_label("negate"), assembly.new_label("end"));
+        format!("    pop rcx\n    pop rax\n    cmp rcx, -1\n    je {}\n    cqo\n    idiv rcx\n    push rax\n    jmp {}\n{}:\n    neg rax\n    push rax\n{}:", negate, end, negate, end)
+    }
 }
 
 pub struct InstrFDiv {}
@@ -1027,7 +1284,6 @@ impl ByteCodeInstruction for InstrFDiv {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_float();
         let nb2 = sf.pop_float();
-        if nb1 == 0.0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
         sf.push_float(nb2 / nb1);
         return InstrNextAction::NEXT;
@@ -1040,15 +1296,18 @@ impl ByteCodeInstruction for InstrFDiv {
 
 pub struct InstrDDiv {}
 impl ByteCodeInstruction for InstrDDiv {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_double();
         let nb2 = sf.pop_double();
-        if nb1 == 0.0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
         sf.push_double(nb2 / nb1);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      ddiv"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm14, rax\n    pop rax\n    movq xmm15, rax\n    divsd xmm15, xmm14\n    movq rax, xmm15\n    push rax")
+    }
 }
 
 ///////////// 0x7
@@ -1060,23 +1319,31 @@ impl ByteCodeInstruction for InstrIRem {
         let nb2 = sf.pop_int();
         if nb1 == 0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
-        sf.push_int(nb2 % nb1);
+        sf.push_int(nb2.wrapping_rem(nb1));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      irem"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    pop rax\n    cqo\n    idiv rcx\n    push rdx")
+    }
 }
 
 pub struct InstrLRem {}
 impl ByteCodeInstruction for InstrLRem {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_long();
         let nb2 = sf.pop_long();
         if nb1 == 0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
-        sf.push_long(nb2 % nb1);
+        sf.push_long(nb2.wrapping_rem(nb1));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      lrem"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        let (zero, end) = (assembly.new_label("zero"), assembly.new_label("end"));
+        format!("    pop rcx\n    pop rax\n    cmp rcx, -1\n    je {}\n    cqo\n    idiv rcx\n    push rdx\n    jmp {}\n{}:\n    push 0\n{}:", zero, end, zero, end)
+    }
 }
 
 pub struct InstrFRem {}
@@ -1084,81 +1351,83 @@ impl ByteCodeInstruction for InstrFRem {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_float();
         let nb2 = sf.pop_float();
-        if nb1 == 0.0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
         sf.push_float(nb2 % nb1);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      frem"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm15, rax\n    cvtss2sd xmm1, xmm15\n    pop rax\n    movq xmm15, rax\n    cvtss2sd xmm0, xmm15\n{}\n    cvtsd2ss xmm15, xmm0\n    movq rax, xmm15\n    push rax",
+            assembly.call_routine("__math_fmod"))
+    }
 }
 
 pub struct InstrDRem {}
 impl ByteCodeInstruction for InstrDRem {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb1 = sf.pop_double();
         let nb2 = sf.pop_double();
-        if nb1 == 0.0 { return exception!("java/lang/ArithmeticException", "/ by zero"); }
 
         sf.push_double(nb2 % nb1);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      drem"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm1, rax\n    pop rax\n    movq xmm0, rax\n{}\n    movq rax, xmm0\n    push rax", assembly.call_routine("__math_fmod"))
+    }
 }
 
 pub struct InstrINeg {}
 impl ByteCodeInstruction for InstrINeg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_int();
-        sf.push_int(-nb);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::INeg }
     fn print(&self) { println!("      ineg"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    neg rax\n    movsxd rax, eax\n    push rax")
+    }
 }
 
 pub struct InstrLNeg {}
 impl ByteCodeInstruction for InstrLNeg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_long();
-        sf.push_long(-nb);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LNeg }
     fn print(&self) { println!("      lneg"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    neg rax\n    push rax")
+    }
 }
 
 pub struct InstrFNeg {}
 impl ByteCodeInstruction for InstrFNeg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_float();
-        sf.push_float(-nb);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FNeg }
     fn print(&self) { println!("      fneg"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    mov rbx, 0x80000000\n    xor rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrDNeg {}
 impl ByteCodeInstruction for InstrDNeg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_double();
-        sf.push_double(-nb);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::DNeg }
     fn print(&self) { println!("      dneg"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    mov rbx, 0x8000000000000000\n    xor rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrIShl {}
 impl ByteCodeInstruction for InstrIShl {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int() & 31;
-        let value1 = sf.pop_int();
-
-        sf.push_int(value1 << value2);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IShl }
     fn print(&self) { println!("      ishl"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    and rcx, 31\n    pop rax\n    shl rax, cl\n    movsxd rax, eax\n    push rax")
+    }
 }
 
 pub struct InstrLShl {}
 impl ByteCodeInstruction for InstrLShl {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let value2 = sf.pop_long() & 63;
         let value1 = sf.pop_long();
@@ -1167,22 +1436,23 @@ impl ByteCodeInstruction for InstrLShl {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      lshl"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    pop rax\n    shl rax, cl\n    push rax")
+    }
 }
 
 pub struct InstrIShr {}
 impl ByteCodeInstruction for InstrIShr {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int() & 31;
-        let value1 = sf.pop_int();
-
-        sf.push_int(value1 >> value2);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IShr }
     fn print(&self) { println!("      ishr"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    and rcx, 31\n    pop rax\n    sar rax, cl\n    push rax")
+    }
 }
 
 pub struct InstrLShr {}
 impl ByteCodeInstruction for InstrLShr {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let value2 = sf.pop_long() & 63;
         let value1 = sf.pop_long();
@@ -1191,6 +1461,9 @@ impl ByteCodeInstruction for InstrLShr {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      lshr"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    pop rax\n    sar rax, cl\n    push rax")
+    }
 }
 
 pub struct InstrIUShr {}
@@ -1207,10 +1480,14 @@ impl ByteCodeInstruction for InstrIUShr {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      iushr"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    and rcx, 31\n    pop rax\n    shl rax, 32\n    shr rax, 32\n    shr rax, cl\n    movsxd rax, eax\n    push rax")
+    }
 }
 
 pub struct InstrLUShr {}
 impl ByteCodeInstruction for InstrLUShr {
+    fn pushes_category2(&self) -> bool { true }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let value2 = sf.pop_long() & 63;
         let value1 = sf.pop_long();
@@ -1223,108 +1500,93 @@ impl ByteCodeInstruction for InstrLUShr {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      lushr"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    pop rax\n    shr rax, cl\n    push rax")
+    }
 }
 
 pub struct InstrIAnd {}
 impl ByteCodeInstruction for InstrIAnd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 & nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IAnd }
     fn print(&self) { println!("      iand"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    and rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrLAnd {}
 impl ByteCodeInstruction for InstrLAnd {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 & nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LAnd }
     fn print(&self) { println!("      land"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    and rax, rbx\n    push rax")
+    }
 }
 
 ///////////// 0x8
 
 pub struct InstrIOr {}
 impl ByteCodeInstruction for InstrIOr {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 | nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IOr }
     fn print(&self) { println!("      ior"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    or rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrLOr {}
 impl ByteCodeInstruction for InstrLOr {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 | nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LOr }
     fn print(&self) { println!("      lor"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    or rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrIXor {}
 impl ByteCodeInstruction for InstrIXor {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_int();
-        let nb2 = sf.pop_int();
-        sf.push_int(nb2 ^ nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IXor }
     fn print(&self) { println!("      ixor"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    xor rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrLXor {}
 impl ByteCodeInstruction for InstrLXor {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb1 = sf.pop_long();
-        let nb2 = sf.pop_long();
-        sf.push_long(nb2 ^ nb1);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::LXor }
     fn print(&self) { println!("      lxor"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    xor rax, rbx\n    push rax")
+    }
 }
 
 pub struct InstrIInc { idx: u8, count: i8 }
 impl ByteCodeInstruction for InstrIInc {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.variable_to_stack(self.idx as usize);
-        let nb = sf.pop_int();
-        sf.push_int(nb + self.count as i32);
-        sf.stack_to_variable(self.idx as usize);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IInc(self.idx as usize, self.count as i32) }
     fn print(&self) { println!("      iinc {} {}", self.idx, self.count); }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        format!("    add r{}, {:#x}", assembly.var_to_reg(self.idx), self.count)
+        let variable = assembly.variable(self.idx);
+        format!("    mov rax, {}\n    add rax, {}\n    movsxd rax, eax\n    mov {}, rax", variable, self.count, variable)
     }
 }
 
 pub struct InstrI2L {}
 impl ByteCodeInstruction for InstrI2L {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_int();
-        sf.push_long(nb as i64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::I2L }
     fn print(&self) { println!("      i2l"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        String::new()
+    }
 }
 
 pub struct InstrI2F {}
 impl ByteCodeInstruction for InstrI2F {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_int();
-        sf.push_float(nb as f32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::I2F }
     fn print(&self) { println!("      i2f"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         "    pop rax\n    cvtsi2ss xmm15, rax\n    movq rax, xmm15\n    push rax".to_string()
@@ -1333,71 +1595,65 @@ impl ByteCodeInstruction for InstrI2F {
 
 pub struct InstrI2D {}
 impl ByteCodeInstruction for InstrI2D {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_int();
-        sf.push_double(nb as f64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::I2D }
     fn print(&self) { println!("      i2d"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    pop rax\n    cvtsi2sd xmm15, rax\n    movq rax, xmm15\n    push rax".to_string()
+    }
 }
 
 pub struct InstrL2I {}
 impl ByteCodeInstruction for InstrL2I {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_long();
-        sf.push_int(nb as i32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::L2I }
     fn print(&self) { println!("      l2i"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    pop rax\n    movsxd rax, eax\n    push rax".to_string()
+    }
 }
 
 pub struct InstrL2F {}
 impl ByteCodeInstruction for InstrL2F {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_long();
-        sf.push_float(nb as f32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::L2F }
     fn print(&self) { println!("      l2f"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    pop rax\n    cvtsi2ss xmm15, rax\n    movq rax, xmm15\n    push rax".to_string()
+    }
 }
 
 pub struct InstrL2D {}
 impl ByteCodeInstruction for InstrL2D {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_long();
-        sf.push_double(nb as f64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::L2D }
     fn print(&self) { println!("      l2d"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        "    pop rax\n    cvtsi2sd xmm15, rax\n    movq rax, xmm15\n    push rax".to_string()
+    }
 }
 
 pub struct InstrF2I {}
 impl ByteCodeInstruction for InstrF2I {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_float();
-        sf.push_int(nb as i32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::F2I }
     fn print(&self) { println!("      f2i"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm15, rax\n    cvtss2sd xmm0, xmm15\n{}\n    push rax", assembly.call_routine("__math_d2i"))
+    }
 }
 
 pub struct InstrF2L {}
 impl ByteCodeInstruction for InstrF2L {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_float();
-        sf.push_long(nb as i64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::F2L }
     fn print(&self) { println!("      f2l"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm15, rax\n    cvtss2sd xmm0, xmm15\n{}\n    push rax", assembly.call_routine("__math_d2l"))
+    }
 }
 
 pub struct InstrF2D {}
 impl ByteCodeInstruction for InstrF2D {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_float();
-        sf.push_double(nb as f64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::F2D }
     fn print(&self) { println!("      f2d"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    pop rax\n    movq xmm15, rax\n    cvtss2sd xmm14, xmm15\n    movq rax, xmm14\n    push rax")
@@ -1406,33 +1662,28 @@ impl ByteCodeInstruction for InstrF2D {
 
 pub struct InstrD2I {}
 impl ByteCodeInstruction for InstrD2I {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_double();
-        sf.push_int(nb as i32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::D2I }
     fn print(&self) { println!("      d2i"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm0, rax\n{}\n    push rax", assembly.call_routine("__math_d2i"))
+    }
 }
 
 pub struct InstrD2L {}
 impl ByteCodeInstruction for InstrD2L {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_double();
-        sf.push_long(nb as i64);
-        return InstrNextAction::NEXT;
-    }
+    fn pushes_category2(&self) -> bool { true }
+    fn get_op(&self) -> Op { Op::D2L }
     fn print(&self) { println!("      d2l"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    movq xmm0, rax\n{}\n    push rax", assembly.call_routine("__math_d2l"))
+    }
 }
 
 ///////////// 0x9
 
 pub struct InstrD2F {}
 impl ByteCodeInstruction for InstrD2F {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb = sf.pop_double();
-        sf.push_float(nb as f32);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::D2F }
     fn print(&self) { println!("      d2f"); }
     fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
         format!("    pop rax\n    movq xmm15, rax\n    cvtsd2ss xmm14, xmm15\n    movq rax, xmm14\n    push rax")
@@ -1447,16 +1698,22 @@ impl ByteCodeInstruction for InstrI2B {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      i2b"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    shl rax, 56\n    sar rax, 56\n    push rax")
+    }
 }
 
 pub struct InstrI2C {}
 impl ByteCodeInstruction for InstrI2C {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let nb = sf.pop_int();
-        sf.push_char(nb as u8 as char);
+        sf.push_int(nb as u16 as i32);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      i2c"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    shl rax, 48\n    shr rax, 48\n    push rax")
+    }
 }
 
 pub struct InstrI2S {}
@@ -1467,124 +1724,59 @@ impl ByteCodeInstruction for InstrI2S {
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      i2s"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    shl rax, 48\n    sar rax, 48\n    push rax")
+    }
 }
 
 pub struct InstrLCmp {}
 impl ByteCodeInstruction for InstrLCmp {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb2 = sf.pop_long();
-        let nb1 = sf.pop_long();
-        let result: i32;
-        if nb1 == nb2 {
-            result = 0;
-        } else if nb1 > nb2 {
-            result = 1;
-        } else  {
-            result = -1;
-        }
-
-        sf.push_int(result);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::LCmp }
     fn print(&self) { println!("      lcmp"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        comparison_to_asm(assembly, "    pop rbx\n    pop rax\n    cmp rax, rbx", "jg", "jl", None)
+    }
 }
 
 pub struct InstrFCmpl {}
 impl ByteCodeInstruction for InstrFCmpl {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb2 = sf.pop_float();
-        let nb1 = sf.pop_float();
-        let mut result: i32 = 0;
-        if nb1 == f32::NAN || nb2 == f32::NAN {
-            result = -1;
-        } else if nb1 > nb2 {
-            result = 1;
-        } else if nb1 < nb2 {
-            result = -1;
-        }
-
-        sf.push_int(result);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FCmpl }
     fn print(&self) { println!("      fcmpl"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        float_comparison_to_asm(assembly, false, -1)
+    }
 }
 
 pub struct InstrFCmpg {}
 impl ByteCodeInstruction for InstrFCmpg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb2 = sf.pop_float();
-        let nb1 = sf.pop_float();
-        let mut result: i32 = 0;
-        if nb1 == f32::NAN || nb2 == f32::NAN {
-            result = 1;
-        } else if nb1 > nb2 {
-            result = 1;
-        } else if nb1 < nb2 {
-            result = -1;
-        }
-
-        sf.push_int(result);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::FCmpg }
     fn print(&self) { println!("      fcmpg"); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        let mut rng = rand::thread_rng();
-        let n2: u16 = rng.gen();
-        format!("    pop rax\n    movq xmm15, rax\n    pop rax\n    movq xmm14, rax\n    ucomiss xmm14, xmm15\n    jae __branchfcmp{}\n    push -1\n    jmp __branchfcmpend{}\n__branchfcmp{}:\n    push 1\n__branchfcmpend{}:", n2, n2, n2, n2)
-//        format!("    pop rax\n    movq xmm15, rax\n    pop rax\n    movq xmm14, rax\n    ucomiss xmm14, xmm15")
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        float_comparison_to_asm(assembly, false, 1)
     }
-
 }
 
 pub struct InstrDCmpl {}
 impl ByteCodeInstruction for InstrDCmpl {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb2 = sf.pop_double();
-        let nb1 = sf.pop_double();
-        let mut result: i32 = 0;
-        if nb1 == f64::NAN || nb2 == f64::NAN {
-            result = -1;
-        } else if nb1 > nb2 {
-            result = 1;
-        } else if nb1 < nb2 {
-            result = -1;
-        }
-
-        sf.push_int(result);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::DCmpl }
     fn print(&self) { println!("      dcmpl"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        float_comparison_to_asm(assembly, true, -1)
+    }
 }
 
 pub struct InstrDCmpg {}
 impl ByteCodeInstruction for InstrDCmpg {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let nb2 = sf.pop_double();
-        let nb1 = sf.pop_double();
-        let mut result: i32 = 0;
-        if nb1 == f64::NAN || nb2 == f64::NAN {
-            result = 1;
-        } else if nb1 > nb2 {
-            result = 1;
-        } else if nb1 < nb2 {
-            result = -1;
-        }
-
-        sf.push_int(result);
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::DCmpg }
     fn print(&self) { println!("      dcmpg"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        float_comparison_to_asm(assembly, true, 1)
+    }
 }
 
 pub struct InstrIfeq { branch: usize }
 impl ByteCodeInstruction for InstrIfeq {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg == 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfEq(self.branch) }
     fn print(&self) { println!("      ifeq {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1592,17 +1784,14 @@ impl ByteCodeInstruction for InstrIfeq {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    je {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfne { branch: usize }
 impl ByteCodeInstruction for InstrIfne {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg != 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfNe(self.branch) }
     fn print(&self) { println!("      ifne {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1610,17 +1799,14 @@ impl ByteCodeInstruction for InstrIfne {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    jne {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIflt { branch: usize }
 impl ByteCodeInstruction for InstrIflt {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg < 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfLt(self.branch) }
     fn print(&self) { println!("      iflt {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1628,17 +1814,14 @@ impl ByteCodeInstruction for InstrIflt {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    jl {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfge { branch: usize }
 impl ByteCodeInstruction for InstrIfge {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg >= 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfGe(self.branch) }
     fn print(&self) { println!("      ifge {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1647,21 +1830,13 @@ impl ByteCodeInstruction for InstrIfge {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    pop rax\n    cmp rax, 0\n    jge __branch{}", self.branch)
-//        format!("    jae __branch{}", self.branch)
+        format!("    pop rax\n    cmp rax, 0\n    jge {}", assembly.add_jump(self.branch))
     }
 }
 
 pub struct InstrIfgt { branch: usize }
 impl ByteCodeInstruction for InstrIfgt {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg > 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfGt(self.branch) }
     fn print(&self) { println!("      ifgt {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1669,17 +1844,14 @@ impl ByteCodeInstruction for InstrIfgt {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    jg {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfle { branch: usize }
 impl ByteCodeInstruction for InstrIfle {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let arg = sf.pop_int();
-        if arg <= 0 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfLe(self.branch) }
     fn print(&self) { println!("      ifle {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1688,8 +1860,7 @@ impl ByteCodeInstruction for InstrIfle {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    pop rax\n    cmp rax, 0\n    jle __branch{}", self.branch)
+        format!("    pop rax\n    cmp rax, 0\n    jle {}", assembly.add_jump(self.branch))
     }
 }
 
@@ -1697,14 +1868,7 @@ impl ByteCodeInstruction for InstrIfle {
 
 pub struct InstrIfICmpEq { branch: usize }
 impl ByteCodeInstruction for InstrIfICmpEq {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 == value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfICmpEq(self.branch) }
     fn print(&self) { println!("      if_icmpeq {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1712,18 +1876,14 @@ impl ByteCodeInstruction for InstrIfICmpEq {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
-}
-
-pub struct InstrIfICmpNe { branch: usize }
-impl ByteCodeInstruction for InstrIfICmpNe {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 != value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    je {}", assembly.add_jump(self.branch))
     }
+}
+
+pub struct InstrIfICmpNe { branch: usize }
+impl ByteCodeInstruction for InstrIfICmpNe {
+    fn get_op(&self) -> Op { Op::IfICmpNe(self.branch) }
     fn print(&self) { println!("      if_icmpne {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1732,21 +1892,13 @@ impl ByteCodeInstruction for InstrIfICmpNe {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jne __branch{}", self.branch)
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jne {}", assembly.add_jump(self.branch))
     }
 }
 
 pub struct InstrIfICmpLt { branch: usize }
 impl ByteCodeInstruction for InstrIfICmpLt {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 < value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfICmpLt(self.branch) }
     fn print(&self) { println!("      if_icmplt {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1755,21 +1907,13 @@ impl ByteCodeInstruction for InstrIfICmpLt {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jl __branch{}", self.branch)
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jl {}", assembly.add_jump(self.branch))
     }    
 }
 
 pub struct InstrIfICmpGe { branch: usize }
 impl ByteCodeInstruction for InstrIfICmpGe {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 >= value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfICmpGe(self.branch) }
     fn print(&self) { println!("      if_icmpge {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1778,21 +1922,13 @@ impl ByteCodeInstruction for InstrIfICmpGe {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jge __branch{}", self.branch)
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jge {}", assembly.add_jump(self.branch))
     }
 }
 
 pub struct InstrIfICmpGt { branch: usize }
 impl ByteCodeInstruction for InstrIfICmpGt {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 > value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfICmpGt(self.branch) }
     fn print(&self) { println!("      if_icmpgt {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1800,18 +1936,14 @@ impl ByteCodeInstruction for InstrIfICmpGt {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jg {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfICmpLe { branch: usize }
 impl ByteCodeInstruction for InstrIfICmpLe {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let value2 = sf.pop_int();
-        let value1 = sf.pop_int();
-        if value1 <= value2 {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfICmpLe(self.branch) }
     fn print(&self) { println!("      if_icmple {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1819,6 +1951,9 @@ impl ByteCodeInstruction for InstrIfICmpLe {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jle {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfACmpEq { branch: usize }
@@ -1838,6 +1973,9 @@ impl ByteCodeInstruction for InstrIfACmpEq {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    je {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfACmpNe { branch: usize }
@@ -1857,13 +1995,14 @@ impl ByteCodeInstruction for InstrIfACmpNe {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rbx\n    pop rax\n    cmp rax, rbx\n    jne {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrGoto { branch: usize }
 impl ByteCodeInstruction for InstrGoto {
-    fn execute(&self, _sf: &mut StackFrame) -> InstrNextAction {
-        return InstrNextAction::GOTO(self.branch);
-    }
+    fn get_op(&self) -> Op { Op::Goto(self.branch) }
     fn print(&self) { println!("      goto {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -1872,9 +2011,35 @@ impl ByteCodeInstruction for InstrGoto {
         }
     }
     fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
-        assembly.add_jump(self.branch);
-        format!("    jmp __branch{}", self.branch)
+        format!("    jmp {}", assembly.add_jump(self.branch))
+    }
+}
+
+// jsr and ret implement finally blocks in class files older than Java 6
+pub struct InstrJsr { branch: usize, return_address: usize }
+impl ByteCodeInstruction for InstrJsr {
+    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
+        sf.push_value(Value::ReturnAddress(self.return_address));
+        return InstrNextAction::GOTO(self.branch);
+    }
+    fn print(&self) { println!("      jsr {}", self.branch); }
+    fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
+        match (address_map.get(&self.branch), address_map.get(&self.return_address)) {
+            (Some(branch_idx), Some(return_idx)) => {
+                self.branch = *branch_idx;
+                self.return_address = *return_idx;
+            },
+            _ => panic!("Unknown branch position {}", self.branch)
+        }
+    }
+}
+
+pub struct InstrRet { index: usize }
+impl ByteCodeInstruction for InstrRet {
+    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
+        return InstrNextAction::GOTO(sf.get_return_address(self.index));
     }
+    fn print(&self) { println!("      ret {}", self.index); }
 }
 
 pub struct InstrTableSwitch { default: usize, low: usize, table: Vec<usize> }
@@ -1914,6 +2079,15 @@ impl ByteCodeInstruction for InstrTableSwitch {
             _ => panic!("Unknown branch position {}", self.default)
         };
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        // A comparison for each value of the table
+        let mut content = "    pop rax".to_string();
+        for (idx, branch) in self.table.iter().enumerate() {
+            content.push_str(&format!("\n    cmp rax, {}\n    je {}", self.low as i32 + idx as i32, assembly.add_jump(*branch)));
+        }
+        content.push_str(&format!("\n    jmp {}", assembly.add_jump(self.default)));
+        content
+    }
 }
 
 pub struct InstrLookupSwitch { default: usize, lookup: HashMap<i32, usize> }
@@ -1950,94 +2124,117 @@ impl ByteCodeInstruction for InstrLookupSwitch {
             _ => panic!("Unknown branch position {}", self.default)
         };
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        let mut values: Vec<&i32> = self.lookup.keys().collect();
+        values.sort();
+        let mut content = "    pop rax".to_string();
+        for value in values {
+            content.push_str(&format!("\n    cmp rax, {}\n    je {}", value, assembly.add_jump(self.lookup[value])));
+        }
+        content.push_str(&format!("\n    jmp {}", assembly.add_jump(self.default)));
+        content
+    }
 }
 
 pub struct InstrIReturn {}
 impl ByteCodeInstruction for InstrIReturn {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.set_return_arg_flag();
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::ValueReturn }
     fn print(&self) { println!("      ireturn"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { format!("    pop rax\n{}", METHOD_EPILOGUE) }
 }
 
 pub struct InstrLReturn {}
 impl ByteCodeInstruction for InstrLReturn {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.set_return_arg_flag();
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::ValueReturn }
     fn print(&self) { println!("      lreturn"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { format!("    pop rax\n{}", METHOD_EPILOGUE) }
 }
 
 pub struct InstrFReturn {}
 impl ByteCodeInstruction for InstrFReturn {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.set_return_arg_flag();
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::ValueReturn }
     fn print(&self) { println!("      freturn"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { format!("    pop rax\n    movq xmm0, rax\n{}", METHOD_EPILOGUE) }
 }
 
 pub struct InstrDReturn {}
 impl ByteCodeInstruction for InstrDReturn {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.set_return_arg_flag();
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::ValueReturn }
     fn print(&self) { println!("      dreturn"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { format!("    pop rax\n    movq xmm0, rax\n{}", METHOD_EPILOGUE) }
 }
 
 ///////////// 0xb
 
 pub struct InstrAReturn {}
 impl ByteCodeInstruction for InstrAReturn {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        sf.set_return_arg_flag();
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::ValueReturn }
     fn print(&self) { println!("      areturn"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { format!("    pop rax\n{}", METHOD_EPILOGUE) }
 }
 
 pub struct InstrReturn {}
 impl ByteCodeInstruction for InstrReturn {
-    fn execute(&self, _sf: &mut StackFrame) -> InstrNextAction {
-        return InstrNextAction::RETURN;
-    }
+    fn get_op(&self) -> Op { Op::Return }
     fn print(&self) { println!("      return"); }
-    fn convert_to_linux_intel_asm(&self, _assembly: &mut Assembly) -> String { "    mov rax, 60\n    mov rdi, 0\n    syscall".to_string() }
-    fn convert_to_macos_intel_asm(&self, _assembly: &mut Assembly) -> String { "    mov rax, 0x02000001\n    mov rdi, 0\n    syscall".to_string() }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { METHOD_EPILOGUE.to_string() }
 }
 
-pub struct InstrGetStatic { class_name: String, field_name: String, type_desc: String }
+pub struct InstrGetStatic { class_name: String, field_name: String, type_desc: String, class: OnceLock<Arc<dyn JavaClass>>, initialized: AtomicBool }
 impl ByteCodeInstruction for InstrGetStatic {
+    fn pushes_category2(&self) -> bool { self.type_desc == "J" || self.type_desc == "D" }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let class = get_class(&self.class_name);
-        sf.push(class.get_static_object(&self.field_name));
+        let class = match resolve_field_class(&self.class, &self.class_name, &self.field_name) {
+            Ok(class) => class,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+        if let Err(e) = initialize(&self.initialized, class) { return InstrNextAction::EXCEPTION(e); }
+        match class.get_static_object(&self.field_name) {
+            Ok(value) => sf.push_value(Value::from_object(value)),
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      getstatic {}.{} -> {}", self.class_name, self.field_name, self.type_desc); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String { "    nop".to_string() }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.get_static_field(&self.class_name, &self.field_name)
+    }
 }
-pub struct InstrPutStatic { class_name: String, field_name: String, type_desc: String }
+pub struct InstrPutStatic { class_name: String, field_name: String, type_desc: String, class: OnceLock<Arc<dyn JavaClass>>, initialized: AtomicBool }
 impl ByteCodeInstruction for InstrPutStatic {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let class = get_class(&self.class_name);
-        class.put_static_object(&self.field_name, sf.pop());
-        return InstrNextAction::NEXT;
+        let class = match resolve_field_class(&self.class, &self.class_name, &self.field_name) {
+            Ok(class) => class,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+        if let Err(e) = initialize(&self.initialized, class) { return InstrNextAction::EXCEPTION(e); }
+        match class.put_static_object(&self.field_name, sf.pop()) {
+            Ok(()) => InstrNextAction::NEXT,
+            Err(e) => InstrNextAction::EXCEPTION(e)
+        }
     }
     fn print(&self) { println!("      putstatic {}.{} <- {}", self.class_name, self.field_name, self.type_desc); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.put_static_field(&self.class_name, &self.field_name)
+    }
 }
 
-pub struct InstrGetField { class_name: String, field_name: String }
+pub struct InstrGetField { class_name: String, field_name: String, type_desc: String }
 impl ByteCodeInstruction for InstrGetField {
+    fn pushes_category2(&self) -> bool { self.type_desc == "J" || self.type_desc == "D" }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let instance = sf.pop();
         let field = instance.lock().unwrap().get_field(&self.field_name);
-        sf.push(field);
+        match field {
+            Ok(value) => sf.push_value(Value::from_object(value)),
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      getfield {}.{}", self.class_name, self.field_name); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    push qword [rax+{}]", assembly.field_offset(&self.class_name, &self.field_name))
+    }
 }
 
 pub struct InstrPutField { class_name: String, field_name: String }
@@ -2045,71 +2242,234 @@ impl ByteCodeInstruction for InstrPutField {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let value = sf.pop();
         let instance = sf.pop();
-        instance.lock().unwrap().set_field(&self.field_name, value);
-        return InstrNextAction::NEXT;
+        let result = instance.lock().unwrap().set_field(&self.field_name, value);
+        match result {
+            Ok(()) => InstrNextAction::NEXT,
+            Err(e) => InstrNextAction::EXCEPTION(e)
+        }
     }
     fn print(&self) { println!("      putfield {}.{}", self.class_name, self.field_name); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rcx\n    pop rax\n    mov [rax+{}], rcx", assembly.field_offset(&self.class_name, &self.field_name))
+    }
+}
+
+// Constant-pool classes are resolved the first time an instruction runs, and then kept by the instruction.
+// A class which cannot be loaded throws a NoClassDefFoundError
+fn resolve_class<'a>(class: &'a OnceLock<Arc<dyn JavaClass>>, class_name: &String) -> Result<&'a Arc<dyn JavaClass>, Arc<Mutex<dyn JavaInstance>>> {
+    if let Some(resolved) = class.get() { return Ok(resolved); }
+    let loaded = load_class(class_name)?;
+    Ok(class.get_or_init(|| loaded))
+}
+
+// getstatic and putstatic resolve the class declaring the field, which is the one they initialize
+fn resolve_field_class<'a>(class: &'a OnceLock<Arc<dyn JavaClass>>, class_name: &String, field_name: &String) -> Result<&'a Arc<dyn JavaClass>, Arc<Mutex<dyn JavaInstance>>> {
+    if let Some(resolved) = class.get() { return Ok(resolved); }
+    let referenced = load_class(class_name)?;
+    let declaring = get_field_declaring_class(&referenced, field_name)?.unwrap_or(referenced);
+    Ok(class.get_or_init(|| declaring))
+}
+
+// Field resolution (JVMS §5.4.3.2) looks for a static field in the referenced class, then its superinterfaces, then
+// its superclass. Native classes do not describe their fields, and are left to find them
+pub fn get_field_declaring_class(class: &Arc<dyn JavaClass>, field_name: &String) -> Result<Option<Arc<dyn JavaClass>>, Arc<Mutex<dyn JavaInstance>>> {
+    if class.get_field_descriptor(field_name, true).is_some() {
+        return Ok(Some(class.clone()));
+    }
+    for interface in class.get_interfaces() {
+        if let Some(declaring) = get_field_declaring_class(&load_class(&interface)?, field_name)? {
+            return Ok(Some(declaring));
+        }
+    }
+    let parent = class.get_parent();
+    if parent.is_empty() {
+        return Ok(None);
+    }
+    get_field_declaring_class(&load_class(&parent)?, field_name)
+}
+
+// Active uses of a class initialize it (JVMS §5.5), and the instruction stops checking once it is
+fn initialize(initialized: &AtomicBool, class: &Arc<dyn JavaClass>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+    if initialized.load(Ordering::Acquire) { return Ok(()); }
+    if initialize_class(class)? {
+        initialized.store(true, Ordering::Release);
+    }
+    Ok(())
+}
+
+// invokestatic initializes the class declaring the method, which may be a superclass of the referenced one
+pub fn get_declaring_class(class: &Arc<dyn JavaClass>, method_name: &String, type_desc: &String) -> Arc<dyn JavaClass> {
+    let mut current = class.clone();
+    while current.resolve_method(method_name, type_desc).is_none() {
+        let parent = current.get_parent();
+        if parent.eq("") { return class.clone(); }
+        current = get_class(&parent);
+    }
+    current
+}
+
+// Monomorphic inline cache of a call site of invokevirtual or invokeinterface: the class of the first object it
+// called the method of, with the class declaring the selected method and its handle. Calls on objects of this class
+// skip the lookups of the class and of the method, and the others take the slow path
+pub struct InlineCache { receiver: String, class: Arc<dyn JavaClass>, handle: usize }
+
+fn invoke_virtual(sf: &mut StackFrame, cache: &OnceLock<Option<InlineCache>>, class_name: &String, method_name: &String, type_desc: &String,
+    this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> Result<MethodCallResult, Arc<Mutex<dyn JavaInstance>>> {
+    let cached = {
+        let object = this.lock().unwrap();
+        if object.is_null() {
+            return Err(new_java_exception(&"java/lang/NullPointerException".to_string(), &format!("Cannot invoke {}.{}() on a null object", class_name, method_name)));
+        }
+        match cache.get() {
+            Some(Some(cache)) if object.is_bytecode() && object.has_class_name(&cache.receiver) => Some(cache),
+            _ => None
+        }
+    };
+    if let Some(cache) = cached {
+        return Ok(cache.class.execute_resolved_method(sf, cache.handle, this, args));
+    }
+
+    let class = get_receiver_class(&this, class_name)?;
+    if this.lock().unwrap().is_bytecode() {
+        let selected = select_method(&class, method_name, type_desc);
+        cache.get_or_init(|| selected.map(|(declaring, handle)| InlineCache { receiver: class.get_name(), class: declaring, handle }));
+    }
+    Ok(class.execute_method(sf, method_name, type_desc, this, args))
+}
+
+// Method selection (JVMS §5.4.6) of a bytecode method: the class declaring it and its handle. Methods which are
+// native or inherited from a native class are not selected
+fn select_method(class: &Arc<dyn JavaClass>, method_name: &String, type_desc: &String) -> Option<(Arc<dyn JavaClass>, usize)> {
+    let mut current = class.clone();
+    loop {
+        if current.has_native_method(method_name, type_desc) {
+            return None;
+        }
+        if let Some(handle) = current.resolve_method(method_name, type_desc) {
+            return Some((current, handle));
+        }
+        let parent = current.get_parent();
+        if parent.is_empty() {
+            break;
+        }
+        current = get_class(&parent);
+    }
+    select_default_method(&**class, method_name, type_desc).ok().flatten()
 }
 
-pub struct InstrInvokeVirtual { class_name: String, method_name: String, type_desc: String, nb_args: usize }
+// Method selection (JVMS §5.4.6) starts at the runtime class of the receiver, then walks up
+// its superclasses, and lambdas use their own class. Native instances are looked up from the class
+// of the method reference
+fn get_receiver_class(this: &Arc<Mutex<dyn JavaInstance>>, class_name: &String) -> Result<Arc<dyn JavaClass>, Arc<Mutex<dyn JavaInstance>>> {
+    let object = this.lock().unwrap();
+    if object.is_bytecode() || object.get_lambda().is_some() {
+        Ok(get_class(&object.get_class_name()))
+    } else {
+        load_class(class_name)
+    }
+}
+
+// Whether the object is an instance of the class, one of its subclasses or implements the interface
+pub fn is_instance_of(object: &dyn JavaInstance, class_name: &String) -> bool {
+    if object.supports_interface(class_name) { return true; }
+
+    let mut current_class_name = object.get_class_name();
+    while !current_class_name.eq("") {
+        if current_class_name.eq(class_name) { return true; }
+        if !class_exists(&current_class_name) { return false; }
+        current_class_name = get_class(&current_class_name).get_parent();
+    }
+    false
+}
+
+pub struct InstrInvokeVirtual { class_name: String, method_name: String, type_desc: String, nb_args: usize, cache: OnceLock<Option<InlineCache>> }
+impl InstrInvokeVirtual {
+    pub fn new(class_name: &String, method_name: &String, type_desc: &String) -> InstrInvokeVirtual {
+        InstrInvokeVirtual { class_name: class_name.clone(), method_name: method_name.clone(), type_desc: type_desc.clone(),
+            nb_args: get_nb_arguments(type_desc), cache: OnceLock::new() }
+    }
+
+    #[cfg(test)]
+    pub fn get_cached_receiver(&self) -> Option<String> {
+        self.cache.get()?.as_ref().map(|cache| cache.receiver.clone())
+    }
+}
 impl ByteCodeInstruction for InstrInvokeVirtual {
+    fn pushes_category2(&self) -> bool { matches!(get_return_type(&self.type_desc), 'J' | 'D') }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let mut args: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::new();
+        let mut args: Vec<Value> = Vec::new();
         for _ in 0..self.nb_args {
-            args.push(sf.pop().clone());
+            args.push(sf.pop_value());
         }
         let this = sf.pop();
-        let class = get_class(&self.class_name);
-        class.execute_method(sf, &self.method_name, this, args);
-        return InstrNextAction::NEXT;
-    }
-    fn print(&self) { println!("      invokevirtual {}.{}{}(<{} arguments>)", self.class_name, self.method_name, self.type_desc, self.nb_args); }
-    fn convert_to_linux_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        match &self.method_name[..] {
-            "print" => {
-                format!("    pop rdi\n    mov rsi, rdi\n    call __string_length\n    mov rdx, rax\n    mov rax, 1\n    mov rdi, 1\n    syscall")
-            },
-            "println" => {
-                format!("    pop rdi\n    mov rsi, rdi\n    call __string_length\n    mov rdx, rax\n    mov rax, 1\n    mov rdi, 1\n    syscall\n    mov rax, 1\n    mov rdi, 1\n    mov rsi, qword str_cr\n    mov rdx, 1\n    syscall")
-            },
-            _ => panic!("{}.{}() conversion to x64 assembly not implemented", self.class_name, self.method_name)
+        let result = match invoke_virtual(sf, &self.cache, &self.class_name, &self.method_name, &self.type_desc, this, args) {
+            Ok(result) => result,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+
+        match result {
+            MethodCallResult::SUCCESS => InstrNextAction::NEXT,
+            MethodCallResult::EXCEPTION(e) => InstrNextAction::EXCEPTION(e)
         }
     }
-    fn convert_to_macos_intel_asm(&self, _assembly: &mut Assembly) -> String {
-        match &self.method_name[..] {
-            "print" => {
-                format!("    pop rdi\n    mov rsi, rdi\n    call __string_length\n    mov rdx, rax\n    mov rax, 0x02000004\n    mov rdi, 1\n    syscall")
-            },
-            "println" => {
-                format!("    pop rdi\n    mov rsi, rdi\n    call __string_length\n    mov rdx, rax\n    mov rax, 0x02000004\n    mov rdi, 1\n    syscall\n    mov rax, 0x02000004\n    mov rdi, 1\n    mov rsi, qword str_cr\n    mov rdx, 1\n    syscall")
-            },
-            _ => panic!("{}.{}() conversion to x64 assembly not implemented", self.class_name, self.method_name)
-        }
+    fn print(&self) { println!("      invokevirtual {}.{}{}(<{} arguments>)", self.class_name, self.method_name, self.type_desc, self.nb_args); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.call_virtual_method(&self.class_name, &self.method_name, &self.type_desc, false)
     }
 }
 
-pub struct InstrInvokeSpecial { class_name: String, method_name: String, type_desc: String, nb_args: usize }
+pub struct InstrInvokeSpecial { class_name: String, method_name: String, type_desc: String, nb_args: usize,
+    class: OnceLock<Arc<dyn JavaClass>>, method: OnceLock<Option<usize>> }
 impl ByteCodeInstruction for InstrInvokeSpecial {
+    fn pushes_category2(&self) -> bool { matches!(get_return_type(&self.type_desc), 'J' | 'D') }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         if get_debug() >= 1 { sf.print_stack(); }
 
-        let mut args: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::new();
+        let mut args: Vec<Value> = Vec::new();
         for _ in 0..self.nb_args {
-            args.push(sf.pop().clone());
+            args.push(sf.pop_value());
         }
         let this = sf.pop();
-        let class = get_class(&self.class_name);
-        class.execute_method(sf,  &self.method_name, this, args);
-        return InstrNextAction::NEXT;
+        if this.lock().unwrap().is_null() {
+            return exception!("java/lang/NullPointerException", format!("Cannot invoke {}.{}() on a null object", self.class_name, self.method_name));
+        }
+        let class = match resolve_class(&self.class, &self.class_name) {
+            Ok(class) => class,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+        let result = match *self.method.get_or_init(|| class.resolve_method(&self.method_name, &self.type_desc)) {
+            Some(handle) => class.execute_resolved_method(sf, handle, this, args),
+            None => class.execute_method(sf, &self.method_name, &self.type_desc, this, args)
+        };
+
+        match result {
+            MethodCallResult::SUCCESS => InstrNextAction::NEXT,
+            MethodCallResult::EXCEPTION(e) => InstrNextAction::EXCEPTION(e)
+        }
     }
     fn print(&self) { println!("      invokespecial {}.{}{}(<{} arguments>)", self.class_name, self.method_name, self.type_desc, self.nb_args); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.call_special_method(&self.class_name, &self.method_name, &self.type_desc)
+    }
 }
 
-pub struct InstrInvokeStatic { class_name: String, method_name: String, type_desc: String, nb_args: usize }
+pub struct InstrInvokeStatic { class_name: String, method_name: String, type_desc: String, nb_args: usize,
+    class: OnceLock<Arc<dyn JavaClass>>, method: OnceLock<Option<usize>>, initialized: AtomicBool }
 impl ByteCodeInstruction for InstrInvokeStatic {
+    fn pushes_category2(&self) -> bool { matches!(get_return_type(&self.type_desc), 'J' | 'D') }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let class = get_class(&self.class_name);
-        let result = class.execute_static_method(sf, &self.method_name, self.nb_args);
+        let class = match resolve_class(&self.class, &self.class_name) {
+            Ok(class) => class,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+        if let Err(e) = initialize(&self.initialized, &get_declaring_class(class, &self.method_name, &self.type_desc)) {
+            return InstrNextAction::EXCEPTION(e);
+        }
+        // Native classes and inherited methods have no handle, and are looked up by name
+        let result = match *self.method.get_or_init(|| class.resolve_method(&self.method_name, &self.type_desc)) {
+            Some(handle) => class.execute_resolved_static_method(sf, handle),
+            None => class.execute_static_method(sf, &self.method_name, &self.type_desc, self.nb_args)
+        };
 
         match result {
             MethodCallResult::SUCCESS => InstrNextAction::NEXT,
@@ -2117,40 +2477,54 @@ impl ByteCodeInstruction for InstrInvokeStatic {
         }
     }
     fn print(&self) { println!("      invokestatic {}.{}{}(<{} arguments>)", self.class_name, self.method_name, self.type_desc, self.nb_args); }
-    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
         if self.class_name.eq("java/lang/Math") {
             match &self.method_name[..] {
                 "sqrt" => {
                     return format!("    pop rax\n    movq xmm15, rax\n    sqrtsd xmm14, xmm15\n    movq rax, xmm14\n    push rax");
                 },
-                // Not an ideal implementation, but unfortunately SSE does not support log()
-                "log" => {
-                    return format!("    pop rax\n    movq xmm15, rax\n    sqrtsd xmm14, xmm15\n    sqrtsd xmm14, xmm14\n    movq rax, xmm14\n    push rax");
-                }
+                // SSE has no transcendental functions, they are routines of the runtime
+                "log" | "exp" | "sin" | "cos" => {
+                    let routine = match &self.method_name[..] { "log" => "__math_log", "exp" => "__math_exp", "sin" => "__math_sin", _ => "__math_cos" };
+                    return format!("    pop rax\n    movq xmm0, rax\n{}\n    movq rax, xmm0\n    push rax", assembly.call_routine(routine));
+                },
+                "pow" => {
+                    return format!("    pop rax\n    movq xmm1, rax\n    pop rax\n    movq xmm0, rax\n{}\n    movq rax, xmm0\n    push rax", assembly.call_routine("__math_pow"));
+                },
                 _ => {
                     panic!("Static method Math.{}() does not support conversion to assembly", self.method_name);
                 }
             }
         };
 
-        panic!("Static method {}.{}() does not support conversion to assembly", self.class_name, self.method_name);
+        assembly.call_static_method(&self.class_name, &self.method_name, &self.type_desc)
     }
 }
 
-pub struct InstrInvokeInterface { class_name: String, method_name: String, type_desc: String, count: usize, nb_args: usize }
+pub struct InstrInvokeInterface { class_name: String, method_name: String, type_desc: String, count: usize, nb_args: usize, cache: OnceLock<Option<InlineCache>> }
 impl ByteCodeInstruction for InstrInvokeInterface {
+    fn pushes_category2(&self) -> bool { matches!(get_return_type(&self.type_desc), 'J' | 'D') }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
 
-        let mut args: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::new();
+        let mut args: Vec<Value> = Vec::new();
         for _ in 0..self.nb_args {
-            args.push(sf.pop().clone());
+            args.push(sf.pop_value());
         }
         let this = sf.pop();
-        let class = get_class(&self.class_name);
-        class.execute_method(sf, &self.method_name, this, args);
-        return InstrNextAction::NEXT;
+        let result = match invoke_virtual(sf, &self.cache, &self.class_name, &self.method_name, &self.type_desc, this, args) {
+            Ok(result) => result,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+
+        match result {
+            MethodCallResult::SUCCESS => InstrNextAction::NEXT,
+            MethodCallResult::EXCEPTION(e) => InstrNextAction::EXCEPTION(e)
+        }
     }
     fn print(&self) { println!("      invokeinterface {}.{}{}(<{} arguments>) {}", self.class_name, self.method_name, self.type_desc, self.nb_args, self.count); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.call_virtual_method(&self.class_name, &self.method_name, &self.type_desc, true)
+    }
 }
 
 pub struct InstrInvokeDynamic {
@@ -2161,6 +2535,7 @@ pub struct InstrInvokeDynamic {
     class_name: String
 }
 impl ByteCodeInstruction for InstrInvokeDynamic {
+    fn pushes_category2(&self) -> bool { matches!(get_return_type(&self.method_type), 'J' | 'D') }
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let class = get_class(&self.class_name);
         let the_class = class;
@@ -2182,7 +2557,7 @@ impl ByteCodeInstruction for InstrInvokeDynamic {
         type_desc.push_str(")V");
 
         let class = get_class(&bootstrap.class_name);
-        let result = class.execute_static_method(sf, &bootstrap.method_name, self.method_nb_args);
+        let result = class.execute_static_method(sf, &bootstrap.method_name, &bootstrap.type_name, self.method_nb_args);
 
         match result {
             MethodCallResult::SUCCESS => InstrNextAction::NEXT,
@@ -2192,61 +2567,107 @@ impl ByteCodeInstruction for InstrInvokeDynamic {
     fn print(&self) { println!("      invokedynamic {} {}{}", self.bootstrap_method_idx, self.method_name, self.method_type); }
 }
 
-pub struct InstrNew { class_name: String }
+pub struct InstrNew { class_name: String, class: OnceLock<Arc<dyn JavaClass>>, initialized: AtomicBool }
 impl ByteCodeInstruction for InstrNew {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let class = get_class(&self.class_name);
+        let class = match resolve_class(&self.class, &self.class_name) {
+            Ok(class) => class,
+            Err(e) => return InstrNextAction::EXCEPTION(e)
+        };
+        if let Err(e) = initialize(&self.initialized, class) { return InstrNextAction::EXCEPTION(e); }
         sf.push(class.new());
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      new {}", self.class_name); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.new_object(&self.class_name)
+    }
 }
 
 pub struct InstrNewArray { atype: u8 }
+impl InstrNewArray {
+    // Zero of the type of the elements, boxed like the array stores of this type do
+    fn get_default_value(&self) -> Arc<Mutex<dyn JavaInstance>> {
+        match self.atype {
+            // boolean and byte
+            4 | 8 => Arc::new(Mutex::new(NativeByteInstance::new(0))),
+            5 => Arc::new(Mutex::new(NativeCharInstance::new('\0'))),
+            6 => Value::Float(0.0).to_object(),
+            7 => Value::Double(0.0).to_object(),
+            9 => Arc::new(Mutex::new(NativeShortInstance::new(0))),
+            10 => Value::Int(0).to_object(),
+            11 => Value::Long(0).to_object(),
+            _ => panic!("newarray: unknown array type {}", self.atype)
+        }
+    }
+}
 impl ByteCodeInstruction for InstrNewArray {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let count = sf.pop_int();
+        if count < 0 {
+            return exception!("java/lang/NegativeArraySizeException", count.to_string());
+        }
         let mut array: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::with_capacity(count as usize);
         for _i in 0..count {
-            array.push(Arc::new(Mutex::new(NativeIntegerInstance::new(0))));
+            array.push(self.get_default_value());
         }
         sf.push_array(Arc::new(Mutex::new(array)));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      newarray {}", self.atype); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        // Elements of all types take 8 bytes
+        assembly.new_array()
+    }
 }
 
 pub struct InstrANewArray { class_name: String }
 impl ByteCodeInstruction for InstrANewArray {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let count = sf.pop_int();
+        if count < 0 {
+            return exception!("java/lang/NegativeArraySizeException", count.to_string());
+        }
         let mut array: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::with_capacity(count as usize);
         for _i in 0..count {
-            array.push(Arc::new(Mutex::new(NativeIntegerInstance::new(0))));
+            array.push(null_instance());
         }
         sf.push_array(Arc::new(Mutex::new(array)));
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      anewarray {}", self.class_name); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.new_array()
+    }
 }
 
 pub struct InstrArrayLength { }
 impl ByteCodeInstruction for InstrArrayLength {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let array = sf.pop_array();
+        let arg = sf.pop();
+        if arg.lock().unwrap().is_null() {
+            return exception!("java/lang/NullPointerException", "Cannot read the array length of a null array");
+        }
+        let array = arg.lock().unwrap().get_array();
         sf.push_int(array.lock().unwrap().len() as i32);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      arraylength"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    push qword [rax+8]")
+    }
 }
 
 pub struct InstrAThrow { }
 impl ByteCodeInstruction for InstrAThrow {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let exception = sf.pop();
+        if exception.lock().unwrap().is_null() {
+            return exception!("java/lang/NullPointerException", "Cannot throw a null exception");
+        }
         return InstrNextAction::EXCEPTION(exception.clone());
     }
-    fn print(&self) { println!("      arraylength"); }
+    fn print(&self) { println!("      athrow"); }
 }
 
 ///////////// 0xc
@@ -2255,39 +2676,73 @@ pub struct InstrCheckCast { class_name: String }
 impl ByteCodeInstruction for InstrCheckCast {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let arg = sf.pop();
-        let is_cast_ok = {
+        // null can be cast to any class
+        let (is_cast_ok, arg_class_name) = {
             let object = arg.lock().unwrap();
-            object.get_class_name().eq(&self.class_name) || object.supports_interface(&self.class_name)
+            (object.is_null() || is_instance_of(&*object, &self.class_name), object.get_class_name())
         };
         if is_cast_ok {
             sf.push(arg);
             return InstrNextAction::NEXT;
         } else {
-            return exception!("java/lang/ClassCastException", "cannot be cast");
+            return exception!("java/lang/ClassCastException", format!("class {} cannot be cast to class {}", arg_class_name.replace("/", "."), self.class_name.replace("/", ".")));
         }
     }
     fn print(&self) { println!("      checkcast"); }
+    fn convert_to_intel_asm(&self, _assembly: &mut Assembly) -> String {
+        // Casts are not checked
+        String::new()
+    }
 }
 
 pub struct InstrInstanceOf { class_name: String }
 impl ByteCodeInstruction for InstrInstanceOf {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
         let arg = sf.pop();
-        sf.push_bool(arg.lock().unwrap().get_class_name().eq(&self.class_name));
+        let is_instance = {
+            let object = arg.lock().unwrap();
+            !object.is_null() && is_instance_of(&*object, &self.class_name)
+        };
+        sf.push_bool(is_instance);
         return InstrNextAction::NEXT;
     }
     fn print(&self) { println!("      instanceof"); }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        assembly.instance_of(&self.class_name)
+    }
 }
 
-pub struct InstrIfNull { branch: usize }
-impl ByteCodeInstruction for InstrIfNull {
+pub struct InstrMonitorEnter { }
+impl ByteCodeInstruction for InstrMonitorEnter {
     fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let isnull = sf.pop_isnull();
-        if isnull {
-            return InstrNextAction::GOTO(self.branch);
+        let object = sf.pop();
+        if object.lock().unwrap().is_null() {
+            return exception!("java/lang/NullPointerException", "Cannot enter synchronized block on a null object");
+        }
+        monitor_enter(get_object_id(&object));
+        return InstrNextAction::NEXT;
+    }
+    fn print(&self) { println!("      monitorenter"); }
+}
+
+pub struct InstrMonitorExit { }
+impl ByteCodeInstruction for InstrMonitorExit {
+    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
+        let object = sf.pop();
+        if object.lock().unwrap().is_null() {
+            return exception!("java/lang/NullPointerException", "Cannot exit synchronized block on a null object");
+        }
+        if !monitor_exit(get_object_id(&object)) {
+            return exception!("java/lang/IllegalMonitorStateException", "current thread is not owner");
         }
         return InstrNextAction::NEXT;
     }
+    fn print(&self) { println!("      monitorexit"); }
+}
+
+pub struct InstrIfNull { branch: usize }
+impl ByteCodeInstruction for InstrIfNull {
+    fn get_op(&self) -> Op { Op::IfNull(self.branch) }
     fn print(&self) { println!("      ifnull {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -2295,17 +2750,14 @@ impl ByteCodeInstruction for InstrIfNull {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    je {}", assembly.add_jump(self.branch))
+    }
 }
 
 pub struct InstrIfNotNull { branch: usize }
 impl ByteCodeInstruction for InstrIfNotNull {
-    fn execute(&self, sf: &mut StackFrame) -> InstrNextAction {
-        let isnull = sf.pop_isnull();
-        if !isnull {
-            return InstrNextAction::GOTO(self.branch);
-        }
-        return InstrNextAction::NEXT;
-    }
+    fn get_op(&self) -> Op { Op::IfNonNull(self.branch) }
     fn print(&self) { println!("      ifnotnull {}", self.branch); }
     fn set_branch(&mut self, address_map: &HashMap<usize, usize>) {
         match address_map.get(&self.branch) {
@@ -2313,25 +2765,35 @@ impl ByteCodeInstruction for InstrIfNotNull {
             _ => panic!("Unknown branch position {}", self.branch)
         }
     }
+    fn convert_to_intel_asm(&self, assembly: &mut Assembly) -> String {
+        format!("    pop rax\n    cmp rax, 0\n    jne {}", assembly.add_jump(self.branch))
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////
 
+// An entry of the exception table. The handler covers [start_pc, end_pc[ and, without a class
+// name (catch_type 0), catches any exception: this is how finally blocks are compiled
 pub struct Exception {
     pub start_pc: usize,
     pub end_pc: usize,
     pub handler_pc: usize,
-    pub name: String
+    pub name: Option<String>
 }
 
 impl Exception {
     pub fn catches(&self, exception: Arc<Mutex<dyn JavaInstance>>, instr_idx: usize) -> Option<usize> {
-        if self.start_pc > instr_idx || self.end_pc < instr_idx { return None; }
+        if self.start_pc > instr_idx || self.end_pc <= instr_idx { return None; }
+
+        let name = match &self.name {
+            Some(name) => name,
+            None => return Some(self.handler_pc)
+        };
 
         let mut class_name= exception.lock().unwrap().get_class_name();
 
         while !class_name.eq("") {
-            if class_name.eq(&self.name) {
+            if class_name.eq(name) {
                 return Some(self.handler_pc);
             }
 
@@ -2344,10 +2806,20 @@ impl Exception {
 }
 
 pub struct ByteCode {
+    pub method_name: String,
+    pub type_desc: String,
+    pub argument_sizes: Vec<usize>,
     pub instructions: Vec<Box<dyn ByteCodeInstruction>>,
+    // The compact form of each instruction, at the same index
+    pub ops: Vec<Op>,
+    pub jit: JitState,
     pub exceptions: Vec<Exception>,
     address_map: HashMap<usize, usize>,
-    pub line_number_table: Vec<(usize, usize)>
+    pub line_number_table: Vec<(usize, usize)>,
+    pub is_synchronized: bool,
+    pub is_static: bool,
+    pub max_stack: usize,
+    pub max_locals: usize
 }
 
 impl ByteCode {
@@ -2399,13 +2871,13 @@ impl ByteCode {
                 0x12 => {
                     let idx = data.get_u8() as usize;
                     match constants_string_ref.get(&idx) {
-                        Some(string) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeStringInstance::new(string.value.clone()))), instance_type: "string".to_string() }),
+                        Some(string) => Box::new(InstrLdc { value: Value::Reference(Arc::new(Mutex::new(NativeStringInstance::new(string.value.clone())))), instance_type: "string".to_string(), interned: OnceLock::new() }),
                         _ => match constants_float.get(&idx) {
-                            Some(float) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeFloatInstance::new(float.value))), instance_type: "float".to_string() }),
+                            Some(float) => Box::new(InstrLdc { value: Value::Float(float.value), instance_type: "float".to_string(), interned: OnceLock::new() }),
                             _ => match constants_integer.get(&idx) {
-                                Some(int) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeIntegerInstance::new(int.value))), instance_type: "int".to_string() }),
+                                Some(int) => Box::new(InstrLdc { value: Value::Int(int.value), instance_type: "int".to_string(), interned: OnceLock::new() }),
                                 _ =>  match constants_class.get(&idx) {
-                                    Some(class) => Box::new(InstrLdc { value: Arc::new(Mutex::new(JavaClassInstance::new(class.name.clone()))), instance_type: "class".to_string() }),
+                                    Some(class) => Box::new(InstrLdc { value: Value::Reference(Arc::new(Mutex::new(JavaClassInstance::new(class.name.clone())))), instance_type: "class".to_string(), interned: OnceLock::new() }),
                                     _ => panic!("ldc: unknown index {}", idx)
                                 }
                             }
@@ -2415,11 +2887,11 @@ impl ByteCode {
                 0x13 => {
                     let idx = data.get_u16size();
                     match constants_string_ref.get(&idx) {
-                        Some(string) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeStringInstance::new(string.value.clone()))), instance_type: "string".to_string() }),
+                        Some(string) => Box::new(InstrLdc { value: Value::Reference(Arc::new(Mutex::new(NativeStringInstance::new(string.value.clone())))), instance_type: "string".to_string(), interned: OnceLock::new() }),
                         _ => match constants_float.get(&idx) {
-                            Some(float) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeFloatInstance::new(float.value))), instance_type: "float".to_string() }),
+                            Some(float) => Box::new(InstrLdc { value: Value::Float(float.value), instance_type: "float".to_string(), interned: OnceLock::new() }),
                             _ => match constants_integer.get(&idx) {
-                                Some(int) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeIntegerInstance::new(int.value))), instance_type: "int".to_string() }),
+                                Some(int) => Box::new(InstrLdc { value: Value::Int(int.value), instance_type: "int".to_string(), interned: OnceLock::new() }),
                                 _ =>  panic!("ldc_w: unknown index {}", idx)
                             }
                         }
@@ -2428,9 +2900,9 @@ impl ByteCode {
                 0x14 => {
                     let idx = data.get_u16size();
                     match constants_double.get(&idx) {
-                        Some(double) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeDoubleInstance::new(double.value))), instance_type: "double".to_string() }),
+                        Some(double) => Box::new(InstrLdc { value: Value::Double(double.value), instance_type: "double".to_string(), interned: OnceLock::new() }),
                         _ => match constants_long.get(&idx) {
-                            Some(long) => Box::new(InstrLdc { value: Arc::new(Mutex::new(NativeLongInstance::new(long.value))), instance_type: "long".to_string() }),
+                            Some(long) => Box::new(InstrLdc { value: Value::Long(long.value), instance_type: "long".to_string(), interned: OnceLock::new() }),
                             _ => panic!("ldc2_w: unknown index {}", idx)
                         }
                     }
@@ -2561,7 +3033,7 @@ impl ByteCode {
                 0x90 => Box::new(InstrD2F {}),
                 0x91 => Box::new(InstrI2B {}),
                 0x92 => Box::new(InstrI2C {}),
-                0x93 => Box::new(InstrI2C {}),
+                0x93 => Box::new(InstrI2S {}),
                 0x94 => Box::new(InstrLCmp {}),
                 0x95 => Box::new(InstrFCmpl {}),
                 0x96 => Box::new(InstrFCmpg {}),
@@ -2582,6 +3054,8 @@ impl ByteCode {
                 0xa5 => Box::new(InstrIfACmpEq { branch: (data_offset as i16 + data.get_i16()) as usize }),
                 0xa6 => Box::new(InstrIfACmpNe { branch: (data_offset as i16 + data.get_i16()) as usize }),
                 0xa7 => Box::new(InstrGoto { branch: (data_offset as i16 + data.get_i16()) as usize }),
+                0xa8 => Box::new(InstrJsr { branch: (data_offset as i16 + data.get_i16()) as usize, return_address: data_offset + 3 }),
+                0xa9 => Box::new(InstrRet { index: data.get_u8() as usize }),
                 0xaa => {
                     let offset = data_offset;
                     for _ in 0..((4 - (offset + 1) % 4) % 4) {
@@ -2623,7 +3097,9 @@ impl ByteCode {
                     Some(method) => Box::new(InstrGetStatic {
                         class_name: method.class_name.clone(),
                         field_name: method.field_name.clone(),
-                        type_desc: method.type_name.clone()
+                        type_desc: method.type_name.clone(),
+                        class: OnceLock::new(),
+                        initialized: AtomicBool::new(false)
                     }),
                     _ => panic!("Unknown field")
                 },
@@ -2631,14 +3107,17 @@ impl ByteCode {
                     Some(method) => Box::new(InstrPutStatic {
                         class_name: method.class_name.clone(),
                         field_name: method.field_name.clone(),
-                        type_desc: method.type_name.clone()
+                        type_desc: method.type_name.clone(),
+                        class: OnceLock::new(),
+                        initialized: AtomicBool::new(false)
                     }),
                     _ => panic!("Unknown field")
                 },
                 0xb4 => match constants_field.get(&data.get_u16size()) {
                     Some(method) => Box::new(InstrGetField {
                         class_name: method.class_name.clone(),
-                        field_name: method.field_name.clone()
+                        field_name: method.field_name.clone(),
+                        type_desc: method.type_name.clone()
                     }),
                     _ => panic!("Unknown field")
                 },
@@ -2650,12 +3129,7 @@ impl ByteCode {
                     _ => panic!("Unknown field")
                 },
                 0xb6 => match constants_method.get(&data.get_u16size()) {
-                    Some(method) => Box::new(InstrInvokeVirtual {
-                        class_name: method.class_name.clone(),
-                        method_name: method.method_name.clone(),
-                        type_desc: method.type_name.clone(),
-                        nb_args: get_nb_arguments(&method.type_name)
-                    }),
+                    Some(method) => Box::new(InstrInvokeVirtual::new(&method.class_name, &method.method_name, &method.type_name)),
                     _ => panic!("Unknown method")
                 },
                 0xb7 => match constants_method.get(&data.get_u16size()) {
@@ -2663,7 +3137,9 @@ impl ByteCode {
                         class_name: method.class_name.clone(),
                         method_name: method.method_name.clone(),
                         type_desc: method.type_name.clone(),
-                        nb_args: get_nb_arguments(&method.type_name)
+                        nb_args: get_nb_arguments(&method.type_name),
+                        class: OnceLock::new(),
+                        method: OnceLock::new()
                     }),
                     _ => panic!("Unknown method")
                 },
@@ -2672,7 +3148,10 @@ impl ByteCode {
                         class_name: method.class_name.clone(),
                         method_name: method.method_name.clone(),
                         type_desc: method.type_name.clone(),
-                        nb_args: get_nb_arguments(&method.type_name)
+                        nb_args: get_nb_arguments(&method.type_name),
+                        class: OnceLock::new(),
+                        method: OnceLock::new(),
+                        initialized: AtomicBool::new(false)
                     }),
                     _ => panic!("Unknown method")
                 },
@@ -2682,7 +3161,8 @@ impl ByteCode {
                         class_name: method.class_name.clone(),
                         method_name: method.method_name.clone(),
                         type_desc: method.type_name.clone(),
-                        nb_args: get_nb_arguments(&method.type_name)
+                        nb_args: get_nb_arguments(&method.type_name),
+                        cache: OnceLock::new()
                     }),
                     _ => panic!("Unknown interface")
                 },
@@ -2703,7 +3183,9 @@ impl ByteCode {
                 },
                 0xbb => match constants_class.get(&data.get_u16size()) {
                     Some(class) => Box::new(InstrNew {
-                        class_name: class.name.clone()
+                        class_name: class.name.clone(),
+                        class: OnceLock::new(),
+                        initialized: AtomicBool::new(false)
                     }),
                     _ => panic!("Unknown class")
                 },
@@ -2737,13 +3219,14 @@ impl ByteCode {
                         _ => panic!("Unknown class at index {}", idx)
                     }
                 },
-//                0xc2 => monitorenter
-//                0xc3 => monitorexit
+                0xc2 => Box::new(InstrMonitorEnter {}),
+                0xc3 => Box::new(InstrMonitorExit {}),
 //                0xc4 => wide
 //                0xc5 => multianewarray
                 0xc6 => Box::new(InstrIfNull { branch: (data_offset as i16 + data.get_i16()) as usize }),
                 0xc7 => Box::new(InstrIfNotNull { branch: (data_offset as i16 + data.get_i16()) as usize }),
                 0xc8 => Box::new(InstrGoto { branch: (data_offset as i32 + data.get_i32()) as usize }),
+                0xc9 => Box::new(InstrJsr { branch: (data_offset as i32 + data.get_i32()) as usize, return_address: data_offset + 5 }),
                 _ => panic!("Unknown opcode {:#02x}", opcode)
             };
 
@@ -2753,33 +3236,47 @@ impl ByteCode {
             instr_idx += 1;
         }
 
+        // The end of an exception range is exclusive, so it may be the end of the code
+        address_map.insert(data.get_offset(), instr_idx);
+
         for instr in instructions.iter_mut() {
             instr.set_branch(&address_map);
         }
 
+        let ops = instructions.iter().map(|instr| instr.get_op()).collect();
+
         ByteCode {
+            method_name: String::new(),
+            type_desc: String::new(),
+            argument_sizes: Vec::new(),
             instructions,
+            ops,
+            jit: JitState::new(),
             exceptions: Vec::new(),
             address_map,
-            line_number_table
+            line_number_table,
+            is_synchronized: false,
+            is_static: false,
+            max_stack: 0,
+            max_locals: 0
         }
     }
 
-    pub fn add_exception(&mut self, start_pc: usize, end_pc: usize, handler_pc: usize, name: &String) {
-        self.exceptions.push(Exception {
-            start_pc: *self.address_map.get(&start_pc).unwrap(),
-            end_pc: *self.address_map.get(&end_pc).unwrap(),
-            handler_pc: *self.address_map.get(&handler_pc).unwrap(),
-            name: name.clone()
-        });
-
+    pub fn add_exception(&mut self, start_pc: usize, end_pc: usize, handler_pc: usize, name: Option<String>) {
         if get_debug() >= 2 {
-            println!("    Method exception [{}..{}] -> {}, type={}",
+            println!("    Method exception [{}..{}[ -> {}, type={}",
                 *self.address_map.get(&start_pc).unwrap(),
                 *self.address_map.get(&end_pc).unwrap(),
                 *self.address_map.get(&handler_pc).unwrap(),
-                name);
+                name.as_deref().unwrap_or("any"));
         }
+
+        self.exceptions.push(Exception {
+            start_pc: *self.address_map.get(&start_pc).unwrap(),
+            end_pc: *self.address_map.get(&end_pc).unwrap(),
+            handler_pc: *self.address_map.get(&handler_pc).unwrap(),
+            name
+        });
     }
 
     pub fn add_line_number(&mut self, line: usize, bytecode_offset: usize) {
@@ -1,30 +1,33 @@
-use std::fs;
-use std::io::Read;
-use std::fs::File;
-use std::path::Path;
-use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::collections::HashMap;
-
-use crate::asm::Arch;
-use crate::asm::bytecode_to_asm;
-use crate::bytecode::InstrNextAction;
-use crate::get_class;
-use crate::get_debug;
+use std::collections::{HashMap, HashSet};
+
+use crate::asm::{Arch, Assembly, Emit};
+use crate::asm::class_to_asm;
+use crate::api::call_native_function;
+use crate::bytecode::{InstrNextAction, Op};
+use crate::vm::{self, get_class};
+use crate::jit;
+use crate::vm::get_debug;
 use crate::java_class::JavaClass;
 use crate::java_class::MethodCallResult;
-use crate::jvm::StackFrame;
+use crate::java_class::get_argument_sizes;
+use crate::jvm::{StackFrame, Value};
+use crate::native_java_classes::NativeDoubleInstance;
 use crate::native_java_classes::NativeFloatInstance;
 use crate::native_java_classes::NativeIntegerInstance;
-use crate::native_java_classes::NativeNullInstance;
+use crate::native_java_classes::NativeLongInstance;
+use crate::native_java_classes::null_instance;
 use crate::native_java_classes::NativeStringInstance;
+use crate::native_java_classes::new_java_exception;
 use crate::{bytecode::ByteCode, jvm::JavaInstance};
 use crate::java_class::BytecodeInstance;
+use crate::monitor::{get_object_id, monitor_enter, monitor_exit};
 
 pub struct BytecodeClass {
     pub name: String,
     superclass_name: String,
+    interfaces: Vec<String>,
     pub constants_class: HashMap<usize, ConstantClass>,
     constants_string: HashMap<usize, ConstantString>,
     constants_string_ref: HashMap<usize, ConstantStringRef>,
@@ -33,9 +36,14 @@ pub struct BytecodeClass {
     constants_name_type: HashMap<usize, ConstantNameType>,
     constants_method_handle: HashMap<usize, ConstantMethodHandle>,
     constants_dynamic: HashMap<usize, ConstantInvokeDynamic>,
-    methods: Rc<HashMap<String, ByteCode>>,
+    methods: Vec<ByteCode>,
+    // Index of each method in methods, by method key
+    method_index: HashMap<String, usize>,
+    // Keys of the methods implemented by Rust functions registered in the VM
+    native_methods: HashSet<String>,
     pub bootstrap_methods: Vec<AttributeBootstrapMethod>,
     fields: HashMap<String, String>,
+    static_field_descriptors: HashMap<String, String>,
     static_fields: Arc<Mutex<HashMap<String, Arc<Mutex<dyn JavaInstance>>>>>,
     has_static_init: bool,
     source_file: String
@@ -47,9 +55,8 @@ impl JavaClass for BytecodeClass {
         let parent = superclass.new();
 
         let mut fields: HashMap<String, Arc<Mutex<dyn JavaInstance>>> = HashMap::new();
-        for (field_name, _) in self.fields.iter() {
-//            let class = get_class(&field_class);
-            fields.insert(field_name.clone(), Arc::new(Mutex::new(NativeNullInstance {})));
+        for (field_name, descriptor) in self.fields.iter() {
+            fields.insert(field_name.clone(), get_default_value(descriptor));
         }
 
         return Arc::new(Mutex::new(BytecodeInstance { class_name: self.get_name(), parent: Some(parent), fields: fields }));
@@ -59,6 +66,14 @@ impl JavaClass for BytecodeClass {
         return self.name.clone();
     }
 
+    fn get_parent(&self) -> String {
+        return self.superclass_name.clone();
+    }
+
+    fn get_interfaces(&self) -> Vec<String> {
+        return self.interfaces.clone();
+    }
+
     fn get_bootstrap_method(&self, idx: usize) -> Option<&AttributeBootstrapMethod> {
         return self.bootstrap_methods.get(idx);
     }
@@ -67,16 +82,6 @@ impl JavaClass for BytecodeClass {
         return &self.constants_method_handle;
     }
 
-    fn get_dependent_classes(&self) -> Vec<String> {
-        let mut class_names: Vec<String> = Vec::new();
-
-        for constant_class in self.constants_class.values() {
-            class_names.push(constant_class.name.clone());
-        }
-
-        class_names
-    }
-
     fn has_static_init(&self) -> bool { self.has_static_init }
 
     fn print(&self) {
@@ -109,101 +114,119 @@ impl JavaClass for BytecodeClass {
         }
     }
 
-    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) -> MethodCallResult {
-        if self.methods.contains_key(method_name) {
-            if get_debug() >= 1 { println!("Execute bytecode method {}.{}(<{} arguments>)", self.get_name(), method_name, args.len()); }
-
-            let var = Arc::new(Mutex::new(NativeNullInstance {}));
-            let mut variables: [Arc<Mutex<dyn JavaInstance>>; 16] = [var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone()];
+    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
+        match self.resolve_method(method_name, type_desc) {
+            Some(handle) => self.execute_resolved_method(sf, handle, this, args),
+            None if self.native_methods.contains(&get_method_key(method_name, type_desc)) => {
+                // Arguments are in reverse order: args[0] is the last one
+                let args = args.into_iter().rev().collect();
+                self.execute_native_method(sf, method_name, type_desc, Some(this), args)
+            },
+            None => {
+                match select_default_method(self, method_name, type_desc) {
+                    Ok(Some((interface, handle))) => return interface.execute_resolved_method(sf, handle, this, args),
+                    Ok(None) => {},
+                    Err(e) => return MethodCallResult::EXCEPTION(e)
+                }
 
-            let mut i = args.len() + 1;
+                // Inherited method: the receiver stays the same object, so that the methods it
+                // calls on this are still dispatched from its runtime class
+                let superclass = get_class(&self.superclass_name);
+                if get_debug() >= 1 { println!("Execute bytecode method {}.{}{}(<{} arguments>)", superclass.get_name(), method_name, type_desc, args.len()); }
 
-            for arg in args.iter() {
-                i -= 1;
-                variables[i] = arg.clone();
+                superclass.execute_method(sf, method_name, type_desc, this, args)
             }
-            variables[0] = this.clone();
+        }
+    }
 
-            let mut sf_new = StackFrame::new(variables);
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, nb_args: usize) -> MethodCallResult {
+        match self.resolve_method(method_name, type_desc) {
+            Some(handle) => self.execute_resolved_static_method(sf, handle),
+            None if self.native_methods.contains(&get_method_key(method_name, type_desc)) => {
+                let mut args: Vec<Value> = (0..nb_args).map(|_| sf.pop_value()).collect();
+                args.reverse();
+                self.execute_native_method(sf, method_name, type_desc, None, args)
+            },
+            None => {
+                let superclass = get_class(&self.superclass_name);
+                if get_debug() >= 1 { println!("Execute static method {}.{}{}(<{} arguments>)", superclass.get_name(), method_name, type_desc, nb_args); }
 
-            let result = self.execute_bytecode(&mut sf_new, method_name);
+                superclass.execute_static_method(sf, method_name, type_desc, nb_args)
+            }
+        }
+    }
 
-            match result {
-                MethodCallResult::SUCCESS => {
-                    if sf_new.return_arg {
-                        sf.push(sf_new.pop());
-                    }
-                    return result;        
-                },
-                MethodCallResult::EXCEPTION(e) => {
-                    return MethodCallResult::EXCEPTION(e.clone());
-                }
-            };
-        } else {
-            let superclass = get_class(&self.superclass_name);
-            if get_debug() >= 1 { println!("Execute bytecode method {}.{}(<{} arguments>)", superclass.get_name(), method_name, args.len()); }
+    fn resolve_method(&self, method_name: &String, type_desc: &String) -> Option<usize> {
+        self.method_index.get(&get_method_key(method_name, type_desc)).copied()
+    }
 
-            let this2 = this.clone();
-            let object = this.lock().unwrap().cast_as(this2, &self.superclass_name);
-            return superclass.execute_method(sf, method_name, object, args);
-        }
+    fn has_native_method(&self, method_name: &String, type_desc: &String) -> bool {
+        self.native_methods.contains(&get_method_key(method_name, type_desc))
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, nb_args: usize) -> MethodCallResult {
-        if self.methods.contains_key(method_name) {
-            if get_debug() >= 1 { println!("Execute static method {}.{}(<{} arguments>)", self.get_name(), method_name, nb_args); }
+    fn execute_resolved_method(&self, sf: &mut StackFrame, handle: usize, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
+        let method = &self.methods[handle];
+        if get_debug() >= 1 { println!("Execute bytecode method {}.{}{}(<{} arguments>)", self.get_name(), method.method_name, method.type_desc, args.len()); }
 
-            let var = Arc::new(Mutex::new(NativeNullInstance {}));
-            let mut variables: [Arc<Mutex<dyn JavaInstance>>; 16] = [var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone(),
-                var.clone(), var.clone(), var.clone(), var.clone()];
+        // this is in slot 0, followed by the arguments, which are in reverse order
+        let mut sf_new = StackFrame::new(method.max_locals, method.max_stack);
+        sf_new.set_variable(0, Value::Reference(this.clone()));
+        let mut slot = 1;
+        for (arg, size) in args.into_iter().rev().zip(method.argument_sizes.iter()) {
+            sf_new.set_variable(slot, arg);
+            slot += size;
+        }
 
-            for i in 0..nb_args {
-                variables[nb_args - 1 - i] = sf.pop();
+        let result = self.execute_synchronized(&mut sf_new, method, get_object_id(&this));
+        if let MethodCallResult::SUCCESS = result {
+            if sf_new.return_arg {
+                sf.push_value(sf_new.pop_value());
             }
+        }
+        result
+    }
 
-            let mut sf_new = StackFrame::new(variables);
+    fn execute_resolved_static_method(&self, sf: &mut StackFrame, handle: usize) -> MethodCallResult {
+        let method = &self.methods[handle];
+        if get_debug() >= 1 { println!("Execute static method {}.{}{}(<{} arguments>)", self.get_name(), method.method_name, method.type_desc, method.argument_sizes.len()); }
 
-            let result = self.execute_bytecode(&mut sf_new, method_name);
+        let mut sf_new = StackFrame::new(method.max_locals, method.max_stack);
+        let mut slot: usize = method.argument_sizes.iter().sum();
+        for size in method.argument_sizes.iter().rev() {
+            slot -= size;
+            sf_new.set_variable(slot, sf.pop_value());
+        }
 
-            match result {
-                MethodCallResult::SUCCESS => {
-                    if sf_new.return_arg {
-                        sf.push(sf_new.pop());
-                    }
-                    return MethodCallResult::SUCCESS;
-                },
-                MethodCallResult::EXCEPTION(e) => {
-//                    let frame = self.name.replace("/", ".") + "." + method_name + "()";
-//                    e.lock().unwrap().execute_method(sf, &"addStackFrame".to_string(), e.clone(), vec![Arc::new(Mutex::new(NativeStringInstance::new(frame) ))]);
-                    return MethodCallResult::EXCEPTION(e.clone());
-                }
+        // Static synchronized methods use the monitor of the class
+        let class_id = self as *const BytecodeClass as usize;
+        let result = self.execute_synchronized(&mut sf_new, method, class_id);
+        if let MethodCallResult::SUCCESS = result {
+            if sf_new.return_arg {
+                sf.push_value(sf_new.pop_value());
             }
-        } else {
-            let superclass = get_class(&self.superclass_name);
-            if get_debug() >= 1 { println!("Execute static method {}.{}(<{} arguments>)", superclass.get_name(), method_name, nb_args); }
-
-            return superclass.execute_static_method(sf, method_name, nb_args);
         }
+        result
     }
 
-    fn get_static_object(&self, field_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
-        return self.static_fields.lock().unwrap().get(field_name).unwrap().clone();
+    fn get_static_object(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
+        match self.static_fields.lock().unwrap().get(field_name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name)))
+        }
     }
 
-    fn put_static_object(&self, field_name: &String, value: Arc<Mutex<dyn JavaInstance>>) {
+    fn put_static_object(&self, field_name: &String, value: Arc<Mutex<dyn JavaInstance>>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+        if !self.static_field_descriptors.contains_key(field_name) {
+            return Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name)));
+        }
         self.static_fields.lock().unwrap().insert(field_name.clone(), value.clone());
+        Ok(())
     }
 
-    fn convert_to_asm(&self, method_name: &String, arch: &str) {
-        let bytecode = match self.methods.get(method_name) {
-            Some(b) => b,
-            _ => panic!("Unknown method {} in class {}", method_name, self.get_name())
-        };
+    fn convert_to_asm(&self, method_name: &String, type_desc: &String, arch: &str, emit: &str) {
+        if self.resolve_method(method_name, type_desc).is_none() {
+            panic!("Unknown method {}{} in class {}", method_name, type_desc, self.get_name());
+        }
 
         let arch = match arch {
             "linux" => Arch::LinuxX64,
@@ -211,13 +234,93 @@ impl JavaClass for BytecodeClass {
             _ => panic!("Unsupported architecture: {}", arch)
         };
 
-        bytecode_to_asm(&self.get_name(), bytecode, arch);
+        let emit = match emit {
+            "asm" => Emit::Asm,
+            "exe" => Emit::Exe,
+            _ => panic!("Unsupported output: {}", emit)
+        };
+
+        class_to_asm(&self.get_name(), method_name, type_desc, arch, emit);
+    }
+
+    fn convert_method_to_asm(&self, method_name: &String, type_desc: &String, assembly: &mut Assembly) {
+        match self.resolve_method(method_name, type_desc) {
+            Some(handle) => assembly.add_method(&self.get_name(), &self.methods[handle]),
+            None => panic!("Unknown method {}{} in class {}", method_name, type_desc, self.get_name())
+        }
+    }
+
+    // Sorted, so that the layout of the objects does not depend on the order of the class file
+    fn get_instance_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.fields.keys().cloned().collect();
+        fields.sort();
+        fields
+    }
+
+    fn get_field_descriptor(&self, field_name: &String, is_static: bool) -> Option<String> {
+        let fields = if is_static { &self.static_field_descriptors } else { &self.fields };
+        fields.get(field_name).cloned()
+    }
+}
+
+// Interfaces implemented by a class or extended by an interface, directly or not
+fn get_superinterfaces(class: &Arc<dyn JavaClass>) -> Result<Vec<String>, Arc<Mutex<dyn JavaInstance>>> {
+    let mut superinterfaces: Vec<String> = Vec::new();
+    let mut pending = class.get_interfaces();
+    while let Some(interface_name) = pending.pop() {
+        if superinterfaces.contains(&interface_name) {
+            continue;
+        }
+        pending.extend(vm::load_class(&interface_name)?.get_interfaces());
+        superinterfaces.push(interface_name);
+    }
+    Ok(superinterfaces)
+}
+
+// Method selection (JVMS §5.4.6) of a method which none of the superclasses declares: the maximally-specific
+// method of the superinterfaces, i.e. a default method which no other of them overrides
+pub fn select_default_method(class: &dyn JavaClass, method_name: &String, type_desc: &String) -> Result<Option<(Arc<dyn JavaClass>, usize)>, Arc<Mutex<dyn JavaInstance>>> {
+    let mut interfaces = class.get_interfaces();
+    let mut current = class.get_parent();
+    while !current.is_empty() {
+        let superclass = get_class(&current);
+        if superclass.resolve_method(method_name, type_desc).is_some() {
+            return Ok(None);
+        }
+        interfaces.extend(superclass.get_interfaces());
+        current = superclass.get_parent();
+    }
+
+    // Interfaces declaring the method, with their superinterfaces
+    let mut candidates: Vec<(Arc<dyn JavaClass>, usize, Vec<String>)> = Vec::new();
+    for interface_name in interfaces {
+        let mut declaring = get_superinterfaces(&vm::load_class(&interface_name)?)?;
+        declaring.push(interface_name);
+        for interface_name in declaring {
+            let interface = vm::load_class(&interface_name)?;
+            if let Some(handle) = interface.resolve_method(method_name, type_desc) {
+                if !candidates.iter().any(|(candidate, _, _)| candidate.get_name() == interface_name) {
+                    let superinterfaces = get_superinterfaces(&interface)?;
+                    candidates.push((interface, handle, superinterfaces));
+                }
+            }
+        }
+    }
+
+    let maximally_specific: Vec<_> = candidates.iter()
+        .filter(|(interface, _, _)| !candidates.iter().any(|(_, _, superinterfaces)| superinterfaces.contains(&interface.get_name())))
+        .collect();
+    match maximally_specific[..] {
+        [] => Ok(None),
+        [(interface, handle, _)] => Ok(Some((interface.clone(), *handle))),
+        _ => Err(new_java_exception(&"java/lang/IncompatibleClassChangeError".to_string(),
+            &format!("Conflicting default methods: {}", maximally_specific.iter().map(|(interface, _, _)| format!("{}.{}", interface.get_name(), method_name)).collect::<Vec<String>>().join(" "))))
     }
 }
 
 impl BytecodeClass {
-    pub fn parse (name: &String) -> BytecodeClass {
-        let mut data = Blob::new(&(name.to_owned() + &String::from(".class")));
+    pub fn parse (data: Vec<u8>) -> BytecodeClass {
+        let mut data = Blob::new(data);
         if get_debug() >= 3 { data.print(); }
         data.skip(8);
 
@@ -276,6 +379,7 @@ impl BytecodeClass {
                     let constant_double = ConstantDouble::new(&mut data);
                     if get_debug() >= 2 { print!("#{}  ", constant_idx); constant_double.print(); }
                     constants_double.insert(constant_idx, constant_double);
+                    constant_idx += 1;
                 },
                 // CONSTANT_Class
                 7 => {
@@ -386,13 +490,19 @@ impl BytecodeClass {
         // interfaces_count
         let interfaces_count = data.get_u16size();
         if get_debug() >= 2 { println!("{} interfaces", interfaces_count); }
+        let mut interfaces: Vec<String> = Vec::new();
         for _ in 0..interfaces_count {
-            let interface_method_idx = data.get_u16size();
-            if get_debug() >= 2 { println!("  - {}", interface_method_idx); }
+            let interface_idx = data.get_u16size();
+            match constants_class.get(&interface_idx) {
+                Some(class) => interfaces.push(class.name.clone()),
+                _ => panic!("Unknown class ID {}", interface_idx)
+            };
+            if get_debug() >= 2 { println!("  - {}", interfaces[interfaces.len() - 1]); }
         }
 
         let static_fields: Arc<Mutex<HashMap<String, Arc<Mutex<dyn JavaInstance>>>>> = Arc::new(Mutex::new(HashMap::new()));
         let mut fields: HashMap<String, String> = HashMap::new();
+        let mut static_field_descriptors: HashMap<String, String> = HashMap::new();
 
         // fields_count
         let fields_count = data.get_u16size();
@@ -415,24 +525,15 @@ impl BytecodeClass {
             if (field_access_flag & 8) == 8 {
                 match constants_string.get(&field_descriptor_idx) {
                     Some(string) => {
-                        match &string.value[0..1] {
-                            "I" => static_fields.lock().unwrap().insert(field_name, Arc::new(Mutex::new(NativeIntegerInstance::new(0)))),
-                            "F" => static_fields.lock().unwrap().insert(field_name, Arc::new(Mutex::new(NativeFloatInstance::new(0.0)))),
-                            _ => static_fields.lock().unwrap().insert(field_name, Arc::new(Mutex::new(NativeNullInstance {})))
-                        }
+                        static_fields.lock().unwrap().insert(field_name.clone(), get_default_value(&string.value));
+                        static_field_descriptors.insert(field_name, string.value.clone());
                     },
                     _ => panic!("Unknown string index {}", field_descriptor_idx)
                 };
-            // normal fields
+            // normal fields, with their descriptor
             } else {
                 match constants_string.get(&field_descriptor_idx) {
-                    Some(string) => {
-                        if string.value.starts_with("L") && string.value.ends_with(";") {
-                            fields.insert(field_name, string.value[1..string.value.len()-1].to_string());
-                        } else if string.value.starts_with("[") {
-                            fields.insert(field_name, "java.lang.ArrayList".to_string());
-                        }
-                    },
+                    Some(string) => fields.insert(field_name, string.value.clone()),
                     _ => panic!("Unknown string index {}", field_descriptor_idx)
                 };
             }
@@ -453,10 +554,12 @@ impl BytecodeClass {
 
         // methods_count
         let methods_count = data.get_u16size();
-        let mut methods: HashMap<String, ByteCode> = HashMap::new();
+        let mut methods: Vec<ByteCode> = Vec::new();
+        let mut method_index: HashMap<String, usize> = HashMap::new();
+        let mut native_methods: HashSet<String> = HashSet::new();
 
         for _ in 0..methods_count {
-            let _method_access_flag = data.get_u16size();
+            let method_access_flag = data.get_u16size();
 
             let method_idx = data.get_u16size();
             let method_name = match constants_string.get(&method_idx) {
@@ -474,6 +577,8 @@ impl BytecodeClass {
 
             let attributes_count = data.get_u16size();
             if get_debug() >= 2 { println!("  Descriptor {}, {} attribute(s)", descriptor_name, attributes_count); }
+            // ACC_NATIVE methods have no code
+            if (method_access_flag & 0x100) == 0x100 { native_methods.insert(get_method_key(&method_name, &descriptor_name)); }
 
             for _ in 0..attributes_count {
                 let attribute_name_idx = data.get_u16size();
@@ -486,7 +591,8 @@ impl BytecodeClass {
                 if get_debug() >= 2 { println!("    Method attribute {} (size: {})", attribute_name, attribute_size); }
 
                 if attribute_name.eq("Code") {
-                    data.skip(4);
+                    let max_stack = data.get_u16size();
+                    let max_locals = data.get_u16size();
                     let mut code = data.get_blob();
                     let code_size = code.data.len();
                     if get_debug() >= 2 {
@@ -501,6 +607,12 @@ impl BytecodeClass {
                         &constants_string_ref, &constants_method, &constants_field, &constants_name_type,
                         &constants_dynamic, &constants_integer, &constants_long, &constants_float, &constants_double,
                         &constant_class.name);
+                    // ACC_SYNCHRONIZED
+                    bytecode.is_synchronized = (method_access_flag & 0x20) == 0x20;
+                    // ACC_STATIC
+                    bytecode.is_static = (method_access_flag & 0x08) == 0x08;
+                    bytecode.max_stack = max_stack;
+                    bytecode.max_locals = max_locals;
 
                     let exceptions_count = data.get_u16size();
                     for _ in 0..exceptions_count {
@@ -508,15 +620,17 @@ impl BytecodeClass {
                         let end_pc = data.get_u16size();
                         let handler_pc = data.get_u16size();
                         let catch_type = data.get_u16size();
+                        // catch_type 0 is a catch-all handler, e.g. for finally blocks
                         let name = match constants_class.get(&&catch_type) {
-                            Some(class) => class.name.clone(),
+                            Some(class) => Some(class.name.clone()),
+                            _ if catch_type == 0 => None,
                             _ => panic!("Unknown class index {}", catch_type)
                         };
                         if get_debug() >= 2 {
-                            println!("    Method exception [{}..{}] -> {}, type={}", start_pc, end_pc, handler_pc, name);
+                            println!("    Method exception [{}..{}[ -> {}, type={}", start_pc, end_pc, handler_pc, name.as_deref().unwrap_or("any"));
                         }
 
-                        bytecode.add_exception(start_pc, end_pc, handler_pc, &name);
+                        bytecode.add_exception(start_pc, end_pc, handler_pc, name);
                     }
 
                     let attributes_count = data.get_u16size();
@@ -542,7 +656,11 @@ impl BytecodeClass {
                         }
                     }
 
-                    methods.insert(method_name.clone(), bytecode);
+                    bytecode.method_name = method_name.clone();
+                    bytecode.type_desc = descriptor_name.clone();
+                    bytecode.argument_sizes = get_argument_sizes(&descriptor_name);
+                    method_index.insert(get_method_key(&method_name, &descriptor_name), methods.len());
+                    methods.push(bytecode);
                 } else {
                     data.skip(attribute_size);
                 }
@@ -588,6 +706,7 @@ impl BytecodeClass {
         BytecodeClass {
             name: constant_class.name.clone(),
             superclass_name,
+            interfaces,
             constants_class,
             constants_string,
             constants_string_ref,
@@ -597,105 +716,168 @@ impl BytecodeClass {
             constants_method_handle,
             constants_dynamic,
             bootstrap_methods,
-            methods: Rc::new(methods),
+            methods,
+            method_index,
+            native_methods,
             fields,
+            static_field_descriptors,
             static_fields,
             has_static_init,
             source_file
         }
     }
 
-    fn execute_bytecode(&self, sf: &mut StackFrame, method_name: &String) -> MethodCallResult {
-        let bytecode = match self.methods.get(method_name) {
-            Some(method) => method,
-            _ => panic!("Unknown method {} in class {}", method_name, self.name)
-        };
+    // Native methods call the function registered in the VM, and throw an UnsatisfiedLinkError without one
+    fn execute_native_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Option<Arc<Mutex<dyn JavaInstance>>>, args: Vec<Value>) -> MethodCallResult {
+        if get_debug() >= 1 { println!("Execute native method {}.{}{}(<{} arguments>)", self.get_name(), method_name, type_desc, args.len()); }
+
+        match vm::current().get_native_method(&self.name, method_name, type_desc) {
+            Some(function) => call_native_function(&function, sf, type_desc, this, args),
+            None => MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/UnsatisfiedLinkError".to_string(), &format!("{}.{}{}", self.name, method_name, type_desc)))
+        }
+    }
+
+    // Synchronized methods hold the monitor while they run, and release it even if an exception is thrown
+    fn execute_synchronized(&self, sf: &mut StackFrame, bytecode: &ByteCode, monitor_id: usize) -> MethodCallResult {
+        if !bytecode.is_synchronized {
+            return self.execute_bytecode(sf, bytecode);
+        }
+
+        monitor_enter(monitor_id);
+        let result = self.execute_bytecode(sf, bytecode);
+        monitor_exit(monitor_id);
+        result
+    }
 
+    // The debug level is checked once per call, so that the dispatch loop does not pay for tracing
+    fn execute_bytecode(&self, sf: &mut StackFrame, bytecode: &ByteCode) -> MethodCallResult {
+        if get_debug() >= 1 {
+            self.trace_bytecode(sf, bytecode)
+        } else {
+            self.run_bytecode(sf, bytecode)
+        }
+    }
+
+    fn run_bytecode(&self, sf: &mut StackFrame, bytecode: &ByteCode) -> MethodCallResult {
         let mut instr_idx: usize = 0;
+        // The exception which already got a stack frame for this method (e.g. rethrown by a finally block)
+        let mut exc_framed: Option<Arc<Mutex<dyn JavaInstance>>> = None;
+
+        // Invocations and back-edges make the method hot, and it then continues as compiled code
+        let mut check_jit = true;
 
         loop {
-            match bytecode.instructions.get(instr_idx) {
-                Some(instr) => {
-                    if get_debug() >= 1 {
-                        print!("Execute {} ", instr_idx);
-                        instr.print();
-                    }
-                    match instr.execute(sf) {
-                        InstrNextAction::NEXT => {
-                            instr_idx += 1;
-                        },
-                        InstrNextAction::GOTO(idx) => {
-                            instr_idx = idx;
-                        },
-                        InstrNextAction::RETURN => {
-                            if get_debug() >= 1 { sf.print_stack(); }
-                            return MethodCallResult::SUCCESS;
-                        },
-                        InstrNextAction::EXCEPTION(exc_thrown) => {
-                            let mut exception_caught = false;
-                            let exc_thrown_name = exc_thrown.lock().unwrap().get_class_name();
-                            if get_debug() >= 1 { println!("Exception {} caught! at instruction {}", exc_thrown_name, instr_idx); }
-
-                            let mut line_nb: usize = 0;
-                            for (instr, line) in bytecode.line_number_table.iter() {
-                                if *instr > instr_idx { break; }
-                                line_nb = *line;
-                            }
+            let compiled = if check_jit { check_jit = false; jit::get_hot_code(bytecode) } else { None };
+            let action = match compiled {
+                Some(code) => code.execute(sf, bytecode, &mut instr_idx),
+                None => match bytecode.ops[instr_idx] {
+                    Op::Instr => bytecode.instructions[instr_idx].execute(sf),
+                    op => op.execute(sf)
+                }
+            };
+            match action {
+                InstrNextAction::NEXT => instr_idx += 1,
+                InstrNextAction::GOTO(idx) => {
+                    check_jit = idx <= instr_idx;
+                    instr_idx = idx;
+                },
+                InstrNextAction::RETURN => return MethodCallResult::SUCCESS,
+                InstrNextAction::EXCEPTION(exc_thrown) => match self.catch_exception(sf, bytecode, &exc_thrown, instr_idx, &mut exc_framed) {
+                    Some(handler_idx) => instr_idx = handler_idx,
+                    None => return MethodCallResult::EXCEPTION(exc_thrown)
+                }
+            }
+        }
+    }
 
-                            let frame = format!("{}.{}({}:{})", self.name.replace("/", "."), method_name, self.source_file, line_nb);
-                            exc_thrown.lock().unwrap().execute_method(sf, &"addStackFrame".to_string(), exc_thrown.clone(), vec![Arc::new(Mutex::new(NativeStringInstance::new(frame) ))]);
-
-                            for exc_caught in bytecode.exceptions.iter() {
-                                match exc_caught.catches(exc_thrown.clone(), instr_idx) {
-                                    Some(handler_pc) => {
-                                        instr_idx = handler_pc;
-                                        sf.push(exc_thrown.clone());
-                                        if get_debug() >= 1 { println!("Exception caught. Jumping to instruction {}", instr_idx); }
-                                        exception_caught = true;
-                                        break;
-                                    },
-                                    None => {}
-                                };
-                            }
+    // Same as run_bytecode(), but prints each instruction before running it
+    fn trace_bytecode(&self, sf: &mut StackFrame, bytecode: &ByteCode) -> MethodCallResult {
+        let mut instr_idx: usize = 0;
+        let mut exc_framed: Option<Arc<Mutex<dyn JavaInstance>>> = None;
 
-                            if !exception_caught {
-                                return MethodCallResult::EXCEPTION(exc_thrown);
-                            }
-                        }
-                    }
-                },
+        loop {
+            let instr = match bytecode.instructions.get(instr_idx) {
+                Some(instr) => instr,
                 _ => panic!("No instruction {}", instr_idx)
+            };
+            print!("Execute {} ", instr_idx);
+            instr.print();
+
+            match instr.execute(sf) {
+                InstrNextAction::NEXT => instr_idx += 1,
+                InstrNextAction::GOTO(idx) => instr_idx = idx,
+                InstrNextAction::RETURN => {
+                    sf.print_stack();
+                    return MethodCallResult::SUCCESS;
+                },
+                InstrNextAction::EXCEPTION(exc_thrown) => match self.catch_exception(sf, bytecode, &exc_thrown, instr_idx, &mut exc_framed) {
+                    Some(handler_idx) => instr_idx = handler_idx,
+                    None => return MethodCallResult::EXCEPTION(exc_thrown)
+                }
+            }
+        }
+    }
+
+    // Adds the stack frame of this method to the exception, and returns the instruction of its handler, if any
+    fn catch_exception(&self, sf: &mut StackFrame, bytecode: &ByteCode, exc_thrown: &Arc<Mutex<dyn JavaInstance>>, instr_idx: usize,
+        exc_framed: &mut Option<Arc<Mutex<dyn JavaInstance>>>) -> Option<usize> {
+        let exc_thrown_name = exc_thrown.lock().unwrap().get_class_name();
+        if get_debug() >= 1 { println!("Exception {} caught! at instruction {}", exc_thrown_name, instr_idx); }
+
+        let mut line_nb: usize = 0;
+        for (instr, line) in bytecode.line_number_table.iter() {
+            if *instr > instr_idx { break; }
+            line_nb = *line;
+        }
+
+        let is_framed = match exc_framed {
+            Some(e) => Arc::ptr_eq(e, exc_thrown),
+            None => false
+        };
+        if !is_framed {
+            let frame = format!("{}.{}({}:{})", self.name.replace("/", "."), bytecode.method_name, self.source_file, line_nb);
+            // Exceptions defined in bytecode inherit addStackFrame() from the native Throwable
+            let exc_class = get_class(&exc_thrown_name);
+            exc_class.execute_method(sf, &"addStackFrame".to_string(), &"(Ljava/lang/String;)V".to_string(), exc_thrown.clone(), vec![Value::Reference(Arc::new(Mutex::new(NativeStringInstance::new(frame))))]);
+            *exc_framed = Some(exc_thrown.clone());
+        }
+
+        for exc_caught in bytecode.exceptions.iter() {
+            if let Some(handler_pc) = exc_caught.catches(exc_thrown.clone(), instr_idx) {
+                sf.clear_stack();
+                sf.push(exc_thrown.clone());
+                if get_debug() >= 1 { println!("Exception caught. Jumping to instruction {}", handler_pc); }
+                return Some(handler_pc);
             }
         }
+        None
     }
 
 }
 
+// The initial value of a field, given its descriptor: 0 for numbers and booleans, null for references
+fn get_default_value(descriptor: &String) -> Arc<Mutex<dyn JavaInstance>> {
+    match &descriptor[0..1] {
+        "I" | "Z" | "B" | "C" | "S" => Arc::new(Mutex::new(NativeIntegerInstance::new(0))),
+        "J" => Arc::new(Mutex::new(NativeLongInstance::new(0))),
+        "F" => Arc::new(Mutex::new(NativeFloatInstance::new(0.0))),
+        "D" => Arc::new(Mutex::new(NativeDoubleInstance::new(0.0))),
+        _ => null_instance()
+    }
+}
+
+// Methods are identified by their name and descriptor (e.g. "foo(I)V"), so that overloads do not collide
+pub fn get_method_key(method_name: &String, type_desc: &String) -> String {
+    format!("{}{}", method_name, type_desc)
+}
+
 pub struct Blob {
     offset: usize,
     data: Vec<u8>
 }
 
 impl Blob {
-    pub fn new (name: &str) -> Blob {
-        let mut filename = &name.to_string();
-        let mut new_path: String = String::from("java/");
-        new_path.push_str(filename);
-        let filename2 = new_path.clone();
-
-        if !Path::new(filename).exists() {
-            if !Path::new(&new_path).exists() {
-                panic!("Cannot find {}", name);
-            }
-
-            filename = &filename2;
-        }
-
-        let mut f = File::open(filename).expect("no file found");
-        let metadata = fs::metadata(filename).expect("unable to read metadata");
-        let mut data = vec![0; metadata.len() as usize];
-        f.read(&mut data).expect("buffer overflow");
-
+    pub fn new (data: Vec<u8>) -> Blob {
         Blob {
             offset: 0,
             data: data
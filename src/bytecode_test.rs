@@ -2,16 +2,21 @@
 mod tests {
     use std::{sync::{Arc, Mutex}};
 
-    use crate::{bytecode::{ByteCodeInstruction, InstrIAdd, InstrIShl, InstrIShr, InstrIUShr, InstrLShl, InstrLShr, InstrLUShr}, jvm::{JavaInstance, StackFrame}, native_java_classes::{NativeNullInstance}};
+    use crate::{bytecode::{ByteCodeInstruction, Exception, InstrDup2X2, InstrInvokeVirtual, InstrIAdd, InstrIShl, InstrIShr, InstrIUShr, InstrLShl, InstrLShr, InstrLUShr, InstrPop2}, jvm::{JavaInstance, StackFrame, Value}, native_java_classes::{NativeIntegerInstance, NativeNullInstance}, vm, ClassPath, JavaException, JavaValue, Vm};
 
     fn get_stack_frame() -> StackFrame {
-        let var = Arc::new(Mutex::new(NativeNullInstance {}));
-        let variables: [Arc<Mutex<dyn JavaInstance>>; 16] = [var.clone(), var.clone(), var.clone(), var.clone(),
-            var.clone(), var.clone(), var.clone(), var.clone(),
-            var.clone(), var.clone(), var.clone(), var.clone(),
-            var.clone(), var.clone(), var.clone(), var.clone()];
+        StackFrame::new(16, 16)
+    }
+
+    // Calls a static method of one of the Java classes of tests/java
+    fn invoke_static(class_name: &str, method_name: &str, type_desc: &str, args: &[JavaValue]) -> JavaValue {
+        try_invoke_static(class_name, method_name, type_desc, args).unwrap()
+    }
 
-            StackFrame::new(variables)
+    fn try_invoke_static(class_name: &str, method_name: &str, type_desc: &str, args: &[JavaValue]) -> Result<JavaValue, JavaException> {
+        let vm = Vm::new(ClassPath::parse("tests/java"), 0);
+        let class = vm.load_class(class_name).unwrap();
+        class.invoke_static(method_name, type_desc, args)
     }
 
     #[test]
@@ -104,4 +109,206 @@ mod tests {
         let result = sf.pop_long();
         assert_eq!(result, -42);
     }
+
+    #[test]
+    fn test_catch_all_exception_range() {
+        let exception: Arc<Mutex<dyn JavaInstance>> = Arc::new(Mutex::new(NativeNullInstance {}));
+        let finally = Exception { start_pc: 2, end_pc: 5, handler_pc: 7, name: None };
+
+        assert_eq!(finally.catches(exception.clone(), 1), None);
+        assert_eq!(finally.catches(exception.clone(), 2), Some(7));
+        assert_eq!(finally.catches(exception.clone(), 4), Some(7));
+        assert_eq!(finally.catches(exception.clone(), 5), None);
+    }
+
+    #[test]
+    fn test_instr_dup2_category2() {
+        let mut sf = get_stack_frame();
+        sf.push_int(1);
+        sf.push_long(2);
+        sf.push_long(3);
+
+        // Form 4 of dup2_x2: two longs
+        let instr = InstrDup2X2 {};
+        instr.execute(&mut sf);
+        assert_eq!(sf.pop_long(), 3);
+        assert_eq!(sf.pop_long(), 2);
+        assert_eq!(sf.pop_long(), 3);
+
+        // pop2 removes a single long, or two ints
+        sf.push_long(4);
+        let instr = InstrPop2 {};
+        instr.execute(&mut sf);
+        assert_eq!(sf.pop_int(), 1);
+
+        sf.push_int(5);
+        sf.push_int(6);
+        sf.push_int(7);
+        instr.execute(&mut sf);
+        assert_eq!(sf.pop_int(), 5);
+    }
+
+    #[test]
+    fn test_narrowing_conversions() {
+        assert_eq!(invoke_static("Conversions", "toShort", "(I)I", &[32768.into()]), JavaValue::Int(-32768));
+        assert_eq!(invoke_static("Conversions", "toShort", "(I)I", &[(-32769).into()]), JavaValue::Int(32767));
+        assert_eq!(invoke_static("Conversions", "toChar", "(I)I", &[(-1).into()]), JavaValue::Int(65535));
+        assert_eq!(invoke_static("Conversions", "toByte", "(I)I", &[200.into()]), JavaValue::Int(-56));
+    }
+
+    #[test]
+    fn test_new_array_default_values() {
+        assert_eq!(invoke_static("Arrays", "addToLong", "(J)J", &[7i64.into()]), JavaValue::Long(7));
+        assert_eq!(invoke_static("Arrays", "addToDouble", "(D)D", &[1.5f64.into()]), JavaValue::Double(1.5));
+        assert_eq!(invoke_static("Arrays", "addToFloat", "(F)F", &[2.5f32.into()]), JavaValue::Float(2.5));
+        assert_eq!(invoke_static("Arrays", "sumDefaults", "()I", &[]), JavaValue::Int(0));
+    }
+
+    #[test]
+    fn test_inherited_static_fields() {
+        assert_eq!(invoke_static("StaticFields", "inherited", "()Ljava/lang/String;", &[]), JavaValue::String("StaticBase StaticConstants 2 10".to_string()));
+    }
+
+    #[test]
+    fn test_faults() {
+        let fault = |method_name: &str, type_desc: &str, args: &[JavaValue]| try_invoke_static("Faults", method_name, type_desc, args).unwrap_err().to_string();
+        assert_eq!(fault("callOnNull", "()I", &[]), "java.lang.NullPointerException: Cannot invoke java/lang/String.length() on a null object");
+        assert_eq!(fault("fieldOfNull", "()I", &[]), "java.lang.NullPointerException: Cannot read field count of a null object");
+        assert_eq!(fault("lengthOfNull", "()I", &[]), "java.lang.NullPointerException: Cannot read the array length of a null array");
+        assert_eq!(fault("elementOfNull", "()I", &[]), "java.lang.NullPointerException: Cannot access an element of a null array");
+        assert_eq!(fault("throwNull", "()V", &[]), "java.lang.NullPointerException: Cannot throw a null exception");
+        assert_eq!(fault("arrayIndex", "(I)I", &[5.into()]), "java.lang.ArrayIndexOutOfBoundsException: Index 5 out of bounds for length 2");
+        assert!(fault("castInteger", "()Ljava/lang/String;", &[]).starts_with("java.lang.ClassCastException: "));
+        assert_eq!(fault("negativeSize", "(I)I", &[(-1).into()]), "java.lang.NegativeArraySizeException: -1");
+        assert_eq!(fault("removedField", "()I", &[]), "java.lang.NoSuchFieldError: FaultsHolder.removed");
+        assert_eq!(invoke_static("Faults", "caught", "()Ljava/lang/String;", &[]), JavaValue::String("NPE Index -1 out of bounds for length 2 CCE -3 NPE".to_string()));
+    }
+
+    #[test]
+    fn test_class_initialization() {
+        let order = invoke_static("Initialization", "order", "(Z)Ljava/lang/String;", &[false.into()]);
+        assert_eq!(order, JavaValue::String("InitBase InitSub 6 42 2".to_string()));
+    }
+
+    #[test]
+    fn test_lazy_loading() {
+        let missing = invoke_static("Initialization", "missingClass", "(Z)Ljava/lang/String;", &[false.into()]);
+        assert_eq!(missing, JavaValue::String("not loaded".to_string()));
+    }
+
+    #[test]
+    fn test_initialization_errors() {
+        let failed = invoke_static("Initialization", "failedInitialization", "()Ljava/lang/String;", &[]);
+        assert_eq!(failed, JavaValue::String("EIIE caused by true boom, NCDFE Could not initialize class InitBoom".to_string()));
+        let caught = invoke_static("Initialization", "caughtMissingClass", "()Ljava/lang/String;", &[]);
+        assert_eq!(caught, JavaValue::String("NCDFE InitMissing caused by true InitMissing".to_string()));
+
+        // Uncaught, with the same exception chains
+        let vm = Vm::new(ClassPath::parse("tests/java"), 0);
+        let class = vm.load_class("InitBoom").unwrap();
+        let exception = class.get_static_field("value").unwrap_err();
+        assert_eq!(exception.get_class_name(), "java/lang/ExceptionInInitializerError");
+        assert_eq!(exception.get_cause().unwrap().to_string(), "java.lang.IllegalStateException: boom");
+        let exception = class.get_static_field("value").unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.NoClassDefFoundError: Could not initialize class InitBoom");
+
+        let exception = try_invoke_static("Initialization", "missingClass", "(Z)Ljava/lang/String;", &[true.into()]).unwrap_err();
+        assert_eq!(exception.to_string(), "java.lang.NoClassDefFoundError: InitMissing");
+        assert_eq!(exception.get_cause().unwrap().to_string(), "java.lang.ClassNotFoundException: InitMissing");
+    }
+
+    #[test]
+    fn test_default_methods() {
+        let greetings = invoke_static("Defaults", "greetAll", "()Ljava/lang/String;", &[]);
+        assert_eq!(greetings, JavaValue::String("Hello plain, Hello loud!, Hi from Hello overriding, Hello plain!".to_string()));
+        assert_eq!(invoke_static("Defaults", "thank", "()Ljava/lang/String;", &[]), JavaValue::String("Thanks loud".to_string()));
+    }
+
+    #[test]
+    fn test_inline_cache() {
+        assert_eq!(invoke_static("Dispatch", "mixed", "()I", &[]), JavaValue::Int(127));
+
+        // The call site keeps the method selected for the class of its first object, Shape.twice() for a Square
+        let vm = Vm::new(ClassPath::parse("tests/java"), 0);
+        let _entered = vm.enter();
+        let square = vm::get_class("Square").new();
+        let triangle = vm::get_class("Triangle").new();
+        let instr = InstrInvokeVirtual::new(&"Shape".to_string(), &"twice".to_string(), &"()I".to_string());
+        let mut sf = get_stack_frame();
+        for (object, expected) in [(square.clone(), 8), (triangle, 103), (square, 8)] {
+            sf.push(object);
+            instr.execute(&mut sf);
+            assert_eq!(sf.pop_int(), expected);
+            assert_eq!(instr.get_cached_receiver(), Some("Square".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_exception_propagation() {
+        let fault = |method_name: &str, type_desc: &str| try_invoke_static("Propagation", method_name, type_desc, &[]).unwrap_err().to_string();
+        assert_eq!(fault("virtualCall", "()I"), "java.lang.RuntimeException: virtual 1");
+        assert_eq!(fault("specialCall", "()I"), "java.lang.ArithmeticException: private");
+        assert_eq!(fault("constructor", "()LPropagation;"), "java.lang.IllegalStateException: constructor");
+        assert_eq!(fault("interfaceCall", "()I"), "java.lang.IllegalArgumentException: interface 2");
+        assert_eq!(fault("nativeCall", "()I"), "java.lang.NumberFormatException: For input string: \"two\"");
+        let caught = invoke_static("Propagation", "caught", "()Ljava/lang/String;", &[]);
+        assert_eq!(caught, JavaValue::String("virtual 1, private, constructor, interface 2, native".to_string()));
+    }
+
+    #[test]
+    fn test_frame_sizes() {
+        let args = [1.into(), 2i64.into(), 3.5f64.into(), 4.5f32.into(), 5i64.into(), 6.into()];
+        assert_eq!(invoke_static("Locals", "mix", "(IJDFJI)D", &args), JavaValue::Double(22.0));
+        assert_eq!(invoke_static("Locals", "manyLocals", "(I)I", &[10.into()]), JavaValue::Int(306));
+        assert_eq!(invoke_static("Locals", "instanceCall", "()J", &[]), JavaValue::Long(120));
+    }
+
+    #[test]
+    fn test_tagged_values() {
+        // Primitives stay unboxed on the operand stack and in the local variables
+        let mut sf = StackFrame::new(4, 4);
+        sf.push_long(1 << 40);
+        sf.stack_to_variable(2);
+        sf.push_double(2.5);
+        sf.stack_to_variable(0);
+        sf.variable_to_stack(2);
+        sf.variable_to_stack(0);
+        assert!(matches!(sf.pop_value(), Value::Double(value) if value == 2.5));
+        assert!(matches!(sf.pop_value(), Value::Long(value) if value == 1 << 40));
+        assert!(Value::Long(0).is_category2() && Value::Double(0.0).is_category2() && !Value::Int(0).is_category2());
+        sf.set_variable(3, Value::ReturnAddress(7));
+        assert_eq!(sf.get_return_address(3), 7);
+
+        // Only references are objects, and primitive objects of fields and arrays get unboxed
+        assert!(matches!(Value::from_object(Arc::new(Mutex::new(NativeIntegerInstance::new(3)))), Value::Int(3)));
+        assert!(matches!(Value::from_object(Value::Float(1.5).to_object()), Value::Float(value) if value == 1.5));
+        assert!(Value::null().is_null() && !Value::Int(0).is_null());
+        // There is a single null object
+        assert!(matches!((Value::null(), Value::null()), (Value::Reference(a), Value::Reference(b)) if Arc::ptr_eq(&a, &b)));
+    }
+
+    #[test]
+    fn test_overloads() {
+        let f = |type_desc: &str, args: &[JavaValue]| invoke_static("Overloads", "f", type_desc, args);
+        assert_eq!(f("(I)Ljava/lang/String;", &[1.into()]), JavaValue::String("int 1".to_string()));
+        assert_eq!(f("(J)Ljava/lang/String;", &[2i64.into()]), JavaValue::String("long 2".to_string()));
+        assert_eq!(f("(D)Ljava/lang/String;", &[2.5f64.into()]), JavaValue::String("double 2.5".to_string()));
+        assert_eq!(f("(Ljava/lang/String;)Ljava/lang/String;", &["s".into()]), JavaValue::String("String s".to_string()));
+        assert_eq!(f("(II)Ljava/lang/String;", &[3.into(), 4.into()]), JavaValue::String("int,int 7".to_string()));
+        let all = invoke_static("Overloads", "all", "()Ljava/lang/String;", &[]);
+        assert_eq!(all, JavaValue::String("int 1, long 2, double 2.5, String s, Object, int,int 7, g int 5, g char c".to_string()));
+    }
+
+    #[test]
+    fn test_jit() {
+        // Each method runs a single loop past JIT_THRESHOLD back-edges, so the compiled code is entered in the middle of it
+        let hot = |method_name: &str, type_desc: &str, args: &[JavaValue]| invoke_static("HotLoops", method_name, type_desc, args);
+        assert_eq!(hot("ints", "(I)I", &[5000.into()]), JavaValue::Int(110312740));
+        assert_eq!(hot("longs", "(I)J", &[5000.into()]), JavaValue::Long(-8627828014270652779));
+        assert_eq!(hot("floats", "(I)F", &[5000.into()]), JavaValue::Float(2142.2136));
+        assert_eq!(hot("doubles", "(I)D", &[5000.into()]), JavaValue::Double(33480.561352607525));
+        assert_eq!(hot("nanCompares", "(IF)I", &[5000.into(), 0f32.into()]), JavaValue::Int(5000250));
+        assert_eq!(hot("switches", "(I)I", &[5000.into()]), JavaValue::Int(15739));
+        assert_eq!(hot("exceptions", "(I)I", &[5000.into()]), JavaValue::Int(1803193));
+    }
 }
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use zip::ZipArchive;
+
+// Where the .class files are looked for: a list of directories and .jar (zip) archives,
+// searched in order, the same way the -cp option of the java command works
+pub struct ClassPath {
+    entries: Vec<ClassPathEntry>
+}
+
+pub enum ClassPathEntry {
+    Directory(PathBuf),
+    Jar(PathBuf, Mutex<ZipArchive<File>>)
+}
+
+impl ClassPathEntry {
+    pub fn new(path: &Path) -> Option<ClassPathEntry> {
+        if path.is_dir() {
+            return Some(ClassPathEntry::Directory(path.to_path_buf()));
+        }
+
+        if !path.is_file() { return None; }
+
+        // Files which cannot be read or are not zip archives are skipped, like missing ones
+        let file = File::open(path).ok()?;
+        match ZipArchive::new(file) {
+            Ok(archive) => Some(ClassPathEntry::Jar(path.to_path_buf(), Mutex::new(archive))),
+            Err(_) => None
+        }
+    }
+
+    pub fn read_file(&self, filename: &str) -> Option<Vec<u8>> {
+        match self {
+            ClassPathEntry::Directory(dir) => {
+                let path = dir.join(filename);
+                if !path.is_file() { return None; }
+                match fs::read(&path) {
+                    Ok(data) => Some(data),
+                    Err(why) => panic!("Cannot read {}: {}", path.display(), why)
+                }
+            },
+            ClassPathEntry::Jar(jar, archive) => {
+                let mut archive = archive.lock().unwrap();
+                let mut file = match archive.by_name(filename) {
+                    Ok(f) => f,
+                    Err(_) => return None
+                };
+                let mut data: Vec<u8> = Vec::with_capacity(file.size() as usize);
+                match file.read_to_end(&mut data) {
+                    Ok(_) => Some(data),
+                    Err(why) => panic!("Cannot read {} from {}: {}", filename, jar.display(), why)
+                }
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        match self {
+            ClassPathEntry::Directory(dir) => println!("  Directory {}", dir.display()),
+            ClassPathEntry::Jar(jar, _) => println!("  Jar {}", jar.display())
+        };
+    }
+}
+
+impl ClassPath {
+    pub fn new() -> ClassPath {
+        ClassPath { entries: Vec::new() }
+    }
+
+    // Parses a list of paths separated by ':' (';' on Windows). As with java, "dir/*" stands for every .jar in dir
+    pub fn parse(paths: &str) -> ClassPath {
+        let mut classpath = ClassPath::new();
+        for path in std::env::split_paths(paths) {
+            classpath.add_path(&path);
+        }
+        classpath
+    }
+
+    pub fn add(&mut self, path: &str) {
+        self.add_path(Path::new(path));
+    }
+
+    pub fn add_path(&mut self, path: &Path) {
+        if path.file_name().map_or(false, |name| name == "*") {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            let mut jars: Vec<PathBuf> = match fs::read_dir(dir) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                    .filter(|p| is_jar(p)).collect(),
+                Err(_) => Vec::new()
+            };
+            jars.sort();
+            for jar in jars.iter() {
+                self.add_path(jar);
+            }
+            return;
+        }
+
+        // Nonexistent and unreadable entries are silently ignored, like java does
+        if let Some(entry) = ClassPathEntry::new(path) {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn entries(&self) -> &Vec<ClassPathEntry> {
+        &self.entries
+    }
+
+    // Reads a file (e.g. "com/acme/Main.class") from the first classpath entry containing it
+    pub fn read_file(&self, filename: &str) -> Option<Vec<u8>> {
+        for entry in self.entries.iter() {
+            if let Some(data) = entry.read_file(filename) {
+                return Some(data);
+            }
+        }
+        None
+    }
+
+    // Reads a class given its internal name (e.g. "com/acme/Main")
+    pub fn read_class(&self, class_name: &str) -> Option<Vec<u8>> {
+        self.read_file(&format!("{}.class", class_name))
+    }
+
+    pub fn print(&self) {
+        println!("Classpath:");
+        for entry in self.entries.iter() {
+            entry.print();
+        }
+    }
+}
+
+impl ClassPath {
+    // The classpath of an executable jar (java -jar): the jar itself followed by the
+    // entries of its manifest's Class-Path, which are relative to the jar's directory.
+    // Returns the classpath and the manifest
+    pub fn from_jar(jar: &str) -> (ClassPath, Manifest) {
+        let jar_path = Path::new(jar);
+        let entry = match ClassPathEntry::new(jar_path) {
+            Some(ClassPathEntry::Jar(path, archive)) => ClassPathEntry::Jar(path, archive),
+            _ => panic!("Unable to access jarfile {}", jar)
+        };
+
+        let manifest = match entry.read_file("META-INF/MANIFEST.MF") {
+            Some(data) => Manifest::parse(&String::from_utf8_lossy(&data)),
+            None => panic!("No manifest found in {}", jar)
+        };
+
+        let mut classpath = ClassPath::new();
+        classpath.entries.push(entry);
+
+        if let Some(class_path) = manifest.get("Class-Path") {
+            let base_dir = jar_path.parent().unwrap_or(Path::new("."));
+            for path in class_path.split_whitespace() {
+                classpath.add_path(&base_dir.join(path));
+            }
+        }
+
+        (classpath, manifest)
+    }
+}
+
+// The main section of a jar's META-INF/MANIFEST.MF: "Name: value" lines, where lines starting
+// with a single space are the continuation of the previous line (lines are limited to 72 bytes)
+pub struct Manifest {
+    attributes: HashMap<String, String>
+}
+
+impl Manifest {
+    pub fn parse(content: &str) -> Manifest {
+        let mut attributes: HashMap<String, String> = HashMap::new();
+        let mut lines: Vec<String> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim_end_matches('\r');
+            // The main section ends at the first blank line
+            if line.is_empty() { break; }
+
+            // Continuation lines without a header are ignored
+            if line.starts_with(' ') {
+                if let Some(previous) = lines.last_mut() {
+                    previous.push_str(&line[1..]);
+                }
+            } else {
+                lines.push(line.to_string());
+            }
+        }
+
+        // Malformed headers are ignored
+        for line in lines.iter() {
+            if let Some(idx) = line.find(": ") {
+                attributes.insert(line[..idx].to_string(), line[idx+2..].to_string());
+            }
+        }
+
+        Manifest { attributes }
+    }
+
+    // Attribute names are case-insensitive
+    pub fn get(&self, name: &str) -> Option<&String> {
+        for (key, value) in self.attributes.iter() {
+            if key.eq_ignore_ascii_case(name) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    pub fn get_main_class(&self) -> Option<String> {
+        self.get("Main-Class").map(|main_class| to_internal_name(main_class.trim()))
+    }
+}
+
+// The classpath used when none is specified: the current directory, then the java folder
+impl Default for ClassPath {
+    fn default() -> ClassPath {
+        let mut classpath = ClassPath::new();
+        classpath.add(".");
+        classpath.add("java");
+        classpath
+    }
+}
+
+fn is_jar(path: &Path) -> bool {
+    path.is_file() && path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("jar"))
+}
+
+// Class names can be given with dots (com.acme.Main) or slashes (com/acme/Main)
+pub fn to_internal_name(class_name: &str) -> String {
+    class_name.trim_end_matches(".class").replace(".", "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classpath::{ClassPath, Manifest, to_internal_name};
+    use crate::{JavaValue, Vm};
+
+    #[test]
+    fn test_to_internal_name() {
+        assert_eq!(to_internal_name("com.acme.Main"), "com/acme/Main");
+        assert_eq!(to_internal_name("com/acme/Main"), "com/acme/Main");
+        assert_eq!(to_internal_name("Hello.class"), "Hello");
+    }
+
+    #[test]
+    fn test_read_class_from_directory() {
+        let classpath = ClassPath::parse("does-not-exist:java");
+        assert_eq!(classpath.entries().len(), 1);
+        let data = classpath.read_class("Hello").unwrap();
+        assert_eq!(&data[0..4], &[0xca, 0xfe, 0xba, 0xbe]);
+        assert!(classpath.read_class("com/acme/Missing").is_none());
+    }
+
+    #[test]
+    fn test_read_class_from_jar() {
+        let classpath = ClassPath::parse("tests/jar/lib/greeting.jar");
+        assert_eq!(classpath.entries().len(), 1);
+        let data = classpath.read_class("jarred/Greeting").unwrap();
+        assert_eq!(&data[0..4], &[0xca, 0xfe, 0xba, 0xbe]);
+        assert!(classpath.read_class("jarred/Missing").is_none());
+
+        let vm = Vm::new(ClassPath::parse("tests/jar/lib/greeting.jar"), 0);
+        let greeting = vm.load_class("jarred.Greeting").unwrap();
+        let greeted = greeting.invoke_static("greet", "(Ljava/lang/String;)Ljava/lang/String;", &[JavaValue::from("jar")]).unwrap();
+        assert_eq!(greeted, JavaValue::String("Hello from a jar, jar".to_string()));
+    }
+
+    #[test]
+    fn test_wildcard_and_corrupt_jar() {
+        // lib/* has greeting.jar and corrupt.jar, which is not a zip archive and gets skipped
+        let classpath = ClassPath::parse("tests/jar/lib/*");
+        assert_eq!(classpath.entries().len(), 1);
+        assert!(classpath.read_class("jarred/Greeting").is_some());
+
+        let classpath = ClassPath::parse("tests/jar/lib/corrupt.jar:java");
+        assert_eq!(classpath.entries().len(), 1);
+        assert!(classpath.read_class("Hello").is_some());
+    }
+
+    #[test]
+    fn test_from_jar() {
+        // The Main-Class of app.jar is split on a continuation line, and lib/greeting.jar is relative to the jar
+        let (classpath, manifest) = ClassPath::from_jar("tests/jar/app.jar");
+        assert_eq!(manifest.get_main_class().unwrap(), "app/Main");
+        assert_eq!(manifest.get("Class-Path").unwrap(), "lib/greeting.jar");
+        assert_eq!(classpath.entries().len(), 2);
+        assert!(classpath.read_class("jarred/Greeting").is_some());
+
+        let vm = Vm::new(classpath, 0);
+        let main = vm.load_class("app/Main").unwrap();
+        let greeted = main.invoke_static("run", "(Ljava/lang/String;)Ljava/lang/String;", &[JavaValue::from("app")]).unwrap();
+        assert_eq!(greeted, JavaValue::String("Hello from a jar, app".to_string()));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse("Manifest-Version: 1.0\r\nMain-Class: com.acme.Ma\r\n in\r\nClass-Path: lib/a.jar\n  lib/b.jar\n\nName: com/acme/\nSealed: true\n");
+        assert_eq!(manifest.get_main_class().unwrap(), "com/acme/Main");
+        assert_eq!(manifest.get("class-path").unwrap(), "lib/a.jar lib/b.jar");
+        assert!(manifest.get("Sealed").is_none());
+
+        let manifest = Manifest::parse(" orphan\nMalformed\nMain-Class: Main\n");
+        assert_eq!(manifest.get_main_class().unwrap(), "Main");
+        assert!(manifest.get("Malformed").is_none());
+    }
+}
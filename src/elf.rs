@@ -0,0 +1,122 @@
+// Writer of static ELF64 executables for Linux x86-64: a read/execute segment with the headers and the code,
+// and a read/write segment with the data. Section headers are only there for tools like objdump
+use std::{fs::File, io::Write};
+
+const BASE_ADDRESS: u64 = 0x400000;
+const PAGE_SIZE: usize = 0x1000;
+// The code starts on the page after the headers
+const CODE_OFFSET: usize = PAGE_SIZE;
+pub const CODE_ADDRESS: u64 = BASE_ADDRESS + CODE_OFFSET as u64;
+
+const EHDR_SIZE: u16 = 64;
+const PHDR_SIZE: u16 = 56;
+const SHDR_SIZE: u16 = 64;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+
+fn data_offset(code_size: usize) -> usize {
+    (CODE_OFFSET + code_size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+// Address of the data, which follows the code on its own pages
+pub fn data_address(code_size: usize) -> u64 {
+    BASE_ADDRESS + data_offset(code_size) as u64
+}
+
+struct Writer { bytes: Vec<u8> }
+
+impl Writer {
+    fn u16(&mut self, value: u16) { self.bytes.extend_from_slice(&value.to_le_bytes()); }
+    fn u32(&mut self, value: u32) { self.bytes.extend_from_slice(&value.to_le_bytes()); }
+    fn u64(&mut self, value: u64) { self.bytes.extend_from_slice(&value.to_le_bytes()); }
+    fn pad_to(&mut self, offset: usize) { self.bytes.resize(offset, 0); }
+
+    fn program_header(&mut self, flags: u32, offset: usize, size: usize) {
+        self.u32(1);    // PT_LOAD
+        self.u32(flags);
+        self.u64(offset as u64);
+        self.u64(BASE_ADDRESS + offset as u64);
+        self.u64(BASE_ADDRESS + offset as u64);
+        self.u64(size as u64);
+        self.u64(size as u64);
+        self.u64(PAGE_SIZE as u64);
+    }
+
+    fn section_header(&mut self, name: u32, section_type: u32, flags: u64, address: u64, offset: usize, size: usize) {
+        self.u32(name);
+        self.u32(section_type);
+        self.u64(flags);
+        self.u64(address);
+        self.u64(offset as u64);
+        self.u64(size as u64);
+        self.u32(0);
+        self.u32(0);
+        self.u64(if flags & SHF_EXECINSTR != 0 { 16 } else { 1 });
+        self.u64(0);
+    }
+}
+
+// Writes an executable running the code from its entry offset. Addresses in the code and data must already be
+// resolved, from CODE_ADDRESS and data_address()
+pub fn write_executable(filename: &String, code: &[u8], data: &[u8], entry: usize) {
+    let data_offset = data_offset(code.len());
+    let shstrtab = b"\0.text\0.data\0.shstrtab\0";
+    let shstrtab_offset = data_offset + data.len();
+    let shdr_offset = (shstrtab_offset + shstrtab.len() + 7) / 8 * 8;
+
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);    // 64 bits, little endian, System V
+    writer.pad_to(16);
+    writer.u16(2);      // ET_EXEC
+    writer.u16(0x3e);   // EM_X86_64
+    writer.u32(1);
+    writer.u64(CODE_ADDRESS + entry as u64);
+    writer.u64(EHDR_SIZE as u64);
+    writer.u64(shdr_offset as u64);
+    writer.u32(0);
+    writer.u16(EHDR_SIZE);
+    writer.u16(PHDR_SIZE);
+    writer.u16(2);
+    writer.u16(SHDR_SIZE);
+    writer.u16(4);
+    writer.u16(3);      // Index of .shstrtab
+
+    writer.program_header(PF_R | PF_X, 0, CODE_OFFSET + code.len());
+    writer.program_header(PF_R | PF_W, data_offset, data.len());
+
+    writer.pad_to(CODE_OFFSET);
+    writer.bytes.extend_from_slice(code);
+    writer.pad_to(data_offset);
+    writer.bytes.extend_from_slice(data);
+    writer.bytes.extend_from_slice(shstrtab);
+    writer.pad_to(shdr_offset);
+
+    writer.bytes.extend_from_slice(&[0; SHDR_SIZE as usize]);
+    writer.section_header(1, SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, CODE_ADDRESS, CODE_OFFSET, code.len());
+    writer.section_header(7, SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, data_address(code.len()), data_offset, data.len());
+    writer.section_header(13, SHT_STRTAB, 0, 0, shstrtab_offset, shstrtab.len());
+
+    match File::create(filename) {
+        Err(why) => panic!("Couldn't create {}: {}", filename, why),
+        Ok(mut file) => {
+            if let Err(why) = file.write_all(&writer.bytes) {
+                panic!("Couldn't write to {}: {}", filename, why);
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                if let Err(why) = file.set_permissions(std::fs::Permissions::from_mode(0o755)) {
+                    panic!("Couldn't make {} executable: {}", filename, why);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1024 @@
+// Intermediate representation of the assembly of a method, between the conversion of its bytecode instructions
+// and the text. The operand stack and the local variables become virtual registers, the code gets optimized
+// (constant and copy propagation, constant folding, dead code elimination, peephole), and the virtual registers
+// get allocated to x64 registers by linear scan, or spilled into the stack frame
+use std::collections::{HashMap, HashSet};
+
+use crate::x64::Reg;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Loc {
+    Reg(Reg),
+    Xmm(u8),
+    Virt(usize)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Operand {
+    Loc(Loc),
+    // [base + disp]
+    Mem(Reg, i32),
+    Imm(i64),
+    Label(String)
+}
+
+#[derive(Clone, Debug)]
+pub enum Instr {
+    Label(String),
+    Op(String, Vec<Operand>)
+}
+
+const REGISTERS: [(&str, Reg); 16] = [("rax", Reg::RAX), ("rcx", Reg::RCX), ("rdx", Reg::RDX), ("rbx", Reg::RBX),
+    ("rsp", Reg::RSP), ("rbp", Reg::RBP), ("rsi", Reg::RSI), ("rdi", Reg::RDI), ("r8", Reg::R8), ("r9", Reg::R9),
+    ("r10", Reg::R10), ("r11", Reg::R11), ("r12", Reg::R12), ("r13", Reg::R13), ("r14", Reg::R14), ("r15", Reg::R15)];
+// Lower halves, only read by movsxd, and eax written by mov with a 32-bit immediate (zero extended into rax)
+const REGISTERS32: [&str; 16] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi",
+    "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d"];
+// Low bytes, the count of shifts (cl) and the source of the byte stores of the runtime
+const REGISTERS8: [&str; 16] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil",
+    "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b"];
+
+fn parse_register(name: &str) -> Option<Reg> {
+    REGISTERS.iter().find(|(reg_name, _)| *reg_name == name).map(|(_, reg)| *reg)
+        .or_else(|| REGISTERS32.iter().position(|reg_name| *reg_name == name).map(|idx| REGISTERS[idx].1))
+        .or_else(|| REGISTERS8.iter().position(|reg_name| *reg_name == name).map(|idx| REGISTERS[idx].1))
+}
+
+fn register_name(reg: Reg) -> &'static str {
+    REGISTERS[reg as usize].0
+}
+
+// Decimal, 0x hexadecimal or nasm's h suffixed hexadecimal numbers
+pub fn parse_number(number: &str) -> Option<i64> {
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, number.strip_prefix('+').unwrap_or(number))
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(hex) = digits.strip_suffix('h') {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else {
+        digits.parse::<u64>().ok()? as i64
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+fn parse_operand(operand: &str) -> Operand {
+    let operand = operand.trim();
+    let operand = ["qword ", "dword ", "byte "].iter()
+        .find_map(|size| operand.strip_prefix(size))
+        .unwrap_or(operand).trim();
+
+    if let Some(address) = operand.strip_prefix('[').and_then(|address| address.strip_suffix(']')) {
+        let (base, disp) = match address.find(['+', '-']) {
+            Some(idx) => (&address[..idx], parse_number(&address[idx..])),
+            None => (address, Some(0))
+        };
+        return match (parse_register(base.trim()), disp) {
+            (Some(base), Some(disp)) => Operand::Mem(base, disp as i32),
+            _ => panic!("Unsupported memory operand in assembly: {}", operand)
+        };
+    }
+    if let Some(float) = operand.strip_prefix("__float32__(").and_then(|float| float.strip_suffix(')')) {
+        return match float.parse::<f32>() {
+            Ok(float) => Operand::Imm(float.to_bits() as i64),
+            Err(_) => panic!("Invalid float in assembly: {}", operand)
+        };
+    }
+    if let Some(number) = parse_number(operand) {
+        return Operand::Imm(number);
+    }
+    if let Some(reg) = parse_register(operand) {
+        return Operand::Loc(Loc::Reg(reg));
+    }
+    if let Some(xmm) = operand.strip_prefix("xmm").and_then(|idx| idx.parse::<u8>().ok()) {
+        return Operand::Loc(Loc::Xmm(xmm));
+    }
+    Operand::Label(operand.to_string())
+}
+
+// Parses a line of assembly (without data directives), None if it is empty or a comment
+pub fn parse_line(line: &str) -> Option<Instr> {
+    let line = match line.find(';') { Some(idx) => &line[..idx], None => line }.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if let Some(name) = line.strip_suffix(':') {
+        return Some(Instr::Label(name.to_string()));
+    }
+    Some(match line.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => Instr::Op(mnemonic.to_string(), operands.split(',').map(parse_operand).collect()),
+        None => Instr::Op(line.to_string(), Vec::new())
+    })
+}
+
+fn is_jcc(mnemonic: &str) -> bool {
+    mnemonic.starts_with('j') && mnemonic != "jmp"
+}
+
+fn jump_target<'a>(mnemonic: &str, operands: &'a [Operand]) -> Option<&'a String> {
+    match operands {
+        [Operand::Label(target)] if mnemonic == "jmp" || is_jcc(mnemonic) => Some(target),
+        _ => None
+    }
+}
+
+// Result of a conditional jump after comparing two constants
+fn evaluate_jcc(mnemonic: &str, a: i64, b: i64) -> Option<bool> {
+    match mnemonic {
+        "je" | "jz" => Some(a == b),
+        "jne" | "jnz" => Some(a != b),
+        "jl" => Some(a < b),
+        "jge" => Some(a >= b),
+        "jle" => Some(a <= b),
+        "jg" => Some(a > b),
+        "jb" => Some((a as u64) < (b as u64)),
+        "jae" => Some((a as u64) >= (b as u64)),
+        "jbe" => Some((a as u64) <= (b as u64)),
+        "ja" => Some((a as u64) > (b as u64)),
+        _ => None
+    }
+}
+
+fn fold(mnemonic: &str, a: i64, b: i64) -> Option<i64> {
+    match mnemonic {
+        "add" => Some(a.wrapping_add(b)),
+        "sub" => Some(a.wrapping_sub(b)),
+        "imul" => Some(a.wrapping_mul(b)),
+        "and" => Some(a & b),
+        "or" => Some(a | b),
+        "xor" => Some(a ^ b),
+        // Like the processor, shifts only use the low 6 bits of the count
+        "shl" => Some(a.wrapping_shl((b & 63) as u32)),
+        "sar" => Some(a >> (b & 63)),
+        "shr" => Some(((a as u64) >> (b & 63)) as i64),
+        _ => None
+    }
+}
+
+fn fits_i32(imm: i64) -> bool {
+    imm >= i32::MIN as i64 && imm <= i32::MAX as i64
+}
+
+const ALU: [&str; 6] = ["add", "sub", "and", "or", "xor", "cmp"];
+// Shifts by an immediate or by cl
+const SHIFTS: [&str; 3] = ["shl", "shr", "sar"];
+const SSE_ARITHMETIC: [&str; 8] = ["addss", "subss", "mulss", "divss", "addsd", "subsd", "mulsd", "divsd"];
+// Instructions only defining their first operand, from the second one
+const MOVES: [&str; 11] = ["mov", "movq", "movsd", "movzx", "movsxd", "cvtsi2ss", "cvtsi2sd", "cvtss2sd", "cvtsd2ss", "sqrtss", "sqrtsd"];
+
+const CALLER_SAVED: [Reg; 9] = [Reg::RAX, Reg::RCX, Reg::RDX, Reg::RSI, Reg::RDI, Reg::R8, Reg::R9, Reg::R10, Reg::R11];
+const ARGUMENTS: [Reg; 6] = [Reg::RDI, Reg::RSI, Reg::RDX, Reg::RCX, Reg::R8, Reg::R9];
+// Registers for the virtual registers, caller-saved ones first, then rbx which is always saved, then the callee-saved
+// ones the method must save. rax is left to the converted instructions, r11 is the scratch register of spilled operands
+const ALLOCATABLE: [Reg; 12] = [Reg::R10, Reg::R9, Reg::R8, Reg::RSI, Reg::RDI, Reg::RDX, Reg::RCX, Reg::RBX, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+const CALLEE_SAVED: [Reg; 4] = [Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+const SCRATCH: Reg = Reg::R11;
+// The math routines of the runtime (see runtime.rs) take their arguments in xmm0 and xmm1, and only write these registers
+const MATH_CLOBBERED: [Reg; 3] = [Reg::RAX, Reg::RCX, Reg::RDX];
+
+// The stack and frame pointers are not tracked
+fn is_tracked(loc: &Loc) -> bool {
+    !matches!(loc, Loc::Reg(Reg::RSP) | Loc::Reg(Reg::RBP))
+}
+
+fn is_general(loc: &Loc) -> bool {
+    matches!(loc, Loc::Reg(_) | Loc::Virt(_)) && is_tracked(loc)
+}
+
+// The error routines of the runtime (e.g. __array_index_error) exit the program, so they end their basic block like jmp
+fn is_error_call(mnemonic: &str, operands: &[Operand]) -> bool {
+    mnemonic == "call" && matches!(operands, [Operand::Label(name)] if name.starts_with("__") && name.ends_with("_error"))
+}
+
+// Locations defined and used by an instruction, including the implicit ones
+fn effects(instr: &Instr) -> (Vec<Loc>, Vec<Loc>) {
+    let (mnemonic, operands) = match instr {
+        Instr::Label(_) => return (Vec::new(), Vec::new()),
+        Instr::Op(mnemonic, operands) => (mnemonic.as_str(), operands)
+    };
+    let loc = |idx: usize| -> Vec<Loc> {
+        match operands.get(idx) {
+            Some(Operand::Loc(loc)) if is_tracked(loc) => vec![*loc],
+            _ => Vec::new()
+        }
+    };
+    let regs = |regs: &[Reg]| -> Vec<Loc> { regs.iter().map(|reg| Loc::Reg(*reg)).collect() };
+    let xmms = |range: std::ops::Range<u8>| -> Vec<Loc> { range.map(Loc::Xmm).collect() };
+
+    let mut uses: Vec<Loc> = operands.iter().filter_map(|operand| match operand {
+        Operand::Mem(base, _) if is_tracked(&Loc::Reg(*base)) => Some(Loc::Reg(*base)),
+        _ => None
+    }).collect();
+    let defs = match mnemonic {
+        _ if MOVES.contains(&mnemonic) => { uses.extend(loc(1)); loc(0) },
+        "pop" => loc(0),
+        "xor" if operands.len() == 2 && operands[0] == operands[1] => loc(0),
+        "cmp" | "ucomiss" | "ucomisd" => { uses.extend(loc(0)); uses.extend(loc(1)); Vec::new() },
+        _ if ALU.contains(&mnemonic) || SHIFTS.contains(&mnemonic) || SSE_ARITHMETIC.contains(&mnemonic) || ["imul", "inc", "neg"].contains(&mnemonic) => {
+            uses.extend(loc(0));
+            uses.extend(loc(1));
+            loc(0)
+        },
+        "push" => { uses.extend(loc(0)); Vec::new() },
+        "cqo" => { uses.push(Loc::Reg(Reg::RAX)); regs(&[Reg::RDX]) },
+        "idiv" => {
+            uses.extend(loc(0));
+            uses.extend(regs(&[Reg::RAX, Reg::RDX]));
+            regs(&[Reg::RAX, Reg::RDX])
+        },
+        "call" if matches!(&operands[..], [Operand::Label(name)] if name.starts_with("__math_")) => {
+            uses.extend(xmms(0..2));
+            let mut defs = regs(&MATH_CLOBBERED);
+            defs.extend(xmms(0..3));
+            defs
+        },
+        "call" => {
+            uses.extend(regs(&ARGUMENTS));
+            uses.extend(xmms(0..8));
+            let mut defs = regs(&CALLER_SAVED);
+            defs.extend(xmms(0..16));
+            defs
+        },
+        "syscall" => {
+            uses.extend(regs(&[Reg::RAX, Reg::RDI, Reg::RSI, Reg::RDX]));
+            regs(&[Reg::RAX, Reg::RCX, Reg::R11])
+        },
+        // The returned value is used too, see liveness()
+        "ret" => { uses.push(Loc::Reg(Reg::RBX)); Vec::new() },
+        _ => Vec::new()
+    };
+    (defs, uses)
+}
+
+// Instructions without other effect than defining their first operand, removed if it is not used
+fn is_pure(instr: &Instr) -> bool {
+    match instr {
+        Instr::Op(mnemonic, operands) => {
+            let mnemonic = mnemonic.as_str();
+            let pure = MOVES.contains(&mnemonic) || SSE_ARITHMETIC.contains(&mnemonic)
+                || (ALU.contains(&mnemonic) && mnemonic != "cmp") || SHIFTS.contains(&mnemonic) || ["imul", "inc", "neg"].contains(&mnemonic);
+            pure && matches!(operands.first(), Some(Operand::Loc(loc)) if is_tracked(loc))
+        },
+        Instr::Label(_) => false
+    }
+}
+
+struct Liveness {
+    defs: Vec<Vec<Loc>>,
+    live_in: Vec<HashSet<Loc>>,
+    live_out: Vec<HashSet<Loc>>
+}
+
+fn liveness(instrs: &[Instr], returned: Option<Loc>) -> Liveness {
+    let labels: HashMap<&String, usize> = instrs.iter().enumerate().filter_map(|(idx, instr)| match instr {
+        Instr::Label(name) => Some((name, idx)),
+        _ => None
+    }).collect();
+    let successors: Vec<Vec<usize>> = instrs.iter().enumerate().map(|(idx, instr)| {
+        let next = if idx + 1 < instrs.len() { vec![idx + 1] } else { Vec::new() };
+        match instr {
+            Instr::Op(mnemonic, operands) if mnemonic == "ret" || is_error_call(mnemonic, operands) => Vec::new(),
+            Instr::Op(mnemonic, operands) => match jump_target(mnemonic, operands) {
+                Some(target) => {
+                    let mut successors = vec![labels[target]];
+                    if mnemonic != "jmp" { successors.extend(next); }
+                    successors
+                },
+                None => next
+            },
+            Instr::Label(_) => next
+        }
+    }).collect();
+
+    let (defs, mut uses): (Vec<Vec<Loc>>, Vec<Vec<Loc>>) = instrs.iter().map(effects).unzip();
+    for (instr, uses) in instrs.iter().zip(uses.iter_mut()) {
+        if matches!(instr, Instr::Op(mnemonic, _) if mnemonic == "ret") {
+            uses.extend(returned);
+        }
+    }
+    let mut live_in: Vec<HashSet<Loc>> = vec![HashSet::new(); instrs.len()];
+    let mut live_out: Vec<HashSet<Loc>> = vec![HashSet::new(); instrs.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..instrs.len()).rev() {
+            let out: HashSet<Loc> = successors[idx].iter().flat_map(|succ| live_in[*succ].iter().copied()).collect();
+            let mut live: HashSet<Loc> = out.iter().filter(|loc| !defs[idx].contains(loc)).copied().collect();
+            live.extend(uses[idx].iter().copied());
+            if live != live_in[idx] || out != live_out[idx] {
+                live_in[idx] = live;
+                live_out[idx] = out;
+                changed = true;
+            }
+        }
+    }
+    Liveness { defs, live_in, live_out }
+}
+
+fn find(parents: &mut [usize], virt: usize) -> usize {
+    let mut root = virt;
+    while parents[root] != root { root = parents[root]; }
+    parents[virt] = root;
+    root
+}
+
+// Values pushed on different paths to a label are in the same register
+fn merge_stacks(parents: &mut [usize], category2: &mut [bool], stack: &[usize], other: &[usize], label: &String) {
+    if stack.len() != other.len() {
+        panic!("Operand stack of different sizes at label {}", label);
+    }
+    for (virt, other) in stack.iter().zip(other.iter()) {
+        let (root, other_root) = (find(parents, *virt), find(parents, *other));
+        parents[root] = other_root;
+        category2[other_root] |= category2[root];
+    }
+}
+
+// Pops the values on top of the operand stack which take the given number of slots of the bytecode's operand stack,
+// where a long or double takes two. They are returned from the deepest one
+fn pop_slots(parents: &mut [usize], category2: &[bool], stack: &mut Vec<usize>, nb_slots: usize) -> Vec<usize> {
+    let mut values: Vec<usize> = Vec::new();
+    let mut slots = 0;
+    while slots < nb_slots {
+        let virt = match stack.pop() {
+            Some(virt) => virt,
+            None => panic!("Pop from an empty operand stack in assembly")
+        };
+        slots += if category2[find(parents, virt)] { 2 } else { 1 };
+        values.insert(0, virt);
+    }
+    if slots != nb_slots {
+        panic!("Stack manipulation splitting a long or double in assembly");
+    }
+    values
+}
+
+fn mov(dst: Operand, src: Operand) -> Instr {
+    Instr::Op("mov".to_string(), vec![dst, src])
+}
+
+pub struct Method {
+    instrs: Vec<Instr>,
+    nb_variables: usize,
+    // Register of the returned value, rax or xmm0
+    returned: Option<Loc>
+}
+
+impl Method {
+    // Parses the assembly of a method, where the local variables are in the stack frame (see Assembly::variable)
+    // and the operand stack is the stack. Each variable becomes a virtual register, and so does each pushed value
+    pub fn new(source: &str, nb_variables: usize, returned: Option<Loc>) -> Method {
+        let mut instrs: Vec<Instr> = Vec::new();
+        let mut parents: Vec<usize> = (0..nb_variables).collect();
+        // Whether each virtual register holds a long or double, which the bytecode's stack manipulations depend on
+        let mut category2: Vec<bool> = vec![false; nb_variables];
+        // Virtual registers of the operand stack, at this point of the code and at the labels
+        let mut stack: Vec<usize> = Vec::new();
+        let mut label_stacks: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut reachable = true;
+
+        for line in source.lines() {
+            match parse_line(line) {
+                None => {},
+                Some(Instr::Label(name)) => {
+                    match label_stacks.get(&name) {
+                        Some(recorded) if reachable => merge_stacks(&mut parents, &mut category2, &stack, recorded, &name),
+                        Some(recorded) => stack = recorded.clone(),
+                        // Only reached by backward jumps, which the bytecode does with an empty stack
+                        None if !reachable => stack.clear(),
+                        None => {}
+                    }
+                    label_stacks.insert(name.clone(), stack.clone());
+                    reachable = true;
+                    instrs.push(Instr::Label(name));
+                },
+                // Code after a jump without a label, e.g. an exception handler, is never run
+                Some(Instr::Op(_, _)) if !reachable => {},
+                Some(Instr::Op(mnemonic, operands)) => {
+                    let mut operands: Vec<Operand> = operands.into_iter().map(|operand| match operand {
+                        Operand::Mem(Reg::RBP, disp) if disp <= -16 && (-disp - 16) % 8 == 0 => {
+                            Operand::Loc(Loc::Virt(((-disp - 16) / 8) as usize))
+                        },
+                        operand => operand
+                    }).collect();
+
+                    match (mnemonic.as_str(), operands.len()) {
+                        ("push", 1) => {
+                            let virt = parents.len();
+                            parents.push(virt);
+                            category2.push(false);
+                            stack.push(virt);
+                            // push sign extends its 32-bit immediate
+                            let value = match operands.pop().unwrap() {
+                                Operand::Imm(imm) => Operand::Imm(imm as i32 as i64),
+                                value => value
+                            };
+                            instrs.push(mov(Operand::Loc(Loc::Virt(virt)), value));
+                        },
+                        ("pop", 1) => {
+                            let virt = match stack.pop() {
+                                Some(virt) => virt,
+                                None => panic!("Pop from an empty operand stack in assembly")
+                            };
+                            instrs.push(mov(operands.pop().unwrap(), Operand::Loc(Loc::Virt(virt))));
+                        },
+                        // Marks the value on top of the stack as a long or double
+                        ("category2", 0) => {
+                            let virt = find(&mut parents, *stack.last().unwrap());
+                            category2[virt] = true;
+                        },
+                        ("pop2", 0) => {
+                            pop_slots(&mut parents, &category2, &mut stack, 2);
+                        },
+                        // Copies of the values on top, inserted below the values of the given number of slots
+                        ("dup2", 0) | ("dup_x2", 0) | ("dup2_x1", 0) | ("dup2_x2", 0) => {
+                            let (nb_copied, nb_skipped) = match mnemonic.as_str() {
+                                "dup2" => (2, 0),
+                                "dup_x2" => (1, 2),
+                                "dup2_x1" => (2, 1),
+                                _ => (2, 2)
+                            };
+                            let copied = pop_slots(&mut parents, &category2, &mut stack, nb_copied);
+                            let skipped = pop_slots(&mut parents, &category2, &mut stack, nb_skipped);
+                            for virt in copied.iter() {
+                                let copy = parents.len();
+                                parents.push(copy);
+                                category2.push(category2[find(&mut parents, *virt)]);
+                                stack.push(copy);
+                                instrs.push(mov(Operand::Loc(Loc::Virt(copy)), Operand::Loc(Loc::Virt(*virt))));
+                            }
+                            stack.extend(skipped);
+                            stack.extend(copied);
+                        },
+                        _ => {
+                            if let Some(target) = jump_target(&mnemonic, &operands) {
+                                match label_stacks.get(target) {
+                                    Some(recorded) => merge_stacks(&mut parents, &mut category2, &stack, recorded, target),
+                                    None => { label_stacks.insert(target.clone(), stack.clone()); }
+                                }
+                            }
+                            if mnemonic == "jmp" || mnemonic == "ret" || is_error_call(&mnemonic, &operands) {
+                                reachable = false;
+                            }
+                            instrs.push(Instr::Op(mnemonic, operands));
+                        }
+                    }
+                }
+            }
+        }
+
+        for instr in instrs.iter_mut() {
+            if let Instr::Op(_, operands) = instr {
+                for operand in operands.iter_mut() {
+                    if let Operand::Loc(Loc::Virt(virt)) = operand {
+                        *virt = find(&mut parents, *virt);
+                    }
+                }
+            }
+        }
+        Method { instrs, nb_variables, returned }
+    }
+
+    pub fn optimize(&mut self) {
+        // Each pass enables the others, until nothing changes
+        for _ in 0..16 {
+            let propagated = self.propagate();
+            let eliminated = self.eliminate_dead_code();
+            let renamed = self.rename_temporaries();
+            let cleaned = self.remove_jumps();
+            if !propagated && !eliminated && !renamed && !cleaned {
+                break;
+            }
+        }
+    }
+
+    // Forward propagation of constants and copies inside basic blocks, folding arithmetic and branches on constants
+    fn propagate(&mut self) -> bool {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Known { Const(i64), Copy(Loc) }
+
+        let mut changed = false;
+        let mut known: HashMap<Loc, Known> = HashMap::new();
+        // Last comparison of two constants, which can only be removed if all the jumps after it get folded
+        let mut flags: Option<(usize, i64, i64)> = None;
+        let mut removed: HashSet<usize> = HashSet::new();
+        let mut folded_cmps: HashSet<usize> = HashSet::new();
+        let mut kept_cmps: HashSet<usize> = HashSet::new();
+
+        for idx in 0..self.instrs.len() {
+            let (mnemonic, operands) = match &mut self.instrs[idx] {
+                Instr::Label(_) => {
+                    known.clear();
+                    flags = None;
+                    continue;
+                },
+                Instr::Op(mnemonic, operands) => (mnemonic.clone(), operands)
+            };
+
+            // Sources: the second operand, and the first one of instructions only using it
+            let uses_first = ["cmp", "push", "idiv"].contains(&mnemonic.as_str());
+            // A float moved out of an xmm register and back gets moved between the xmm registers
+            let xmm_dst = mnemonic == "movq" && matches!(operands.first(), Some(Operand::Loc(Loc::Xmm(_))));
+            // The count of a shift stays in cl, unless it is a constant
+            let shift = SHIFTS.contains(&mnemonic.as_str());
+            for (pos, operand) in operands.iter_mut().enumerate() {
+                if pos == 0 && !uses_first {
+                    continue;
+                }
+                let loc = match operand { Operand::Loc(loc) if is_general(loc) => *loc, _ => continue };
+                match known.get(&loc) {
+                    Some(Known::Copy(source)) if (is_general(source) || xmm_dst) && !shift => { *operand = Operand::Loc(*source); changed = true; },
+                    Some(Known::Const(value)) if pos == 1 && shift => {
+                        *operand = Operand::Imm(*value & 63);
+                        changed = true;
+                    },
+                    Some(Known::Const(value)) if pos == 1 && (mnemonic == "mov" || (ALU.contains(&mnemonic.as_str()) && fits_i32(*value))) => {
+                        *operand = Operand::Imm(*value);
+                        changed = true;
+                    },
+                    _ => {}
+                }
+            }
+
+            let constant = |operand: &Operand, known: &HashMap<Loc, Known>| match operand {
+                Operand::Imm(value) => Some(*value),
+                Operand::Loc(loc) => match known.get(loc) { Some(Known::Const(value)) => Some(*value), _ => None },
+                _ => None
+            };
+
+            // Arithmetic on constants, and sign extension of a constant
+            if let [Operand::Loc(dst), src] = &operands[..] {
+                let folded = match (constant(&Operand::Loc(*dst), &known), constant(src, &known)) {
+                    (_, Some(value)) if mnemonic == "movsxd" => Some(value as i32 as i64),
+                    (Some(a), Some(b)) => fold(&mnemonic, a, b),
+                    _ => None
+                };
+                if let Some(value) = folded {
+                    self.instrs[idx] = mov(Operand::Loc(*dst), Operand::Imm(value));
+                    changed = true;
+                }
+            }
+
+            if xmm_dst && matches!(&self.instrs[idx], Instr::Op(_, operands) if matches!(operands[1], Operand::Loc(Loc::Xmm(_)))) {
+                if let Instr::Op(mnemonic, _) = &mut self.instrs[idx] {
+                    *mnemonic = "movsd".to_string();
+                }
+            }
+
+            // Branches on constants
+            let (mnemonic, operands) = match &self.instrs[idx] { Instr::Op(mnemonic, operands) => (mnemonic.clone(), operands.clone()), _ => unreachable!() };
+            if mnemonic == "cmp" {
+                flags = match (constant(&operands[0], &known), constant(&operands[1], &known)) {
+                    (Some(a), Some(b)) => Some((idx, a, b)),
+                    _ => None
+                };
+            } else if is_jcc(&mnemonic) {
+                if let Some((cmp_idx, a, b)) = flags {
+                    match evaluate_jcc(&mnemonic, a, b) {
+                        Some(taken) => {
+                            folded_cmps.insert(cmp_idx);
+                            if taken {
+                                self.instrs[idx] = Instr::Op("jmp".to_string(), operands.clone());
+                            } else {
+                                removed.insert(idx);
+                            }
+                            changed = true;
+                        },
+                        None => {
+                            kept_cmps.insert(cmp_idx);
+                            flags = None;
+                        }
+                    }
+                }
+            } else if mnemonic != "mov" && mnemonic != "movq" {
+                flags = None;
+            }
+
+            let (defs, _) = effects(&self.instrs[idx]);
+            for def in defs.iter() {
+                known.remove(def);
+                known.retain(|_, value| *value != Known::Copy(*def));
+            }
+            match &self.instrs[idx] {
+                Instr::Op(mnemonic, operands) if mnemonic == "mov" => match &operands[..] {
+                    [Operand::Loc(dst), Operand::Imm(value)] if is_general(dst) => { known.insert(*dst, Known::Const(*value)); },
+                    // Variables are not replaced by their source, which would extend its life
+                    [Operand::Loc(dst), Operand::Loc(src)] if is_general(dst) && is_general(src) && dst != src => {
+                        match known.get(src) {
+                            Some(Known::Const(value)) => { known.insert(*dst, Known::Const(*value)); },
+                            _ if matches!(dst, Loc::Virt(virt) if *virt < self.nb_variables) => {},
+                            _ => { known.insert(*dst, Known::Copy(*src)); }
+                        }
+                    },
+                    _ => {}
+                },
+                Instr::Op(mnemonic, operands) if mnemonic == "movq" => match &operands[..] {
+                    [Operand::Loc(dst @ Loc::Virt(virt)), Operand::Loc(src @ Loc::Xmm(_))] if *virt >= self.nb_variables => { known.insert(*dst, Known::Copy(*src)); },
+                    [Operand::Loc(dst @ Loc::Reg(_)), Operand::Loc(src @ Loc::Xmm(_))] if is_general(dst) => { known.insert(*dst, Known::Copy(*src)); },
+                    _ => {}
+                },
+                Instr::Op(mnemonic, _) if mnemonic == "jmp" || mnemonic == "ret" => known.clear(),
+                _ => {}
+            }
+        }
+
+        removed.extend(folded_cmps.difference(&kept_cmps));
+        if !removed.is_empty() {
+            let mut idx = 0;
+            self.instrs.retain(|_| { idx += 1; !removed.contains(&(idx - 1)) });
+        }
+        changed
+    }
+
+    fn eliminate_dead_code(&mut self) -> bool {
+        let liveness = liveness(&self.instrs, self.returned);
+        let mut idx = 0;
+        let len = self.instrs.len();
+        self.instrs.retain(|instr| {
+            idx += 1;
+            let defs = &liveness.defs[idx - 1];
+            let self_move = matches!(instr, Instr::Op(mnemonic, operands)
+                if ["mov", "movq", "movsd"].contains(&mnemonic.as_str()) && operands[0] == operands[1]);
+            let dead = is_pure(instr) && !defs.is_empty() && defs.iter().all(|def| !liveness.live_out[idx - 1].contains(def));
+            !(self_move || dead)
+        });
+        self.instrs.len() != len
+    }
+
+    // A temporary computed and then moved into a register gets computed in that register directly, e.g.
+    // mov rax, v1 / add rax, v2 / mov v3, rax becomes mov v3, v1 / add v3, v2
+    fn rename_temporaries(&mut self) -> bool {
+        let mut changed = false;
+        let mut idx = 0;
+        let mut liveness = liveness(&self.instrs, self.returned);
+        while idx < self.instrs.len() {
+            let (dst, temp) = match &self.instrs[idx] {
+                Instr::Op(mnemonic, operands) if mnemonic == "mov" => match &operands[..] {
+                    [Operand::Loc(dst), Operand::Loc(temp)] if is_general(dst) && dst != temp && !liveness.live_out[idx].contains(temp) => {
+                        match temp {
+                            Loc::Reg(reg) if *reg != Reg::RSP && *reg != Reg::RBP => (*dst, *temp),
+                            Loc::Virt(virt) if *virt >= self.nb_variables => (*dst, *temp),
+                            _ => { idx += 1; continue; }
+                        }
+                    },
+                    _ => { idx += 1; continue; }
+                },
+                _ => { idx += 1; continue; }
+            };
+
+            // Looks for the definition of the temporary, in the same basic block
+            let mut start: Option<usize> = None;
+            let mut pos = idx;
+            while pos > 0 {
+                pos -= 1;
+                let (defs, uses) = effects(&self.instrs[pos]);
+                let operands = match &self.instrs[pos] {
+                    Instr::Label(_) => break,
+                    Instr::Op(mnemonic, _) if ["call", "syscall", "idiv", "cqo", "ret", "jmp"].contains(&mnemonic.as_str()) || is_jcc(mnemonic) => break,
+                    Instr::Op(_, operands) => operands
+                };
+                let defines_only = MOVES.contains(&match &self.instrs[pos] { Instr::Op(mnemonic, _) => mnemonic.as_str(), _ => "" })
+                    && operands[0] == Operand::Loc(temp) && !uses.contains(&temp);
+                if defines_only {
+                    // The destination can be the source of the temporary
+                    if operands.get(1) == Some(&Operand::Loc(dst)) || !uses.contains(&dst) {
+                        start = Some(pos);
+                    }
+                    break;
+                }
+                if defs.contains(&dst) || uses.contains(&dst) {
+                    break;
+                }
+                // The base of an address and the count of a shift stay in their register
+                let fixed = operands.iter().any(|operand| matches!(operand, Operand::Mem(base, _) if Loc::Reg(*base) == temp))
+                    || (SHIFTS.contains(&match &self.instrs[pos] { Instr::Op(mnemonic, _) => mnemonic.as_str(), _ => "" }) && operands.get(1) == Some(&Operand::Loc(temp)));
+                if fixed {
+                    break;
+                }
+                if defs.contains(&temp) && operands[0] != Operand::Loc(temp) {
+                    break;
+                }
+            }
+
+            match start {
+                Some(start) => {
+                    for instr in self.instrs[start..idx].iter_mut() {
+                        if let Instr::Op(_, operands) = instr {
+                            for operand in operands.iter_mut() {
+                                if *operand == Operand::Loc(temp) {
+                                    *operand = Operand::Loc(dst);
+                                }
+                            }
+                        }
+                    }
+                    self.instrs.remove(idx);
+                    liveness = crate::ir::liveness(&self.instrs, self.returned);
+                    changed = true;
+                },
+                None => idx += 1
+            }
+        }
+        changed
+    }
+
+    // Removes jumps to the next instruction, unreachable code and unused labels
+    fn remove_jumps(&mut self) -> bool {
+        let len = self.instrs.len();
+        let mut instrs: Vec<Instr> = Vec::new();
+        let mut reachable = true;
+        for instr in self.instrs.drain(..) {
+            match &instr {
+                Instr::Label(_) => { reachable = true; instrs.push(instr); },
+                _ if !reachable => {},
+                Instr::Op(mnemonic, operands) => {
+                    reachable = mnemonic != "jmp" && mnemonic != "ret" && !is_error_call(mnemonic, operands);
+                    instrs.push(instr);
+                }
+            }
+        }
+
+        let mut idx = 0;
+        while idx < instrs.len() {
+            let jump_to_next = match (&instrs[idx], instrs.get(idx + 1)) {
+                (Instr::Op(mnemonic, operands), Some(Instr::Label(label))) => jump_target(mnemonic, operands) == Some(label),
+                _ => false
+            };
+            if jump_to_next {
+                instrs.remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+
+        let targets: HashSet<String> = instrs.iter().filter_map(|instr| match instr {
+            Instr::Op(mnemonic, operands) => jump_target(mnemonic, operands).cloned(),
+            _ => None
+        }).collect();
+        instrs.retain(|instr| !matches!(instr, Instr::Label(label) if !targets.contains(label)));
+
+        self.instrs = instrs;
+        self.instrs.len() != len
+    }
+
+    // Linear scan allocation of the virtual registers, over the intervals where they are live. A register can
+    // only hold a virtual register if it is not written while the virtual register is live, e.g. by a call
+    fn allocate_registers(&self) -> HashMap<usize, Operand> {
+        let liveness = liveness(&self.instrs, self.returned);
+        let nb_instrs = self.instrs.len();
+
+        let mut intervals: HashMap<usize, (usize, usize)> = HashMap::new();
+        for idx in 0..nb_instrs {
+            for loc in liveness.live_in[idx].iter().chain(liveness.defs[idx].iter()) {
+                if let Loc::Virt(virt) = loc {
+                    let interval = intervals.entry(*virt).or_insert((idx, idx));
+                    interval.0 = interval.0.min(idx);
+                    interval.1 = interval.1.max(idx);
+                }
+            }
+        }
+
+        let is_move = |idx: usize, dst: Loc, src: Loc| matches!(&self.instrs[idx],
+            Instr::Op(mnemonic, operands) if mnemonic == "mov" && operands[..] == [Operand::Loc(dst), Operand::Loc(src)]);
+        let conflicts = |virt: usize, reg: Reg| -> bool {
+            let (virt, reg) = (Loc::Virt(virt), Loc::Reg(reg));
+            if nb_instrs > 0 && liveness.live_in[0].contains(&virt) && liveness.live_in[0].contains(&reg) {
+                return true;
+            }
+            (0..nb_instrs).any(|idx| {
+                let defs = &liveness.defs[idx];
+                let live_out = &liveness.live_out[idx];
+                (defs.contains(&reg) && live_out.contains(&virt) && !is_move(idx, reg, virt))
+                    || (defs.contains(&virt) && live_out.contains(&reg) && !is_move(idx, virt, reg))
+            })
+        };
+
+        let mut order: Vec<usize> = intervals.keys().copied().collect();
+        order.sort_by_key(|virt| (intervals[virt].0, *virt));
+
+        let mut registers: HashMap<usize, Reg> = HashMap::new();
+        let mut spilled: Vec<usize> = Vec::new();
+        let mut active: Vec<usize> = Vec::new();
+        for virt in order {
+            let (start, end) = intervals[&virt];
+            // An interval ending where another starts can share its register, as instructions read before writing
+            active.retain(|other| intervals[other].1 > start);
+
+            let free = ALLOCATABLE.iter().find(|reg| {
+                !active.iter().any(|other| registers[other] == **reg) && !conflicts(virt, **reg)
+            });
+            match free {
+                Some(reg) => {
+                    registers.insert(virt, *reg);
+                    active.push(virt);
+                },
+                None => {
+                    // Spills the interval ending last, which frees a register for longer
+                    let victim = active.iter().copied()
+                        .filter(|other| !conflicts(virt, registers[other]))
+                        .max_by_key(|other| intervals[other].1);
+                    match victim {
+                        Some(victim) if intervals[&victim].1 > end => {
+                            let reg = registers.remove(&victim).unwrap();
+                            active.retain(|other| *other != victim);
+                            spilled.push(victim);
+                            registers.insert(virt, reg);
+                            active.push(virt);
+                        },
+                        _ => spilled.push(virt)
+                    }
+                }
+            }
+        }
+
+        // The frame has rbx, the callee-saved registers, then the spilled virtual registers
+        let nb_saved = CALLEE_SAVED.iter().filter(|reg| registers.values().any(|used| used == *reg)).count();
+        let mut allocation: HashMap<usize, Operand> = registers.into_iter().map(|(virt, reg)| (virt, Operand::Loc(Loc::Reg(reg)))).collect();
+        spilled.sort();
+        for (slot, virt) in spilled.into_iter().enumerate() {
+            allocation.insert(virt, Operand::Mem(Reg::RBP, -(16 + 8 * (nb_saved + slot) as i32)));
+        }
+        allocation
+    }
+
+    // Optimizes the method and writes it as a function
+    pub fn emit(mut self, label: &String) -> String {
+        self.optimize();
+        let allocation = self.allocate_registers();
+
+        let saved: Vec<Reg> = CALLEE_SAVED.iter().copied()
+            .filter(|reg| allocation.values().any(|operand| *operand == Operand::Loc(Loc::Reg(*reg))))
+            .collect();
+        let nb_spilled = allocation.values().filter(|operand| matches!(operand, Operand::Mem(_, _))).count();
+
+        let mut content: Vec<String> = Vec::new();
+        content.push(format!("\n{}:\n    push rbp\n    mov rbp, rsp\n    push rbx\n", label));
+        if saved.len() + nb_spilled > 0 {
+            content.push(format!("    sub rsp, {}\n", 8 * (saved.len() + nb_spilled)));
+        }
+        let save_slot = |idx: usize| Operand::Mem(Reg::RBP, -(16 + 8 * idx as i32));
+        for (idx, reg) in saved.iter().enumerate() {
+            content.push(format_instr("mov", &[save_slot(idx), Operand::Loc(Loc::Reg(*reg))]));
+        }
+
+        for instr in self.instrs.iter() {
+            match instr {
+                Instr::Label(name) => content.push(format!("{}:\n", name)),
+                Instr::Op(mnemonic, operands) => {
+                    let operands: Vec<Operand> = operands.iter().map(|operand| match operand {
+                        Operand::Loc(Loc::Virt(virt)) => allocation[virt].clone(),
+                        operand => operand.clone()
+                    }).collect();
+                    if mnemonic == "mov" && operands[0] == operands[1] {
+                        continue;
+                    }
+                    if mnemonic == "leave" {
+                        for (idx, reg) in saved.iter().enumerate() {
+                            content.push(format_instr("mov", &[Operand::Loc(Loc::Reg(*reg)), save_slot(idx)]));
+                        }
+                    }
+                    legalize(&mut content, mnemonic, operands);
+                }
+            }
+        }
+        content.concat()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind { Reg, Xmm, Mem, Imm32, Imm64, Label }
+
+fn kind(operand: &Operand) -> Kind {
+    match operand {
+        Operand::Loc(Loc::Xmm(_)) => Kind::Xmm,
+        Operand::Loc(_) => Kind::Reg,
+        Operand::Mem(_, _) => Kind::Mem,
+        Operand::Imm(imm) if fits_i32(*imm) => Kind::Imm32,
+        Operand::Imm(_) => Kind::Imm64,
+        Operand::Label(_) => Kind::Label
+    }
+}
+
+// Operand kinds of the instructions the assembler supports
+fn is_supported(mnemonic: &str, kinds: &[Kind]) -> bool {
+    use Kind::*;
+    match (mnemonic, kinds) {
+        ("mov", [Reg, Reg | Imm32 | Imm64 | Label | Mem]) | ("mov", [Mem, Reg | Imm32]) => true,
+        ("movzx", [Reg, Mem]) => true,
+        ("movq", [Xmm, Reg | Mem]) | ("movq", [Reg | Mem, Xmm]) | ("movsd", [Xmm, Xmm]) => true,
+        (op, [Reg, Reg | Imm32 | Mem]) | (op, [Mem, Reg | Imm32]) if ALU.contains(&op) => true,
+        ("imul", [Reg, Reg | Mem]) => true,
+        (op, [Reg, Reg | Imm32]) if SHIFTS.contains(&op) => true,
+        ("movsxd", [Reg, Reg | Mem]) => true,
+        ("idiv" | "inc" | "neg", [Reg]) => true,
+        ("push", [Reg | Imm32 | Mem]) | ("pop", [Reg | Mem]) => true,
+        ("cvtsi2ss" | "cvtsi2sd", [Xmm, Reg]) => true,
+        (op, [Xmm, Xmm]) if SSE_ARITHMETIC.contains(&op) => true,
+        ("sqrtss" | "sqrtsd" | "ucomiss" | "ucomisd" | "cvtss2sd" | "cvtsd2ss", [Xmm, Xmm]) => true,
+        (_, [Label]) => true,
+        (_, []) => true,
+        _ => false
+    }
+}
+
+fn format_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Loc(Loc::Reg(reg)) => register_name(*reg).to_string(),
+        Operand::Loc(Loc::Xmm(idx)) => format!("xmm{}", idx),
+        Operand::Loc(Loc::Virt(virt)) => panic!("Virtual register {} was not allocated", virt),
+        Operand::Mem(base, disp) if *disp < 0 => format!("qword [{}-{}]", register_name(*base), -disp),
+        Operand::Mem(base, disp) if *disp > 0 => format!("qword [{}+{}]", register_name(*base), disp),
+        Operand::Mem(base, _) => format!("qword [{}]", register_name(*base)),
+        Operand::Imm(imm) => format!("{}", imm),
+        Operand::Label(label) => label.clone()
+    }
+}
+
+fn format_instr(mnemonic: &str, operands: &[Operand]) -> String {
+    let operands: Vec<String> = operands.iter().enumerate().map(|(idx, operand)| match operand {
+        // The source of movsxd is 32-bit
+        Operand::Loc(Loc::Reg(reg)) if mnemonic == "movsxd" && idx == 1 => REGISTERS32[*reg as usize].to_string(),
+        Operand::Mem(_, _) if mnemonic == "movsxd" && idx == 1 => format_operand(operand).replacen("qword", "dword", 1),
+        Operand::Mem(_, _) if mnemonic == "movzx" && idx == 1 => format_operand(operand).replacen("qword", "byte", 1),
+        Operand::Loc(Loc::Reg(reg)) if SHIFTS.contains(&mnemonic) && idx == 1 => REGISTERS8[*reg as usize].to_string(),
+        // Address of a label
+        Operand::Label(label) if mnemonic == "mov" => format!("qword {}", label),
+        operand => format_operand(operand)
+    }).collect();
+    if operands.is_empty() {
+        format!("    {}\n", mnemonic)
+    } else {
+        format!("    {} {}\n", mnemonic, operands.join(", "))
+    }
+}
+
+// Writes an instruction, going through the scratch register for an operand of a kind it does not support,
+// e.g. a spilled virtual register
+fn legalize(content: &mut Vec<String>, mnemonic: &str, operands: Vec<Operand>) {
+    let kinds: Vec<Kind> = operands.iter().map(kind).collect();
+    if is_supported(mnemonic, &kinds) {
+        content.push(format_instr(mnemonic, &operands));
+        return;
+    }
+
+    let scratch = Operand::Loc(Loc::Reg(SCRATCH));
+    // Immediates first, then the source, then the destination
+    let mut candidates: Vec<usize> = (0..operands.len()).filter(|idx| matches!(kinds[*idx], Kind::Imm32 | Kind::Imm64)).collect();
+    candidates.extend((0..operands.len()).rev().filter(|idx| kinds[*idx] == Kind::Mem));
+    for idx in candidates {
+        let mut replaced = kinds.clone();
+        replaced[idx] = Kind::Reg;
+        if !is_supported(mnemonic, &replaced) {
+            continue;
+        }
+        let mut new_operands = operands.clone();
+        new_operands[idx] = scratch.clone();
+        let (defs, uses) = effects(&Instr::Op(mnemonic.to_string(), new_operands.clone()));
+        let scratch_loc = Loc::Reg(SCRATCH);
+        if uses.contains(&scratch_loc) || idx > 0 || !defs.contains(&scratch_loc) {
+            content.push(format_instr("mov", &[scratch.clone(), operands[idx].clone()]));
+        }
+        content.push(format_instr(mnemonic, &new_operands));
+        if defs.contains(&scratch_loc) {
+            content.push(format_instr("mov", &[operands[idx].clone(), scratch.clone()]));
+        }
+        return;
+    }
+    panic!("Unsupported instruction in assembly: {} {:?}", mnemonic, operands);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_to_registers() {
+        // int a = 2; int b = a + 3; return b;
+        let source = "    push 0x2\n    pop qword [rbp-16]\n    push qword [rbp-16]\n    push 0x3\n    pop rbx\n    pop rax\n    add rax, rbx\n    push rax\n    pop qword [rbp-24]\n    push qword [rbp-24]\n    pop rax\n    mov rbx, [rbp-8]\n    leave\n    ret\n";
+        let text = Method::new(source, 2, Some(Loc::Reg(Reg::RAX))).emit(&"f".to_string());
+        assert_eq!(text, "\nf:\n    push rbp\n    mov rbp, rsp\n    push rbx\n    mov rax, 5\n    mov rbx, qword [rbp-8]\n    leave\n    ret\n");
+    }
+
+    #[test]
+    fn test_int_wrapping() {
+        // return 2147483647 + 1;
+        let source = "    push 0x7fffffff\n    push 0x1\n    pop rbx\n    pop rax\n    add rax, rbx\n    movsxd rax, eax\n    push rax\n    pop rax\n    mov rbx, [rbp-8]\n    leave\n    ret\n";
+        let text = Method::new(source, 0, Some(Loc::Reg(Reg::RAX))).emit(&"f".to_string());
+        assert_eq!(text, "\nf:\n    push rbp\n    mov rbp, rsp\n    push rbx\n    mov rax, -2147483648\n    mov rbx, qword [rbp-8]\n    leave\n    ret\n");
+        assert_eq!(parse_number("-9223372036854775808"), Some(i64::MIN));
+    }
+
+    #[test]
+    fn test_stack_manipulations() {
+        // Form 3 of dup2_x2, with a long on top of two ints: (3 - 2 + 1) * 3
+        let source = "    push 0x1\n    push 0x2\n    push 0x3\n    category2\n    dup2_x2\n    pop rax\n    pop rbx\n    sub rax, rbx\n    pop rbx\n    add rax, rbx\n    pop rbx\n    imul rax, rbx\n    push rax\n    pop rax\n    mov rbx, [rbp-8]\n    leave\n    ret\n";
+        let text = Method::new(source, 0, Some(Loc::Reg(Reg::RAX))).emit(&"f".to_string());
+        assert_eq!(text, "\nf:\n    push rbp\n    mov rbp, rsp\n    push rbx\n    mov rax, 6\n    mov rbx, qword [rbp-8]\n    leave\n    ret\n");
+
+        // pop2 removes a single long, or two ints
+        let source = "    push 0x1\n    push 0x2\n    push 0x3\n    pop2\n    push 0x4\n    category2\n    pop2\n    pop rax\n    mov rbx, [rbp-8]\n    leave\n    ret\n";
+        let text = Method::new(source, 0, Some(Loc::Reg(Reg::RAX))).emit(&"f".to_string());
+        assert_eq!(text, "\nf:\n    push rbp\n    mov rbp, rsp\n    push rbx\n    mov rax, 1\n    mov rbx, qword [rbp-8]\n    leave\n    ret\n");
+    }
+}
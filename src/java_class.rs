@@ -1,31 +1,83 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use crate::{StackFrame, get_debug};
+use crate::vm::get_debug;
+use crate::asm::Assembly;
 use crate::bytecode_class::AttributeBootstrapMethod;
 use crate::bytecode_class::ConstantMethodHandle;
-use crate::jvm::JavaInstance;
+use crate::jvm::{JavaInstance, StackFrame, Value};
+use crate::native_java_classes::new_java_exception;
 
 pub fn get_nb_arguments(type_desc: &String) -> usize {
+    return get_argument_sizes(type_desc).len();
+}
+
+// Number of local variable slots taken by each argument: long and double take two
+pub fn get_argument_sizes(type_desc: &String) -> Vec<usize> {
+    return get_argument_types(type_desc).iter().map(|arg_type| match arg_type {
+        'J' | 'D' => 2,
+        _ => 1
+    }).collect();
+}
+
+// Descriptor letter of each argument, with L for objects and arrays
+pub fn get_argument_types(type_desc: &String) -> Vec<char> {
     let start_bytes = type_desc.find("(").unwrap_or(0);
     let end_bytes = type_desc.find(")").unwrap_or(type_desc.len());
     let arguments = &type_desc[start_bytes+1..end_bytes];
 
-    let mut nb_arguments = 0;
+    let mut types = Vec::new();
     let mut idx: usize = 0;
     let size: usize = arguments.len();
     while idx < size {
+        // Arrays are references, whatever the type of their elements
+        let is_array = arguments[idx..].starts_with("[");
+        while arguments[idx..].starts_with("[") { idx += 1; }
+
         match arguments.chars().nth(idx) {
-            Some('B') | Some('C')| Some('D')| Some('F')| Some('I')| Some('J')| Some('S')| Some('Z') => { nb_arguments += 1; idx += 1; },
+            Some(arg_type @ ('B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z')) => {
+                types.push(if is_array { 'L' } else { arg_type });
+                idx += 1;
+            },
             Some('L') => {
                 idx += arguments[idx..].find(";").unwrap() + 1;
-                nb_arguments += 1;
+                types.push('L');
             }
             Some(_) => { idx += 1; }
             None => { break; }
         }
     };
-    return nb_arguments;
+    return types;
+}
+
+// Descriptor of each argument, e.g. "I" and "[Ljava/lang/String;" for (I[Ljava/lang/String;)V
+pub fn get_argument_descriptors(type_desc: &String) -> Vec<String> {
+    let start_bytes = type_desc.find("(").map_or(0, |idx| idx + 1);
+    let end_bytes = type_desc.find(")").unwrap_or(type_desc.len());
+    let arguments = &type_desc[start_bytes..end_bytes];
+
+    let mut descriptors = Vec::new();
+    let mut idx: usize = 0;
+    while idx < arguments.len() {
+        let start = idx;
+        while arguments[idx..].starts_with("[") { idx += 1; }
+        match arguments[idx..].chars().next() {
+            Some('L') => { idx += arguments[idx..].find(";").unwrap() + 1; },
+            Some(_) => { idx += 1; },
+            None => { break; }
+        }
+        descriptors.push(arguments[start..idx].to_string());
+    }
+    descriptors
+}
+
+// Descriptor letter of the return value, V for void
+pub fn get_return_type(type_desc: &String) -> char {
+    match type_desc.find(")").and_then(|idx| type_desc.chars().nth(idx + 1)) {
+        Some('[') => 'L',
+        Some(return_type) => return_type,
+        None => panic!("Invalid method descriptor {}", type_desc)
+    }
 }
 
 ///////////////////////////////////////////
@@ -50,16 +102,20 @@ pub enum MethodCallResult {
     EXCEPTION(Arc<Mutex<dyn JavaInstance>>)
 }
 
-pub trait JavaClass {
+pub trait JavaClass: Send + Sync {
     fn new(&self) -> Arc<Mutex<dyn JavaInstance>> { panic!("Class {} cannot be instantiated", self.get_name()); }
     fn has_static_init(&self) -> bool { false }
-    fn get_dependent_classes(&self) -> Vec<String> { Vec::new() }
     fn get_bootstrap_method(&self, _idx: usize) -> Option<&AttributeBootstrapMethod> { return None; }
-    fn convert_to_asm(&self, _method_name: &String, _arch: &str) { panic!("Class {} does not support conversion to assembly", self.get_name()); }
+    fn convert_to_asm(&self, _method_name: &String, _type_desc: &String, _arch: &str, _emit: &str) { panic!("Class {} does not support conversion to assembly", self.get_name()); }
+    fn convert_method_to_asm(&self, method_name: &String, type_desc: &String, _assembly: &mut Assembly) { panic!("Method {}.{}{} does not support conversion to assembly", self.get_name(), method_name, type_desc); }
+    fn get_instance_fields(&self) -> Vec<String> { Vec::new() }
+    // Only known for the fields declared by bytecode classes
+    fn get_field_descriptor(&self, _field_name: &String, _is_static: bool) -> Option<String> { None }
     fn get_name(&self) -> String;
     fn print(&self) { }
     fn get_parent(&self) -> String { "".to_string() }
-    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) -> MethodCallResult {
+    fn get_interfaces(&self) -> Vec<String> { Vec::new() }
+    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         if get_debug() >= 1 { println!("Execute native method {}.{}(<{} arguments>)", self.get_name(), method_name, args.len()); }
 
         let expected_class = self.get_name();
@@ -71,17 +127,26 @@ pub trait JavaClass {
             this.lock().unwrap().cast_as(this2, &expected_class)
         };
 
-        object.lock().unwrap().execute_method(sf, method_name, object.clone(), args);
-        return MethodCallResult::SUCCESS;
+        return object.lock().unwrap().execute_method(sf, method_name, type_desc, object.clone(), args);
+    }
+    fn execute_static_method(&self, _sf: &mut StackFrame, method_name: &String, type_desc: &String, _nb_args: usize) -> MethodCallResult {
+        MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}{}", self.get_name(), method_name, type_desc)))
     }
-    fn execute_static_method(&self, _sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
-        panic!("Class {} does not support static method {}", self.get_name(), method_name);
+    // A resolved method is a handle on a method declared by the class, that call sites keep to skip the lookup by name.
+    // Native classes do not resolve methods, so they are always called by name
+    fn resolve_method(&self, _method_name: &String, _type_desc: &String) -> Option<usize> { None }
+    fn has_native_method(&self, _method_name: &String, _type_desc: &String) -> bool { false }
+    fn execute_resolved_method(&self, _sf: &mut StackFrame, _handle: usize, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
+        panic!("Class {} does not resolve methods", self.get_name());
     }
-    fn get_static_object(&self, field_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
-        panic!("Class {} does not have static field {}", self.get_name(), field_name);
+    fn execute_resolved_static_method(&self, _sf: &mut StackFrame, _handle: usize) -> MethodCallResult {
+        panic!("Class {} does not resolve methods", self.get_name());
     }
-    fn put_static_object(&self, field_name: &String, _value: Arc<Mutex<dyn JavaInstance>>) {
-        panic!("Class {} does not have static field {} to update", self.get_name(), field_name);
+    fn get_static_object(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name)))
+    }
+    fn put_static_object(&self, field_name: &String, _value: Arc<Mutex<dyn JavaInstance>>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name)))
     }
     fn get_method_handles(&self) -> &HashMap<usize, ConstantMethodHandle> {
         panic!("Class {} has no get_method_handles() implemented", self.get_name());
@@ -98,16 +163,23 @@ pub struct BytecodeInstance {
 
 impl JavaInstance for BytecodeInstance {
     fn is_bytecode(&self) -> bool { return true; }
+    fn has_class_name(&self, class_name: &str) -> bool { self.class_name == class_name }
     fn get_class_name(&self) -> String {
         return self.class_name.clone();
     }
+    // An instance of a subclass holds an instance of its superclass, which is used when
+    // calling the methods of a native superclass (e.g. Thread or Exception)
     fn cast_as(&self, _this: Arc<Mutex<dyn JavaInstance>>, class_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
         match &self.parent {
             Some(p) => {
-                if !p.lock().unwrap().get_class_name().eq(class_name) {
-                    panic!("Instance of class {} cannot be cast into {}", self.class_name, class_name);
+                if p.lock().unwrap().get_class_name().eq(class_name) {
+                    return p.clone();
+                }
+                let parent = p.lock().unwrap();
+                if parent.is_bytecode() || parent.supports_interface(class_name) {
+                    return parent.cast_as(p.clone(), class_name);
                 }
-                p.clone()
+                panic!("Instance of class {} cannot be cast into {}", self.class_name, class_name);
             },
             None => panic!("Instance of class {} cannot be cast into {}", self.class_name, class_name)
         }
@@ -116,25 +188,43 @@ impl JavaInstance for BytecodeInstance {
         print!("<{} bytecode instance>", self.get_class_name());
     }
 
-    fn get_field(&self, field_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
+    // Inherited fields are stored in the instance of the superclass
+    fn get_field(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
         return match self.fields.get(field_name) {
-            Some(value) => value.clone(),
-            _ => panic!("{} instance does not have field {}", self.get_class_name(), field_name)
+            Some(value) => Ok(value.clone()),
+            _ => match &self.parent {
+                Some(p) if p.lock().unwrap().is_bytecode() => p.lock().unwrap().get_field(field_name),
+                _ => Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_class_name(), field_name)))
+            }
         };
     }
 
-    fn set_field(&mut self, field_name: &String, value: Arc<Mutex<dyn JavaInstance>>) {
+    fn set_field(&mut self, field_name: &String, value: Arc<Mutex<dyn JavaInstance>>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+        if !self.fields.contains_key(field_name) {
+            if let Some(p) = &self.parent {
+                if p.lock().unwrap().is_bytecode() {
+                    return p.lock().unwrap().set_field(field_name, value);
+                }
+            }
+            return Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_class_name(), field_name)));
+        }
         self.fields.insert(field_name.clone(), value);
+        Ok(())
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::java_class::get_nb_arguments;
+    use crate::java_class::{get_argument_descriptors, get_nb_arguments};
 
     #[test]
     fn test_get_nb_arguments() {
         assert_eq!(get_nb_arguments(&"(Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/String;".to_string()), 2);
     }
+
+    #[test]
+    fn test_get_argument_descriptors() {
+        assert_eq!(get_argument_descriptors(&"(IJ[[ZLjava/lang/String;)V".to_string()), vec!["I", "J", "[[Z", "Ljava/lang/String;"]);
+    }
 }
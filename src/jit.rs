@@ -0,0 +1,520 @@
+// Tiered compilation: methods start in the interpreter, and once they are hot (invocations and back-edges),
+// they get compiled into x86-64 machine code, in executable memory.
+//
+// Compiled code works on its own copy of the frame, where each local variable and operand stack entry is a slot
+// of two words: the value and a tag with its type. Arithmetic, conversions, comparisons and branches run natively,
+// and the other instructions (method calls, objects, returns...) call back into the interpreter, with the frame
+// synchronized before and after. Compiled code can be entered at any instruction, e.g. at a loop of main()
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use crate::bytecode::{ByteCode, InstrNextAction, Op};
+use crate::jvm::{StackFrame, Value};
+use crate::x64::{Alu, Cond, Encoder, Label, Mem, Reg, Shift, Sse, Xmm};
+
+// Invocations and back-edges after which a method gets compiled
+const JIT_THRESHOLD: usize = 1000;
+
+// Slot tags. References (and return addresses) stay in JitContext::values, and their slot has their index
+const TAG_NONE: u64 = 0;
+const TAG_INT: u64 = 1;
+const TAG_LONG: u64 = 2;
+const TAG_FLOAT: u64 = 3;
+const TAG_DOUBLE: u64 = 4;
+const TAG_VALUE: u64 = 5;
+
+const SLOT_SIZE: i32 = 16;
+type Slot = [u64; 2];
+
+// What compiled code does after a call back into the interpreter
+const STATUS_NEXT: u64 = 0;
+const STATUS_EXIT: u64 = 1;
+const STATUS_BRANCH: u64 = 2;
+
+pub struct JitState {
+    counter: AtomicUsize,
+    code: OnceLock<Option<JitCode>>
+}
+
+impl JitState {
+    pub fn new() -> JitState {
+        JitState { counter: AtomicUsize::new(0), code: OnceLock::new() }
+    }
+}
+
+// Counts an invocation or a back-edge of the method, and returns its compiled code once it is hot
+pub fn get_hot_code(bytecode: &ByteCode) -> Option<&JitCode> {
+    if let Some(code) = bytecode.jit.code.get() {
+        return code.as_ref();
+    }
+    if bytecode.jit.counter.fetch_add(1, Ordering::Relaxed) + 1 < JIT_THRESHOLD {
+        return None;
+    }
+    bytecode.jit.code.get_or_init(|| compile(bytecode)).as_ref()
+}
+
+// The fields used by compiled code come first, at fixed offsets
+#[repr(C)]
+struct JitContext {
+    sp: *mut Slot,
+    locals: *mut Slot,
+    stack: *mut Slot,
+    sf: *mut StackFrame,
+    bytecode: *const ByteCode,
+    values: Vec<Value>,
+    action: Option<InstrNextAction>,
+    instr_idx: usize
+}
+
+const CTX_SP: i32 = 0;
+const CTX_LOCALS: i32 = 8;
+
+impl JitContext {
+    fn to_slot(&mut self, value: &Value) -> Slot {
+        match value {
+            Value::Int(nb) => [*nb as u32 as u64, TAG_INT],
+            Value::Long(nb) => [*nb as u64, TAG_LONG],
+            Value::Float(nb) => [nb.to_bits() as u64, TAG_FLOAT],
+            Value::Double(nb) => [nb.to_bits(), TAG_DOUBLE],
+            // Boxed numbers are used as primitives, unless another thread holds them
+            Value::Reference(object) => {
+                let primitive = match object.try_lock() {
+                    Ok(object) => object.get_primitive(),
+                    Err(_) => None
+                };
+                match primitive {
+                    Some(primitive) => self.to_slot(&primitive),
+                    None => {
+                        self.values.push(value.clone());
+                        [(self.values.len() - 1) as u64, TAG_VALUE]
+                    }
+                }
+            },
+            Value::ReturnAddress(_) => {
+                self.values.push(value.clone());
+                [(self.values.len() - 1) as u64, TAG_VALUE]
+            }
+        }
+    }
+
+    fn from_slot(&self, slot: &Slot) -> Value {
+        match slot[1] {
+            TAG_INT => Value::Int(slot[0] as u32 as i32),
+            TAG_LONG => Value::Long(slot[0] as i64),
+            TAG_FLOAT => Value::Float(f32::from_bits(slot[0] as u32)),
+            TAG_DOUBLE => Value::Double(f64::from_bits(slot[0])),
+            TAG_VALUE => self.values[slot[0] as usize].clone(),
+            tag => panic!("Invalid JIT slot tag {}", tag)
+        }
+    }
+
+    // Copies the interpreter frame into the slots
+    fn load(&mut self) {
+        self.values.clear();
+        let sf = unsafe { &mut *self.sf };
+        for (idx, value) in sf.variables.iter().enumerate() {
+            let slot = self.to_slot(value);
+            unsafe { *self.locals.add(idx) = slot; }
+        }
+        for (idx, value) in sf.stack.iter().enumerate() {
+            let slot = self.to_slot(value);
+            unsafe { *self.stack.add(idx) = slot; }
+        }
+        self.sp = unsafe { self.stack.add(sf.stack.len()) };
+    }
+
+    // Copies the slots back into the interpreter frame
+    fn store(&mut self) {
+        let sf = unsafe { &mut *self.sf };
+        for idx in 0..sf.variables.len() {
+            let slot = unsafe { &*self.locals.add(idx) };
+            if slot[1] != TAG_NONE {
+                sf.variables[idx] = self.from_slot(slot);
+            }
+        }
+        sf.stack.clear();
+        let depth = unsafe { self.sp.offset_from(self.stack) } as usize;
+        for idx in 0..depth {
+            let value = self.from_slot(unsafe { &*self.stack.add(idx) });
+            sf.stack.push(value);
+        }
+    }
+}
+
+// Runs an instruction of compiled code in the interpreter. A panic cannot unwind through the compiled code
+// of this extern "C" function, so it aborts the process instead
+extern "C" fn jit_callback(ctx: *mut JitContext, instr_idx: usize) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    let bytecode = unsafe { &*ctx.bytecode };
+    ctx.store();
+    let sf = unsafe { &mut *ctx.sf };
+    match bytecode.instructions[instr_idx].execute(sf) {
+        InstrNextAction::NEXT => {
+            ctx.load();
+            STATUS_NEXT
+        },
+        InstrNextAction::GOTO(idx) if get_branch(bytecode.ops[instr_idx]) == Some(idx) => {
+            ctx.load();
+            STATUS_BRANCH
+        },
+        action => {
+            ctx.action = Some(action);
+            ctx.instr_idx = instr_idx;
+            STATUS_EXIT
+        }
+    }
+}
+
+fn get_branch(op: Op) -> Option<usize> {
+    match op {
+        Op::IfEq(branch) | Op::IfNe(branch) | Op::IfLt(branch) | Op::IfGe(branch) | Op::IfGt(branch) | Op::IfLe(branch) |
+        Op::IfICmpEq(branch) | Op::IfICmpNe(branch) | Op::IfICmpLt(branch) | Op::IfICmpGe(branch) | Op::IfICmpGt(branch) | Op::IfICmpLe(branch) |
+        Op::IfNull(branch) | Op::IfNonNull(branch) | Op::Goto(branch) => Some(branch),
+        _ => None
+    }
+}
+
+pub struct JitCode {
+    memory: *mut u8,
+    size: usize
+}
+
+// The code is not modified once compiled, so threads can run it at the same time
+unsafe impl Send for JitCode {}
+unsafe impl Sync for JitCode {}
+
+impl JitCode {
+    // Runs the compiled code from an instruction, until it returns, throws an exception or jumps to an instruction
+    // that has to be run by the interpreter, which is then the next instruction
+    pub fn execute(&self, sf: &mut StackFrame, bytecode: &ByteCode, instr_idx: &mut usize) -> InstrNextAction {
+        let mut locals: Vec<Slot> = vec![[0, TAG_NONE]; sf.variables.len()];
+        let mut stack: Vec<Slot> = vec![[0, TAG_NONE]; bytecode.max_stack.max(sf.stack.len())];
+        let mut ctx = JitContext {
+            sp: stack.as_mut_ptr(),
+            locals: locals.as_mut_ptr(),
+            stack: stack.as_mut_ptr(),
+            sf,
+            bytecode,
+            values: Vec::new(),
+            action: None,
+            instr_idx: *instr_idx
+        };
+        ctx.load();
+
+        let entry: extern "C" fn(*mut JitContext, usize) -> u64 = unsafe { std::mem::transmute(self.memory) };
+        entry(&mut ctx, *instr_idx);
+
+        *instr_idx = ctx.instr_idx;
+        match ctx.action.take() {
+            Some(action) => action,
+            None => panic!("Compiled code of {}{} ended without an action", bytecode.method_name, bytecode.type_desc)
+        }
+    }
+}
+
+impl Drop for JitCode {
+    fn drop(&mut self) {
+        unsafe { munmap(self.memory, self.size); }
+    }
+}
+
+fn local(idx: usize) -> Mem { Mem::new(Reg::R12, idx as i32 * SLOT_SIZE) }
+fn local_tag(idx: usize) -> Mem { Mem::new(Reg::R12, idx as i32 * SLOT_SIZE + 8) }
+// Operand stack entry, from the top (1 is the last one)
+fn top(nb: i32) -> Mem { Mem::new(Reg::R13, -nb * SLOT_SIZE) }
+fn top_tag(nb: i32) -> Mem { Mem::new(Reg::R13, -nb * SLOT_SIZE + 8) }
+
+fn push_tag(enc: &mut Encoder, tag: u64) {
+    enc.mov_mi(top_tag(0), tag as i32);
+    enc.alu_ri(Alu::Add, true, Reg::R13, SLOT_SIZE);
+}
+
+fn pop(enc: &mut Encoder, nb: i32) {
+    enc.alu_ri(Alu::Sub, true, Reg::R13, nb * SLOT_SIZE);
+}
+
+fn copy_slot(enc: &mut Encoder, from_value: Mem, from_tag: Mem, to_value: Mem, to_tag: Mem) {
+    enc.mov_rm(Reg::RAX, from_value);
+    enc.mov_rm(Reg::RCX, from_tag);
+    enc.mov_mr(to_value, Reg::RAX);
+    enc.mov_mr(to_tag, Reg::RCX);
+}
+
+// Pops the second operand, and replaces the first operand with the result
+fn int_binary(enc: &mut Encoder, wide: bool, alu: Option<Alu>) {
+    pop(enc, 1);
+    if wide {
+        enc.mov_rm(Reg::RAX, top(1));
+        enc.mov_rm(Reg::RCX, top(0));
+    } else {
+        enc.mov32_rm(Reg::RAX, top(1));
+        enc.mov32_rm(Reg::RCX, top(0));
+    }
+    match alu {
+        Some(alu) => enc.alu_rr(alu, wide, Reg::RAX, Reg::RCX),
+        None => enc.imul_rr(wide, Reg::RAX, Reg::RCX)
+    }
+    enc.mov_mr(top(1), Reg::RAX);
+}
+
+fn int_shift(enc: &mut Encoder, shift: Shift) {
+    pop(enc, 1);
+    enc.mov32_rm(Reg::RCX, top(0));
+    enc.mov32_rm(Reg::RAX, top(1));
+    enc.shift_cl(shift, false, Reg::RAX);
+    enc.mov_mr(top(1), Reg::RAX);
+}
+
+fn float_binary(enc: &mut Encoder, double: bool, op: Sse) {
+    pop(enc, 1);
+    enc.movs_rm(double, Xmm(0), top(1));
+    enc.sse_op_rm(op, double, Xmm(0), top(0));
+    enc.movs_mr(double, top(1), Xmm(0));
+}
+
+// Replaces the operand with its conversion, from an integer (wide for a long) into a float or a double
+fn int_to_float(enc: &mut Encoder, wide: bool, double: bool) {
+    enc.cvtsi2s_rm(double, wide, Xmm(0), top(1));
+    enc.movs_mr(double, top(1), Xmm(0));
+    enc.mov_mi(top_tag(1), if double { TAG_DOUBLE } else { TAG_FLOAT } as i32);
+}
+
+// 1, 0 or -1 from the flags of a comparison: greater is set if the first operand is greater, and less if it is less
+fn compare_result(enc: &mut Encoder, greater: Cond, less: Cond) {
+    enc.setcc(greater, Reg::RCX);
+    enc.setcc(less, Reg::RDX);
+    enc.movzx8_rr(Reg::RCX, Reg::RCX);
+    enc.movzx8_rr(Reg::RDX, Reg::RDX);
+    enc.alu_rr(Alu::Sub, false, Reg::RCX, Reg::RDX);
+    enc.mov_mr(top(1), Reg::RCX);
+    enc.mov_mi(top_tag(1), TAG_INT as i32);
+}
+
+fn emit_callback(enc: &mut Encoder, instr_idx: usize, branch: Option<Label>, exit: Label) {
+    enc.mov_mr(Mem::new(Reg::RBX, CTX_SP), Reg::R13);
+    enc.mov_rr(Reg::RDI, Reg::RBX);
+    enc.mov_ri(Reg::RSI, instr_idx as i64);
+    enc.mov_ri(Reg::RAX, jit_callback as *const () as usize as i64);
+    enc.call_r(Reg::RAX);
+    enc.mov_rm(Reg::R13, Mem::new(Reg::RBX, CTX_SP));
+    enc.alu_ri(Alu::Cmp, false, Reg::RAX, STATUS_EXIT as i32);
+    enc.jcc(Cond::E, exit);
+    if let Some(branch) = branch {
+        enc.alu_ri(Alu::Cmp, false, Reg::RAX, STATUS_BRANCH as i32);
+        enc.jcc(Cond::E, branch);
+    }
+}
+
+// Generates the machine code of a method. Registers: rbx is the context, r12 the local variables and r13 the top of the operand stack
+fn generate(bytecode: &ByteCode) -> (Vec<u8>, Vec<usize>) {
+    let mut enc = Encoder::new();
+    let labels: Vec<Label> = bytecode.ops.iter().map(|_| enc.new_label()).collect();
+    let exit = enc.new_label();
+    let table = enc.new_label();
+
+    // The entry point is void f(JitContext *ctx, size_t instr_idx). Three pushes keep the stack aligned on 16 bytes for calls
+    enc.push(Reg::RBX);
+    enc.push(Reg::R12);
+    enc.push(Reg::R13);
+    enc.mov_rr(Reg::RBX, Reg::RDI);
+    enc.mov_rm(Reg::R12, Mem::new(Reg::RBX, CTX_LOCALS));
+    enc.mov_rm(Reg::R13, Mem::new(Reg::RBX, CTX_SP));
+    enc.lea_label(Reg::RAX, table);
+    enc.jmp_m(Mem::indexed(Reg::RAX, Reg::RSI, 8, 0));
+
+    for (instr_idx, op) in bytecode.ops.iter().enumerate() {
+        enc.bind(labels[instr_idx]);
+        match *op {
+            Op::Nop => {},
+            Op::IConst(value) => {
+                enc.mov_mi(top(0), value);
+                push_tag(&mut enc, TAG_INT);
+            },
+            Op::LConst(value) => {
+                enc.mov_ri(Reg::RAX, value);
+                enc.mov_mr(top(0), Reg::RAX);
+                push_tag(&mut enc, TAG_LONG);
+            },
+            Op::FConst(value) => {
+                enc.mov_mi(top(0), value.to_bits() as i32);
+                push_tag(&mut enc, TAG_FLOAT);
+            },
+            Op::DConst(value) => {
+                enc.mov_ri(Reg::RAX, value.to_bits() as i64);
+                enc.mov_mr(top(0), Reg::RAX);
+                push_tag(&mut enc, TAG_DOUBLE);
+            },
+            Op::Load(idx) => {
+                copy_slot(&mut enc, local(idx), local_tag(idx), top(0), top_tag(0));
+                enc.alu_ri(Alu::Add, true, Reg::R13, SLOT_SIZE);
+            },
+            Op::Store(idx) => {
+                pop(&mut enc, 1);
+                copy_slot(&mut enc, top(0), top_tag(0), local(idx), local_tag(idx));
+            },
+            Op::IInc(idx, count) => enc.alu_mi(Alu::Add, false, local(idx), count),
+            Op::Pop => pop(&mut enc, 1),
+            Op::Dup => {
+                copy_slot(&mut enc, top(1), top_tag(1), top(0), top_tag(0));
+                enc.alu_ri(Alu::Add, true, Reg::R13, SLOT_SIZE);
+            },
+            Op::IAdd => int_binary(&mut enc, false, Some(Alu::Add)),
+            Op::ISub => int_binary(&mut enc, false, Some(Alu::Sub)),
+            Op::IMul => int_binary(&mut enc, false, None),
+            Op::IAnd => int_binary(&mut enc, false, Some(Alu::And)),
+            Op::IOr => int_binary(&mut enc, false, Some(Alu::Or)),
+            Op::IXor => int_binary(&mut enc, false, Some(Alu::Xor)),
+            Op::LAdd => int_binary(&mut enc, true, Some(Alu::Add)),
+            Op::LSub => int_binary(&mut enc, true, Some(Alu::Sub)),
+            Op::LMul => int_binary(&mut enc, true, None),
+            Op::LAnd => int_binary(&mut enc, true, Some(Alu::And)),
+            Op::LOr => int_binary(&mut enc, true, Some(Alu::Or)),
+            Op::LXor => int_binary(&mut enc, true, Some(Alu::Xor)),
+            Op::IShl => int_shift(&mut enc, Shift::Shl),
+            Op::IShr => int_shift(&mut enc, Shift::Sar),
+            Op::INeg => enc.neg_m(false, top(1)),
+            Op::LNeg => enc.neg_m(true, top(1)),
+            Op::FAdd => float_binary(&mut enc, false, Sse::Add),
+            Op::FSub => float_binary(&mut enc, false, Sse::Sub),
+            Op::FMul => float_binary(&mut enc, false, Sse::Mul),
+            Op::DAdd => float_binary(&mut enc, true, Sse::Add),
+            Op::DSub => float_binary(&mut enc, true, Sse::Sub),
+            Op::DMul => float_binary(&mut enc, true, Sse::Mul),
+            Op::FNeg => enc.alu_mi(Alu::Xor, false, top(1), i32::MIN),
+            Op::DNeg => enc.btc_mi(top(1), 63),
+            Op::I2L => {
+                enc.movsxd_rm(Reg::RAX, top(1));
+                enc.mov_mr(top(1), Reg::RAX);
+                enc.mov_mi(top_tag(1), TAG_LONG as i32);
+            },
+            Op::L2I => enc.mov_mi(top_tag(1), TAG_INT as i32),
+            Op::I2F => int_to_float(&mut enc, false, false),
+            Op::I2D => int_to_float(&mut enc, false, true),
+            Op::L2F => int_to_float(&mut enc, true, false),
+            Op::L2D => int_to_float(&mut enc, true, true),
+            Op::F2D => {
+                enc.cvts2s_rm(false, Xmm(0), top(1));
+                enc.movs_mr(true, top(1), Xmm(0));
+                enc.mov_mi(top_tag(1), TAG_DOUBLE as i32);
+            },
+            Op::D2F => {
+                enc.cvts2s_rm(true, Xmm(0), top(1));
+                enc.movs_mr(false, top(1), Xmm(0));
+                enc.mov_mi(top_tag(1), TAG_FLOAT as i32);
+            },
+            Op::LCmp => {
+                pop(&mut enc, 1);
+                enc.mov_rm(Reg::RAX, top(1));
+                enc.alu_rm(Alu::Cmp, true, Reg::RAX, top(0));
+                compare_result(&mut enc, Cond::G, Cond::L);
+            },
+            // NaN sets the below flag, which is less for fcmpl and dcmpl, and greater for fcmpg and dcmpg
+            Op::FCmpl | Op::FCmpg | Op::DCmpl | Op::DCmpg => {
+                let double = matches!(op, Op::DCmpl | Op::DCmpg);
+                pop(&mut enc, 1);
+                enc.movs_rm(double, Xmm(0), top(1));
+                enc.movs_rm(double, Xmm(1), top(0));
+                if matches!(op, Op::FCmpl | Op::DCmpl) {
+                    enc.ucomis_rr(double, Xmm(0), Xmm(1));
+                    enc.setcc(Cond::A, Reg::RCX);
+                    enc.setcc(Cond::B, Reg::RDX);
+                } else {
+                    enc.ucomis_rr(double, Xmm(1), Xmm(0));
+                    enc.setcc(Cond::B, Reg::RCX);
+                    enc.setcc(Cond::A, Reg::RDX);
+                }
+                enc.movzx8_rr(Reg::RCX, Reg::RCX);
+                enc.movzx8_rr(Reg::RDX, Reg::RDX);
+                enc.alu_rr(Alu::Sub, false, Reg::RCX, Reg::RDX);
+                enc.mov_mr(top(1), Reg::RCX);
+                enc.mov_mi(top_tag(1), TAG_INT as i32);
+            },
+            Op::IfEq(branch) | Op::IfNe(branch) | Op::IfLt(branch) | Op::IfGe(branch) | Op::IfGt(branch) | Op::IfLe(branch) => {
+                pop(&mut enc, 1);
+                enc.alu_mi(Alu::Cmp, false, top(0), 0);
+                enc.jcc(get_condition(*op), labels[branch]);
+            },
+            Op::IfICmpEq(branch) | Op::IfICmpNe(branch) | Op::IfICmpLt(branch) | Op::IfICmpGe(branch) | Op::IfICmpGt(branch) | Op::IfICmpLe(branch) => {
+                pop(&mut enc, 2);
+                enc.mov32_rm(Reg::RAX, top(0));
+                enc.alu_rm(Alu::Cmp, false, Reg::RAX, top(-1));
+                enc.jcc(get_condition(*op), labels[branch]);
+            },
+            Op::Goto(branch) => enc.jmp(labels[branch]),
+            // Everything else runs in the interpreter, e.g. the conversions of floats into integers, which saturate
+            _ => {
+                let branch = get_branch(*op).map(|branch| labels[branch]);
+                emit_callback(&mut enc, instr_idx, branch, exit);
+            }
+        }
+    }
+    // Compiled code always leaves through a return or a callback
+    enc.ud2();
+
+    enc.bind(exit);
+    enc.pop(Reg::R13);
+    enc.pop(Reg::R12);
+    enc.pop(Reg::RBX);
+    enc.ret();
+
+    // Jump table of the entry points, filled once the code has its address
+    while enc.code.len() % 8 != 0 {
+        enc.int3();
+    }
+    enc.bind(table);
+    let offsets: Vec<usize> = labels.iter().map(|label| enc.label_offset(*label)).collect();
+    (enc.finish(), offsets)
+}
+
+fn get_condition(op: Op) -> Cond {
+    match op {
+        Op::IfEq(_) | Op::IfICmpEq(_) => Cond::E,
+        Op::IfNe(_) | Op::IfICmpNe(_) => Cond::NE,
+        Op::IfLt(_) | Op::IfICmpLt(_) => Cond::L,
+        Op::IfGe(_) | Op::IfICmpGe(_) => Cond::GE,
+        Op::IfGt(_) | Op::IfICmpGt(_) => Cond::G,
+        Op::IfLe(_) | Op::IfICmpLe(_) => Cond::LE,
+        _ => panic!("Not a conditional branch")
+    }
+}
+
+extern "C" {
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+    fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn compile(bytecode: &ByteCode) -> Option<JitCode> {
+    let (mut code, offsets) = generate(bytecode);
+    let size = code.len() + offsets.len() * 8;
+
+    let memory = unsafe { mmap(std::ptr::null_mut(), size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) };
+    if memory as isize == -1 {
+        return None;
+    }
+    for offset in offsets.iter() {
+        code.extend_from_slice(&(memory as u64 + *offset as u64).to_le_bytes());
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(code.as_ptr(), memory, size);
+        if mprotect(memory, size, PROT_READ | PROT_EXEC) != 0 {
+            munmap(memory, size);
+            return None;
+        }
+    }
+    Some(JitCode { memory, size })
+}
+
+// Other platforms stay in the interpreter
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn compile(_bytecode: &ByteCode) -> Option<JitCode> {
+    None
+}
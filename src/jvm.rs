@@ -1,22 +1,26 @@
 use std::sync::{Arc, Mutex};
 
-use crate::native_java_classes::{NativeArrayInstance, NativeByteInstance, NativeCharInstance, NativeNullInstance, NativeShortInstance};
-use crate::native_java_classes::NativeBooleanInstance;
+use crate::native_java_classes::{NativeArrayInstance, int_to_char, null_instance};
 use crate::native_java_classes::NativeFloatInstance;
 use crate::native_java_classes::NativeDoubleInstance;
 use crate::native_java_classes::NativeIntegerInstance;
 use crate::native_java_classes::NativeLongInstance;
 use crate::native_java_classes::NativeStringInstance;
-use crate::streams::StreamFunction;
+use crate::native_java_classes::new_java_exception;
+use crate::streams::{Lambda, StreamFunction};
+use crate::threads::JavaThread;
+use crate::java_class::MethodCallResult;
 
 //////////////////////////////////////////
 
-pub trait JavaInstance {
+pub trait JavaInstance: Send {
     fn is_bytecode(&self) -> bool { false }
 //    fn get_parent(&self) -> Option<Arc<Mutex<dyn JavaInstance>>> { None }
     fn cast_as(&self, _this: Arc<Mutex<dyn JavaInstance>>, class_name: &String) -> Arc<Mutex<dyn JavaInstance>> { panic!("Instance of class {} cannot be cast into a {}", self.get_class_name(), class_name); }
     fn supports_interface(&self, _interface_name: &String) -> bool { false }
     fn get_class_name(&self) -> String;
+    // Compares the class of the object, which bytecode objects do without building its name
+    fn has_class_name(&self, class_name: &str) -> bool { self.get_class_name() == class_name }
     fn get_int(&self) -> i32 { panic!("{} cannot be converted into an integer", self.get_class_name()); }
     fn get_long(&self) -> i64 { panic!("{} cannot be converted into an long", self.get_class_name()); }
     fn get_float(&self) -> f32 { panic!("{} cannot be converted into a float", self.get_class_name()); }
@@ -24,37 +28,143 @@ pub trait JavaInstance {
     fn get_string(&self) -> String { panic!("{} cannot be converted into a string", self.get_class_name()); }
     fn get_bool(&self) -> bool { panic!("{} cannot be converted into a boolean", self.get_class_name()); }
     fn get_short(&self) -> i16 { panic!("{} cannot be converted into a short", self.get_class_name()); }
-    fn get_byte(&self) -> u8 { panic!("{} cannot be converted into a byte", self.get_class_name()); }
     fn get_char(&self) -> char { panic!("{} cannot be converted into a char", self.get_class_name()); }
     fn get_array(&self) -> Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>> { panic!("{} cannot be converted into an array", self.get_class_name()); }
     fn is_null(&self) -> bool { false }
+    // Integers, longs, floats and doubles are kept unboxed on the operand stack and in local variables
+    fn get_primitive(&self) -> Option<Value> { None }
 
-    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
-        panic!("{} does not support any method ({} requested)", self.get_class_name(), method_name);
+    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
+        MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}{}", self.get_class_name(), method_name, type_desc)))
     }
-    fn get_field(&self, field_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
-        panic!("This {} instance has no field ({} requested)", self.get_class_name(), field_name);
+    fn get_field(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_class_name(), field_name)))
     }
-    fn set_field(&mut self, field_name: &String, _value: Arc<Mutex<dyn JavaInstance>>) {
-        panic!("This {} instance has no field to update ({} requested)", self.get_class_name(), field_name);
+    fn set_field(&mut self, field_name: &String, _value: Arc<Mutex<dyn JavaInstance>>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_class_name(), field_name)))
     }
     fn get_stream_function(&self) -> Arc<Mutex<dyn StreamFunction>> { panic!("{} cannot be converted into a StreamFunction", self.get_class_name()); }
+    fn get_lambda(&self) -> Option<Arc<Lambda>> { None }
+    fn get_thread(&self) -> Arc<JavaThread> { panic!("{} cannot be converted into a Thread", self.get_class_name()); }
     fn print(&self) {
         print!("<{} instance>", self.get_class_name());
     }
 }
 
+// A slot of the operand stack or of the local variables. Primitive values are stored inline,
+// only references point to heap objects
+#[derive(Clone)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Reference(Arc<Mutex<dyn JavaInstance>>),
+    // Pushed by jsr, the index of the instruction following it
+    ReturnAddress(usize)
+}
+
+impl Value {
+    pub fn null() -> Value {
+        Value::Reference(null_instance())
+    }
+
+    // Primitive objects read from a field or an array are unboxed. This locks the object, so it must not
+    // be used for references that may be locked by the current thread
+    pub fn from_object(object: Arc<Mutex<dyn JavaInstance>>) -> Value {
+        let primitive = object.lock().unwrap().get_primitive();
+        primitive.unwrap_or(Value::Reference(object))
+    }
+
+    // Boxes primitive values, to store them in fields and arrays
+    pub fn to_object(&self) -> Arc<Mutex<dyn JavaInstance>> {
+        match self {
+            Value::Int(value) => Arc::new(Mutex::new(NativeIntegerInstance::new(*value))),
+            Value::Long(value) => Arc::new(Mutex::new(NativeLongInstance::new(*value))),
+            Value::Float(value) => Arc::new(Mutex::new(NativeFloatInstance::new(*value))),
+            Value::Double(value) => Arc::new(Mutex::new(NativeDoubleInstance::new(*value))),
+            Value::Reference(object) => object.clone(),
+            Value::ReturnAddress(address) => panic!("Return address {} cannot be converted into an object", address)
+        }
+    }
+
+    // Longs and doubles are category 2 computational types (JVMS §2.11.1): they count as two entries
+    // for the stack manipulation instructions, but are stored as a single one
+    pub fn is_category2(&self) -> bool { matches!(self, Value::Long(_) | Value::Double(_)) }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            Value::Reference(object) => object.lock().unwrap().is_null(),
+            _ => false
+        }
+    }
+
+    pub fn get_int(&self) -> i32 {
+        match self {
+            Value::Int(value) => *value,
+            _ => self.to_object().lock().unwrap().get_int()
+        }
+    }
+    pub fn get_long(&self) -> i64 {
+        match self {
+            Value::Long(value) => *value,
+            _ => self.to_object().lock().unwrap().get_long()
+        }
+    }
+    pub fn get_float(&self) -> f32 {
+        match self {
+            Value::Float(value) => *value,
+            _ => self.to_object().lock().unwrap().get_float()
+        }
+    }
+    pub fn get_double(&self) -> f64 {
+        match self {
+            Value::Double(value) => *value,
+            _ => self.to_object().lock().unwrap().get_double()
+        }
+    }
+    // Booleans, bytes, chars and shorts are ints in the JVM
+    pub fn get_bool(&self) -> bool {
+        match self {
+            Value::Int(value) => *value != 0,
+            _ => self.to_object().lock().unwrap().get_bool()
+        }
+    }
+    pub fn get_short(&self) -> i16 {
+        match self {
+            Value::Int(value) => *value as i16,
+            _ => self.to_object().lock().unwrap().get_short()
+        }
+    }
+    pub fn get_char(&self) -> char {
+        match self {
+            Value::Int(value) => int_to_char(*value),
+            _ => self.to_object().lock().unwrap().get_char()
+        }
+    }
+    pub fn get_string(&self) -> String { self.to_object().lock().unwrap().get_string() }
+    pub fn get_array(&self) -> Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>> { self.to_object().lock().unwrap().get_array() }
+
+    pub fn print(&self) {
+        match self {
+            Value::ReturnAddress(address) => print!("<return address {}>", address),
+            _ => self.to_object().lock().unwrap().print()
+        }
+    }
+}
+
 pub struct StackFrame {
-    stack: Vec<Arc<Mutex<dyn JavaInstance>>>,
-    variables: [Arc<Mutex<dyn JavaInstance>>; 16],
+    pub(crate) stack: Vec<Value>,
+    pub(crate) variables: Vec<Value>,
     pub return_arg: bool
 }
 
 impl StackFrame {
-    pub fn new(variables: [Arc<Mutex<dyn JavaInstance>>; 16]) -> StackFrame {
+    // Sized from the max_locals and max_stack of the Code attribute
+    pub fn new(max_locals: usize, max_stack: usize) -> StackFrame {
         StackFrame {
-            stack: Vec::new(),
-            variables,
+            stack: Vec::with_capacity(max_stack),
+            variables: vec![Value::null(); max_locals],
             return_arg: false
         }
     }
@@ -63,47 +173,55 @@ impl StackFrame {
         self.return_arg = true;
     }
 
-    pub fn push(&mut self, object: Arc<Mutex<dyn JavaInstance>>) { self.stack.push(object.clone()); }
-    pub fn pop(&mut self) -> Arc<Mutex<dyn JavaInstance>> { return self.stack.pop().unwrap(); }
+    pub fn push_value(&mut self, value: Value) { self.stack.push(value); }
+    pub fn pop_value(&mut self) -> Value { return self.stack.pop().unwrap(); }
 
-    pub fn push_null(&mut self) { self.push(Arc::new(Mutex::new(NativeNullInstance::new()))); }
-    pub fn pop_isnull(&mut self) -> bool { return (*self.pop()).lock().unwrap().is_null(); }
+    pub fn push(&mut self, object: Arc<Mutex<dyn JavaInstance>>) { self.push_value(Value::Reference(object)); }
+    pub fn clear_stack(&mut self) { self.stack.clear(); }
+    pub fn pop(&mut self) -> Arc<Mutex<dyn JavaInstance>> { return self.pop_value().to_object(); }
 
-    pub fn pop_int(&mut self) -> i32 { return (*self.pop()).lock().unwrap().get_int(); }
-    pub fn push_int(&mut self, value: i32) { self.push(Arc::new(Mutex::new(NativeIntegerInstance::new(value)))); }
+    pub fn push_null(&mut self) { self.push_value(Value::null()); }
+    pub fn pop_isnull(&mut self) -> bool { return self.pop_value().is_null(); }
 
-    pub fn pop_long(&mut self) -> i64 { return (*self.pop()).lock().unwrap().get_long(); }
-    pub fn push_long(&mut self, value: i64) { self.push(Arc::new(Mutex::new(NativeLongInstance::new(value)))); }
+    pub fn pop_int(&mut self) -> i32 { return self.pop_value().get_int(); }
+    pub fn push_int(&mut self, value: i32) { self.push_value(Value::Int(value)); }
 
-    pub fn pop_short(&mut self) -> i16 { return (*self.pop()).lock().unwrap().get_short(); }
-    pub fn push_short(&mut self, value: i16) { self.push(Arc::new(Mutex::new(NativeShortInstance::new(value)))); }
+    pub fn pop_long(&mut self) -> i64 { return self.pop_value().get_long(); }
+    pub fn push_long(&mut self, value: i64) { self.push_value(Value::Long(value)); }
 
-    pub fn pop_byte(&mut self) -> u8 { return (*self.pop()).lock().unwrap().get_byte(); }
-    pub fn push_byte(&mut self, value: u8) { self.push(Arc::new(Mutex::new(NativeByteInstance::new(value)))); }
+    pub fn push_short(&mut self, value: i16) { self.push_value(Value::Int(value as i32)); }
 
-    pub fn pop_char(&mut self) -> char { return (*self.pop()).lock().unwrap().get_char(); }
-    pub fn push_char(&mut self, value: char) { self.push(Arc::new(Mutex::new(NativeCharInstance::new(value)))); }
+    // Java bytes are signed (e.g. bipush -3)
+    pub fn push_byte(&mut self, value: u8) { self.push_value(Value::Int(value as i8 as i32)); }
 
-    pub fn pop_float(&mut self) -> f32 { return (*self.pop()).lock().unwrap().get_float(); }
-    pub fn push_float(&mut self, value: f32) { self.push(Arc::new(Mutex::new(NativeFloatInstance::new(value)))); }
+    pub fn pop_float(&mut self) -> f32 { return self.pop_value().get_float(); }
+    pub fn push_float(&mut self, value: f32) { self.push_value(Value::Float(value)); }
 
-    pub fn pop_double(&mut self) -> f64 { return (*self.pop()).lock().unwrap().get_double(); }
-    pub fn push_double(&mut self, value: f64) { self.push(Arc::new(Mutex::new(NativeDoubleInstance::new(value)))); }
+    pub fn pop_double(&mut self) -> f64 { return self.pop_value().get_double(); }
+    pub fn push_double(&mut self, value: f64) { self.push_value(Value::Double(value)); }
 
-    pub fn pop_string(&mut self) -> String { return (*self.pop()).lock().unwrap().get_string(); }
+    pub fn pop_string(&mut self) -> String { return self.pop_value().get_string(); }
     pub fn push_string(&mut self, value: String) { self.push(Arc::new(Mutex::new(NativeStringInstance::new(value)))); }
 
-    pub fn pop_bool(&mut self) -> bool { return (*self.pop()).lock().unwrap().get_bool(); }
-    pub fn push_bool(&mut self, value: bool) { self.push(Arc::new(Mutex::new(NativeBooleanInstance::new(value)))); }
+    pub fn pop_bool(&mut self) -> bool { return self.pop_value().get_bool(); }
+    pub fn push_bool(&mut self, value: bool) { self.push_value(Value::Int(value as i32)); }
 
-    pub fn pop_array(&mut self) -> Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>> {
-        let object = self.pop();
-        return object.lock().unwrap().get_array();
-    }
+    pub fn pop_array(&mut self) -> Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>> { return self.pop_value().get_array(); }
     pub fn push_array(&mut self, value: Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>>) { self.push(Arc::new(Mutex::new(NativeArrayInstance { values: value }))); }
 
+    pub fn set_variable(&mut self, idx: usize, value: Value) {
+        self.variables[idx] = value;
+    }
+
+    pub fn get_return_address(&self, idx: usize) -> usize {
+        match self.variables[idx] {
+            Value::ReturnAddress(address) => address,
+            _ => panic!("Variable {} is not a return address", idx)
+        }
+    }
+
     pub fn stack_to_variable(&mut self, idx: usize) {
-        self.variables[idx] = self.stack.pop().unwrap().clone();
+        self.variables[idx] = self.stack.pop().unwrap();
     }
 
     pub fn variable_to_stack(&mut self, idx: usize) {
@@ -112,17 +230,9 @@ impl StackFrame {
 
     pub fn print_stack(&self) {
         println!("    Stack:");
-        for frame in &self.stack {
+        for value in &self.stack {
             print!("    > ");
-            (**frame).lock().unwrap().print();
-            println!("");
-        }
-    }
-
-    pub fn print_variables(&self) {
-        for i in 0..8 {
-            print!("    Var {}: ", i);
-            (*self.variables[i]).lock().unwrap().print();
+            value.print();
             println!("");
         }
     }
@@ -0,0 +1,29 @@
+// A Java Virtual Machine which can be embedded: create a Vm, load classes from its classpath, call their
+// methods with Rust values, and get back their results or the Java exceptions they threw
+//
+//     let vm = Vm::new(ClassPath::parse("classes:lib/*"), 0);
+//     let class = vm.load_class("com.acme.Calculator")?;
+//     let sum = class.invoke_static("add", "(II)I", &[JavaValue::Int(1), JavaValue::Int(2)])?;
+
+mod java_class;
+mod jvm;
+mod bytecode;
+mod bytecode_class;
+mod native_java_classes;
+mod streams;
+mod bytecode_test;
+mod asm;
+mod classpath;
+mod monitor;
+mod threads;
+mod x64;
+mod elf;
+mod jit;
+mod ir;
+mod runtime;
+mod vm;
+mod api;
+
+pub use crate::api::{Class, JavaException, JavaValue, NativeClass, NativeFunction, Object};
+pub use crate::classpath::{ClassPath, Manifest, to_internal_name};
+pub use crate::vm::Vm;
@@ -1,111 +1,47 @@
-mod java_class;
-mod jvm;
-mod bytecode;
-mod bytecode_class;
-mod native_java_classes;
-mod streams;
-mod bytecode_test;
-mod asm;
-
-use std::collections::HashSet;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::thread;
-use std::time::Duration;
-
 extern crate clap;
 use clap::{Arg, App};
-use java_class::MethodCallResult;
-use jvm::JavaInstance;
-use native_java_classes::{NativeGenericClass, NativeNullInstance};
-use native_java_classes::NativeStringInstance;
-
-use crate::java_class::JavaClass;
-use crate::bytecode_class::BytecodeClass;
-use crate::native_java_classes::register_native_classes;
-use crate::jvm::StackFrame;
-
-struct Classes {
-    classes: Option<HashMap<String, Arc<dyn JavaClass>>>
-}
-
-impl Classes {
-    fn add(&mut self, value: Arc<dyn JavaClass>) {
-        let key = value.get_name();
-        self.classes.as_mut().unwrap().insert(key, value);
-    }
-
-    fn has(&self, class_name: &String) -> bool {
-        self.classes.as_ref().unwrap().contains_key(class_name)
-    }
-
-    fn all(&self) -> Vec<Arc<dyn JavaClass>> {
-        let mut classes: Vec<Arc<dyn JavaClass>> = Vec::new();
-        
-        let map = match self.classes.as_ref() {
-            Some(m) => m,
-            _ => return Vec::new()
-        };
-
-        for class in map.values() {
-            classes.push(class.clone());
-        }
-
-        classes
-    }
 
-    fn add_bytecode(&mut self, name: String) -> Arc<dyn JavaClass> {
-//        let ptr: Arc<dyn JavaClass> = Arc::new(BytecodeClass::parse(&name));
-
-        let class = Arc::new(BytecodeClass::parse(&name));
-        self.classes.as_mut().unwrap().insert(name.clone(), class.clone());
-        class
-    }
-}
-
-// An (unfinished) attempt to have the classes available as a global variable
-static mut CLASSES: Classes = Classes { classes: None };
-static mut DEBUG: u8 = 0;
-
-pub fn get_debug() -> u8 { unsafe { DEBUG } }
-pub fn get_class(class_name: &String) -> Arc<dyn JavaClass> {
-    unsafe {
-        match &CLASSES.classes {
-            Some(map) => {
-                let arrays_name = "java/util/Arrays".to_string();
-                let class_name_to_find = if class_name.starts_with("[") { &arrays_name } else { class_name };
-                match map.get(class_name_to_find) {
-                    Some(class) => class.clone(),
-                    _ => panic!("Class {} not found", class_name_to_find)
-                }
-            },
-            _ => panic!("Class repository not initialized (key {} not found)", class_name)
+use jvm::{ClassPath, JavaValue, Vm, to_internal_name};
+
+// Options followed by their value
+const OPTIONS_WITH_VALUE: [&str; 8] = ["-d", "--debug", "-a", "--asm", "--emit", "-cp", "-classpath", "--classpath"];
+
+// clap only supports single-letter short options, so the java-style -cp, -classpath and -jar are turned into
+// --classpath and --jar. Like with java, the options stop at the main class: the arguments of the program are kept
+// as they are, after a -- so that clap does not parse them
+fn to_clap_arguments(args: Vec<String>) -> Vec<String> {
+    let mut clap_args: Vec<String> = Vec::new();
+    let mut args = args.into_iter();
+    // The name of the program
+    clap_args.extend(args.next());
+
+    while let Some(arg) = args.next() {
+        // With -jar, the main class comes from the manifest, so the arguments of the program follow the jar
+        if arg.eq("-jar") || arg.eq("--jar") {
+            clap_args.push("--jar".to_string());
+            clap_args.extend(args.next());
+            clap_args.push("--".to_string());
+            clap_args.extend(args);
+            break;
         }
-    }
-}
-pub fn class_exists(class_name: &String) -> bool {
-    unsafe {
-        match &CLASSES.classes {
-            Some(map) => {
-                let arrays_name = "java/util/Arrays".to_string();
-                let class_name_to_find = if class_name.starts_with("[") { &arrays_name } else { class_name };
-                map.contains_key(class_name_to_find)
-            },
-            _ => panic!("Class repository not initialized (key {} not found)", class_name)
+        if !arg.starts_with("-") {
+            clap_args.push("--".to_string());
+            clap_args.push(arg);
+            clap_args.extend(args);
+            break;
         }
+        let has_value = OPTIONS_WITH_VALUE.contains(&&arg[..]);
+        clap_args.push(match &arg[..] {
+            "-cp" | "-classpath" => "--classpath".to_string(),
+            _ => arg
+        });
+        if has_value { clap_args.extend(args.next()); }
     }
-}
-pub fn get_classes() -> Vec<Arc<dyn JavaClass>> {
-    unsafe {
-        CLASSES.all()
-    }
+    clap_args
 }
 
-static GLOBAL_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
-
 fn main() {
-    // Parses arguments
+    let args = to_clap_arguments(std::env::args().collect());
     let matches = App::new("JVM")
         .version("0.1.0")
         .arg(Arg::with_name("debug")
@@ -118,121 +54,109 @@ fn main() {
                 .long("asm")
                 .takes_value(true)
                 .help("Compiles into assembly (macos or linux)"))
+        .arg(Arg::with_name("emit")
+                .long("emit")
+                .takes_value(true)
+                .possible_values(&["asm", "exe"])
+                .requires("asm")
+                .help("Output of --asm: assembly source (default), or an executable for linux"))
+        .arg(Arg::with_name("classpath")
+                .long("classpath")
+                .takes_value(true)
+                .help("Directories and jar files to search for class files, separated by ':'"))
+        .arg(Arg::with_name("jar")
+                .long("jar")
+                .takes_value(true)
+                .conflicts_with("classpath")
+                .help("Executable jar file to run, using the Main-Class and Class-Path of its manifest"))
         .arg(Arg::with_name("class")
                 .takes_value(false)
-                .required(true))
+                .required_unless("jar"))
         .arg(Arg::with_name("arguments")
                 .takes_value(false)
                 .multiple(true))
-        .get_matches();
+        .get_matches_from(args);
 
     let debug: u8 = match matches.value_of("debug") {
         Some(st) => st.parse::<u8>().unwrap(),
         _ => 0
     };
-    unsafe {
-        DEBUG = debug;
-    }
     let asm = matches.value_of("asm");
-    let class_name = matches.value_of("class").unwrap();
-    let arguments: Vec<&str> = match matches.values_of("arguments") {
+    let emit = matches.value_of("emit").unwrap_or("asm");
+    let mut arguments: Vec<&str> = match matches.values_of("arguments") {
         Some(values) => values.collect(),
         _ => Vec::new()
     };
 
-    // Setup the class repository
-    unsafe {
-        CLASSES.classes = Some(HashMap::new());
-    }
-    register_native_classes();
-
-    // Load the class and all the dependencies
-    let mut classes_to_load: HashSet<String> = HashSet::new();
-    classes_to_load.insert(class_name.to_string());
-
-    while classes_to_load.len() > 0 {
-        for class_name in classes_to_load.clone().iter() {
-            let java_class: Arc<dyn JavaClass> = if class_name.starts_with("java/lang") {
-                Arc::new(NativeGenericClass { name: class_name.clone() })
-            } else {
-                unsafe { CLASSES.add_bytecode(class_name.to_string()) }
-            };
-
-            for dependent_class_name in java_class.get_dependent_classes().iter() {
-                if !dependent_class_name.starts_with("[") && unsafe { !CLASSES.has(&dependent_class_name) } {
-                    classes_to_load.insert(dependent_class_name.clone());
-                }
+    let (classpath, class_name) = match matches.value_of("jar") {
+        // With -jar, the main class comes from the manifest, so the first positional
+        // value is actually the first argument passed to the program
+        Some(jar) => {
+            if let Some(arg) = matches.value_of("class") {
+                arguments.insert(0, arg);
             }
-
-            classes_to_load.remove(class_name);
+            let (classpath, manifest) = ClassPath::from_jar(jar);
+            match manifest.get_main_class() {
+                Some(main_class) => (classpath, main_class),
+                None => panic!("No Main-Class manifest attribute in {}", jar)
+            }
+        },
+        None => {
+            let classpath = match matches.value_of("classpath") {
+                Some(paths) => ClassPath::parse(paths),
+                None => ClassPath::default()
+            };
+            (classpath, to_internal_name(matches.value_of("class").unwrap()))
         }
-    }
-
-    let mut java_args: Vec<Arc<Mutex<dyn JavaInstance>>> = Vec::new();
-    for i in 0..arguments.len() {
-        java_args.push(Arc::new(Mutex::new(NativeStringInstance::new(String::from(arguments[i])))));
-    }
-
-    let var = Arc::new(Mutex::new(NativeNullInstance {}));
-    let variables: [Arc<Mutex<dyn JavaInstance>>; 16] = [var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone()];
-
-    let mut sf = StackFrame::new(variables);
-    sf.push_array(Arc::new(Mutex::new(java_args)));
-
-    let classes = get_classes();
-    let mut main_classes: Vec<Arc<dyn JavaClass>> = Vec::new();
-    let mut hidden_classes: Vec<Arc<dyn JavaClass>> = Vec::new();
-
-    for class in classes.iter() {
-        let is_hidden = class.get_name().contains("$");
-        if is_hidden {
-            hidden_classes.push(class.clone());
-        } else {
-            main_classes.push(class.clone());
+    };
+    // The VM runs in the main thread, and in the threads started by the program
+    if debug >= 2 { classpath.print(); }
+    let vm = Vm::new(classpath, debug);
+
+    let class = match vm.load_class(&class_name) {
+        Ok(class) => class,
+        Err(_) => {
+            eprintln!("Error: Could not find or load main class {}", class_name.replace("/", "."));
+            eprintln!("Caused by: java.lang.ClassNotFoundException: {}", class_name.replace("/", "."));
+            std::process::exit(1);
         }
-    }
+    };
+    if debug >= 2 { class.print(); }
 
-    for class in main_classes.iter_mut() {
-        if class.has_static_init() {
-            class.execute_static_method(&mut sf, &"<clinit>".to_string(), 0);
-        }
+    if let Some(arch) = asm {
+        class.convert_to_asm("main", "([Ljava/lang/String;)V", arch, emit);
+        return;
     }
 
-    for class in hidden_classes.iter_mut() {
-        if class.has_static_init() {
-            class.execute_static_method(&mut sf, &"<clinit>".to_string(), 0);
-        }
+    // The main class gets initialized before its main method is called
+    let args = JavaValue::Array(arguments.iter().map(|arg| JavaValue::from(*arg)).collect());
+    // Like java, an uncaught exception prints its stack trace and exits with status 1
+    if let Err(e) = class.invoke_static("main", "([Ljava/lang/String;)V", &[args]) {
+        eprint!("Exception in thread \"main\" ");
+        e.print_stack_trace();
+        std::process::exit(1);
     }
 
-    let java_class = get_class(&String::from(class_name));
-    if debug >= 2 { java_class.print(); }
-
-    match asm {
-        Some(arch) => {
-            java_class.convert_to_asm(&"main".to_string(), arch);
-            return;
-        },
-        None => {}
-    };
+    // Wait for other threads to finish
+    vm.wait_for_threads();
+}
 
-    let result = java_class.execute_static_method(&mut sf, &"main".to_string(), 1);
+#[cfg(test)]
+mod tests {
+    use crate::to_clap_arguments;
 
-    match result {
-        MethodCallResult::SUCCESS => {},
-        MethodCallResult::EXCEPTION(e) => {
-            let mut object = e.lock().unwrap();
-            object.execute_method(&mut sf, &"printStackTrace".to_string(), e.clone(), Vec::new());
-        }
-    };
+    fn to_args(line: &str) -> Vec<String> {
+        line.split(' ').map(|arg| arg.to_string()).collect()
+    }
 
-    if debug >= 1 { sf.print_stack(); }
-    if debug >= 2 { sf.print_variables(); }
+    #[test]
+    fn test_to_clap_arguments() {
+        assert_eq!(to_clap_arguments(to_args("jvm -d 1 -cp lib Main -cp foo")), to_args("jvm -d 1 --classpath lib -- Main -cp foo"));
+        assert_eq!(to_clap_arguments(to_args("jvm -classpath -cp Main")), to_args("jvm --classpath -cp -- Main"));
 
-     // Wait for other threads to finish.
-    while GLOBAL_THREAD_COUNT.load(Ordering::SeqCst) != 0 {
-        thread::sleep(Duration::from_millis(1)); 
+        // Program arguments which look like options
+        assert_eq!(to_clap_arguments(to_args("jvm -cp x Main -jar")), to_args("jvm --classpath x -- Main -jar"));
+        assert_eq!(to_clap_arguments(to_args("jvm -d 1 -jar app.jar -jar -cp lib -d")), to_args("jvm -d 1 --jar app.jar -- -jar -cp lib -d"));
+        assert_eq!(to_clap_arguments(to_args("jvm -jar app.jar")), to_args("jvm --jar app.jar --"));
     }
 }
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::jvm::JavaInstance;
+
+// Every Java object can be used as a re-entrant lock, by synchronized blocks (monitorenter and
+// monitorexit) and synchronized methods, and as a condition with wait() and notify().
+// A monitor is identified by the address of its object, and only exists while a thread owns
+// it or waits on it
+struct Monitor {
+    owner: Option<ThreadId>,
+    count: usize,
+    // Tickets of the threads waiting on the monitor, and of the ones notified since
+    waiting: Vec<u64>,
+    notified: Vec<u64>
+}
+
+struct Monitors {
+    monitors: Mutex<HashMap<usize, Monitor>>,
+    next_ticket: Mutex<u64>,
+    // Signaled whenever a monitor is released, a thread is notified or interrupted
+    changed: Condvar
+}
+
+pub enum WaitResult {
+    Notified,
+    TimedOut,
+    Interrupted,
+    NotOwner
+}
+
+fn get_monitors() -> &'static Monitors {
+    static MONITORS: OnceLock<Monitors> = OnceLock::new();
+    MONITORS.get_or_init(|| Monitors { monitors: Mutex::new(HashMap::new()), next_ticket: Mutex::new(0), changed: Condvar::new() })
+}
+
+pub fn get_object_id(object: &Arc<Mutex<dyn JavaInstance>>) -> usize {
+    Arc::as_ptr(object) as *const () as usize
+}
+
+// Blocks until the current thread owns the monitor
+pub fn monitor_enter(id: usize) {
+    let monitors = get_monitors();
+    let current = thread::current().id();
+    let mut map = monitors.monitors.lock().unwrap();
+
+    loop {
+        let monitor = map.entry(id).or_insert(Monitor { owner: None, count: 0, waiting: Vec::new(), notified: Vec::new() });
+        match monitor.owner {
+            Some(owner) if owner != current => {
+                map = monitors.changed.wait(map).unwrap();
+            },
+            _ => {
+                monitor.owner = Some(current);
+                monitor.count += 1;
+                return;
+            }
+        }
+    }
+}
+
+// Returns false if the current thread does not own the monitor (IllegalMonitorStateException)
+pub fn monitor_exit(id: usize) -> bool {
+    let monitors = get_monitors();
+    let current = thread::current().id();
+    let mut map = monitors.monitors.lock().unwrap();
+
+    let monitor = match map.get_mut(&id) {
+        Some(monitor) if monitor.owner == Some(current) => monitor,
+        _ => return false
+    };
+
+    monitor.count -= 1;
+    if monitor.count == 0 {
+        monitor.owner = None;
+        if monitor.waiting.is_empty() && monitor.notified.is_empty() {
+            map.remove(&id);
+        }
+        monitors.changed.notify_all();
+    }
+    true
+}
+
+// Object.wait(): releases the monitor until the thread is notified, the timeout expires or the
+// thread is interrupted, then takes the monitor back with the same re-entrance count
+pub fn monitor_wait(id: usize, timeout: Option<Duration>, is_interrupted: &dyn Fn() -> bool) -> WaitResult {
+    let monitors = get_monitors();
+    let current = thread::current().id();
+    let ticket = {
+        let mut next_ticket = monitors.next_ticket.lock().unwrap();
+        *next_ticket += 1;
+        *next_ticket
+    };
+    let deadline = timeout.map(|duration| Instant::now() + duration);
+    let mut map = monitors.monitors.lock().unwrap();
+
+    let count = match map.get_mut(&id) {
+        Some(monitor) if monitor.owner == Some(current) => {
+            let count = monitor.count;
+            monitor.owner = None;
+            monitor.count = 0;
+            monitor.waiting.push(ticket);
+            count
+        },
+        _ => return WaitResult::NotOwner
+    };
+    monitors.changed.notify_all();
+
+    let mut result = WaitResult::Notified;
+    loop {
+        let monitor = map.get_mut(&id).unwrap();
+        if let Some(idx) = monitor.notified.iter().position(|t| *t == ticket) {
+            monitor.notified.remove(idx);
+            break;
+        }
+        if is_interrupted() {
+            result = WaitResult::Interrupted;
+        } else if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            result = WaitResult::TimedOut;
+        }
+        if !matches!(result, WaitResult::Notified) {
+            monitor.waiting.retain(|t| *t != ticket);
+            break;
+        }
+
+        map = match deadline {
+            Some(deadline) => monitors.changed.wait_timeout(map, deadline.saturating_duration_since(Instant::now())).unwrap().0,
+            None => monitors.changed.wait(map).unwrap()
+        };
+    }
+
+    // Takes the monitor back, it may have been released and removed in the meantime
+    loop {
+        let monitor = map.entry(id).or_insert(Monitor { owner: None, count: 0, waiting: Vec::new(), notified: Vec::new() });
+        if monitor.owner.is_none() {
+            monitor.owner = Some(current);
+            monitor.count = count;
+            return result;
+        }
+        map = monitors.changed.wait(map).unwrap();
+    }
+}
+
+// Object.notify() and notifyAll(). Returns false if the current thread does not own the monitor
+pub fn monitor_notify(id: usize, all: bool) -> bool {
+    let monitors = get_monitors();
+    let current = thread::current().id();
+    let mut map = monitors.monitors.lock().unwrap();
+
+    let monitor = match map.get_mut(&id) {
+        Some(monitor) if monitor.owner == Some(current) => monitor,
+        _ => return false
+    };
+
+    let nb_notified = if all { monitor.waiting.len() } else { monitor.waiting.len().min(1) };
+    let tickets: Vec<u64> = monitor.waiting.drain(0..nb_notified).collect();
+    monitor.notified.extend(tickets);
+    monitors.changed.notify_all();
+    true
+}
+
+// Wakes up the waiting threads so that they check whether they have been interrupted
+pub fn wake_waiting_threads() {
+    let monitors = get_monitors();
+    let _map = monitors.monitors.lock().unwrap();
+    monitors.changed.notify_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::monitor::{monitor_enter, monitor_exit, monitor_notify, monitor_wait, WaitResult};
+
+    #[test]
+    fn test_reentrant_monitor() {
+        let id = 0x1000;
+        monitor_enter(id);
+        monitor_enter(id);
+        assert!(monitor_exit(id));
+
+        // Another thread can neither release nor take the monitor while it is owned
+        let counter = Arc::new(Mutex::new(0));
+        let counter2 = counter.clone();
+        let handle = thread::spawn(move || {
+            assert!(!monitor_exit(id));
+            monitor_enter(id);
+            *counter2.lock().unwrap() += 1;
+            monitor_exit(id);
+        });
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(*counter.lock().unwrap(), 0);
+
+        assert!(monitor_exit(id));
+        handle.join().unwrap();
+        assert_eq!(*counter.lock().unwrap(), 1);
+        assert!(!monitor_exit(id));
+    }
+
+    #[test]
+    fn test_wait_notify() {
+        let id = 0x2000;
+        assert!(matches!(monitor_wait(id, None, &|| false), WaitResult::NotOwner));
+
+        monitor_enter(id);
+        assert!(matches!(monitor_wait(id, Some(Duration::from_millis(10)), &|| false), WaitResult::TimedOut));
+
+        let handle = thread::spawn(move || {
+            monitor_enter(id);
+            assert!(monitor_notify(id, false));
+            monitor_exit(id);
+        });
+        assert!(matches!(monitor_wait(id, None, &|| false), WaitResult::Notified));
+        assert!(monitor_exit(id));
+        handle.join().unwrap();
+    }
+}
@@ -1,43 +1,62 @@
 use core::time;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
 
-use rand::Rng;
 
-use crate::{CLASSES, GLOBAL_THREAD_COUNT, class_exists, get_class};
-use crate::StackFrame;
-use crate::jvm::JavaInstance;
+use crate::vm::{self, Vm, class_exists, get_class, get_debug};
+use crate::jvm::{JavaInstance, StackFrame, Value};
 use crate::java_class::{JavaClass, MethodCallResult};
+use crate::monitor::{get_object_id, monitor_notify, monitor_wait, WaitResult};
+use crate::threads::{JavaThread, ThreadState, MAX_PRIORITY, MIN_PRIORITY, get_current_thread, set_current_thread};
 use crate::streams::NativeStreamClass;
 use crate::streams::NativeLambdaMetafactoryClass;
+use crate::streams::NativeLambdaClass;
 use crate::streams::NativeStreamInstance;
 
-pub fn register_native_classes() {
-    unsafe {
-        CLASSES.add(Arc::new(NativeObjectClass {}));
-        CLASSES.add(Arc::new(NativePrintStreamClass {}));
-        CLASSES.add(Arc::new(NativeSystemClass {}));
-        CLASSES.add(Arc::new(NativeStringClass {}));
-        CLASSES.add(Arc::new(NativeIntegerClass {}));
-        CLASSES.add(Arc::new(NativeArraysClass {}));
-        CLASSES.add(Arc::new(NativeListClass {}));
-        CLASSES.add(Arc::new(NativeArrayListClass {}));
-        CLASSES.add(Arc::new(NativeStreamClass {}));
-        CLASSES.add(Arc::new(NativeMathClass {}));
-        CLASSES.add(Arc::new(NativeLambdaMetafactoryClass {}));
-        CLASSES.add(Arc::new(NativeEnumClass {}));
-        CLASSES.add(Arc::new(NativeNoSuchFieldErrorClass {}));
-        CLASSES.add(Arc::new(NativeMethodHandlesLookupClass {}));
-        CLASSES.add(Arc::new(NativeMethodHandlesClass {}));
-        CLASSES.add(Arc::new(NativeStringBuilderClass {}));
-        CLASSES.add(Arc::new(NativeThreadClass {}));
-        CLASSES.add(Arc::new(NativeGenericExceptionClass { name: "java/lang/Throwable".to_string(), parent: "".to_string() }));
-        CLASSES.add(Arc::new(NativeGenericExceptionClass { name: "java/lang/Exception".to_string(), parent: "java/lang/Throwable".to_string() }));
-        CLASSES.add(Arc::new(NativeGenericExceptionClass { name: "java/lang/RuntimeException".to_string(), parent: "java/lang/Exception".to_string() }));
-        CLASSES.add(Arc::new(NativeGenericExceptionClass { name: "java/lang/ArithmeticException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
-    }
+pub fn register_native_classes(vm: &Vm) {
+    vm.add_class(Arc::new(NativeObjectClass {}));
+    vm.add_class(Arc::new(NativePrintStreamClass {}));
+    vm.add_class(Arc::new(NativeSystemClass {}));
+    vm.add_class(Arc::new(NativeStringClass {}));
+    vm.add_class(Arc::new(NativeIntegerClass {}));
+    vm.add_class(Arc::new(NativeArraysClass {}));
+    vm.add_class(Arc::new(NativeListClass {}));
+    vm.add_class(Arc::new(NativeArrayListClass {}));
+    vm.add_class(Arc::new(NativeStreamClass {}));
+    vm.add_class(Arc::new(NativeMathClass {}));
+    vm.add_class(Arc::new(NativeLambdaMetafactoryClass {}));
+    vm.add_class(Arc::new(NativeEnumClass {}));
+    vm.add_class(Arc::new(NativeMethodHandlesLookupClass {}));
+    vm.add_class(Arc::new(NativeMethodHandlesClass {}));
+    vm.add_class(Arc::new(NativeStringBuilderClass {}));
+    vm.add_class(Arc::new(NativeThreadClass {}));
+    vm.add_class(Arc::new(NativeLambdaClass {}));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/Throwable".to_string(), parent: "".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/Exception".to_string(), parent: "java/lang/Throwable".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/RuntimeException".to_string(), parent: "java/lang/Exception".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ArithmeticException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NullPointerException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ClassCastException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NegativeArraySizeException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IndexOutOfBoundsException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ArrayIndexOutOfBoundsException".to_string(), parent: "java/lang/IndexOutOfBoundsException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/InterruptedException".to_string(), parent: "java/lang/Exception".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ReflectiveOperationException".to_string(), parent: "java/lang/Exception".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ClassNotFoundException".to_string(), parent: "java/lang/ReflectiveOperationException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IllegalArgumentException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IllegalThreadStateException".to_string(), parent: "java/lang/IllegalArgumentException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NumberFormatException".to_string(), parent: "java/lang/IllegalArgumentException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IllegalMonitorStateException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IllegalStateException".to_string(), parent: "java/lang/RuntimeException".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/Error".to_string(), parent: "java/lang/Throwable".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/AssertionError".to_string(), parent: "java/lang/Error".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/LinkageError".to_string(), parent: "java/lang/Error".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NoClassDefFoundError".to_string(), parent: "java/lang/LinkageError".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/ExceptionInInitializerError".to_string(), parent: "java/lang/LinkageError".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/UnsatisfiedLinkError".to_string(), parent: "java/lang/LinkageError".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/IncompatibleClassChangeError".to_string(), parent: "java/lang/LinkageError".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NoSuchFieldError".to_string(), parent: "java/lang/IncompatibleClassChangeError".to_string() }));
+    vm.add_class(Arc::new(NativeGenericExceptionClass { name: "java/lang/NoSuchMethodError".to_string(), parent: "java/lang/IncompatibleClassChangeError".to_string() }));
 }
 
 //////////
@@ -47,11 +66,12 @@ impl JavaInstance for NativeObjectInstance {
     fn get_class_name(&self) -> String {
         return "java/lang/Object".to_string();
     }
-    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => { },
-            _ => panic!("Class {} does not support method {}", self.get_class_name(), method_name)
+            _ => return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}", self.get_class_name(), method_name)))
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -69,44 +89,91 @@ impl JavaClass for NativeObjectClass {
         println!("Native Object class");
     }
 
-    fn execute_method(&self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Arc<Mutex<dyn JavaInstance>>>) -> MethodCallResult {
+    // wait() and notify() are implemented here rather than in the instance, as they apply to any object
+    // and wait() must not keep the object locked
+    fn execute_method(&self, _sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => {
                 return MethodCallResult::SUCCESS;
             },
-            _ => panic!("Class {} does not support method {}", self.get_name(), method_name)
+            "wait" => {
+                let millis = if type_desc.eq("()V") { 0 } else { args[args.len() - 1].get_long() };
+                if millis < 0 {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalArgumentException".to_string(), &"timeout value is negative".to_string()));
+                }
+
+                let (_, java_thread) = get_current_thread();
+                let timeout = if millis == 0 { None } else { Some(time::Duration::from_millis(millis as u64)) };
+                java_thread.set_state(if timeout.is_some() { ThreadState::TimedWaiting } else { ThreadState::Waiting });
+                let result = monitor_wait(get_object_id(&this), timeout, &|| java_thread.is_interrupted(true));
+                java_thread.set_state(ThreadState::Runnable);
+                return match result {
+                    WaitResult::NotOwner => MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalMonitorStateException".to_string(), &"current thread is not owner".to_string())),
+                    WaitResult::Interrupted => MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/InterruptedException".to_string(), &"wait interrupted".to_string())),
+                    WaitResult::Notified | WaitResult::TimedOut => MethodCallResult::SUCCESS
+                };
+            },
+            "notify" | "notifyAll" => {
+                if !monitor_notify(get_object_id(&this), method_name.eq("notifyAll")) {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalMonitorStateException".to_string(), &"current thread is not owner".to_string()));
+                }
+                return MethodCallResult::SUCCESS;
+            },
+            _ => return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}", self.get_name(), method_name)))
         };
     }
 
-    fn execute_static_method(&self, _sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, _sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         match &method_name[..] {
             "clinit" => {
                 return MethodCallResult::SUCCESS;
             },
-            _ => panic!("Class {} does not support static method {}", self.get_name(), method_name)
+            _ => return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}", self.get_name(), method_name)))
         };
     }
 }
 
 /////////////////// java.io.PrintStream
 
+// print() and println() are overloaded, the descriptor tells how to convert the argument
+fn print_argument(type_desc: &String, args: &Vec<Value>) -> String {
+    let object = match args.get(0) {
+        Some(object) => object,
+        None => return "".to_string()
+    };
+
+    match &type_desc[..] {
+        "(I)V" | "(S)V" | "(B)V" => object.get_int().to_string(),
+        "(J)V" => object.get_long().to_string(),
+        "(F)V" => float_to_string(object.get_float()),
+        "(D)V" => double_to_string(object.get_double()),
+        "(Z)V" => (object.get_int() != 0).to_string(),
+        "(C)V" => int_to_char(object.get_int()).to_string(),
+        "([C)V" => {
+            let array = object.get_array();
+            let chars = array.lock().unwrap();
+            chars.iter().map(|c| int_to_char(c.lock().unwrap().get_int())).collect()
+        },
+        _ => if object.is_null() { "null".to_string() } else { object.get_string() }
+    }
+}
+
 pub struct NativePrintStreamInstance {}
 impl JavaInstance for NativePrintStreamInstance {
     fn get_class_name(&self) -> String {
         return "Stream".to_string();
     }
-    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "println" => {
-                let object = args.get(0).unwrap();
-                println!("{}", (**object).lock().unwrap().get_string());
+                println!("{}", print_argument(type_desc, &args));
             },
             "print" => {
-                let object = args.get(0).unwrap();
-                print!("{}", (**object).lock().unwrap().get_string());
+                print!("{}", print_argument(type_desc, &args));
             },
             _ => panic!("Native class {} does not have method {}", self.get_class_name(), method_name)
         }
+        return MethodCallResult::SUCCESS;
     }
 } 
 
@@ -121,17 +188,13 @@ impl JavaClass for NativePrintStreamClass {
         println!("Native Stream class");
     }
 
-    fn execute_method(&self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) -> MethodCallResult {
+    fn execute_method(&self, _sf: &mut StackFrame, method_name: &String, type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "println" => {
-                let string = args.get(0).unwrap().lock().unwrap().get_string();
-
-                println!("{}", string);
+                println!("{}", print_argument(type_desc, &args));
             },
             "print" => {
-                let string = args.get(0).unwrap().lock().unwrap().get_string();
-
-                print!("{}", string);
+                print!("{}", print_argument(type_desc, &args));
             },
             _ => panic!("Native class {} does not have method {}", self.get_name(), method_name)
         };
@@ -152,14 +215,45 @@ impl JavaClass for NativeSystemClass {
         println!("Native System class");
     }
 
-    fn get_static_object(&self, field_name: &String) -> Arc<Mutex<dyn JavaInstance>> {
+    fn get_static_object(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
         if field_name.eq("out") {
-            return Arc::new(Mutex::new(NativePrintStreamInstance {}));
+            return Ok(Arc::new(Mutex::new(NativePrintStreamInstance {})));
         }
-            
-        panic!("Native class {} does not have static field [{}]", self.get_name(), field_name);
+
+        Err(new_java_exception(&"java/lang/NoSuchFieldError".to_string(), &format!("{}.{}", self.get_name(), field_name)))
+    }
+
+}
+
+/////////////////// Conversions to string, following Float.toString() and Double.toString()
+
+pub fn float_to_string(value: f32) -> String {
+    java_number_format(value as f64, format!("{}", value), format!("{:e}", value))
+}
+
+pub fn double_to_string(value: f64) -> String {
+    java_number_format(value, format!("{}", value), format!("{:e}", value))
+}
+
+// Java always prints at least one decimal, and switches to the scientific notation
+// outside of [10^-3, 10^7[ (e.g. 1.0E7)
+fn java_number_format(value: f64, plain: String, scientific: String) -> String {
+    if value.is_nan() { return "NaN".to_string(); }
+    if value.is_infinite() { return if value > 0.0 { "Infinity".to_string() } else { "-Infinity".to_string() }; }
+
+    let abs = value.abs();
+    if abs == 0.0 || (abs >= 1e-3 && abs < 1e7) {
+        if plain.contains('.') { plain } else { plain + ".0" }
+    } else {
+        let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+        let mantissa = if mantissa.contains('.') { mantissa.to_string() } else { mantissa.to_string() + ".0" };
+        format!("{}E{}", mantissa, &exponent[1..])
     }
+}
 
+// Java chars are UTF-16 code units
+pub fn int_to_char(value: i32) -> char {
+    std::char::from_u32(value as u16 as u32).unwrap_or(std::char::REPLACEMENT_CHARACTER)
 }
 
 /////////////////// Null
@@ -172,10 +266,25 @@ impl NativeNullInstance {
     }
 }
 
+// The null reference, a single instance shared by all the null values, fields and array elements
+pub fn null_instance() -> Arc<Mutex<dyn JavaInstance>> {
+    static NULL: OnceLock<Arc<Mutex<dyn JavaInstance>>> = OnceLock::new();
+    NULL.get_or_init(|| Arc::new(Mutex::new(NativeNullInstance::new()))).clone()
+}
+
 impl JavaInstance for NativeNullInstance {
     fn get_class_name(&self) -> String { "null".to_string() }
     fn is_null(&self) -> bool { true }
     fn print(&self) { print!("<null>"); }
+    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
+        MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NullPointerException".to_string(), &format!("Cannot invoke {}() on a null object", method_name)))
+    }
+    fn get_field(&self, field_name: &String) -> Result<Arc<Mutex<dyn JavaInstance>>, Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NullPointerException".to_string(), &format!("Cannot read field {} of a null object", field_name)))
+    }
+    fn set_field(&mut self, field_name: &String, _value: Arc<Mutex<dyn JavaInstance>>) -> Result<(), Arc<Mutex<dyn JavaInstance>>> {
+        Err(new_java_exception(&"java/lang/NullPointerException".to_string(), &format!("Cannot assign field {} of a null object", field_name)))
+    }
 }
 
 /////////////////// java.lang.Integer
@@ -190,7 +299,10 @@ impl NativeIntegerInstance {
 
 impl JavaInstance for NativeIntegerInstance {
     fn get_class_name(&self) -> String { "java/lang/Integer".to_string() }
+    fn get_primitive(&self) -> Option<Value> { Some(Value::Int(self.value)) }
     fn get_int(&self) -> i32 { self.value }
+    // Booleans are ints in the JVM (e.g. the result of iconst_1)
+    fn get_bool(&self) -> bool { self.value != 0 }
     fn get_string(&self) -> String { self.value.to_string() }
     fn print(&self) { print!("{}", self.value); }
 }
@@ -206,11 +318,14 @@ impl JavaClass for NativeIntegerClass {
         println!("Native Integer class");
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         match &method_name[..] {
             "parseInt" => {
                 let string = sf.pop_string();
-                sf.push_int(string.parse::<i32>().unwrap());
+                match string.parse::<i32>() {
+                    Ok(value) => sf.push_int(value),
+                    Err(_) => return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NumberFormatException".to_string(), &format!("For input string: \"{}\"", string)))
+                }
             },
             "valueOf" => {
                 let int = sf.pop_int();
@@ -235,7 +350,9 @@ impl NativeLongInstance {
 
 impl JavaInstance for NativeLongInstance {
     fn get_class_name(&self) -> String { "java/lang/Long".to_string() }
+    fn get_primitive(&self) -> Option<Value> { Some(Value::Long(self.value)) }
     fn get_long(&self) -> i64 { self.value }
+    fn get_string(&self) -> String { self.value.to_string() }
     fn print(&self) { print!("{}l", self.value); }
 }
 
@@ -270,8 +387,8 @@ impl JavaInstance for NativeByteInstance {
     fn get_class_name(&self) -> String {
         return "java/lang/Byte".to_string();
     }
-    fn get_byte(&self) -> u8 { self.value }
-    fn get_int(&self) -> i32 { self.value as i32 }
+    // Java bytes are signed (e.g. bipush -3)
+    fn get_int(&self) -> i32 { self.value as i8 as i32 }
     fn print(&self) {
         print!("{}", self.value);
     }
@@ -294,6 +411,8 @@ impl JavaInstance for NativeCharInstance {
     fn get_char(&self) -> char {
         return self.value;
     }
+    fn get_int(&self) -> i32 { self.value as i32 }
+    fn get_string(&self) -> String { self.value.to_string() }
     fn print(&self) {
         print!("{}", self.value);
     }
@@ -306,9 +425,11 @@ impl JavaInstance for NativeFloatInstance {
     fn get_class_name(&self) -> String {
         return "java/lang/Float".to_string();
     }
+    fn get_primitive(&self) -> Option<Value> { Some(Value::Float(self.value)) }
     fn get_float(&self) -> f32 {
         return self.value;
     }
+    fn get_string(&self) -> String { float_to_string(self.value) }
     fn print(&self) {
         print!("{}", self.value);
     }
@@ -327,9 +448,11 @@ impl JavaInstance for NativeDoubleInstance {
     fn get_class_name(&self) -> String {
         return "java/lang/Double".to_string();
     }
+    fn get_primitive(&self) -> Option<Value> { Some(Value::Double(self.value)) }
     fn get_double(&self) -> f64 {
         return self.value;
     }
+    fn get_string(&self) -> String { double_to_string(self.value) }
     fn print(&self) {
         print!("{}", self.value);
     }
@@ -341,33 +464,6 @@ impl NativeDoubleInstance {
     }
 }
 
-/////////////////// java.lang.Boolean
-
-pub struct NativeBooleanInstance { value: bool }
-impl JavaInstance for NativeBooleanInstance {
-    fn get_class_name(&self) -> String {
-        return "java/lang/Boolean".to_string();
-    }
-    fn get_bool(&self) -> bool {
-        return self.value;
-    }
-    fn get_int(&self) -> i32 {
-        if self.value {
-            return 1;
-        }
-        return 0;
-    }
-    fn print(&self) {
-        print!("{}", self.value);
-    }
-}
-
-impl NativeBooleanInstance {
-    pub fn new(value: bool) -> NativeBooleanInstance {
-        NativeBooleanInstance { value }
-    }
-}
-
 /////////////////// java.lang.String
 
 pub struct NativeStringInstance { value: String }
@@ -381,11 +477,14 @@ impl JavaInstance for NativeStringInstance {
     fn print(&self) {
         print!("\"{}\"", self.value);
     }
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => {},
+            "intern" => {
+                sf.push(vm::current().intern_string(&self.value, this));
+            },
             "startsWith" => {
-                let arg = args.get(0).unwrap().lock().unwrap().get_string();
+                let arg = args[0].get_string();
                 let this = self.get_string();
 
                 sf.push_bool(this.starts_with(&arg));
@@ -410,13 +509,14 @@ impl JavaInstance for NativeStringInstance {
                 sf.push_int(hash);
             },
             "equals" => {
-                let arg = args.get(0).unwrap().lock().unwrap().get_string();
+                let arg = args[0].get_string();
                 let this = self.get_string();
 
                 sf.push_bool(this.eq(&arg));
             }
             _ => panic!("String.{}() not implemented yet", method_name)
-        };        
+        };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -440,7 +540,16 @@ impl JavaClass for NativeStringClass {
         println!("Native Integer class");
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    // Strings are immutable, so methods run on a copy instead of keeping the string locked while they read their
+    // arguments, which may be the same (interned) string
+    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
+        if get_debug() >= 1 { println!("Execute native method {}.{}(<{} arguments>)", self.get_name(), method_name, args.len()); }
+
+        let mut string = NativeStringInstance::new(this.lock().unwrap().get_string());
+        string.execute_method(sf, method_name, type_desc, this, args)
+    }
+
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         if method_name.eq("format") {
             let array = sf.pop_array();
             let string = sf.pop_string();
@@ -491,11 +600,18 @@ struct NativeStringBuilderInstance { content: String }
 impl JavaInstance for NativeStringBuilderInstance {
     fn get_class_name(&self) -> String { "java/lang/StringBuilder".to_string() }
 
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => { },
             "append" => {
-                let string = args[0].lock().unwrap().get_string();
+                let object = &args[0];
+                let string = match &type_desc[..] {
+                    "(C)Ljava/lang/StringBuilder;" => int_to_char(object.get_int()).to_string(),
+                    "(Z)Ljava/lang/StringBuilder;" => (object.get_int() != 0).to_string(),
+                    "(F)Ljava/lang/StringBuilder;" => float_to_string(object.get_float()),
+                    "(D)Ljava/lang/StringBuilder;" => double_to_string(object.get_double()),
+                    _ => if object.is_null() { "null".to_string() } else { object.get_string() }
+                };
                 self.content.push_str(&string);
                 sf.push(this.clone());
             },
@@ -504,6 +620,7 @@ impl JavaInstance for NativeStringBuilderInstance {
             },
             _ => panic!("Native class {} does not have method [{}]", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -526,7 +643,7 @@ impl JavaInstance for NativeArrayInstance {
         return "java/util/Arrays".to_string();
     }
 
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "clone" => {
                 let array: Vec<Arc<Mutex<dyn JavaInstance>>> = self.values.lock().unwrap().clone();
@@ -534,6 +651,7 @@ impl JavaInstance for NativeArrayInstance {
             },
             _ => panic!("Native instance {} does not support method {}", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 
     fn get_array(&self) -> Arc<Mutex<Vec<Arc<Mutex<dyn JavaInstance>>>>> {
@@ -561,7 +679,7 @@ impl JavaClass for NativeArraysClass {
         println!("Native Arrays class");
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         if method_name.eq("asList") {
             let array = sf.pop_array();
 
@@ -606,23 +724,23 @@ impl JavaInstance for NativeArrayListInstance {
         }
         print!("]>");
     }
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => {
-                return;
+                return MethodCallResult::SUCCESS;
             },
             "stream" => {
                 let list = self.get_array();
                 sf.push(Arc::new(Mutex::new(NativeStreamInstance::new(list))));
             },
             "add" => {
-                let value= args.get(0).unwrap();
-                self.content.lock().unwrap().push(value.clone());
+                self.content.lock().unwrap().push(args[0].to_object());
 
                 sf.push_bool(true);
             },
             _ => panic!("Native class {} does not have method [{}]", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -651,7 +769,7 @@ impl JavaInstance for NativeListInstance {
     fn get_class_name(&self) -> String {
         return "java/util/List".to_string();
     }
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, _args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "stream" => {
                 let list = sf.pop_array();
@@ -666,6 +784,7 @@ impl JavaInstance for NativeListInstance {
             }
             _ => panic!("Native class {} does not have method [{}]", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -694,7 +813,7 @@ impl JavaClass for NativeMathClass {
         println!("Native Math class");
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         match &method_name[..] {
             "sqrt" => {
                 let nb = sf.pop_double();
@@ -703,6 +822,28 @@ impl JavaClass for NativeMathClass {
             "log" => {
                 let nb = sf.pop_double();
                 sf.push_double(nb.ln());
+            },
+            "exp" => {
+                let nb = sf.pop_double();
+                sf.push_double(nb.exp());
+            },
+            "sin" => {
+                let nb = sf.pop_double();
+                sf.push_double(nb.sin());
+            },
+            "cos" => {
+                let nb = sf.pop_double();
+                sf.push_double(nb.cos());
+            },
+            "pow" => {
+                let exponent = sf.pop_double();
+                let nb = sf.pop_double();
+                // Unlike C, Java gives NaN for 1 to the power of an infinity or NaN
+                if exponent.is_nan() || (exponent.is_infinite() && nb.abs() == 1.0) {
+                    sf.push_double(f64::NAN);
+                } else {
+                    sf.push_double(nb.powf(exponent));
+                }
             }
             _ => panic!("Native class {} does not have static method [{}]", self.get_name(), method_name)
         };
@@ -717,17 +858,18 @@ impl JavaInstance for NativeEnumInstance {
         return "java/lang/Enum".to_string();
     }
 
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "<init>" => {
-                self.name = args.get(1).unwrap().lock().unwrap().get_string();
-                self.ordinal = args.get(0).unwrap().lock().unwrap().get_int();
+                self.name = args[1].get_string();
+                self.ordinal = args[0].get_int();
             },
             "ordinal" => {
                 sf.push_int(self.ordinal);
             },
             _ => panic!("Native class {} does not have method [{}]", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -749,78 +891,59 @@ impl JavaClass for NativeEnumClass {
 
 /////////////////// java.lang.Thread
 
-struct NativeThreadInstance {
-    object: Arc<Mutex<dyn JavaInstance>>,
-    name: String
+// The state of the thread is kept in JavaThread, so that the methods of the Thread class never need
+// to keep the object locked: Thread.run() calls user code and join() blocks
+pub struct NativeThreadInstance {
+    thread: Arc<JavaThread>
 }
 
-struct ThreadObjects {
-    objects: Option<HashMap<i32, Arc<Mutex<dyn JavaInstance>>>>
-}
-
-impl ThreadObjects {
-    fn add(&mut self, idx: i32, object: Arc<Mutex<dyn JavaInstance>>) {
-        unsafe {
-            match self.objects.as_mut() {
-                Some(map) => { map.insert(idx, object.clone()); },
-                None => {
-                    let mut mp: HashMap<i32, Arc<Mutex<dyn JavaInstance>>> = HashMap::new();
-                    mp.insert(idx, object.clone());
-                    THREAD_OBJECTS.objects = Some(mp);
-                }
-            };
-        }
+impl NativeThreadInstance {
+    pub fn new(thread: Arc<JavaThread>) -> NativeThreadInstance {
+        NativeThreadInstance { thread }
     }
 }
 
-fn new_thread(id: i32) {
-    let this = get_thread_object(id);
-    let var = Arc::new(Mutex::new(NativeObjectInstance {}));
-    let variables: [Arc<Mutex<dyn JavaInstance>>; 16] = [var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone(),
-        var.clone(), var.clone(), var.clone(), var.clone()];
-
-    let mut sf = StackFrame::new(variables);
-
-    let class = get_class(&this.lock().unwrap().get_class_name());
-    class.execute_method(&mut sf, &"run".to_string(), this.clone(), Vec::new());
+impl JavaInstance for NativeThreadInstance {
+    fn get_class_name(&self) -> String { "java/lang/Thread".to_string() }
+    fn get_thread(&self) -> Arc<JavaThread> { self.thread.clone() }
 }
 
-static mut THREAD_OBJECTS: ThreadObjects = ThreadObjects { objects: None };
-
-fn get_thread_object(idx: i32) -> Arc<Mutex<dyn JavaInstance>> {
-    unsafe {
-        match &THREAD_OBJECTS.objects {
-            Some(map) => map.get(&idx).unwrap().clone(),
-            None => panic!("THREAD_OBJECTS not initialized")
-        }
+// this is the Thread object, which may be an instance of a subclass overriding run()
+fn start_thread(this: Arc<Mutex<dyn JavaInstance>>, java_thread: Arc<JavaThread>) -> MethodCallResult {
+    let vm = vm::current();
+    if !vm.threads.thread_started(&java_thread) {
+        return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalThreadStateException".to_string(), &format!("Thread {} already started", java_thread.get_name())));
     }
+
+    // The thread runs in the VM which started it
+    thread::spawn(move || {
+        let _entered = vm.enter();
+        run_thread(this, &java_thread);
+        vm.threads.thread_terminated(&java_thread);
+    });
+    MethodCallResult::SUCCESS
 }
 
-impl JavaInstance for NativeThreadInstance {
-    fn get_class_name(&self) -> String { "java/lang/Thread".to_string() }
+fn run_thread(this: Arc<Mutex<dyn JavaInstance>>, java_thread: &Arc<JavaThread>) {
+    let mut sf = StackFrame::new(0, 1);
+    set_current_thread(this.clone(), java_thread.clone());
 
-    fn execute_method(&mut self, _sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
-        match &method_name[..] {
-            "<init>" => {
-                self.name = args[0].lock().unwrap().get_string();
-                self.object = args[1].clone();
-            },
-            "start" => {
-                let mut rng = rand::thread_rng();
-                let idx = rng.gen::<i32>();
-
-                // Cannot manage to pass the JavaInstance object inside thread::spawn(), so we need to pass it another way
-                unsafe { THREAD_OBJECTS.add(idx, self.object.clone()); };
-                GLOBAL_THREAD_COUNT.fetch_add(1, Ordering::SeqCst);
-                thread::spawn(move || {
-                    new_thread(idx);
-                    GLOBAL_THREAD_COUNT.fetch_sub(1, Ordering::SeqCst);
-                });
-            },
-            _ => panic!("Class instance {} does not support method {}", self.get_class_name(), method_name)
-        };
+    let class = get_class(&this.lock().unwrap().get_class_name());
+    let result = class.execute_method(&mut sf, &"run".to_string(), &"()V".to_string(), this.clone(), Vec::new());
+
+    if let MethodCallResult::EXCEPTION(e) = result {
+        // Exceptions thrown by the handler itself are ignored, like in the JVM
+        match java_thread.get_uncaught_exception_handler().or_else(|| vm::current().threads.get_default_uncaught_exception_handler()) {
+            Some(handler) => {
+                let handler_class = get_class(&handler.lock().unwrap().get_class_name());
+                handler_class.execute_method(&mut sf, &"uncaughtException".to_string(), &"(Ljava/lang/Thread;Ljava/lang/Throwable;)V".to_string(), handler.clone(), vec![Value::Reference(e), Value::Reference(this)]);
+            },
+            None => {
+                eprint!("Exception in thread \"{}\" ", java_thread.get_name());
+                let exception_class = get_class(&e.lock().unwrap().get_class_name());
+                exception_class.execute_method(&mut sf, &"printStackTrace".to_string(), &"()V".to_string(), e.clone(), Vec::new());
+            }
+        }
     }
 }
 
@@ -828,15 +951,129 @@ struct NativeThreadClass {}
 
 impl JavaClass for NativeThreadClass {
     fn new(&self) -> Arc<Mutex<dyn JavaInstance>> {
-        Arc::new(Mutex::new(NativeThreadInstance { object: Arc::new(Mutex::new(NativeNullInstance {})), name: "".to_string() } ))
+        Arc::new(Mutex::new(NativeThreadInstance::new(JavaThread::new("".to_string()))))
+    }
+
+    // Arguments are in reverse order: args[0] is the last one
+    fn execute_method(&self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
+        let object = if this.lock().unwrap().is_bytecode() {
+            this.lock().unwrap().cast_as(this.clone(), &self.get_name())
+        } else {
+            this.clone()
+        };
+        let java_thread = object.lock().unwrap().get_thread();
+
+        match (&method_name[..], &type_desc[..]) {
+            ("<init>", "()V") => {
+                java_thread.set_name(vm::current().threads.next_thread_name());
+            },
+            ("<init>", "(Ljava/lang/String;)V") => {
+                java_thread.set_name(args[0].get_string());
+            },
+            ("<init>", "(Ljava/lang/Runnable;)V") => {
+                java_thread.set_name(vm::current().threads.next_thread_name());
+                java_thread.set_target(args[0].to_object());
+            },
+            ("<init>", "(Ljava/lang/Runnable;Ljava/lang/String;)V") => {
+                java_thread.set_name(args[0].get_string());
+                java_thread.set_target(args[1].to_object());
+            },
+            ("start", _) => {
+                return start_thread(this, java_thread);
+            },
+            ("run", _) => {
+                if let Some(target) = java_thread.get_target() {
+                    let class = get_class(&target.lock().unwrap().get_class_name());
+                    return class.execute_method(sf, method_name, type_desc, target.clone(), Vec::new());
+                }
+            },
+            ("join", _) => {
+                let millis = if type_desc.eq("()V") { 0 } else { args[args.len() - 1].get_long() };
+                if millis < 0 {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalArgumentException".to_string(), &"timeout value is negative".to_string()));
+                }
+                java_thread.join(if millis == 0 { None } else { Some(time::Duration::from_millis(millis as u64)) });
+            },
+            ("getName", _) => {
+                sf.push_string(java_thread.get_name());
+            },
+            ("setName", _) => {
+                java_thread.set_name(args[0].get_string());
+            },
+            ("getId", _) => {
+                sf.push_long(java_thread.get_id() as i64);
+            },
+            ("getPriority", _) => {
+                sf.push_int(java_thread.get_priority());
+            },
+            ("setPriority", _) => {
+                let priority = args[0].get_int();
+                if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalArgumentException".to_string(), &format!("Invalid priority {}", priority)));
+                }
+                java_thread.set_priority(priority);
+            },
+            ("isDaemon", _) => {
+                sf.push_bool(java_thread.is_daemon());
+            },
+            ("setDaemon", _) => {
+                if java_thread.get_state() != ThreadState::New {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/IllegalThreadStateException".to_string(), &format!("Thread {} already started", java_thread.get_name())));
+                }
+                java_thread.set_daemon(args[0].get_bool());
+            },
+            ("isAlive", _) => {
+                sf.push_bool(java_thread.is_alive());
+            },
+            ("interrupt", _) => {
+                java_thread.interrupt();
+            },
+            ("isInterrupted", _) => {
+                sf.push_bool(java_thread.is_interrupted(false));
+            },
+            ("getUncaughtExceptionHandler", _) => {
+                match java_thread.get_uncaught_exception_handler() {
+                    Some(handler) => sf.push(handler),
+                    None => sf.push_null()
+                };
+            },
+            ("setUncaughtExceptionHandler", _) => {
+                let is_null = args[0].is_null();
+                java_thread.set_uncaught_exception_handler(if is_null { None } else { Some(args[0].to_object()) });
+            },
+            _ => return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/NoSuchMethodError".to_string(), &format!("{}.{}{}", self.get_name(), method_name, type_desc)))
+        };
+        MethodCallResult::SUCCESS
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, _nb_args: usize) -> MethodCallResult {
         match &method_name[..] {
             "sleep" => {
                 let nb_millis = sf.pop_long() as u64;
                 let duration = time::Duration::from_millis(nb_millis);
-                thread::sleep(duration);
+                let (_, java_thread) = get_current_thread();
+                if !java_thread.sleep(duration) {
+                    return MethodCallResult::EXCEPTION(new_java_exception(&"java/lang/InterruptedException".to_string(), &"sleep interrupted".to_string()));
+                }
+            },
+            "currentThread" => {
+                let (object, _) = get_current_thread();
+                sf.push(object);
+            },
+            "interrupted" => {
+                let (_, java_thread) = get_current_thread();
+                sf.push_bool(java_thread.is_interrupted(true));
+            },
+            "getDefaultUncaughtExceptionHandler" => {
+                match vm::current().threads.get_default_uncaught_exception_handler() {
+                    Some(handler) => sf.push(handler),
+                    None => sf.push_null()
+                };
+            },
+            "setDefaultUncaughtExceptionHandler" => {
+                let handler = sf.pop();
+                let is_null = handler.lock().unwrap().is_null();
+                vm::current().threads.set_default_uncaught_exception_handler(if is_null { None } else { Some(handler) });
             },
             _ => panic!("Class instance {} does not support static method {}", self.get_name(), method_name)
         };
@@ -847,20 +1084,6 @@ impl JavaClass for NativeThreadClass {
     fn print(&self) { println!("Native Thread class"); }
 }
 
-/////////////////// java.lang.NoSuchFieldError
-
-struct NativeNoSuchFieldErrorClass {}
-
-impl JavaClass for NativeNoSuchFieldErrorClass {
-    fn get_name(&self) -> String {
-        return "java/lang/NoSuchFieldError".to_string();
-    }
-
-    fn print(&self) {
-        println!("Native NoSuchFieldError class");
-    }
-}
-
 /////////////////// java.lang.invoke.MethodHandles$Lookup
 
 struct NativeMethodHandlesLookupClass {}
@@ -889,22 +1112,34 @@ impl JavaClass for NativeMethodHandlesClass {
     }
 }
 
-/////////////////// Generic class
-
-pub struct NativeGenericClass { pub name: String }
-
-impl JavaClass for NativeGenericClass {
-    fn get_name(&self) -> String { self.name.clone() }
-    fn print(&self) { println!("Native {} class", self.name); }
-}
-
 /////////////////// java.lang.Exception
 
 pub struct NativeGenericExceptionInstance {
     pub name: String,
     pub message: String,
     pub parent_class_name: String,
-    pub stack: Vec<String>
+    pub stack: Vec<String>,
+    pub cause: Option<Arc<Mutex<dyn JavaInstance>>>
+}
+
+impl NativeGenericExceptionInstance {
+    // Like Throwable.printStackTrace(), which prints to System.err, or to the stream it is given (only System.out exists).
+    // The type descriptor, of printStackTrace or printCause, tells which one
+    fn print_stack(&self, sf: &mut StackFrame, header: &str, type_desc: &String, args: Vec<Value>) {
+        let mut trace = format!("{}{}", header, self.name.replace("/", "."));
+        if !self.message.eq("") {
+            trace += &format!(": {}", self.message);
+        }
+        for frame in self.stack.iter() {
+            trace += &format!("\n\tat {}", frame);
+        }
+        if type_desc.eq("()V") { eprintln!("{}", trace); } else { println!("{}", trace); }
+
+        if let Some(cause) = &self.cause {
+            let cause_class = get_class(&cause.lock().unwrap().get_class_name());
+            cause_class.execute_method(sf, &"printCause".to_string(), type_desc, cause.clone(), args);
+        }
+    }
 }
 
 impl JavaInstance for NativeGenericExceptionInstance {
@@ -927,28 +1162,60 @@ impl JavaInstance for NativeGenericExceptionInstance {
         panic!("Instance of class {} cannot be converted to {}", self.name, class_name);
     }
     
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    // Arguments are in reverse order: args[0] is the last one
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
-            "<init>" => {
-                self.message = args[0].lock().unwrap().get_string();
+            "<init>" => match &type_desc[..] {
+                "(Ljava/lang/Throwable;)V" if !args[0].is_null() => {
+                    // The message is the description of the cause
+                    let cause = args[0].to_object();
+                    let cause_name = cause.lock().unwrap().get_class_name();
+                    get_class(&cause_name).execute_method(sf, &"getMessage".to_string(), &"()Ljava/lang/String;".to_string(), cause.clone(), Vec::new());
+                    let cause_message = sf.pop_string();
+                    self.message = if cause_message.eq("") { cause_name.replace("/", ".") } else { format!("{}: {}", cause_name.replace("/", "."), cause_message) };
+                    self.cause = Some(cause);
+                },
+                "()V" | "(Ljava/lang/Throwable;)V" => {},
+                "(Ljava/lang/String;Ljava/lang/Throwable;)V" => {
+                    self.message = args[1].get_string();
+                    self.cause = get_cause(&args[0]);
+                },
+                _ => {
+                    self.message = args[0].get_string();
+                }
             },
             "getMessage" => {
                 sf.push_string(self.message.clone());
             },
+            "getCause" => {
+                match &self.cause {
+                    Some(cause) => sf.push(cause.clone()),
+                    None => sf.push_null()
+                };
+            },
+            "initCause" => {
+                self.cause = get_cause(&args[0]);
+                sf.push(this);
+            },
             "printStackTrace" => {
-                println!("Exception in {}: {}", self.name, self.message);
-                for frame in self.stack.iter() {
-                    println!("        at {}", frame);
-                }
+                self.print_stack(sf, "", type_desc, args);
+            },
+            "printCause" => {
+                self.print_stack(sf, "Caused by: ", type_desc, args);
             },
             "addStackFrame" => {
-                self.stack.push(args[0].lock().unwrap().get_string());
+                self.stack.push(args[0].get_string());
             },
             _ => panic!("Instance of class {} does not support method {}", self.get_class_name(), method_name)
         }
+        return MethodCallResult::SUCCESS;
     }
 }
 
+fn get_cause(value: &Value) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+    if value.is_null() { None } else { Some(value.to_object()) }
+}
+
 pub struct NativeGenericExceptionClass {
     name: String,
     parent: String
@@ -966,7 +1233,8 @@ impl JavaClass for NativeGenericExceptionClass {
             name: self.name.clone(),
             message: "".to_string(),
             parent_class_name: class.get_parent(),
-            stack: Vec::new()
+            stack: Vec::new(),
+            cause: None
         }))
     }
 }
@@ -976,15 +1244,14 @@ impl NativeGenericExceptionClass {
         let top_exception = "java/lang/Exception".to_string();
 
         if !class_exists(name) {
-            unsafe {
-                CLASSES.add(Arc::new(NativeGenericExceptionClass { name: name.clone(), parent: "java/lang/Exception".to_string() }));
-            }
+            vm::current().add_class(Arc::new(NativeGenericExceptionClass { name: name.clone(), parent: "java/lang/Exception".to_string() }));
 
             NativeGenericExceptionInstance {
                 name: name.clone(),
                 message: message.clone(),
                 stack: Vec::new(),
-                parent_class_name: top_exception.clone()
+                parent_class_name: top_exception.clone(),
+                cause: None
             }
         } else {
             let class = get_class(name);
@@ -992,11 +1259,24 @@ impl NativeGenericExceptionClass {
                 name: name.clone(),
                 message: message.clone(),
                 stack: Vec::new(),
-                parent_class_name: class.get_name()
+                parent_class_name: class.get_parent(),
+                cause: None
             }    
         }
     }
 }
+
+// Creates an exception thrown by the JVM itself (e.g. a NullPointerException)
+pub fn new_java_exception(name: &String, message: &String) -> Arc<Mutex<dyn JavaInstance>> {
+    Arc::new(Mutex::new(NativeGenericExceptionClass::new(name, message)))
+}
+
+// Creates an exception thrown by the JVM because of another one (e.g. an ExceptionInInitializerError)
+pub fn new_java_exception_with_cause(name: &String, message: &String, cause: Arc<Mutex<dyn JavaInstance>>) -> Arc<Mutex<dyn JavaInstance>> {
+    let mut exception = NativeGenericExceptionClass::new(name, message);
+    exception.cause = Some(cause);
+    Arc::new(Mutex::new(exception))
+}
 /*
 pub struct NativeExceptionClass { }
 
@@ -1012,4 +1292,18 @@ impl JavaClass for NativeExceptionClass {
         }))
     }
 }
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod tests {
+    use crate::native_java_classes::{double_to_string, float_to_string};
+
+    #[test]
+    fn test_number_to_string() {
+        assert_eq!(double_to_string(2.0), "2.0");
+        assert_eq!(double_to_string(0.001), "0.001");
+        assert_eq!(double_to_string(12345678.0), "1.2345678E7");
+        assert_eq!(double_to_string(-1e-5), "-1.0E-5");
+        assert_eq!(float_to_string(1.1), "1.1");
+        assert_eq!(float_to_string(f32::NAN), "NaN");
+    }
+}
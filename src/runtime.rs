@@ -0,0 +1,2017 @@
+// Runtime library of the compiled programs: the routines their code calls, written in the same subset of nasm as the
+// methods, and the classes and strings they use. Only the routines a program calls get written into it
+use crate::asm::{Arch, method_label};
+
+// Classes implemented by the runtime: superclass, instance fields, and interfaces with their superinterfaces. Objects
+// start with the address of their class descriptor, followed by their fields (8 bytes each), those of the superclass
+// first. Strings and arrays have their length after the descriptor, and then respectively their bytes and their
+// elements (8 bytes each)
+pub static CLASSES: [(&str, &str, &[&str], &[&str]); 6] = [
+    ("java/lang/Object", "", &[], &[]),
+    ("java/lang/String", "java/lang/Object", &["length"], &["java/lang/CharSequence", "java/lang/Comparable"]),
+    ("java/lang/Integer", "java/lang/Object", &["value"], &["java/lang/Comparable"]),
+    ("java/lang/StringBuilder", "java/lang/Object", &["count", "value"], &["java/lang/CharSequence"]),
+    ("java/util/ArrayList", "java/lang/Object", &["size", "elementData"], &["java/util/List", "java/util/Collection", "java/lang/Iterable"]),
+    ("java/lang/Enum", "java/lang/Object", &["name", "ordinal"], &["java/lang/Comparable"])
+];
+
+// Constant strings of the runtime, with their label
+static STRINGS: [(&str, &str); 25] = [
+    ("__str_null", "null"),
+    ("__str_true", "true"),
+    ("__str_false", "false"),
+    ("__str_newline", "\n"),
+    ("__str_NaN", "NaN"),
+    ("__str_Infinity", "Infinity"),
+    ("__str_negative_infinity", "-Infinity"),
+    ("__str_zero", "0.0"),
+    ("__str_negative_zero", "-0.0"),
+    ("__str_at", "@"),
+    ("__str_colon", ": "),
+    ("__str_quote", "\""),
+    ("__str_array", "array"),
+    ("__str_exception", "Exception in thread \"main\" "),
+    ("__str_index", "Index "),
+    ("__str_out_of_bounds", " out of bounds for length "),
+    ("__str_string_index", "String index out of range: "),
+    ("__str_for_input_string", "For input string: \""),
+    ("__str_ArrayIndexOutOfBoundsException", "java.lang.ArrayIndexOutOfBoundsException"),
+    ("__str_StringIndexOutOfBoundsException", "java.lang.StringIndexOutOfBoundsException"),
+    ("__str_IndexOutOfBoundsException", "java.lang.IndexOutOfBoundsException"),
+    ("__str_NegativeArraySizeException", "java.lang.NegativeArraySizeException"),
+    ("__str_NullPointerException", "java.lang.NullPointerException"),
+    ("__str_NumberFormatException", "java.lang.NumberFormatException"),
+    ("__str_OutOfMemoryError", "java.lang.OutOfMemoryError")
+];
+
+// Math routines of the runtime, with the routines they jump to, each after the routines using it. They take doubles in
+// xmm0 and xmm1, return a double in xmm0 (or an integer in rax), and only write rax, rcx, rdx and xmm0-xmm2. The x87
+// instructions compute the logarithms, exponentials and sines, with the values moved through the red zone below rsp
+pub static MATH_ROUTINES: [(&str, &[&str], &str); 10] = [
+    // Java's pow: the special cases of y and the negative x, around 2^(y * log2(x)) on x87
+    ("__math_pow", &["__math_exp2"], "
+__math_pow:
+    xor rax, rax
+    movq xmm2, rax
+    ucomisd xmm1, xmm2
+    jp .nan
+    je .one
+    mov rax, 0x3ff0000000000000
+    movq xmm2, rax
+    ucomisd xmm1, xmm2
+    je .done
+    movq rax, xmm0
+    cmp rax, 0
+    jl .negative
+    jmp __math_pow_positive
+.negative:
+    mov rcx, 0x7fffffffffffffff
+    and rax, rcx
+    movq xmm0, rax
+    ; Doubles from 2^53 are even integers, others must be integers for a negative x
+    movq rdx, xmm1
+    and rdx, rcx
+    mov rcx, 0x4340000000000000
+    cmp rdx, rcx
+    jge __math_pow_positive
+    cvttsd2si rdx, xmm1
+    cvtsi2sd xmm2, rdx
+    ucomisd xmm2, xmm1
+    jne .nan
+    and rdx, 1
+    jz __math_pow_positive
+    call __math_pow_positive
+    movq rax, xmm0
+    mov rcx, 0x8000000000000000
+    xor rax, rcx
+    movq xmm0, rax
+    ret
+.nan:
+    mov rax, 0x7ff8000000000000
+    movq xmm0, rax
+    ret
+.one:
+    mov rax, 0x3ff0000000000000
+    movq xmm0, rax
+.done:
+    ret
+
+__math_pow_positive:
+    movq qword [rsp-8], xmm1
+    fld qword [rsp-8]
+    movq qword [rsp-8], xmm0
+    fld qword [rsp-8]
+    fyl2x
+    call __math_exp2
+    fstp qword [rsp-8]
+    movq xmm0, qword [rsp-8]
+    ret
+"),
+    ("__math_exp", &["__math_exp2"], "
+__math_exp:
+    movq qword [rsp-8], xmm0
+    fld qword [rsp-8]
+    fldl2e
+    fmulp st1
+    call __math_exp2
+    fstp qword [rsp-8]
+    movq xmm0, qword [rsp-8]
+    ret
+"),
+    // 2^st0 into st0, as 2^f * 2^n with n the rounded st0 and f in [-0.5, 0.5]
+    ("__math_exp2", &[], "
+__math_exp2:
+    fld st0
+    fsub st0, st0
+    fucomip st0, st0
+    jp .infinite
+    fld st0
+    frndint
+    fxch st1
+    fsub st0, st1
+    f2xm1
+    fld1
+    faddp st1
+    fscale
+    fstp st1
+    ret
+.infinite:
+    ; 2^inf is inf, 2^-inf is 0 and 2^NaN is NaN
+    fldz
+    fucomip st0, st1
+    ja .zero
+    ret
+.zero:
+    fstp st0
+    fldz
+    ret
+"),
+    ("__math_cos", &["__math_sin"], "
+__math_cos:
+    call __math_reduce
+    inc rax
+    jmp __math_quadrant
+"),
+    // sin(r + n * pi/2), from the quadrant n in rax and r in st0
+    ("__math_sin", &["__math_reduce"], "
+__math_sin:
+    call __math_reduce
+__math_quadrant:
+    mov rcx, rax
+    and rcx, 1
+    jnz .cos
+    fsin
+    jmp .sign
+.cos:
+    fcos
+.sign:
+    and rax, 2
+    jz .done
+    fchs
+.done:
+    fstp qword [rsp-8]
+    movq xmm0, qword [rsp-8]
+    ret
+"),
+    // x into st0 = x - n * pi/2 in [-pi/4, pi/4] and rax = n, as fsin and fcos are only accurate there. Below pi/4
+    // (including -0.0 and the subnormals), and for infinities and NaN, x is kept. Above, the reduction is Payne-Hanek's:
+    // with |x| = m * 2^(e - 1075), the fraction of |x| * 2/pi gets computed exactly from m and the 192 bits of 2/pi
+    // starting at bit e - 1 (those before have products which are multiples of 4), 2 bits for the quadrant and 128
+    // for r, which is enough even for the doubles closest to a multiple of pi/2
+    ("__math_reduce", &[], "
+__math_reduce:
+    movq qword [rsp-8], xmm0
+    fld qword [rsp-8]
+    mov rax, qword [rsp-8]
+    mov rcx, 0x7fffffffffffffff
+    and rax, rcx
+    mov rcx, 0x3fe921fb54442d18
+    cmp rax, rcx
+    jb .kept
+    mov rcx, 0x7ff0000000000000
+    cmp rax, rcx
+    jb .reduce
+.kept:
+    mov rax, 0
+    ret
+.reduce:
+    fstp st0
+    push rbx
+    push rsi
+    push rdi
+    push r8
+    push r9
+    ; m into rsi, and the first bit of the window in __two_over_pi (after its 64 zero bits) into rcx
+    mov rcx, rax
+    shr rcx, 52
+    sub rcx, 1013
+    mov rsi, 0x000fffffffffffff
+    and rsi, rax
+    mov rax, 0x0010000000000000
+    or rsi, rax
+    ; The window into r8, r9 and rdi, from its most significant word, each from 2 words of the table
+    mov rbx, rcx
+    shr rbx, 6
+    shl rbx, 3
+    mov rax, qword __two_over_pi
+    add rbx, rax
+    and rcx, 63
+    mov r8, qword [rbx]
+    shl r8, cl
+    mov r9, qword [rbx+8]
+    shl r9, cl
+    mov rdi, qword [rbx+16]
+    shl rdi, cl
+    neg rcx
+    add rcx, 63
+    mov rax, qword [rbx+8]
+    shr rax, 1
+    shr rax, cl
+    or r8, rax
+    mov rax, qword [rbx+16]
+    shr rax, 1
+    shr rax, cl
+    or r9, rax
+    mov rax, qword [rbx+24]
+    shr rax, 1
+    shr rax, cl
+    or rdi, rax
+    ; The 3 lowest words of m * window, into rsi, r9 and rcx, are the quadrant (the top 2 bits) and the fraction
+    mov rax, rsi
+    mul rdi
+    mov rcx, rax
+    mov rdi, rdx
+    mov rax, rsi
+    mul r9
+    add rax, rdi
+    adc rdx, 0
+    mov r9, rax
+    imul rsi, r8
+    add rsi, rdx
+    ; n rounded to the nearest quadrant into rbx, and the fraction in [-0.5, 0.5] as the signed word rdx and the next
+    ; 63 bits in rax
+    mov rbx, 0x2000000000000000
+    add rbx, rsi
+    shr rbx, 62
+    mov rdx, rsi
+    shl rdx, 2
+    mov rdi, r9
+    shr rdi, 62
+    or rdx, rdi
+    mov rax, r9
+    shl rax, 2
+    shr rcx, 62
+    or rax, rcx
+    shr rax, 1
+    ; r = (rdx + rax * 2^-63) * 2^-64 * pi/2
+    mov qword [rsp-8], rax
+    fild qword [rsp-8]
+    mov rax, 0x3c00000000000000
+    mov qword [rsp-8], rax
+    fld qword [rsp-8]
+    fmulp st1
+    mov qword [rsp-8], rdx
+    fild qword [rsp-8]
+    faddp st1
+    fldpi
+    fmulp st1
+    mov rax, 0x3be0000000000000
+    mov qword [rsp-8], rax
+    fld qword [rsp-8]
+    fmulp st1
+    mov rax, rbx
+    pop r9
+    pop r8
+    pop rdi
+    pop rsi
+    pop rbx
+    ; sin(-x) = -sin(x)
+    movq rcx, xmm0
+    cmp rcx, 0
+    jge .done
+    fchs
+    neg rax
+.done:
+    ret
+"),
+    ("__math_log", &[], "
+__math_log:
+    movq qword [rsp-8], xmm0
+    fldln2
+    fld qword [rsp-8]
+    fyl2x
+    fstp qword [rsp-8]
+    movq xmm0, qword [rsp-8]
+    ret
+"),
+    // Remainder of the truncated division of xmm0 by xmm1, Java's % on floats and doubles
+    ("__math_fmod", &[], "
+__math_fmod:
+    movq qword [rsp-8], xmm1
+    fld qword [rsp-8]
+    movq qword [rsp-8], xmm0
+    fld qword [rsp-8]
+.partial:
+    fprem
+    fnstsw ax
+    and rax, 0x400
+    jnz .partial
+    fstp st1
+    fstp qword [rsp-8]
+    movq xmm0, qword [rsp-8]
+    ret
+"),
+    // Java's conversions of a double to an int or a long, which saturate and give 0 for NaN
+    ("__math_d2i", &[], "
+__math_d2i:
+    ucomisd xmm0, xmm0
+    jp .nan
+    mov rax, 0x41dfffffffc00000
+    movq xmm1, rax
+    ucomisd xmm0, xmm1
+    jae .max
+    mov rax, 0xc1e0000000000000
+    movq xmm1, rax
+    ucomisd xmm0, xmm1
+    jbe .min
+    cvttsd2si rax, xmm0
+    ret
+.max:
+    mov rax, 2147483647
+    ret
+.min:
+    mov rax, -2147483648
+    ret
+.nan:
+    xor rax, rax
+    ret
+"),
+    ("__math_d2l", &[], "
+__math_d2l:
+    ucomisd xmm0, xmm0
+    jp .nan
+    mov rax, 0x43e0000000000000
+    movq xmm1, rax
+    ucomisd xmm0, xmm1
+    jae .max
+    ; 0x8000000000000000 below -2^63, which is the minimum
+    cvttsd2si rax, xmm0
+    ret
+.max:
+    mov rax, 0x7fffffffffffffff
+    ret
+.nan:
+    xor rax, rax
+    ret
+")
+];
+
+// Routines of the runtime, with the routines they call. Methods of the classes of the runtime are named by their class,
+// name and descriptor, and are called with the System V convention like the compiled methods. A dependency starting
+// with virtual, e.g. virtual java/lang/Object.toString()Ljava/lang/String;, calls the method of the class of the object
+// through __virtual_<label> (see Assembly::virtual_call). Routines preserve rbx, rbp and r12-r15, and the error
+// routines (ending with _error) exit the program
+pub static ROUTINES: [(&str, &[&str], &str); 95] = [
+    // Bump allocation of rdi bytes, rounded up to 8. The memory comes from mmap in chunks of at least 1 MiB, already
+    // zeroed, and is never freed
+    ("__new", &["__sys_mmap", "__out_of_memory_error"], "
+__new:
+    add rdi, 7
+    and rdi, -8
+    mov rsi, qword __heap
+    mov rax, [rsi]
+    mov rdx, rax
+    add rdx, rdi
+    cmp rdx, [rsi+8]
+    ja .grow
+    mov [rsi], rdx
+    ret
+.grow:
+    ; The rest of the current chunk is lost
+    push rdi
+    mov rax, 0x100000
+    cmp rdi, rax
+    jbe .mmap
+    mov rax, rdi
+    add rax, 4095
+    and rax, -4096
+.mmap:
+    push rax
+    mov rsi, rax
+    call __sys_mmap
+    pop rdx
+    pop rdi
+    cmp rax, -4096
+    ja __out_of_memory_error
+    mov rsi, qword __heap
+    add rdx, rax
+    mov [rsi+8], rdx
+    mov rdx, rax
+    add rdx, rdi
+    mov [rsi], rdx
+    ret
+"),
+    ("__new_string", &["__new"], "
+__new_string:
+    push rdi
+    add rdi, 16
+    call __new
+    pop rdi
+    mov rcx, qword class_java_lang_String
+    mov [rax], rcx
+    mov [rax+8], rdi
+    ret
+"),
+    ("__new_array", &["__new", "__negative_array_size_error"], "
+__new_array:
+    cmp rdi, 0
+    jl __negative_array_size_error
+    push rdi
+    shl rdi, 3
+    add rdi, 16
+    call __new
+    pop rdi
+    mov rcx, qword __class_array
+    mov [rax], rcx
+    mov [rax+8], rdi
+    ret
+"),
+    ("__array_clone", &["__new_array", "__copy_qwords"], "
+__array_clone:
+    push rdi
+    mov rdi, [rdi+8]
+    call __new_array
+    pop rsi
+    push rax
+    mov rdi, rax
+    add rdi, 16
+    mov rdx, [rsi+8]
+    add rsi, 16
+    call __copy_qwords
+    pop rax
+    ret
+"),
+    ("__new_builder", &["__new", "__new_string"], "
+__new_builder:
+    mov rdi, 24
+    call __new
+    mov rcx, qword class_java_lang_StringBuilder
+    mov [rax], rcx
+    push rax
+    mov rdi, 16
+    call __new_string
+    mov rcx, rax
+    pop rax
+    mov [rax+16], rcx
+    ret
+"),
+    // Copies rdx bytes from rsi to rdi, and leaves rdi after them
+    ("__copy_bytes", &[], "
+__copy_bytes:
+    cmp rdx, 0
+    jle .done
+.loop:
+    movzx rax, byte [rsi]
+    mov byte [rdi], al
+    inc rsi
+    inc rdi
+    sub rdx, 1
+    jne .loop
+.done:
+    ret
+"),
+    ("__copy_qwords", &[], "
+__copy_qwords:
+    cmp rdx, 0
+    jle .done
+.loop:
+    mov rax, [rsi]
+    mov [rdi], rax
+    add rsi, 8
+    add rdi, 8
+    sub rdx, 1
+    jne .loop
+.done:
+    ret
+"),
+    // New string of the rsi bytes at rdi
+    ("__string_from_bytes", &["__new_string", "__copy_bytes"], "
+__string_from_bytes:
+    push rdi
+    push rsi
+    mov rdi, rsi
+    call __new_string
+    pop rdx
+    pop rsi
+    push rax
+    mov rdi, rax
+    add rdi, 16
+    call __copy_bytes
+    pop rax
+    ret
+"),
+    // String[] of the command line arguments, from argc in rdi and argv in rsi, without the name of the program
+    ("__main_args", &["__new_array", "__string_from_bytes"], "
+__main_args:
+    push rbx
+    push r12
+    push r13
+    push r14
+    mov r12, rsi
+    mov r13, rdi
+    sub rdi, 1
+    call __new_array
+    mov r14, rax
+    mov rbx, 1
+.argument:
+    cmp rbx, r13
+    jge .done
+    mov rdi, rbx
+    shl rdi, 3
+    add rdi, r12
+    mov rdi, [rdi]
+    mov rsi, rdi
+.length:
+    movzx rax, byte [rsi]
+    cmp rax, 0
+    je .string
+    inc rsi
+    jmp .length
+.string:
+    sub rsi, rdi
+    call __string_from_bytes
+    mov rdi, rbx
+    shl rdi, 3
+    add rdi, r14
+    mov [rdi+8], rax
+    inc rbx
+    jmp .argument
+.done:
+    mov rax, r14
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+"),
+    // Decimal digits of rdi, written backwards in a buffer on the stack. The minimum stays negative when negated, but
+    // its unsigned division is still right
+    ("__string_from_long", &["__string_from_bytes"], "
+__string_from_long:
+    sub rsp, 32
+    mov rax, rdi
+    mov rsi, rsp
+    add rsi, 32
+    mov rcx, 10
+    cmp rax, 0
+    jge .digits
+    neg rax
+.digits:
+    xor rdx, rdx
+    div rcx
+    add rdx, 48
+    sub rsi, 1
+    mov byte [rsi], dl
+    cmp rax, 0
+    jne .digits
+    cmp rdi, 0
+    jge .copy
+    sub rsi, 1
+    mov rdx, 45
+    mov byte [rsi], dl
+.copy:
+    mov rdi, rsi
+    mov rsi, rsp
+    add rsi, 32
+    sub rsi, rdi
+    call __string_from_bytes
+    add rsp, 32
+    ret
+"),
+    // Lowercase hexadecimal digits of rdi, unsigned
+    ("__string_from_hex", &["__string_from_bytes"], "
+__string_from_hex:
+    sub rsp, 32
+    mov rsi, rsp
+    add rsi, 32
+.digits:
+    mov rax, rdi
+    and rax, 15
+    add rax, 48
+    cmp rax, 58
+    jl .digit
+    add rax, 39
+.digit:
+    sub rsi, 1
+    mov byte [rsi], al
+    shr rdi, 4
+    cmp rdi, 0
+    jne .digits
+    mov rdi, rsi
+    mov rsi, rsp
+    add rsi, 32
+    sub rsi, rdi
+    call __string_from_bytes
+    add rsp, 32
+    ret
+"),
+    // Java's Double.toString of xmm0, or Float.toString if rdi is 1 (with the float converted into xmm0): the shortest
+    // digits reading back as the same value, in plain notation from 10^-3 to 10^7, and in scientific notation otherwise.
+    // The precision p goes up from 1, with the digits n = round(|d| * 10^(p-1-e)) where e is the decimal exponent of |d|,
+    // until n * 10^(e-p+1) is |d| again. The x87 extended precision keeps the scaling exact enough
+    ("__string_from_double", &["__scale_by_power_of_ten", "__string_from_long", "__string_from_bytes", "__copy_bytes"], "
+__string_from_double:
+    push rbp
+    mov rbp, rsp
+    sub rsp, 176
+    mov [rbp-8], rbx
+    mov [rbp-16], r12
+    mov [rbp-24], r13
+    mov [rbp-32], r14
+    mov [rbp-40], r15
+    ; Digits always reading back the same value
+    mov rax, 17
+    cmp rdi, 0
+    je .limit
+    mov rax, 9
+.limit:
+    mov [rbp-96], rdi
+    mov [rbp-112], rax
+    ucomisd xmm0, xmm0
+    jp .nan
+    movq rax, xmm0
+    xor rcx, rcx
+    cmp rax, 0
+    jge .positive
+    mov rcx, 1
+.positive:
+    mov [rbp-104], rcx
+    mov rdx, 0x7fffffffffffffff
+    and rax, rdx
+    mov [rbp-48], rax
+    cmp rax, 0
+    je .zero
+    mov rdx, 0x7ff0000000000000
+    cmp rax, rdx
+    je .infinity
+    ; Bits of the float
+    movq xmm1, rax
+    cvtsd2ss xmm1, xmm1
+    movq rdx, xmm1
+    shl rdx, 32
+    shr rdx, 32
+    mov [rbp-56], rdx
+    ; e = floor(log10(|d|)), from a first estimate
+    fldlg2
+    fld qword [rbp-48]
+    fyl2x
+    fistp qword [rbp-64]
+    mov rax, [rbp-64]
+    mov [rbp-72], rax
+.exponent_check:
+    fld qword [rbp-48]
+    mov rdi, [rbp-72]
+    neg rdi
+    call __scale_by_power_of_ten
+    fld1
+    fucomip st0, st1
+    ja .exponent_down
+    mov qword [rbp-64], 10
+    fild qword [rbp-64]
+    fucomip st0, st1
+    jbe .exponent_up
+    fstp st0
+    jmp .digits
+.exponent_down:
+    fstp st0
+    sub qword [rbp-72], 1
+    jmp .exponent_check
+.exponent_up:
+    fstp st0
+    add qword [rbp-72], 1
+    jmp .exponent_check
+.digits:
+    mov qword [rbp-80], 1
+.precision:
+    fld qword [rbp-48]
+    mov rdi, [rbp-80]
+    sub rdi, 1
+    sub rdi, [rbp-72]
+    call __scale_by_power_of_ten
+    frndint
+    fld st0
+    fistp qword [rbp-88]
+    mov rdi, [rbp-72]
+    add rdi, 1
+    sub rdi, [rbp-80]
+    call __scale_by_power_of_ten
+    mov rax, [rbp-80]
+    cmp rax, [rbp-112]
+    jge .accept
+    mov rax, [rbp-96]
+    cmp rax, 0
+    jne .float
+    fstp qword [rbp-64]
+    mov rax, [rbp-64]
+    cmp rax, [rbp-48]
+    je .found
+    jmp .next
+.float:
+    mov qword [rbp-64], 0
+    fstp dword [rbp-64]
+    mov rax, [rbp-64]
+    cmp rax, [rbp-56]
+    je .found
+.next:
+    add qword [rbp-80], 1
+    jmp .precision
+.accept:
+    fstp st0
+.found:
+    ; Digits without their trailing zeros in r12 (r13 of them, at r12+16), times 10^r14
+    mov rdi, [rbp-88]
+    call __string_from_long
+    mov r12, rax
+    mov r13, [rax+8]
+    mov r14, [rbp-72]
+    add r14, 1
+    sub r14, [rbp-80]
+.strip:
+    cmp r13, 1
+    jle .stripped
+    mov rcx, r12
+    add rcx, r13
+    movzx rax, byte [rcx+15]
+    cmp rax, 48
+    jne .stripped
+    sub r13, 1
+    add r14, 1
+    jmp .strip
+.stripped:
+    ; Characters in the buffer at rbp-176, written at r15. rbx points to the next digit, r14 becomes the exponent of the
+    ; first digit
+    mov r15, rbp
+    sub r15, 176
+    mov rax, [rbp-104]
+    cmp rax, 0
+    je .unsigned
+    mov rax, 45
+    mov byte [r15], al
+    inc r15
+.unsigned:
+    mov rbx, r12
+    add rbx, 16
+    add r14, r13
+    sub r14, 1
+    mov rax, [rbp-48]
+    mov rcx, 0x3f50624dd2f1a9fc
+    cmp rax, rcx
+    jl .scientific
+    mov rcx, 0x416312d000000000
+    cmp rax, rcx
+    jge .scientific
+    cmp r14, 0
+    jge .integer_digit
+    ; 0.00ddd
+    mov rax, 48
+    mov byte [r15], al
+    inc r15
+    mov rax, 46
+    mov byte [r15], al
+    inc r15
+.leading_zero:
+    add r14, 1
+    cmp r14, 0
+    jge .fraction
+    mov rax, 48
+    mov byte [r15], al
+    inc r15
+    jmp .leading_zero
+.integer_digit:
+    ; ddd.ddd, with zeros after the digits before the point
+    mov rax, 48
+    cmp r13, 0
+    je .integer_write
+    movzx rax, byte [rbx]
+    inc rbx
+    sub r13, 1
+.integer_write:
+    mov byte [r15], al
+    inc r15
+    sub r14, 1
+    cmp r14, 0
+    jge .integer_digit
+    mov rax, 46
+    mov byte [r15], al
+    inc r15
+    cmp r13, 0
+    jne .fraction
+    mov rax, 48
+    mov byte [r15], al
+    inc r15
+    jmp .done
+.fraction:
+    mov rdi, r15
+    mov rsi, rbx
+    mov rdx, r13
+    call __copy_bytes
+    mov r15, rdi
+    jmp .done
+.scientific:
+    ; d.dddEx
+    movzx rax, byte [rbx]
+    inc rbx
+    sub r13, 1
+    mov byte [r15], al
+    inc r15
+    mov rax, 46
+    mov byte [r15], al
+    inc r15
+    cmp r13, 0
+    jne .mantissa
+    mov rax, 48
+    mov byte [r15], al
+    inc r15
+    jmp .exponent
+.mantissa:
+    mov rdi, r15
+    mov rsi, rbx
+    mov rdx, r13
+    call __copy_bytes
+    mov r15, rdi
+.exponent:
+    mov rax, 69
+    mov byte [r15], al
+    inc r15
+    mov rdi, r14
+    call __string_from_long
+    mov rdi, r15
+    mov rsi, rax
+    add rsi, 16
+    mov rdx, [rax+8]
+    call __copy_bytes
+    mov r15, rdi
+.done:
+    mov rdi, rbp
+    sub rdi, 176
+    mov rsi, r15
+    sub rsi, rdi
+    call __string_from_bytes
+    jmp .return
+.nan:
+    mov rax, qword __str_NaN
+    jmp .return
+.zero:
+    mov rax, qword __str_zero
+    cmp rcx, 0
+    je .return
+    mov rax, qword __str_negative_zero
+    jmp .return
+.infinity:
+    mov rax, qword __str_Infinity
+    cmp rcx, 0
+    je .return
+    mov rax, qword __str_negative_infinity
+.return:
+    mov rbx, [rbp-8]
+    mov r12, [rbp-16]
+    mov r13, [rbp-24]
+    mov r14, [rbp-32]
+    mov r15, [rbp-40]
+    leave
+    ret
+"),
+    // st0 * 10^rdi into st0, by steps of at most 10^22, the largest power of ten which is exact in a double
+    ("__scale_by_power_of_ten", &[], "
+__scale_by_power_of_ten:
+    mov rsi, qword __powers_of_ten
+    cmp rdi, 0
+    jl .divide
+.multiply:
+    cmp rdi, 22
+    jle .multiply_last
+    fld qword [rsi+176]
+    fmulp st1
+    sub rdi, 22
+    jmp .multiply
+.multiply_last:
+    shl rdi, 3
+    add rsi, rdi
+    fld qword [rsi]
+    fmulp st1
+    ret
+.divide:
+    neg rdi
+.divide_step:
+    cmp rdi, 22
+    jle .divide_last
+    fld qword [rsi+176]
+    fxch st1
+    fdiv st0, st1
+    fstp st1
+    sub rdi, 22
+    jmp .divide_step
+.divide_last:
+    shl rdi, 3
+    add rsi, rdi
+    fld qword [rsi]
+    fxch st1
+    fdiv st0, st1
+    fstp st1
+    ret
+"),
+    ("__string_of_char", &["__string_from_bytes"], "
+__string_of_char:
+    push rdi
+    mov rdi, rsp
+    mov rsi, 1
+    call __string_from_bytes
+    pop rdi
+    ret
+"),
+    ("__string_of_boolean", &[], "
+__string_of_boolean:
+    mov rax, qword __str_true
+    cmp rdi, 0
+    jne .done
+    mov rax, qword __str_false
+.done:
+    ret
+"),
+    ("__string_of_float", &["__string_from_double"], "
+__string_of_float:
+    cvtss2sd xmm0, xmm0
+    mov rdi, 1
+    jmp __string_from_double
+"),
+    ("__string_of_double", &["__string_from_double"], "
+__string_of_double:
+    xor rdi, rdi
+    jmp __string_from_double
+"),
+    ("__string_of_object", &["virtual java/lang/Object.toString()Ljava/lang/String;"], "
+__string_of_object:
+    mov rax, qword __str_null
+    cmp rdi, 0
+    je .done
+    jmp __virtual_java_lang_Object_toString__Ljava_lang_String_
+.done:
+    ret
+"),
+    ("__string_concat", &["__new_string", "__copy_bytes"], "
+__string_concat:
+    cmp rdi, 0
+    jne .first
+    mov rdi, qword __str_null
+.first:
+    cmp rsi, 0
+    jne .second
+    mov rsi, qword __str_null
+.second:
+    push rbx
+    push r12
+    push r13
+    mov r12, rdi
+    mov r13, rsi
+    mov rdi, [r12+8]
+    add rdi, [r13+8]
+    call __new_string
+    mov rbx, rax
+    mov rdi, rax
+    add rdi, 16
+    mov rsi, r12
+    add rsi, 16
+    mov rdx, [r12+8]
+    call __copy_bytes
+    mov rsi, r13
+    add rsi, 16
+    mov rdx, [r13+8]
+    call __copy_bytes
+    mov rax, rbx
+    pop r13
+    pop r12
+    pop rbx
+    ret
+"),
+    // Appends the string rsi to the StringBuilder rdi, and returns the StringBuilder. Its buffer is a string whose
+    // length is the capacity, which doubles when it is full
+    ("__builder_append", &["__new_string", "__copy_bytes"], "
+__builder_append:
+    cmp rsi, 0
+    jne .append
+    mov rsi, qword __str_null
+.append:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+    mov r12, rsi
+    mov r13, [rbx+8]
+    add r13, [r12+8]
+    mov rax, [rbx+16]
+    cmp r13, [rax+8]
+    jle .copy
+    mov rdi, [rax+8]
+    shl rdi, 1
+    cmp rdi, r13
+    jge .grow
+    mov rdi, r13
+.grow:
+    call __new_string
+    mov rsi, [rbx+16]
+    mov [rbx+16], rax
+    mov rdi, rax
+    add rdi, 16
+    add rsi, 16
+    mov rdx, [rbx+8]
+    call __copy_bytes
+.copy:
+    mov rdi, [rbx+16]
+    add rdi, 16
+    add rdi, [rbx+8]
+    mov rsi, r12
+    add rsi, 16
+    mov rdx, [r12+8]
+    call __copy_bytes
+    mov [rbx+8], r13
+    mov rax, rbx
+    pop r13
+    pop r12
+    pop rbx
+    ret
+"),
+    // Whether the object rdi is an instance of the class or interface whose descriptor is rsi
+    ("__instance_of", &[], "
+__instance_of:
+    cmp rdi, 0
+    je .false
+    mov rax, [rdi]
+.class:
+    cmp rax, rsi
+    je .true
+    mov rcx, [rax+16]
+    mov rdx, rax
+    add rdx, 24
+.interface:
+    cmp rcx, 0
+    je .superclass
+    cmp [rdx], rsi
+    je .true
+    add rdx, 8
+    sub rcx, 1
+    jmp .interface
+.superclass:
+    mov rax, [rax]
+    cmp rax, 0
+    jne .class
+.false:
+    xor rax, rax
+    ret
+.true:
+    mov rax, 1
+    ret
+"),
+    // Writes the string rsi, or null, to the file descriptor rdi
+    ("__print_string", &["__sys_write"], "
+__print_string:
+    cmp rsi, 0
+    jne .print
+    mov rsi, qword __str_null
+.print:
+    mov rdx, [rsi+8]
+    add rsi, 16
+    jmp __sys_write
+"),
+    ("__println_string", &["__print_string"], "
+__println_string:
+    push rdi
+    call __print_string
+    pop rdi
+    mov rsi, qword __str_newline
+    jmp __print_string
+"),
+    // Uncaught exception rdi (the name of its class) with the message rsi (or 0): prints it like the JVM and exits
+    ("__uncaught", &["__print_string", "__sys_exit"], "
+__uncaught:
+    mov r12, rdi
+    mov r13, rsi
+    mov rdi, 2
+    mov rsi, qword __str_exception
+    call __print_string
+    mov rdi, 2
+    mov rsi, r12
+    call __print_string
+    cmp r13, 0
+    je .newline
+    mov rdi, 2
+    mov rsi, qword __str_colon
+    call __print_string
+    mov rdi, 2
+    mov rsi, r13
+    call __print_string
+.newline:
+    mov rdi, 2
+    mov rsi, qword __str_newline
+    call __print_string
+    mov rdi, 1
+    jmp __sys_exit
+"),
+    // Index rdi out of bounds for the length rsi, in the exception rdx
+    ("__index_error", &["__string_from_long", "__string_concat", "__uncaught"], "
+__index_error:
+    mov r12, rsi
+    mov r13, rdx
+    call __string_from_long
+    mov rdi, qword __str_index
+    mov rsi, rax
+    call __string_concat
+    mov rdi, rax
+    mov rsi, qword __str_out_of_bounds
+    call __string_concat
+    mov r14, rax
+    mov rdi, r12
+    call __string_from_long
+    mov rdi, r14
+    mov rsi, rax
+    call __string_concat
+    mov rdi, r13
+    mov rsi, rax
+    jmp __uncaught
+"),
+    ("__array_index_error", &["__index_error"], "
+__array_index_error:
+    mov rdx, qword __str_ArrayIndexOutOfBoundsException
+    jmp __index_error
+"),
+    ("__string_index_error", &["__string_from_long", "__string_concat", "__uncaught"], "
+__string_index_error:
+    call __string_from_long
+    mov rdi, qword __str_string_index
+    mov rsi, rax
+    call __string_concat
+    mov rdi, qword __str_StringIndexOutOfBoundsException
+    mov rsi, rax
+    jmp __uncaught
+"),
+    ("__negative_array_size_error", &["__string_from_long", "__uncaught"], "
+__negative_array_size_error:
+    call __string_from_long
+    mov rdi, qword __str_NegativeArraySizeException
+    mov rsi, rax
+    jmp __uncaught
+"),
+    ("__number_format_error", &["__string_concat", "__uncaught"], "
+__number_format_error:
+    mov rsi, rdi
+    mov rdi, qword __str_for_input_string
+    call __string_concat
+    mov rdi, rax
+    mov rsi, qword __str_quote
+    call __string_concat
+    mov rdi, qword __str_NumberFormatException
+    mov rsi, rax
+    jmp __uncaught
+"),
+    ("__null_pointer_error", &["__uncaught"], "
+__null_pointer_error:
+    mov rdi, qword __str_NullPointerException
+    xor rsi, rsi
+    jmp __uncaught
+"),
+    ("__out_of_memory_error", &["__uncaught"], "
+__out_of_memory_error:
+    mov rdi, qword __str_OutOfMemoryError
+    xor rsi, rsi
+    jmp __uncaught
+"),
+    // Methods of java.io.PrintStream, on System.out and System.err whose file descriptors are in rdi
+    ("java/io/PrintStream.print(Ljava/lang/String;)V", &["__print_string"], "
+java_io_PrintStream_print_Ljava_lang_String__V:
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(Ljava/lang/String;)V", &["__println_string"], "
+java_io_PrintStream_println_Ljava_lang_String__V:
+    jmp __println_string
+"),
+    ("java/io/PrintStream.println()V", &["__print_string"], "
+java_io_PrintStream_println__V:
+    mov rsi, qword __str_newline
+    jmp __print_string
+"),
+    ("java/io/PrintStream.print(I)V", &["__string_from_long", "__print_string"], "
+java_io_PrintStream_print_I_V:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(I)V", &["__string_from_long", "__println_string"], "
+java_io_PrintStream_println_I_V:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(J)V", &["__string_from_long", "__print_string"], "
+java_io_PrintStream_print_J_V:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(J)V", &["__string_from_long", "__println_string"], "
+java_io_PrintStream_println_J_V:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(C)V", &["__string_of_char", "__print_string"], "
+java_io_PrintStream_print_C_V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_char
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(C)V", &["__string_of_char", "__println_string"], "
+java_io_PrintStream_println_C_V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_char
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(Z)V", &["__string_of_boolean", "__print_string"], "
+java_io_PrintStream_print_Z_V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_boolean
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(Z)V", &["__string_of_boolean", "__println_string"], "
+java_io_PrintStream_println_Z_V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_boolean
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(F)V", &["__string_of_float", "__print_string"], "
+java_io_PrintStream_print_F_V:
+    push rdi
+    call __string_of_float
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(F)V", &["__string_of_float", "__println_string"], "
+java_io_PrintStream_println_F_V:
+    push rdi
+    call __string_of_float
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(D)V", &["__string_of_double", "__print_string"], "
+java_io_PrintStream_print_D_V:
+    push rdi
+    call __string_of_double
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(D)V", &["__string_of_double", "__println_string"], "
+java_io_PrintStream_println_D_V:
+    push rdi
+    call __string_of_double
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/io/PrintStream.print(Ljava/lang/Object;)V", &["__string_of_object", "__print_string"], "
+java_io_PrintStream_print_Ljava_lang_Object__V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_object
+    pop rdi
+    mov rsi, rax
+    jmp __print_string
+"),
+    ("java/io/PrintStream.println(Ljava/lang/Object;)V", &["__string_of_object", "__println_string"], "
+java_io_PrintStream_println_Ljava_lang_Object__V:
+    push rdi
+    mov rdi, rsi
+    call __string_of_object
+    pop rdi
+    mov rsi, rax
+    jmp __println_string
+"),
+    ("java/lang/Object.hashCode()I", &[], "
+java_lang_Object_hashCode__I:
+    mov rax, rdi
+    shr rax, 3
+    and rax, 0x7fffffff
+    ret
+"),
+    ("java/lang/Object.equals(Ljava/lang/Object;)Z", &[], "
+java_lang_Object_equals_Ljava_lang_Object__Z:
+    xor rax, rax
+    cmp rdi, rsi
+    jne .done
+    mov rax, 1
+.done:
+    ret
+"),
+    // The name of the class, from its descriptor, and the hexadecimal hash code
+    ("java/lang/Object.toString()Ljava/lang/String;", &["virtual java/lang/Object.hashCode()I", "__string_from_hex", "__string_concat"], "
+java_lang_Object_toString__Ljava_lang_String_:
+    push r12
+    mov r12, [rdi]
+    mov r12, [r12+8]
+    call __virtual_java_lang_Object_hashCode__I
+    mov rdi, rax
+    shl rdi, 32
+    shr rdi, 32
+    call __string_from_hex
+    push rax
+    mov rdi, r12
+    mov rsi, qword __str_at
+    call __string_concat
+    mov rdi, rax
+    pop rsi
+    call __string_concat
+    pop r12
+    ret
+"),
+    ("java/lang/String.length()I", &[], "
+java_lang_String_length__I:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/String.isEmpty()Z", &[], "
+java_lang_String_isEmpty__Z:
+    xor rax, rax
+    cmp qword [rdi+8], 0
+    jne .done
+    mov rax, 1
+.done:
+    ret
+"),
+    ("java/lang/String.charAt(I)C", &["__string_index_error"], "
+java_lang_String_charAt_I_C:
+    cmp rsi, [rdi+8]
+    jae .error
+    add rdi, rsi
+    movzx rax, byte [rdi+16]
+    ret
+.error:
+    mov rdi, rsi
+    jmp __string_index_error
+"),
+    ("java/lang/String.equals(Ljava/lang/Object;)Z", &[], "
+java_lang_String_equals_Ljava_lang_Object__Z:
+    mov rax, 1
+    cmp rdi, rsi
+    je .done
+    xor rax, rax
+    cmp rsi, 0
+    je .done
+    mov rcx, [rsi]
+    cmp rcx, [rdi]
+    jne .done
+    mov rdx, [rdi+8]
+    cmp rdx, [rsi+8]
+    jne .done
+    add rdi, 16
+    add rsi, 16
+.loop:
+    cmp rdx, 0
+    je .equal
+    movzx rcx, byte [rdi]
+    movzx r8, byte [rsi]
+    cmp rcx, r8
+    jne .done
+    inc rdi
+    inc rsi
+    sub rdx, 1
+    jmp .loop
+.equal:
+    mov rax, 1
+.done:
+    ret
+"),
+    // s[0]*31^(n-1) + ... + s[n-1], which only depends on the lower 32 bits of the products
+    ("java/lang/String.hashCode()I", &[], "
+java_lang_String_hashCode__I:
+    xor rax, rax
+    mov rdx, [rdi+8]
+    add rdi, 16
+    mov r8, 31
+.loop:
+    cmp rdx, 0
+    je .done
+    imul rax, r8
+    movzx rcx, byte [rdi]
+    add rax, rcx
+    inc rdi
+    sub rdx, 1
+    jmp .loop
+.done:
+    movsxd rax, eax
+    ret
+"),
+    ("java/lang/String.toString()Ljava/lang/String;", &[], "
+java_lang_String_toString__Ljava_lang_String_:
+    mov rax, rdi
+    ret
+"),
+    ("java/lang/String.concat(Ljava/lang/String;)Ljava/lang/String;", &["__string_concat"], "
+java_lang_String_concat_Ljava_lang_String__Ljava_lang_String_:
+    jmp __string_concat
+"),
+    ("java/lang/String.valueOf(I)Ljava/lang/String;", &["__string_from_long"], "
+java_lang_String_valueOf_I_Ljava_lang_String_:
+    jmp __string_from_long
+"),
+    ("java/lang/String.valueOf(J)Ljava/lang/String;", &["__string_from_long"], "
+java_lang_String_valueOf_J_Ljava_lang_String_:
+    jmp __string_from_long
+"),
+    ("java/lang/String.valueOf(C)Ljava/lang/String;", &["__string_of_char"], "
+java_lang_String_valueOf_C_Ljava_lang_String_:
+    jmp __string_of_char
+"),
+    ("java/lang/String.valueOf(Z)Ljava/lang/String;", &["__string_of_boolean"], "
+java_lang_String_valueOf_Z_Ljava_lang_String_:
+    jmp __string_of_boolean
+"),
+    ("java/lang/String.valueOf(F)Ljava/lang/String;", &["__string_of_float"], "
+java_lang_String_valueOf_F_Ljava_lang_String_:
+    jmp __string_of_float
+"),
+    ("java/lang/String.valueOf(D)Ljava/lang/String;", &["__string_of_double"], "
+java_lang_String_valueOf_D_Ljava_lang_String_:
+    jmp __string_of_double
+"),
+    ("java/lang/String.valueOf(Ljava/lang/Object;)Ljava/lang/String;", &["__string_of_object"], "
+java_lang_String_valueOf_Ljava_lang_Object__Ljava_lang_String_:
+    jmp __string_of_object
+"),
+    // Only with %n, %% and conversions (e.g. %s or %d) without flags, width nor precision, which all become the
+    // string of their argument
+    ("java/lang/String.format(Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/String;", &["__new_builder", "__builder_append", "__string_from_bytes", "__string_of_object", "java/lang/StringBuilder.toString()Ljava/lang/String;"], "
+java_lang_String_format_Ljava_lang_String__Ljava_lang_Object__Ljava_lang_String_:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    ; The format in r12 from r13 to its end r14, the next argument at r15, and the builder in rbx
+    mov r12, rdi
+    mov r13, rdi
+    add r13, 16
+    mov r14, r13
+    add r14, [rdi+8]
+    mov r15, rsi
+    add r15, 16
+    call __new_builder
+    mov rbx, rax
+.text:
+    ; The characters up to the next % or the end
+    mov rsi, r13
+.scan:
+    cmp rsi, r14
+    je .append
+    movzx rax, byte [rsi]
+    cmp rax, 37
+    je .append
+    inc rsi
+    jmp .scan
+.append:
+    mov rdi, r13
+    sub rsi, r13
+    add r13, rsi
+    cmp rsi, 0
+    je .conversion
+    call __string_from_bytes
+    mov rdi, rbx
+    mov rsi, rax
+    call __builder_append
+.conversion:
+    cmp r13, r14
+    je .done
+    movzx rax, byte [r13+1]
+    add r13, 2
+    cmp rax, 110
+    je .newline
+    cmp rax, 37
+    je .percent
+    mov rdi, [r15]
+    add r15, 8
+    call __string_of_object
+    mov rdi, rbx
+    mov rsi, rax
+    call __builder_append
+    jmp .text
+.newline:
+    mov rdi, rbx
+    mov rsi, qword __str_newline
+    call __builder_append
+    jmp .text
+.percent:
+    mov rdi, r13
+    sub rdi, 1
+    mov rsi, 1
+    call __string_from_bytes
+    mov rdi, rbx
+    mov rsi, rax
+    call __builder_append
+    jmp .text
+.done:
+    mov rdi, rbx
+    call java_lang_StringBuilder_toString__Ljava_lang_String_
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+"),
+    ("java/lang/StringBuilder.<init>()V", &["__new_string"], "
+java_lang_StringBuilder__init___V:
+    push rdi
+    mov rdi, 16
+    call __new_string
+    pop rdi
+    mov qword [rdi+8], 0
+    mov [rdi+16], rax
+    ret
+"),
+    ("java/lang/StringBuilder.<init>(Ljava/lang/String;)V", &["java/lang/StringBuilder.<init>()V", "__builder_append"], "
+java_lang_StringBuilder__init__Ljava_lang_String__V:
+    push rdi
+    push rsi
+    call java_lang_StringBuilder__init___V
+    pop rsi
+    pop rdi
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(Ljava/lang/String;)Ljava/lang/StringBuilder;", &["__builder_append"], "
+java_lang_StringBuilder_append_Ljava_lang_String__Ljava_lang_StringBuilder_:
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(I)Ljava/lang/StringBuilder;", &["__string_from_long", "__builder_append"], "
+java_lang_StringBuilder_append_I_Ljava_lang_StringBuilder_:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(J)Ljava/lang/StringBuilder;", &["__string_from_long", "__builder_append"], "
+java_lang_StringBuilder_append_J_Ljava_lang_StringBuilder_:
+    push rdi
+    mov rdi, rsi
+    call __string_from_long
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(C)Ljava/lang/StringBuilder;", &["__string_of_char", "__builder_append"], "
+java_lang_StringBuilder_append_C_Ljava_lang_StringBuilder_:
+    push rdi
+    mov rdi, rsi
+    call __string_of_char
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(Z)Ljava/lang/StringBuilder;", &["__string_of_boolean", "__builder_append"], "
+java_lang_StringBuilder_append_Z_Ljava_lang_StringBuilder_:
+    push rdi
+    mov rdi, rsi
+    call __string_of_boolean
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(F)Ljava/lang/StringBuilder;", &["__string_of_float", "__builder_append"], "
+java_lang_StringBuilder_append_F_Ljava_lang_StringBuilder_:
+    push rdi
+    call __string_of_float
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(D)Ljava/lang/StringBuilder;", &["__string_of_double", "__builder_append"], "
+java_lang_StringBuilder_append_D_Ljava_lang_StringBuilder_:
+    push rdi
+    call __string_of_double
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(Ljava/lang/Object;)Ljava/lang/StringBuilder;", &["__string_of_object", "__builder_append"], "
+java_lang_StringBuilder_append_Ljava_lang_Object__Ljava_lang_StringBuilder_:
+    push rdi
+    mov rdi, rsi
+    call __string_of_object
+    pop rdi
+    mov rsi, rax
+    jmp __builder_append
+"),
+    ("java/lang/StringBuilder.append(Ljava/lang/CharSequence;)Ljava/lang/StringBuilder;", &["java/lang/StringBuilder.append(Ljava/lang/Object;)Ljava/lang/StringBuilder;"], "
+java_lang_StringBuilder_append_Ljava_lang_CharSequence__Ljava_lang_StringBuilder_:
+    jmp java_lang_StringBuilder_append_Ljava_lang_Object__Ljava_lang_StringBuilder_
+"),
+    ("java/lang/StringBuilder.length()I", &[], "
+java_lang_StringBuilder_length__I:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/StringBuilder.toString()Ljava/lang/String;", &["__string_from_bytes"], "
+java_lang_StringBuilder_toString__Ljava_lang_String_:
+    mov rsi, [rdi+8]
+    mov rdi, [rdi+16]
+    add rdi, 16
+    jmp __string_from_bytes
+"),
+    ("java/lang/Integer.valueOf(I)Ljava/lang/Integer;", &["__new"], "
+java_lang_Integer_valueOf_I_Ljava_lang_Integer_:
+    push rdi
+    mov rdi, 16
+    call __new
+    pop rdi
+    mov rcx, qword class_java_lang_Integer
+    mov [rax], rcx
+    mov [rax+8], rdi
+    ret
+"),
+    ("java/lang/Integer.intValue()I", &[], "
+java_lang_Integer_intValue__I:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/Integer.hashCode()I", &[], "
+java_lang_Integer_hashCode__I:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/Integer.equals(Ljava/lang/Object;)Z", &[], "
+java_lang_Integer_equals_Ljava_lang_Object__Z:
+    xor rax, rax
+    cmp rsi, 0
+    je .done
+    mov rcx, [rsi]
+    cmp rcx, [rdi]
+    jne .done
+    mov rcx, [rsi+8]
+    cmp rcx, [rdi+8]
+    jne .done
+    mov rax, 1
+.done:
+    ret
+"),
+    ("java/lang/Integer.toString()Ljava/lang/String;", &["__string_from_long"], "
+java_lang_Integer_toString__Ljava_lang_String_:
+    mov rdi, [rdi+8]
+    jmp __string_from_long
+"),
+    ("java/lang/Integer.toString(I)Ljava/lang/String;", &["__string_from_long"], "
+java_lang_Integer_toString_I_Ljava_lang_String_:
+    jmp __string_from_long
+"),
+    // Decimal digits with an optional sign, whose value must fit in an int
+    ("java/lang/Integer.parseInt(Ljava/lang/String;)I", &["__number_format_error"], "
+java_lang_Integer_parseInt_Ljava_lang_String__I:
+    cmp rdi, 0
+    je .error
+    mov rsi, rdi
+    add rsi, 16
+    mov rdx, [rdi+8]
+    xor rax, rax
+    xor r8, r8
+    cmp rdx, 0
+    je .error
+    movzx rcx, byte [rsi]
+    cmp rcx, 45
+    je .negative
+    cmp rcx, 43
+    jne .digits
+    jmp .sign
+.negative:
+    mov r8, 1
+.sign:
+    inc rsi
+    sub rdx, 1
+    je .error
+.digits:
+    movzx rcx, byte [rsi]
+    sub rcx, 48
+    jl .error
+    cmp rcx, 9
+    jg .error
+    mov r9, 10
+    imul rax, r9
+    add rax, rcx
+    mov r9, 0x80000000
+    cmp rax, r9
+    jg .error
+    inc rsi
+    sub rdx, 1
+    jne .digits
+    cmp r8, 0
+    je .positive
+    neg rax
+    ret
+.positive:
+    mov r9, 0x7fffffff
+    cmp rax, r9
+    jg .error
+    ret
+.error:
+    jmp __number_format_error
+"),
+    ("java/util/ArrayList.<init>()V", &["__new_array"], "
+java_util_ArrayList__init___V:
+    push rdi
+    mov rdi, 10
+    call __new_array
+    pop rdi
+    mov qword [rdi+8], 0
+    mov [rdi+16], rax
+    ret
+"),
+    // Appends rsi, in an array growing from n to 2n+1 elements when it is full
+    ("java/util/ArrayList.add(Ljava/lang/Object;)Z", &["__new_array", "__copy_qwords"], "
+java_util_ArrayList_add_Ljava_lang_Object__Z:
+    mov rax, [rdi+16]
+    mov rcx, [rdi+8]
+    cmp rcx, [rax+8]
+    jl .store
+    push rdi
+    push rsi
+    mov rdi, [rax+8]
+    shl rdi, 1
+    add rdi, 1
+    call __new_array
+    mov rdi, [rsp+8]
+    mov rsi, [rdi+16]
+    mov [rdi+16], rax
+    mov rdx, [rdi+8]
+    mov rdi, rax
+    add rdi, 16
+    add rsi, 16
+    call __copy_qwords
+    pop rsi
+    pop rdi
+    mov rax, [rdi+16]
+    mov rcx, [rdi+8]
+.store:
+    add rcx, 1
+    mov [rdi+8], rcx
+    shl rcx, 3
+    add rax, rcx
+    mov [rax+8], rsi
+    mov rax, 1
+    ret
+"),
+    ("java/util/ArrayList.get(I)Ljava/lang/Object;", &["__index_error"], "
+java_util_ArrayList_get_I_Ljava_lang_Object_:
+    cmp rsi, [rdi+8]
+    jae .error
+    mov rax, [rdi+16]
+    shl rsi, 3
+    add rax, rsi
+    mov rax, [rax+16]
+    ret
+.error:
+    mov rdx, qword __str_IndexOutOfBoundsException
+    mov rax, [rdi+8]
+    mov rdi, rsi
+    mov rsi, rax
+    jmp __index_error
+"),
+    ("java/util/ArrayList.size()I", &[], "
+java_util_ArrayList_size__I:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/util/ArrayList.isEmpty()Z", &[], "
+java_util_ArrayList_isEmpty__Z:
+    xor rax, rax
+    cmp qword [rdi+8], 0
+    jne .done
+    mov rax, 1
+.done:
+    ret
+"),
+    ("java/lang/Enum.<init>(Ljava/lang/String;I)V", &[], "
+java_lang_Enum__init__Ljava_lang_String_I_V:
+    mov [rdi+8], rsi
+    mov [rdi+16], rdx
+    ret
+"),
+    ("java/lang/Enum.name()Ljava/lang/String;", &[], "
+java_lang_Enum_name__Ljava_lang_String_:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/Enum.toString()Ljava/lang/String;", &[], "
+java_lang_Enum_toString__Ljava_lang_String_:
+    mov rax, [rdi+8]
+    ret
+"),
+    ("java/lang/Enum.ordinal()I", &[], "
+java_lang_Enum_ordinal__I:
+    mov rax, [rdi+16]
+    ret
+")
+];
+
+// System calls of the runtime, for Linux and macOS: __sys_write(fd, address, length), __sys_mmap(length), which returns
+// the address or a value from -4095 to -1 when it fails, and __sys_exit(status)
+pub static SYSTEM_ROUTINES: [(&str, &str, &str); 3] = [
+    ("__sys_write", "
+__sys_write:
+    mov rax, 1
+    syscall
+    ret
+", "
+__sys_write:
+    mov rax, 0x2000004
+    syscall
+    ret
+"),
+    ("__sys_mmap", "
+__sys_mmap:
+    mov rax, 9
+    xor rdi, rdi
+    mov rdx, 3
+    mov r10, 0x22
+    mov r8, -1
+    xor r9, r9
+    syscall
+    ret
+", "
+__sys_mmap:
+    mov rax, 0x20000c5
+    xor rdi, rdi
+    mov rdx, 3
+    mov r10, 0x1002
+    mov r8, -1
+    xor r9, r9
+    syscall
+    jae .done
+    mov rax, -1
+.done:
+    ret
+"),
+    ("__sys_exit", "
+__sys_exit:
+    mov rax, 60
+    syscall
+", "
+__sys_exit:
+    mov rax, 0x2000001
+    syscall
+")
+];
+
+pub fn system_routine(name: &str, arch: Arch) -> Option<&'static str> {
+    SYSTEM_ROUTINES.iter().find(|(routine, _, _)| *routine == name).map(|(_, linux, macos)| match arch {
+        Arch::LinuxX64 => *linux,
+        Arch::MacosX64 => *macos
+    })
+}
+
+pub fn is_routine(name: &str) -> bool {
+    ROUTINES.iter().any(|(routine, _, _)| *routine == name) || MATH_ROUTINES.iter().any(|(routine, _, _)| *routine == name)
+        || SYSTEM_ROUTINES.iter().any(|(routine, _, _)| *routine == name)
+}
+
+// Class, name and descriptor of a method, e.g. java/lang/String.length()I
+pub fn parse_method(name: &str) -> (String, String, String) {
+    match (name.split_once('.'), name.find('(')) {
+        (Some((class_name, method)), Some(idx)) if idx > class_name.len() => {
+            let idx = idx - class_name.len() - 1;
+            (class_name.to_string(), method[..idx].to_string(), method[idx..].to_string())
+        },
+        _ => panic!("Invalid runtime method {}", name)
+    }
+}
+
+// Label of a routine, which is the label of the method for the methods of the runtime classes
+pub fn routine_label(name: &str) -> String {
+    if name.starts_with("__") {
+        return name.to_string();
+    }
+    let (class_name, method_name, type_desc) = parse_method(name);
+    method_label(&class_name, &method_name, &type_desc)
+}
+
+// Superclass, instance fields and interfaces of a class of the runtime
+pub fn get_class(class_name: &str) -> Option<(&'static str, &'static [&'static str], &'static [&'static str])> {
+    CLASSES.iter().find(|(name, _, _, _)| *name == class_name).map(|(_, parent, fields, interfaces)| (*parent, *fields, *interfaces))
+}
+
+// Label of the descriptor of a class, which holds the addresses of the descriptor of its superclass (or 0) and of its
+// name, and then the number of its interfaces and the addresses of their descriptors
+pub fn class_label(class_name: &str) -> String {
+    if class_name.starts_with('[') {
+        return "__class_array".to_string();
+    }
+    format!("class_{}", sanitize(class_name))
+}
+
+pub fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '$' { c } else { '_' }).collect()
+}
+
+// A string constant: descriptor, length and bytes, padded to 8 bytes
+pub fn string_data(label: &str, string: &str) -> String {
+    let mut values: Vec<String> = Vec::new();
+    let mut run = String::new();
+    for byte in string.bytes() {
+        if byte >= 0x20 && byte < 0x7f && byte != b'"' {
+            run.push(byte as char);
+        } else {
+            if !run.is_empty() {
+                values.push(format!("\"{}\"", run));
+                run.clear();
+            }
+            values.push(byte.to_string());
+        }
+    }
+    if !run.is_empty() {
+        values.push(format!("\"{}\"", run));
+    }
+    for _ in 0..(8 - string.len() % 8) % 8 {
+        values.push("0".to_string());
+    }
+
+    let mut data = format!("{}: dq class_java_lang_String, {}\n", label, string.len());
+    if !values.is_empty() {
+        data.push_str(&format!("    db {}\n", values.join(", ")));
+    }
+    data
+}
+
+// The first 1216 bits of 2/pi, enough for the reduction of the largest doubles by __math_reduce
+static TWO_OVER_PI: [u64; 19] = [0xa2f9836e4e441529, 0xfc2757d1f534ddc0, 0xdb6295993c439041, 0xfe5163abdebbc561,
+    0xb7246e3a424dd2e0, 0x06492eea09d1921c, 0xfe1deb1cb129a73e, 0xe88235f52ebb4484, 0xe99c7026b45f7e41, 0x3991d639835339f4,
+    0x9c845f8bbdf9283b, 0x1ff897ffde05980f, 0xef2f118b5a0a6d1f, 0x6d367ecf27cb09b7, 0x4f463f669e5fea2d, 0x7527bac7ebe5f17b,
+    0x3d0739f78a5292ea, 0x6bfb5fb11f8d5d08, 0x56033046fc7b6bab];
+
+// Data of the runtime: its strings, the heap (next free address, and end of the current chunk), the descriptor of the
+// arrays, the powers of ten up to 10^22, which are all exact doubles, and the bits of 2/pi after a word of zeros
+pub fn runtime_data() -> String {
+    let mut data: Vec<String> = Vec::new();
+    for (label, string) in STRINGS.iter() {
+        data.push(string_data(label, string));
+    }
+    data.push("__heap: dq 0, 0\n".to_string());
+    data.push("__class_array: dq class_java_lang_Object, __str_array, 0\n".to_string());
+    let powers: Vec<String> = (0..23).map(|exp| format!("0x{:x}", format!("1e{}", exp).parse::<f64>().unwrap().to_bits())).collect();
+    data.push(format!("__powers_of_ten: dq {}\n", powers.join(", ")));
+    let two_over_pi: Vec<String> = TWO_OVER_PI.iter().map(|bits| format!("0x{:016x}", bits)).collect();
+    data.push(format!("__two_over_pi: dq 0, {}\n", two_over_pi.join(", ")));
+    data.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routine_labels() {
+        for (name, deps, source) in ROUTINES.iter() {
+            assert!(source.contains(&format!("\n{}:\n", routine_label(name))), "{} has no label {}", name, routine_label(name));
+            for dep in deps.iter() {
+                assert!(is_routine(dep.trim_start_matches("virtual ")), "{} depends on unknown {}", name, dep);
+            }
+        }
+        assert_eq!(routine_label("java/lang/String.charAt(I)C"), "java_lang_String_charAt_I_C");
+    }
+
+    #[test]
+    fn test_string_data() {
+        assert_eq!(string_data("s", "a\"b\n"), "s: dq class_java_lang_String, 4\n    db \"a\", 34, \"b\", 10, 0, 0, 0, 0\n");
+        assert_eq!(string_data("e", ""), "e: dq class_java_lang_String, 0\n");
+    }
+}
@@ -1,18 +1,19 @@
 use std::sync::{Arc, Mutex};
 
-use crate::get_class;
+use crate::vm::get_class;
 use crate::java_class::MethodCallResult;
-use crate::jvm::JavaInstance;
+use crate::jvm::{JavaInstance, Value};
 use crate::jvm::StackFrame;
 use crate::java_class::JavaClass;
 
 /////////////////// java.util.stream.Stream
 
-pub trait StreamFunction {
-    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Option<Arc<Mutex<dyn JavaInstance>>>;
+pub trait StreamFunction: Send {
+    // Returns the next object of the stream (None at the end), or the exception thrown by a lambda
+    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Result<Option<Arc<Mutex<dyn JavaInstance>>>, Arc<Mutex<dyn JavaInstance>>>;
     fn get_class_name(&self) -> String { return "".to_string(); }
     fn get_method_name(&self) -> String { return "".to_string(); }
-    fn print(&self);
+    fn get_method_type(&self) -> String { return "".to_string(); }
 }
 
 pub struct NativeStreamData {
@@ -30,16 +31,13 @@ impl NativeStreamData {
 }
 
 impl StreamFunction for NativeStreamData {
-    fn next_object(&mut self, _function_idx: usize, _stream: &NativeStreamInstance, _sf: &mut StackFrame) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+    fn next_object(&mut self, _function_idx: usize, _stream: &NativeStreamInstance, _sf: &mut StackFrame) -> Result<Option<Arc<Mutex<dyn JavaInstance>>>, Arc<Mutex<dyn JavaInstance>>> {
         let object = match self.data.lock().unwrap().get(self.idx) {
             Some(obj) => Some(obj.clone()),
             _ => None
         };
         self.idx += 1;
-        return object;
-    }
-    fn print(&self) {
-        println!("Data");
+        return Ok(object);
     }
 }
 
@@ -50,16 +48,15 @@ impl JavaInstance for NativeStreamInstance {
     fn get_class_name(&self) -> String {
         return "java/util/stream/Stream".to_string();
     }
-    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Arc<Mutex<dyn JavaInstance>>>) {
+    fn execute_method(&mut self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
         match &method_name[..] {
             "filter" | "map" => {
-                let stream_function = args.get(0).unwrap().lock().unwrap().get_stream_function();
+                let stream_function = args[0].to_object().lock().unwrap().get_stream_function();
                 self.operations.insert(0, stream_function);
                 sf.push(this.clone());
             },
             "forEach" => {
-                let arg = args.get(0).unwrap();
-                let consumer = arg.lock().unwrap().get_stream_function();
+                let consumer = args[0].to_object().lock().unwrap().get_stream_function();
                 let current_function = match self.operations.get(0) {
                     Some(function) => &*function,
                     _ => panic!("Stream.{}(): missing function 0", method_name)
@@ -68,16 +65,22 @@ impl JavaInstance for NativeStreamInstance {
 
                 loop {
                     match current_function.lock().unwrap().next_object(0, self, sf) {
-                        Some(object) => {
+                        Ok(Some(object)) => {
                             sf.push(object.clone());
-                            class.execute_static_method(sf, &consumer.lock().unwrap().get_method_name(), 1);
+                            let consumer = consumer.lock().unwrap();
+                            let result = class.execute_static_method(sf, &consumer.get_method_name(), &consumer.get_method_type(), 1);
+                            if let MethodCallResult::EXCEPTION(e) = result {
+                                return MethodCallResult::EXCEPTION(e);
+                            }
                         },
-                        None => break
+                        Ok(None) => break,
+                        Err(e) => return MethodCallResult::EXCEPTION(e)
                     }
                 }
             },
             _ => panic!("Native class {} does not have method [{}]", self.get_class_name(), method_name)
         };
+        return MethodCallResult::SUCCESS;
     }
 }
 
@@ -117,12 +120,12 @@ impl JavaClass for NativeLambdaMetafactoryClass {
         println!("Native Stream class");
     }
 
-    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _nb_args: usize) -> MethodCallResult {
+    fn execute_static_method(&self, sf: &mut StackFrame, method_name: &String, _type_desc: &String, nb_args: usize) -> MethodCallResult {
         if method_name.eq("metafactory") {
             let _arg3 = sf.pop();
             let arg2 = sf.pop_int();
             let _arg1 = sf.pop();
-            let _type = sf.pop();
+            let call_site_type = sf.pop_string();
             let action = sf.pop_string();
             let class_name = sf.pop_string();
 
@@ -163,7 +166,17 @@ impl JavaClass for NativeLambdaMetafactoryClass {
                     };
                     sf.push(Arc::new(Mutex::new(object)));
                 },
-                _ => panic!("LambdaMetafactory.metafactory(): Unsupported command {}", action)
+                _ => {
+                    // Any other functional interface, such as Runnable. The interface is the return type of
+                    // the call site, whose arguments are the values captured by the lambda
+                    let interface_name = call_site_type[call_site_type.find(")L").unwrap() + 2..call_site_type.len() - 1].to_string();
+                    let mut captured: Vec<Value> = Vec::new();
+                    for _ in 0..nb_args {
+                        captured.insert(0, sf.pop_value());
+                    }
+                    let lambda = Lambda { interface_name, class_name, method_name, type_desc, captured };
+                    sf.push(Arc::new(Mutex::new(NativeLambdaInstance { lambda: Arc::new(lambda) })));
+                }
             };
             return MethodCallResult::SUCCESS;
         }
@@ -201,31 +214,32 @@ impl Clone for NativePredicateInstance {
 pub struct NativePredicateClass { }
 
 impl StreamFunction for NativePredicateInstance {
-    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Result<Option<Arc<Mutex<dyn JavaInstance>>>, Arc<Mutex<dyn JavaInstance>>> {
         match stream.operations.get(function_idx + 1) {
             Some(function) => {
                 let class = get_class(&self.class_name);
 
                 loop {
-                    let object = (**function).lock().unwrap().next_object(function_idx + 1, &stream, sf);
+                    let object = (**function).lock().unwrap().next_object(function_idx + 1, &stream, sf)?;
 
                     match object {
                         Some(obj) => {
                             sf.push(obj.clone());
-                            class.execute_static_method(sf, &self.method_name, 1);
+                            if let MethodCallResult::EXCEPTION(e) = class.execute_static_method(sf, &self.method_name, &self.type_desc, 1) {
+                                return Err(e);
+                            }
                             let is_predicate_valid = sf.pop_bool();
                             if is_predicate_valid {
-                                return Some(obj.clone());
+                                return Ok(Some(obj.clone()));
                             }
                         },
-                        None => return None
+                        None => return Ok(None)
                     };
                 }
             },
             _ => panic!("No more function")
         };
     }
-    fn print(&self) { println!("Predicate"); }
 }
 
 impl JavaClass for NativePredicateClass {
@@ -264,26 +278,27 @@ impl Clone for NativeFunctionInstance {
 }
 
 impl StreamFunction for NativeFunctionInstance {
-    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+    fn next_object(&mut self, function_idx: usize, stream: &NativeStreamInstance, sf: &mut StackFrame) -> Result<Option<Arc<Mutex<dyn JavaInstance>>>, Arc<Mutex<dyn JavaInstance>>> {
         match stream.operations.get(function_idx + 1) {
             Some(function) => {
                 let class = get_class(&self.class_name);
 
-                let object = function.lock().unwrap().next_object(function_idx + 1, &stream, sf);
+                let object = function.lock().unwrap().next_object(function_idx + 1, &stream, sf)?;
 
                 match object {
                     Some(obj) => {
                         sf.push(obj);
-                        class.execute_static_method(sf, &self.method_name, 1);
-                        return Some(sf.pop());
+                        if let MethodCallResult::EXCEPTION(e) = class.execute_static_method(sf, &self.method_name, &self.type_desc, 1) {
+                            return Err(e);
+                        }
+                        return Ok(Some(sf.pop()));
                     },
-                    None => return None
+                    None => return Ok(None)
                 };
             },
             _ => panic!("No more function")
         };
     }
-    fn print(&self) { println!("Function"); }
 }
 
 pub struct NativeFunctionClass { }
@@ -314,8 +329,8 @@ impl JavaInstance for NativeConsumerInstance {
 }
 
 impl StreamFunction for NativeConsumerInstance {
-    fn next_object(&mut self, _function_idx: usize, _stream: &NativeStreamInstance, _sf: &mut StackFrame) -> Option<Arc<Mutex<dyn JavaInstance>>> {
-        return None;
+    fn next_object(&mut self, _function_idx: usize, _stream: &NativeStreamInstance, _sf: &mut StackFrame) -> Result<Option<Arc<Mutex<dyn JavaInstance>>>, Arc<Mutex<dyn JavaInstance>>> {
+        return Ok(None);
     }
 
     fn get_class_name(&self) -> String {
@@ -326,8 +341,8 @@ impl StreamFunction for NativeConsumerInstance {
         return self.method_name.clone();
     }
 
-    fn print(&self) {
-        print!("Consumer");
+    fn get_method_type(&self) -> String {
+        return self.type_desc.clone();
     }
 }
 
@@ -352,3 +367,65 @@ impl JavaClass for NativeConsumerClass {
         println!("Native Consumer class");
     }
 }
+
+/////////////////// Lambdas implementing any other functional interface
+
+pub struct Lambda {
+    interface_name: String,
+    class_name: String,
+    method_name: String,
+    type_desc: String,
+    captured: Vec<Value>
+}
+
+impl Lambda {
+    // Calls the static method implementing the lambda with the captured values followed by the arguments,
+    // which are in reverse order
+    fn invoke(&self, sf: &mut StackFrame, args: Vec<Value>) -> MethodCallResult {
+        for value in self.captured.iter() {
+            sf.push_value(value.clone());
+        }
+        for arg in args.iter().rev() {
+            sf.push_value(arg.clone());
+        }
+        let class = get_class(&self.class_name);
+        class.execute_static_method(sf, &self.method_name, &self.type_desc, self.captured.len() + args.len())
+    }
+}
+
+pub struct NativeLambdaInstance {
+    lambda: Arc<Lambda>
+}
+
+impl JavaInstance for NativeLambdaInstance {
+    fn get_class_name(&self) -> String {
+        return "java/lang/invoke/Lambda".to_string();
+    }
+    fn supports_interface(&self, interface_name: &String) -> bool {
+        self.lambda.interface_name.eq(interface_name)
+    }
+    fn get_lambda(&self) -> Option<Arc<Lambda>> {
+        Some(self.lambda.clone())
+    }
+}
+
+pub struct NativeLambdaClass { }
+
+impl JavaClass for NativeLambdaClass {
+    fn get_name(&self) -> String {
+        return "java/lang/invoke/Lambda".to_string();
+    }
+
+    fn print(&self) {
+        println!("Native Lambda class");
+    }
+
+    // The lambda is not kept locked while it runs, as it may be called by several threads
+    fn execute_method(&self, sf: &mut StackFrame, _method_name: &String, _type_desc: &String, this: Arc<Mutex<dyn JavaInstance>>, args: Vec<Value>) -> MethodCallResult {
+        let lambda = match this.lock().unwrap().get_lambda() {
+            Some(lambda) => lambda,
+            None => panic!("{} is not a lambda", this.lock().unwrap().get_class_name())
+        };
+        lambda.invoke(sf, args)
+    }
+}
@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::jvm::JavaInstance;
+use crate::monitor::wake_waiting_threads;
+use crate::native_java_classes::NativeThreadInstance;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThreadState {
+    New,
+    Runnable,
+    Waiting,
+    TimedWaiting,
+    Terminated
+}
+
+// The state of a Java thread, shared between its java.lang.Thread object and the OS thread running it
+pub struct JavaThread {
+    id: u64,
+    name: Mutex<String>,
+    state: Mutex<ThreadState>,
+    // Signaled when the thread changes state or is interrupted
+    changed: Condvar,
+    interrupted: AtomicBool,
+    priority: AtomicI32,
+    daemon: AtomicBool,
+    // The Runnable executed by Thread.run(), and the Thread.UncaughtExceptionHandler
+    target: Mutex<Option<Arc<Mutex<dyn JavaInstance>>>>,
+    uncaught_exception_handler: Mutex<Option<Arc<Mutex<dyn JavaInstance>>>>
+}
+
+pub const MIN_PRIORITY: i32 = 1;
+pub const NORM_PRIORITY: i32 = 5;
+pub const MAX_PRIORITY: i32 = 10;
+
+impl JavaThread {
+    pub fn new(name: String) -> Arc<JavaThread> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Arc::new(JavaThread {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            name: Mutex::new(name),
+            state: Mutex::new(ThreadState::New),
+            changed: Condvar::new(),
+            interrupted: AtomicBool::new(false),
+            priority: AtomicI32::new(NORM_PRIORITY),
+            daemon: AtomicBool::new(false),
+            target: Mutex::new(None),
+            uncaught_exception_handler: Mutex::new(None)
+        })
+    }
+
+    pub fn get_id(&self) -> u64 { self.id }
+
+    pub fn get_name(&self) -> String { self.name.lock().unwrap().clone() }
+    pub fn set_name(&self, name: String) { *self.name.lock().unwrap() = name; }
+
+    pub fn get_priority(&self) -> i32 { self.priority.load(Ordering::SeqCst) }
+    pub fn set_priority(&self, priority: i32) { self.priority.store(priority, Ordering::SeqCst); }
+
+    pub fn is_daemon(&self) -> bool { self.daemon.load(Ordering::SeqCst) }
+    pub fn set_daemon(&self, daemon: bool) { self.daemon.store(daemon, Ordering::SeqCst); }
+
+    pub fn get_target(&self) -> Option<Arc<Mutex<dyn JavaInstance>>> { self.target.lock().unwrap().clone() }
+    pub fn set_target(&self, target: Arc<Mutex<dyn JavaInstance>>) { *self.target.lock().unwrap() = Some(target); }
+
+    pub fn get_uncaught_exception_handler(&self) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+        self.uncaught_exception_handler.lock().unwrap().clone()
+    }
+    pub fn set_uncaught_exception_handler(&self, handler: Option<Arc<Mutex<dyn JavaInstance>>>) {
+        *self.uncaught_exception_handler.lock().unwrap() = handler;
+    }
+
+    pub fn get_state(&self) -> ThreadState { *self.state.lock().unwrap() }
+
+    pub fn set_state(&self, state: ThreadState) {
+        *self.state.lock().unwrap() = state;
+        self.changed.notify_all();
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !matches!(self.get_state(), ThreadState::New | ThreadState::Terminated)
+    }
+
+    // Waits for the thread to terminate, at most timeout if there is one
+    pub fn join(&self, timeout: Option<Duration>) {
+        let deadline = timeout.map(|duration| Instant::now() + duration);
+        let mut state = self.state.lock().unwrap();
+        while !matches!(*state, ThreadState::New | ThreadState::Terminated) {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline { return; }
+                    self.changed.wait_timeout(state, deadline - now).unwrap().0
+                },
+                None => self.changed.wait(state).unwrap()
+            };
+        }
+    }
+
+    // Returns false if the thread got interrupted while sleeping
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        *state = ThreadState::TimedWaiting;
+        let completed = loop {
+            if self.is_interrupted(true) { break false; }
+            let now = Instant::now();
+            if now >= deadline { break true; }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        };
+        *state = ThreadState::Runnable;
+        completed
+    }
+
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+        {
+            let _state = self.state.lock().unwrap();
+            self.changed.notify_all();
+        }
+        wake_waiting_threads();
+    }
+
+    // Thread.interrupted() clears the flag, isInterrupted() does not
+    pub fn is_interrupted(&self, clear: bool) -> bool {
+        if clear {
+            self.interrupted.swap(false, Ordering::SeqCst)
+        } else {
+            self.interrupted.load(Ordering::SeqCst)
+        }
+    }
+}
+
+// The java.lang.Thread object of the thread being executed, and its state
+pub struct CurrentThread {
+    object: Arc<Mutex<dyn JavaInstance>>,
+    thread: Arc<JavaThread>
+}
+
+thread_local! {
+    static CURRENT_THREAD: RefCell<Option<CurrentThread>> = RefCell::new(None);
+}
+
+pub fn set_current_thread(object: Arc<Mutex<dyn JavaInstance>>, thread: Arc<JavaThread>) {
+    CURRENT_THREAD.with(|current| *current.borrow_mut() = Some(CurrentThread { object, thread }));
+}
+
+// Used when a thread enters or leaves a VM, which has its own Thread objects
+pub fn replace_current_thread(thread: Option<CurrentThread>) -> Option<CurrentThread> {
+    CURRENT_THREAD.with(|current| current.replace(thread))
+}
+
+// The Thread object of the current thread. main() runs in a thread created on first use
+pub fn get_current_thread() -> (Arc<Mutex<dyn JavaInstance>>, Arc<JavaThread>) {
+    CURRENT_THREAD.with(|current| {
+        let mut current = current.borrow_mut();
+        if current.is_none() {
+            let thread = JavaThread::new("main".to_string());
+            thread.set_state(ThreadState::Runnable);
+            let object: Arc<Mutex<dyn JavaInstance>> = Arc::new(Mutex::new(NativeThreadInstance::new(thread.clone())));
+            *current = Some(CurrentThread { object, thread });
+        }
+        let current = current.as_ref().unwrap();
+        (current.object.clone(), current.thread.clone())
+    })
+}
+
+// The threads of a VM started and not terminated yet. The VM exits once all the non-daemon ones are done
+pub struct ThreadRegistry {
+    threads: Mutex<HashMap<u64, Arc<JavaThread>>>,
+    terminated: Condvar,
+    default_uncaught_exception_handler: Mutex<Option<Arc<Mutex<dyn JavaInstance>>>>,
+    next_number: AtomicU64
+}
+
+impl ThreadRegistry {
+    pub fn new() -> ThreadRegistry {
+        ThreadRegistry {
+            threads: Mutex::new(HashMap::new()),
+            terminated: Condvar::new(),
+            default_uncaught_exception_handler: Mutex::new(None),
+            next_number: AtomicU64::new(0)
+        }
+    }
+
+    // Name of the threads created without one: Thread-0, Thread-1...
+    pub fn next_thread_name(&self) -> String {
+        format!("Thread-{}", self.next_number.fetch_add(1, Ordering::SeqCst))
+    }
+
+    // Returns false if the thread was already started (IllegalThreadStateException)
+    pub fn thread_started(&self, thread: &Arc<JavaThread>) -> bool {
+        let mut threads = self.threads.lock().unwrap();
+        if thread.get_state() != ThreadState::New {
+            return false;
+        }
+        thread.set_state(ThreadState::Runnable);
+        threads.insert(thread.get_id(), thread.clone());
+        true
+    }
+
+    pub fn thread_terminated(&self, thread: &Arc<JavaThread>) {
+        let mut threads = self.threads.lock().unwrap();
+        thread.set_state(ThreadState::Terminated);
+        threads.remove(&thread.get_id());
+        self.terminated.notify_all();
+    }
+
+    pub fn wait_for_threads(&self) {
+        let mut threads = self.threads.lock().unwrap();
+        while threads.values().any(|thread| !thread.is_daemon()) {
+            threads = self.terminated.wait(threads).unwrap();
+        }
+    }
+
+    pub fn get_default_uncaught_exception_handler(&self) -> Option<Arc<Mutex<dyn JavaInstance>>> {
+        self.default_uncaught_exception_handler.lock().unwrap().clone()
+    }
+
+    pub fn set_default_uncaught_exception_handler(&self, handler: Option<Arc<Mutex<dyn JavaInstance>>>) {
+        *self.default_uncaught_exception_handler.lock().unwrap() = handler;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::threads::{JavaThread, ThreadRegistry, ThreadState};
+
+    #[test]
+    fn test_thread_lifecycle() {
+        let registry = ThreadRegistry::new();
+        let thread = JavaThread::new("worker".to_string());
+        assert!(thread.get_state() == ThreadState::New);
+        assert!(registry.thread_started(&thread));
+        assert!(!registry.thread_started(&thread));
+        assert!(thread.is_alive());
+        thread.join(Some(Duration::from_millis(10)));
+
+        // Daemon threads do not keep the VM running
+        let daemon = JavaThread::new("daemon".to_string());
+        daemon.set_daemon(true);
+        assert!(registry.thread_started(&daemon));
+        registry.thread_terminated(&thread);
+        registry.wait_for_threads();
+        assert!(!thread.is_alive());
+        assert!(daemon.is_alive());
+        registry.thread_terminated(&daemon);
+        assert_eq!(registry.next_thread_name(), "Thread-0");
+    }
+}
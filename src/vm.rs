@@ -0,0 +1,322 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+use crate::api::{Class, JavaException, JavaValue, NativeClass, NativeFunction, Object};
+use crate::bytecode::is_instance_of;
+use crate::bytecode_class::{BytecodeClass, get_method_key};
+use crate::classpath::{ClassPath, to_internal_name};
+use crate::java_class::{JavaClass, MethodCallResult};
+use crate::jvm::{JavaInstance, StackFrame};
+use crate::native_java_classes::{NativeStringInstance, new_java_exception, new_java_exception_with_cause, register_native_classes};
+use crate::threads::{self, CurrentThread, ThreadRegistry};
+
+// A class is loaded the first time it is referenced, and initialized on its first active use (JVMS §5.5)
+struct LoadedClass {
+    class: Arc<dyn JavaClass>,
+    initialization: Arc<Initialization>
+}
+
+enum InitializationState {
+    NotInitialized,
+    BeingInitialized(ThreadId),
+    Initialized,
+    // With the error thrown by the initialization
+    Erroneous(Arc<Mutex<dyn JavaInstance>>)
+}
+
+// Each class has its own initialization lock, and the threads waiting for another one to initialize the class wait
+// on its condition
+struct Initialization {
+    state: Mutex<InitializationState>,
+    changed: Condvar
+}
+
+// A virtual machine: its settings, the classes it loaded, its string pool and its threads. Several VMs can run in
+// the same process, and the code running in a thread uses the current VM of the thread (see enter())
+pub struct Vm {
+    debug: u8,
+    classpath: Mutex<ClassPath>,
+    classes: Mutex<HashMap<String, LoadedClass>>,
+    // String literals and interned strings, which are the same object for the same value
+    strings: Mutex<HashMap<String, Arc<Mutex<dyn JavaInstance>>>>,
+    // Rust functions implementing the native methods of bytecode classes, by class name and method key
+    native_methods: Mutex<HashMap<(String, String), Arc<NativeFunction>>>,
+    pub(crate) threads: ThreadRegistry
+}
+
+thread_local! {
+    static CURRENT_VM: RefCell<Option<Arc<Vm>>> = const { RefCell::new(None) };
+}
+
+// The VM of the code running in the thread
+pub(crate) fn current() -> Arc<Vm> {
+    CURRENT_VM.with(|current| match current.borrow().as_ref() {
+        Some(vm) => vm.clone(),
+        None => panic!("No VM running in thread {:?}", thread::current().id())
+    })
+}
+
+// The VM stays the current one of the thread until the guard is dropped, and then the previous one is restored
+pub(crate) struct EnteredVm {
+    previous: Option<Arc<Vm>>,
+    // None when the VM was already the current one (e.g. Rust code called back by Java code)
+    previous_thread: Option<Option<CurrentThread>>
+}
+
+impl Drop for EnteredVm {
+    fn drop(&mut self) {
+        if let Some(thread) = self.previous_thread.take() {
+            threads::replace_current_thread(thread);
+        }
+        CURRENT_VM.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+impl Vm {
+    pub fn new(classpath: ClassPath, debug: u8) -> Arc<Vm> {
+        let vm = Arc::new(Vm {
+            debug,
+            classpath: Mutex::new(classpath),
+            classes: Mutex::new(HashMap::new()),
+            strings: Mutex::new(HashMap::new()),
+            native_methods: Mutex::new(HashMap::new()),
+            threads: ThreadRegistry::new()
+        });
+        register_native_classes(&vm);
+        vm
+    }
+
+    // Makes the VM the current one of the thread, which then has its own Thread object in the VM
+    pub(crate) fn enter(self: &Arc<Vm>) -> EnteredVm {
+        let previous = CURRENT_VM.with(|current| current.replace(Some(self.clone())));
+        let reentered = previous.as_ref().is_some_and(|vm| Arc::ptr_eq(vm, self));
+        let previous_thread = if reentered { None } else { Some(threads::replace_current_thread(None)) };
+        EnteredVm { previous, previous_thread }
+    }
+
+    pub(crate) fn get_debug(&self) -> u8 { self.debug }
+
+    // Adds directories and jar files to the end of the classpath, separated by ':' like with -cp
+    pub fn add_classpath(&self, paths: &str) {
+        let mut classpath = self.classpath.lock().unwrap();
+        for path in std::env::split_paths(paths) {
+            classpath.add_path(&path);
+        }
+    }
+
+    pub fn print_classpath(&self) {
+        self.classpath.lock().unwrap().print();
+    }
+
+    // Loads a class, given with dots (com.acme.Main) or slashes (com/acme/Main). It gets initialized on its first
+    // active use, e.g. when one of its static methods is called
+    pub fn load_class(self: &Arc<Vm>, class_name: &str) -> Result<Class, JavaException> {
+        let _entered = self.enter();
+        match self.load(&to_internal_name(class_name)) {
+            Ok(class) => Ok(Class::new(self, class)),
+            Err(e) => Err(JavaException::new(self, e))
+        }
+    }
+
+    // Adds a class implemented in Rust, which must be registered before Java code first uses the class
+    pub fn register_class(self: &Arc<Vm>, class: NativeClass) {
+        let class_name = class.get_name();
+        let mut classes = self.classes.lock().unwrap();
+        if classes.contains_key(&class_name) { panic!("Class {} is already loaded", class_name); }
+        classes.insert(class_name, LoadedClass {
+            class: Arc::new(class),
+            initialization: Arc::new(Initialization { state: Mutex::new(InitializationState::NotInitialized), changed: Condvar::new() })
+        });
+    }
+
+    // Implements a native method of a bytecode class (declared with the native keyword) with a Rust function, which
+    // gets this for instance methods, and the arguments converted according to the descriptor
+    pub fn register_native_method<F>(&self, class_name: &str, method_name: &str, type_desc: &str, function: F)
+            where F: Fn(&Arc<Vm>, Option<&Object>, &[JavaValue]) -> Result<JavaValue, JavaException> + Send + Sync + 'static {
+        let key = (to_internal_name(class_name), get_method_key(&method_name.to_string(), &type_desc.to_string()));
+        self.native_methods.lock().unwrap().insert(key, Arc::new(function));
+    }
+
+    pub(crate) fn get_native_method(&self, class_name: &str, method_name: &str, type_desc: &str) -> Option<Arc<NativeFunction>> {
+        self.native_methods.lock().unwrap().get(&(class_name.to_string(), get_method_key(&method_name.to_string(), &type_desc.to_string()))).cloned()
+    }
+
+    // Creates an exception for Rust code to throw, e.g. from a native method. Like with the exceptions thrown by the
+    // VM, the missing exception classes of java packages get created. Returns the error if the exception could not be
+    // created, e.g. when its class is not found
+    pub fn new_exception(self: &Arc<Vm>, class_name: &str, message: &str) -> JavaException {
+        let _entered = self.enter();
+        let class_name = to_internal_name(class_name);
+        if class_name.starts_with("java/") && !class_exists(&class_name) {
+            return JavaException::new(self, new_java_exception(&class_name, &message.to_string()));
+        }
+        let result = self.load_class(&class_name).and_then(|class| class.new_instance("(Ljava/lang/String;)V", &[message.into()]));
+        match result {
+            Ok(exception) => JavaException::from(exception),
+            Err(e) => e
+        }
+    }
+
+    // Waits for the threads started by Java code to finish
+    pub fn wait_for_threads(&self) {
+        self.threads.wait_for_threads();
+    }
+
+    // Keeps the class already added by another thread, if any
+    pub(crate) fn add_class(&self, class: Arc<dyn JavaClass>) -> Arc<dyn JavaClass> {
+        let mut classes = self.classes.lock().unwrap();
+        let loaded = classes.entry(class.get_name()).or_insert_with(|| LoadedClass {
+            class,
+            initialization: Arc::new(Initialization { state: Mutex::new(InitializationState::NotInitialized), changed: Condvar::new() })
+        });
+        loaded.class.clone()
+    }
+
+    // Classes of java/lang are native, the other ones are read from the classpath, and then linked by loading their
+    // superclass and interfaces (JVMS §5.3, §5.4). Arrays use the methods of java/util/Arrays
+    pub(crate) fn load(&self, class_name: &str) -> Result<Arc<dyn JavaClass>, Arc<Mutex<dyn JavaInstance>>> {
+        let arrays_name = "java/util/Arrays".to_string();
+        let class_name = if class_name.starts_with("[") { &arrays_name } else { class_name };
+        if let Some(loaded) = self.classes.lock().unwrap().get(class_name) {
+            return Ok(loaded.class.clone());
+        }
+        if class_name.starts_with("java/lang") { return Err(new_class_not_found_error(class_name)); }
+
+        let data = match self.classpath.lock().unwrap().read_class(class_name) {
+            Some(data) => data,
+            None => return Err(new_class_not_found_error(class_name))
+        };
+        if self.debug >= 1 { println!("Load class {}", class_name); }
+        let class: Arc<dyn JavaClass> = Arc::new(BytecodeClass::parse(data));
+        // Only part of java/lang is native, so the missing interfaces only fail once they get used
+        let parent = class.get_parent();
+        if !parent.is_empty() { self.load(&parent)?; }
+        for interface in class.get_interfaces().iter() {
+            let _ = self.load(interface);
+        }
+
+        Ok(self.add_class(class))
+    }
+
+    // Initializes the class on its first active use: new, getstatic, putstatic, invokestatic, the initialization of a
+    // subclass, or being the main class. Returns whether the class is initialized, as it is still being initialized
+    // when the request comes from its own static initializer
+    pub(crate) fn initialize_class(&self, class: &Arc<dyn JavaClass>) -> Result<bool, Arc<Mutex<dyn JavaInstance>>> {
+        let class_name = class.get_name();
+        let initialization = match self.classes.lock().unwrap().get(&class_name) {
+            Some(loaded) => loaded.initialization.clone(),
+            _ => panic!("Class {} not loaded", class_name)
+        };
+        let current = thread::current().id();
+
+        {
+            let mut state = initialization.state.lock().unwrap();
+            loop {
+                match *state {
+                    InitializationState::BeingInitialized(thread) if thread != current => {
+                        state = initialization.changed.wait(state).unwrap();
+                    },
+                    InitializationState::BeingInitialized(_) => return Ok(false),
+                    InitializationState::Initialized => return Ok(true),
+                    InitializationState::Erroneous(ref error) => {
+                        let message = format!("Could not initialize class {}", class_name.replace("/", "."));
+                        return Err(new_java_exception_with_cause(&"java/lang/NoClassDefFoundError".to_string(), &message, error.clone()));
+                    },
+                    InitializationState::NotInitialized => {
+                        *state = InitializationState::BeingInitialized(current);
+                        break;
+                    }
+                }
+            }
+        }
+
+        // The superclass gets initialized first (superinterfaces are not)
+        if self.debug >= 1 { println!("Initialize class {}", class_name); }
+        let parent = class.get_parent();
+        let mut result = if parent.is_empty() { Ok(true) } else { self.initialize_class(&get_class(&parent)) };
+        if result.is_ok() && class.has_static_init() {
+            let mut sf = StackFrame::new(0, 0);
+            if let MethodCallResult::EXCEPTION(e) = class.execute_static_method(&mut sf, &"<clinit>".to_string(), &"()V".to_string(), 0) {
+                // Errors are thrown as they are, the other exceptions get wrapped
+                let is_error = is_instance_of(&*e.lock().unwrap(), &"java/lang/Error".to_string());
+                result = Err(if is_error { e } else { new_java_exception_with_cause(&"java/lang/ExceptionInInitializerError".to_string(), &"".to_string(), e) });
+            }
+        }
+
+        let mut state = initialization.state.lock().unwrap();
+        *state = match &result {
+            Ok(_) => InitializationState::Initialized,
+            Err(error) => InitializationState::Erroneous(error.clone())
+        };
+        initialization.changed.notify_all();
+        result.map(|_| true)
+    }
+
+    pub(crate) fn intern(&self, value: &str) -> Arc<Mutex<dyn JavaInstance>> {
+        self.intern_string(value, Arc::new(Mutex::new(NativeStringInstance::new(value.to_string()))))
+    }
+
+    // The string of the pool equal to the given one, which gets added to the pool if there is none (String.intern())
+    pub(crate) fn intern_string(&self, value: &str, string: Arc<Mutex<dyn JavaInstance>>) -> Arc<Mutex<dyn JavaInstance>> {
+        self.strings.lock().unwrap().entry(value.to_string()).or_insert(string).clone()
+    }
+}
+
+fn new_class_not_found_error(class_name: &str) -> Arc<Mutex<dyn JavaInstance>> {
+    let cause = new_java_exception(&"java/lang/ClassNotFoundException".to_string(), &class_name.replace("/", "."));
+    new_java_exception_with_cause(&"java/lang/NoClassDefFoundError".to_string(), &class_name.to_string(), cause)
+}
+
+// Shortcuts to the current VM
+
+pub fn get_debug() -> u8 { current().get_debug() }
+
+pub fn load_class(class_name: &str) -> Result<Arc<dyn JavaClass>, Arc<Mutex<dyn JavaInstance>>> {
+    current().load(class_name)
+}
+
+// For the classes which are known to be loaded, e.g. the class of an object
+pub fn get_class(class_name: &str) -> Arc<dyn JavaClass> {
+    match load_class(class_name) {
+        Ok(class) => class,
+        _ => panic!("Class {} not found", class_name)
+    }
+}
+
+pub fn class_exists(class_name: &str) -> bool {
+    load_class(class_name).is_ok()
+}
+
+pub fn initialize_class(class: &Arc<dyn JavaClass>) -> Result<bool, Arc<Mutex<dyn JavaInstance>>> {
+    current().initialize_class(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::classpath::ClassPath;
+    use crate::native_java_classes::NativeGenericExceptionClass;
+    use crate::vm::{Vm, class_exists};
+
+    #[test]
+    fn test_vms_are_separate() {
+        let vm1 = Vm::new(ClassPath::default(), 0);
+        let vm2 = Vm::new(ClassPath::default(), 0);
+
+        {
+            let _entered = vm1.enter();
+            NativeGenericExceptionClass::new(&"my/Error".to_string(), &"message".to_string());
+            assert!(class_exists("my/Error"));
+        }
+        let _entered = vm2.enter();
+        assert!(!class_exists("my/Error"));
+
+        let value = "abc".to_string();
+        assert!(Arc::ptr_eq(&vm1.intern(&value), &vm1.intern(&value)));
+        assert!(!Arc::ptr_eq(&vm1.intern(&value), &vm2.intern(&value)));
+        assert_eq!(vm2.load_class("java.lang.String").ok().map(|c| c.get_name()), Some("java/lang/String".to_string()));
+    }
+}
@@ -0,0 +1,453 @@
+// Encoder of x86-64 machine code, for the subset of instructions the compilers need
+#![allow(dead_code)]
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Reg { RAX = 0, RCX, RDX, RBX, RSP, RBP, RSI, RDI, R8, R9, R10, R11, R12, R13, R14, R15 }
+
+impl Reg {
+    fn low(self) -> u8 { self as u8 & 7 }
+    fn high(self) -> u8 { (self as u8 >> 3) & 1 }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Xmm(pub u8);
+
+// [base + index * scale + disp]
+#[derive(Clone, Copy)]
+pub struct Mem { base: Reg, index: Option<(Reg, u8)>, disp: i32 }
+
+impl Mem {
+    pub fn new(base: Reg, disp: i32) -> Mem { Mem { base, index: None, disp } }
+    pub fn indexed(base: Reg, index: Reg, scale: u8, disp: i32) -> Mem { Mem { base, index: Some((index, scale)), disp } }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond { O = 0, NO, B, AE, E, NE, BE, A, S, NS, P, NP, L, GE, LE, G }
+
+impl Cond {
+    pub fn negate(self) -> Cond {
+        let conds = [Cond::O, Cond::NO, Cond::B, Cond::AE, Cond::E, Cond::NE, Cond::BE, Cond::A,
+            Cond::S, Cond::NS, Cond::P, Cond::NP, Cond::L, Cond::GE, Cond::LE, Cond::G];
+        conds[self as usize ^ 1]
+    }
+}
+
+// Arithmetic instructions sharing the same encoding, by their /digit in the opcode table
+#[derive(Clone, Copy)]
+pub enum Alu { Add = 0, Or = 1, Adc = 2, And = 4, Sub = 5, Xor = 6, Cmp = 7 }
+
+#[derive(Clone, Copy)]
+pub enum Shift { Shl = 4, Shr = 5, Sar = 7 }
+
+// Scalar SSE operations, by their second opcode byte
+#[derive(Clone, Copy)]
+pub enum Sse { Sqrt = 0x51, Add = 0x58, Mul = 0x59, Sub = 0x5c, Div = 0x5e }
+
+// x87 instructions without operand, by their second opcode byte after 0xd9
+#[derive(Clone, Copy)]
+pub enum X87 { Fchs = 0xe0, Fld1 = 0xe8, Fldl2e = 0xea, Fldpi = 0xeb, Fldlg2 = 0xec, Fldln2 = 0xed, Fldz = 0xee, F2xm1 = 0xf0,
+    Fyl2x = 0xf1, Fprem = 0xf8, Frndint = 0xfc, Fscale = 0xfd, Fsin = 0xfe, Fcos = 0xff }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Label(usize);
+
+pub struct Encoder {
+    pub code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    // Positions of rel32 displacements to patch once the labels are bound
+    fixups: Vec<(usize, Label)>
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder { code: Vec::new(), labels: Vec::new(), fixups: Vec::new() }
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    pub fn label_offset(&self, label: Label) -> usize {
+        match self.labels[label.0] {
+            Some(offset) => offset,
+            None => panic!("Label {} is not bound", label.0)
+        }
+    }
+
+    // Resolves the jumps to labels, and returns the machine code
+    pub fn finish(mut self) -> Vec<u8> {
+        for (pos, label) in self.fixups.iter() {
+            let target = self.label_offset(*label) as i64;
+            let rel = (target - (*pos as i64 + 4)) as i32;
+            self.code[*pos..*pos + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+        self.code
+    }
+
+    fn emit(&mut self, bytes: &[u8]) { self.code.extend_from_slice(bytes); }
+    fn emit_i32(&mut self, value: i32) { self.code.extend_from_slice(&value.to_le_bytes()); }
+    fn emit_rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.emit_i32(0);
+    }
+
+    fn rex(&mut self, wide: bool, reg: u8, index: u8, base: u8, force: bool) {
+        let rex = 0x40 | ((wide as u8) << 3) | (reg << 2) | (index << 1) | base;
+        if rex != 0x40 || force {
+            self.code.push(rex);
+        }
+    }
+
+    fn rex_mem(&mut self, wide: bool, reg: u8, mem: &Mem) {
+        let index = match mem.index { Some((index, _)) => index.high(), None => 0 };
+        self.rex(wide, (reg >> 3) & 1, index, mem.base.high(), false);
+    }
+
+    // ModRM (and SIB) bytes for a memory operand
+    fn modrm_mem(&mut self, reg: u8, mem: &Mem) {
+        let reg = (reg & 7) << 3;
+        let (mode, disp8) = if mem.disp == 0 && mem.base.low() != 5 {
+            (0x00, false)
+        } else if mem.disp >= -128 && mem.disp <= 127 {
+            (0x40, true)
+        } else {
+            (0x80, false)
+        };
+        match mem.index {
+            Some((index, scale)) => {
+                let scale_bits = match scale { 1 => 0, 2 => 1, 4 => 2, 8 => 3, _ => panic!("Invalid scale {}", scale) };
+                self.code.push(mode | reg | 4);
+                self.code.push((scale_bits << 6) | (index.low() << 3) | mem.base.low());
+            },
+            None if mem.base.low() == 4 => {
+                self.code.push(mode | reg | 4);
+                self.code.push(0x24);
+            },
+            None => self.code.push(mode | reg | mem.base.low())
+        }
+        if mode == 0x40 && disp8 {
+            self.code.push(mem.disp as i8 as u8);
+        } else if mode == 0x80 {
+            self.emit_i32(mem.disp);
+        }
+    }
+
+    fn op_rr(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: u8) {
+        self.rex(wide, (reg >> 3) & 1, 0, (rm >> 3) & 1, false);
+        self.emit(opcode);
+        self.code.push(0xc0 | ((reg & 7) << 3) | (rm & 7));
+    }
+
+    fn op_rm(&mut self, wide: bool, opcode: &[u8], reg: u8, mem: &Mem) {
+        self.rex_mem(wide, reg, mem);
+        self.emit(opcode);
+        self.modrm_mem(reg, mem);
+    }
+
+    // SSE instructions have their prefix before the REX byte
+    fn sse_rm(&mut self, prefix: u8, wide: bool, opcode: u8, reg: u8, mem: &Mem) {
+        self.code.push(prefix);
+        self.op_rm(wide, &[0x0f, opcode], reg, mem);
+    }
+
+    fn sse_rr(&mut self, prefix: u8, opcode: u8, reg: u8, rm: u8) {
+        self.code.push(prefix);
+        self.op_rr(false, &[0x0f, opcode], reg, rm);
+    }
+
+    ////////// Integer instructions
+
+    pub fn mov_rr(&mut self, dst: Reg, src: Reg) { self.op_rr(true, &[0x89], src as u8, dst as u8); }
+    pub fn mov_rm(&mut self, dst: Reg, mem: Mem) { self.op_rm(true, &[0x8b], dst as u8, &mem); }
+    pub fn mov_mr(&mut self, mem: Mem, src: Reg) { self.op_rm(true, &[0x89], src as u8, &mem); }
+    // Stores the low byte of a register
+    pub fn mov8_mr(&mut self, mem: Mem, src: Reg) {
+        // Without a REX prefix, registers 4 to 7 are ah, ch, dh and bh
+        let index = match mem.index { Some((index, _)) => index.high(), None => 0 };
+        self.rex(false, src.high(), index, mem.base.high(), src as u8 >= 4);
+        self.code.push(0x88);
+        self.modrm_mem(src as u8, &mem);
+    }
+    pub fn mov32_rm(&mut self, dst: Reg, mem: Mem) { self.op_rm(false, &[0x8b], dst as u8, &mem); }
+    pub fn mov32_mr(&mut self, mem: Mem, src: Reg) { self.op_rm(false, &[0x89], src as u8, &mem); }
+    pub fn movsxd_rm(&mut self, dst: Reg, mem: Mem) { self.op_rm(true, &[0x63], dst as u8, &mem); }
+    pub fn movsxd_rr(&mut self, dst: Reg, src: Reg) { self.op_rr(true, &[0x63], dst as u8, src as u8); }
+    pub fn lea(&mut self, dst: Reg, mem: Mem) { self.op_rm(true, &[0x8d], dst as u8, &mem); }
+
+    pub fn mov_ri(&mut self, dst: Reg, imm: i64) {
+        if imm >= i32::MIN as i64 && imm <= i32::MAX as i64 {
+            // Sign-extended 32-bit immediate
+            self.rex(true, 0, 0, dst.high(), false);
+            self.code.push(0xc7);
+            self.code.push(0xc0 | dst.low());
+            self.emit_i32(imm as i32);
+        } else {
+            self.rex(true, 0, 0, dst.high(), false);
+            self.code.push(0xb8 + dst.low());
+            self.emit(&imm.to_le_bytes());
+        }
+    }
+
+    // Always with a 64-bit immediate, which is the last 8 bytes of the instruction (e.g. an address patched later)
+    pub fn mov_ri64(&mut self, dst: Reg, imm: i64) {
+        self.rex(true, 0, 0, dst.high(), false);
+        self.code.push(0xb8 + dst.low());
+        self.emit(&imm.to_le_bytes());
+    }
+
+    // Stores a sign-extended 32-bit immediate into a 64-bit memory location
+    pub fn mov_mi(&mut self, mem: Mem, imm: i32) {
+        self.op_rm(true, &[0xc7], 0, &mem);
+        self.emit_i32(imm);
+    }
+
+    pub fn alu_rr(&mut self, op: Alu, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[((op as u8) << 3) | 1], src as u8, dst as u8);
+    }
+
+    pub fn alu_rm(&mut self, op: Alu, wide: bool, dst: Reg, mem: Mem) {
+        self.op_rm(wide, &[((op as u8) << 3) | 3], dst as u8, &mem);
+    }
+
+    pub fn alu_mr(&mut self, op: Alu, wide: bool, mem: Mem, src: Reg) {
+        self.op_rm(wide, &[((op as u8) << 3) | 1], src as u8, &mem);
+    }
+
+    pub fn alu_ri(&mut self, op: Alu, wide: bool, dst: Reg, imm: i32) {
+        if imm >= -128 && imm <= 127 {
+            self.op_rr(wide, &[0x83], op as u8, dst as u8);
+            self.code.push(imm as i8 as u8);
+        } else {
+            self.op_rr(wide, &[0x81], op as u8, dst as u8);
+            self.emit_i32(imm);
+        }
+    }
+
+    pub fn alu_mi(&mut self, op: Alu, wide: bool, mem: Mem, imm: i32) {
+        if imm >= -128 && imm <= 127 {
+            self.op_rm(wide, &[0x83], op as u8, &mem);
+            self.code.push(imm as i8 as u8);
+        } else {
+            self.op_rm(wide, &[0x81], op as u8, &mem);
+            self.emit_i32(imm);
+        }
+    }
+
+    pub fn inc_r(&mut self, wide: bool, reg: Reg) { self.op_rr(wide, &[0xff], 0, reg as u8); }
+    pub fn imul_rr(&mut self, wide: bool, dst: Reg, src: Reg) { self.op_rr(wide, &[0x0f, 0xaf], dst as u8, src as u8); }
+    pub fn imul_rm(&mut self, wide: bool, dst: Reg, mem: Mem) { self.op_rm(wide, &[0x0f, 0xaf], dst as u8, &mem); }
+    pub fn neg_r(&mut self, wide: bool, reg: Reg) { self.op_rr(wide, &[0xf7], 3, reg as u8); }
+    pub fn neg_m(&mut self, wide: bool, mem: Mem) { self.op_rm(wide, &[0xf7], 3, &mem); }
+
+    // Shifts by cl, or by an immediate
+    pub fn shift_cl(&mut self, op: Shift, wide: bool, reg: Reg) { self.op_rr(wide, &[0xd3], op as u8, reg as u8); }
+    pub fn shift_ri(&mut self, op: Shift, wide: bool, reg: Reg, count: u8) {
+        self.op_rr(wide, &[0xc1], op as u8, reg as u8);
+        self.code.push(count);
+    }
+
+    // Complements a bit of a 64-bit memory location (e.g. the sign of a double)
+    pub fn btc_mi(&mut self, mem: Mem, bit: u8) {
+        self.op_rm(true, &[0x0f, 0xba], 7, &mem);
+        self.code.push(bit);
+    }
+
+    // Sign extends eax into edx (cdq), or rax into rdx (cqo), before a division
+    pub fn sign_extend_rax(&mut self, wide: bool) {
+        if wide { self.code.push(0x48); }
+        self.code.push(0x99);
+    }
+
+    pub fn idiv_r(&mut self, wide: bool, reg: Reg) { self.op_rr(wide, &[0xf7], 7, reg as u8); }
+    pub fn div_r(&mut self, wide: bool, reg: Reg) { self.op_rr(wide, &[0xf7], 6, reg as u8); }
+    pub fn mul_r(&mut self, wide: bool, reg: Reg) { self.op_rr(wide, &[0xf7], 4, reg as u8); }
+
+    pub fn setcc(&mut self, cond: Cond, reg: Reg) {
+        // Without a REX prefix, registers 4 to 7 are ah, ch, dh and bh
+        self.rex(false, 0, 0, reg.high(), reg as u8 >= 4);
+        self.emit(&[0x0f, 0x90 + cond as u8]);
+        self.code.push(0xc0 | reg.low());
+    }
+
+    pub fn movzx8_rr(&mut self, dst: Reg, src: Reg) {
+        self.rex(false, dst.high(), 0, src.high(), src as u8 >= 4);
+        self.emit(&[0x0f, 0xb6]);
+        self.code.push(0xc0 | (dst.low() << 3) | src.low());
+    }
+
+    pub fn movzx8_rm(&mut self, dst: Reg, mem: Mem) { self.op_rm(true, &[0x0f, 0xb6], dst as u8, &mem); }
+
+    pub fn push(&mut self, reg: Reg) {
+        self.rex(false, 0, 0, reg.high(), false);
+        self.code.push(0x50 + reg.low());
+    }
+
+    pub fn pop(&mut self, reg: Reg) {
+        self.rex(false, 0, 0, reg.high(), false);
+        self.code.push(0x58 + reg.low());
+    }
+
+    // Pushes a sign-extended immediate
+    pub fn push_i(&mut self, imm: i32) {
+        if imm >= -128 && imm <= 127 {
+            self.code.push(0x6a);
+            self.code.push(imm as i8 as u8);
+        } else {
+            self.code.push(0x68);
+            self.emit_i32(imm);
+        }
+    }
+
+    pub fn push_m(&mut self, mem: Mem) { self.op_rm(false, &[0xff], 6, &mem); }
+    pub fn pop_m(&mut self, mem: Mem) { self.op_rm(false, &[0x8f], 0, &mem); }
+
+    ////////// Control flow
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push(0xe9);
+        self.emit_rel32(label);
+    }
+
+    pub fn jcc(&mut self, cond: Cond, label: Label) {
+        self.emit(&[0x0f, 0x80 + cond as u8]);
+        self.emit_rel32(label);
+    }
+
+    pub fn jmp_m(&mut self, mem: Mem) { self.op_rm(false, &[0xff], 4, &mem); }
+    pub fn call_r(&mut self, reg: Reg) { self.op_rr(false, &[0xff], 2, reg as u8); }
+
+    pub fn call(&mut self, label: Label) {
+        self.code.push(0xe8);
+        self.emit_rel32(label);
+    }
+
+    // Address of a label, relative to the instruction pointer
+    pub fn lea_label(&mut self, dst: Reg, label: Label) {
+        self.rex(true, dst.high(), 0, 0, false);
+        self.code.push(0x8d);
+        self.code.push((dst.low() << 3) | 5);
+        self.emit_rel32(label);
+    }
+
+    pub fn ret(&mut self) { self.code.push(0xc3); }
+    pub fn leave(&mut self) { self.code.push(0xc9); }
+    pub fn syscall(&mut self) { self.emit(&[0x0f, 0x05]); }
+    pub fn nop(&mut self) { self.code.push(0x90); }
+    pub fn int3(&mut self) { self.code.push(0xcc); }
+    pub fn ud2(&mut self) { self.emit(&[0x0f, 0x0b]); }
+
+    ////////// SSE instructions, on floats (double = false) or doubles
+
+    fn sse_prefix(double: bool) -> u8 { if double { 0xf2 } else { 0xf3 } }
+
+    pub fn movs_rm(&mut self, double: bool, dst: Xmm, mem: Mem) { self.sse_rm(Encoder::sse_prefix(double), false, 0x10, dst.0, &mem); }
+    pub fn movs_mr(&mut self, double: bool, mem: Mem, src: Xmm) { self.sse_rm(Encoder::sse_prefix(double), false, 0x11, src.0, &mem); }
+    pub fn movs_rr(&mut self, double: bool, dst: Xmm, src: Xmm) { self.sse_rr(Encoder::sse_prefix(double), 0x10, dst.0, src.0); }
+    pub fn sse_op_rr(&mut self, op: Sse, double: bool, dst: Xmm, src: Xmm) { self.sse_rr(Encoder::sse_prefix(double), op as u8, dst.0, src.0); }
+    pub fn sse_op_rm(&mut self, op: Sse, double: bool, dst: Xmm, mem: Mem) { self.sse_rm(Encoder::sse_prefix(double), false, op as u8, dst.0, &mem); }
+
+    // Unordered comparison, which sets ZF, PF and CF like an unsigned comparison (all of them for NaN)
+    pub fn ucomis_rr(&mut self, double: bool, a: Xmm, b: Xmm) {
+        if double { self.code.push(0x66); }
+        self.op_rr(false, &[0x0f, 0x2e], a.0, b.0);
+    }
+
+    // Integer (wide_int for a long) to float or double
+    pub fn cvtsi2s_rm(&mut self, double: bool, wide_int: bool, dst: Xmm, mem: Mem) { self.sse_rm(Encoder::sse_prefix(double), wide_int, 0x2a, dst.0, &mem); }
+    pub fn cvtsi2s_rr(&mut self, double: bool, wide_int: bool, dst: Xmm, src: Reg) {
+        self.code.push(Encoder::sse_prefix(double));
+        self.op_rr(wide_int, &[0x0f, 0x2a], dst.0, src as u8);
+    }
+
+    // Float to integer, truncated (0x80000000 or 0x8000000000000000 if it does not fit)
+    pub fn cvtts2si_rr(&mut self, double: bool, wide_int: bool, dst: Reg, src: Xmm) {
+        self.code.push(Encoder::sse_prefix(double));
+        self.op_rr(wide_int, &[0x0f, 0x2c], dst as u8, src.0);
+    }
+
+    // Float to double (double = false), or double to float
+    pub fn cvts2s_rm(&mut self, double: bool, dst: Xmm, mem: Mem) { self.sse_rm(Encoder::sse_prefix(double), false, 0x5a, dst.0, &mem); }
+    pub fn cvts2s_rr(&mut self, double: bool, dst: Xmm, src: Xmm) { self.sse_rr(Encoder::sse_prefix(double), 0x5a, dst.0, src.0); }
+
+    // Moves between general purpose and xmm registers (movq, or movd if not wide)
+    pub fn movq_xr(&mut self, wide: bool, dst: Xmm, src: Reg) {
+        self.code.push(0x66);
+        self.op_rr(wide, &[0x0f, 0x6e], dst.0, src as u8);
+    }
+    pub fn movq_rx(&mut self, wide: bool, dst: Reg, src: Xmm) {
+        self.code.push(0x66);
+        self.op_rr(wide, &[0x0f, 0x7e], src.0, dst as u8);
+    }
+
+    ////////// x87 instructions, on the register stack st0-st7 and doubles in memory
+
+    pub fn fld_m(&mut self, mem: Mem) { self.op_rm(false, &[0xdd], 0, &mem); }
+    pub fn fstp_m(&mut self, mem: Mem) { self.op_rm(false, &[0xdd], 3, &mem); }
+    // Floats in memory
+    pub fn fld32_m(&mut self, mem: Mem) { self.op_rm(false, &[0xd9], 0, &mem); }
+    pub fn fstp32_m(&mut self, mem: Mem) { self.op_rm(false, &[0xd9], 3, &mem); }
+    // Loads a 64-bit integer, and stores st0 as a 64-bit integer, rounded, and pops it
+    pub fn fild_m(&mut self, mem: Mem) { self.op_rm(false, &[0xdf], 5, &mem); }
+    pub fn fistp_m(&mut self, mem: Mem) { self.op_rm(false, &[0xdf], 7, &mem); }
+    pub fn fld_st(&mut self, idx: u8) { self.emit(&[0xd9, 0xc0 + idx]); }
+    pub fn fstp_st(&mut self, idx: u8) { self.emit(&[0xdd, 0xd8 + idx]); }
+    pub fn fxch(&mut self, idx: u8) { self.emit(&[0xd9, 0xc8 + idx]); }
+    // st0 = st0 * st(idx), st0 = st0 - st(idx) and st0 = st0 / st(idx)
+    pub fn fmul_st(&mut self, idx: u8) { self.emit(&[0xd8, 0xc8 + idx]); }
+    pub fn fsub_st(&mut self, idx: u8) { self.emit(&[0xd8, 0xe0 + idx]); }
+    pub fn fdiv_st(&mut self, idx: u8) { self.emit(&[0xd8, 0xf0 + idx]); }
+    // st(idx) = st(idx) + st0 and st(idx) = st(idx) * st0, then pops st0
+    pub fn faddp(&mut self, idx: u8) { self.emit(&[0xde, 0xc0 + idx]); }
+    pub fn fmulp(&mut self, idx: u8) { self.emit(&[0xde, 0xc8 + idx]); }
+    // Compares st0 with st(idx) into ZF, PF and CF like ucomisd, then pops st0
+    pub fn fucomip(&mut self, idx: u8) { self.emit(&[0xdf, 0xe8 + idx]); }
+    pub fn fnstsw_ax(&mut self) { self.emit(&[0xdf, 0xe0]); }
+    pub fn x87(&mut self, op: X87) { self.emit(&[0xd9, op as u8]); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        let mut enc = Encoder::new();
+        enc.mov_rm(Reg::RAX, Mem::new(Reg::R12, 16));
+        enc.mov_mr(Mem::new(Reg::R13, 0), Reg::RCX);
+        enc.alu_rr(Alu::Add, false, Reg::RAX, Reg::RCX);
+        enc.movs_rm(false, Xmm(1), Mem::new(Reg::R13, -16));
+        enc.jmp_m(Mem::indexed(Reg::RAX, Reg::RSI, 8, 0));
+        enc.fld_m(Mem::new(Reg::RSP, -8));
+        enc.faddp(2);
+        enc.mul_r(true, Reg::R9);
+        enc.alu_ri(Alu::Adc, true, Reg::RDX, 0);
+        enc.x87(X87::Fldpi);
+        assert_eq!(enc.finish(), vec![
+            0x49, 0x8b, 0x44, 0x24, 0x10,   // mov rax, [r12+16]
+            0x49, 0x89, 0x4d, 0x00,         // mov [r13], rcx
+            0x01, 0xc8,                     // add eax, ecx
+            0xf3, 0x41, 0x0f, 0x10, 0x4d, 0xf0, // movss xmm1, [r13-16]
+            0xff, 0x24, 0xf0,               // jmp [rax+rsi*8]
+            0xdd, 0x44, 0x24, 0xf8,         // fld qword [rsp-8]
+            0xde, 0xc2,                     // faddp st2, st0
+            0x49, 0xf7, 0xe1,               // mul r9
+            0x48, 0x83, 0xd2, 0x00,         // adc rdx, 0
+            0xd9, 0xeb                      // fldpi
+        ]);
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut enc = Encoder::new();
+        let label = enc.new_label();
+        enc.bind(label);
+        enc.jcc(Cond::L, label);
+        enc.jmp(label);
+        assert_eq!(enc.finish(), vec![0x0f, 0x8c, 0xfa, 0xff, 0xff, 0xff, 0xe9, 0xf5, 0xff, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,168 @@
+// Compiles the Java classes of tests/java and the example programs into Linux x64 executables, and checks
+// that they print the same output as the interpreter
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::{env, fs, path::PathBuf, process::{Command, Output}};
+
+fn get_classpath(dir: &str) -> String {
+    format!("{}/{}", env!("CARGO_MANIFEST_DIR"), dir)
+}
+
+fn run(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?} failed: {}", command, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Returns the output of the interpreter after checking that the executable prints the same
+fn interpret_and_compile(class_name: &str) -> String {
+    interpret_and_compile_from("tests/java", class_name)
+}
+
+// The example programs of the java directory
+fn interpret_and_compile_example(class_name: &str) -> String {
+    interpret_and_compile_from("java", class_name)
+}
+
+fn interpret_and_compile_from(dir: &str, class_name: &str) -> String {
+    let (interpreted, compiled) = get_outputs(dir, class_name);
+    assert!(interpreted.status.success(), "{} failed: {}", class_name, String::from_utf8_lossy(&interpreted.stderr));
+    assert!(compiled.status.success(), "compiled {} failed: {}", class_name, String::from_utf8_lossy(&compiled.stderr));
+    assert_eq!(compiled.stdout, interpreted.stdout, "compiled output of {} differs from the interpreter", class_name);
+    String::from_utf8(interpreted.stdout).unwrap()
+}
+
+// For programs ending with an uncaught exception, returns the output and the stack trace of the interpreter. The
+// executable has no stack frames to print, but must exit with the same status and print the same first line
+fn interpret_and_compile_uncaught(class_name: &str) -> (String, String) {
+    let (interpreted, compiled) = get_outputs("tests/java", class_name);
+    assert_eq!(interpreted.status.code(), Some(1));
+    assert_eq!(compiled.status.code(), Some(1));
+    assert_eq!(compiled.stdout, interpreted.stdout, "compiled output of {} differs from the interpreter", class_name);
+    let stack_trace = String::from_utf8(interpreted.stderr).unwrap();
+    assert_eq!(String::from_utf8(compiled.stderr).unwrap(), format!("{}\n", stack_trace.lines().next().unwrap()));
+    (String::from_utf8(interpreted.stdout).unwrap(), stack_trace)
+}
+
+// Runs the class in the interpreter, then compiled into an executable
+fn get_outputs(dir: &str, class_name: &str) -> (Output, Output) {
+    let classpath = get_classpath(dir);
+    let dir = env::temp_dir().join(format!("jvm-aot-{}-{}", class_name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let interpreted = Command::new(env!("CARGO_BIN_EXE_jvm")).args(["-cp", &classpath, class_name]).output().unwrap();
+    run(Command::new(env!("CARGO_BIN_EXE_jvm")).current_dir(&dir).args(["-cp", &classpath, "--asm", "linux", "--emit", "exe", class_name]));
+    let executable: PathBuf = dir.join(class_name);
+    let compiled = Command::new(&executable).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    (interpreted, compiled)
+}
+
+#[test]
+fn test_conversions() {
+    assert_eq!(interpret_and_compile("Conversions"), "-32768\n32767\n-5\n65535\n-56\n");
+}
+
+#[test]
+fn test_stack_manipulations() {
+    assert_eq!(interpret_and_compile("StackManipulations"), "30\n15.0\n4\n24\n12\n17\n15\n");
+}
+
+#[test]
+fn test_arrays() {
+    assert_eq!(interpret_and_compile("Arrays"), "5\n7\n1.5\n2.5\n0\n");
+}
+
+#[test]
+fn test_static_fields() {
+    assert_eq!(interpret_and_compile("StaticFields"), "StaticBase StaticConstants 2 10\n");
+}
+
+#[test]
+fn test_default_methods() {
+    assert_eq!(interpret_and_compile("Defaults"), "Hello plain, Hello loud!, Hi from Hello overriding, Hello plain!\nThanks loud\n");
+}
+
+#[test]
+fn test_arguments() {
+    assert_eq!(interpret_and_compile("Arguments"), "-99277\n10528.5\n256.0\n135\n");
+}
+
+#[test]
+fn test_trigonometry() {
+    // -0.0 and the subnormals are not reduced, while 1e15, 1e22 and the largest doubles need all the bits of 2/pi
+    assert_eq!(interpret_and_compile("Trigonometry"), "0.0 1.0\n-0.0 1.0\n1.0E-310 1.0\n\
+        -3.5E-320 1.0\n2.2250738585072014E-308 1.0\n1.0E-300 1.0\n\
+        0.479425538604203 0.8775825618903728\n-0.7071067811865475 0.7071067811865476\n0.7071067811865475 0.7071067811865476\n\
+        1.0 6.123233995736766E-17\n-1.2246467991473532E-16 -1.0\n-0.5063656411097588 0.8623188722876839\n\
+        0.5458434494486996 0.8378871813639024\n0.7249165551445564 -0.6888366918779438\n0.8582727931702359 -0.5131937377869703\n\
+        -0.8582727931702359 -0.5131937377869703\n-0.8522008497671888 0.523214785395139\n0.8522008497671888 0.523214785395139\n\
+        -0.9533431285501374 -0.30188885247096525\n-0.8178819121159085 -0.5753861119575491\n0.004961954789184062 -0.9999876894265599\n\
+        -0.004961954789184062 -0.9999876894265599\n");
+}
+
+#[test]
+fn test_math_functions() {
+    // NaN and infinite arguments and results, and the compares of NaN, which are false except for !=
+    assert_eq!(interpret_and_compile("MathFunctions"), "log(1.0) = 0.0\nlog(2.718281828459045) = 1.0\nlog(10.0) = 2.302585092994046\n\
+        log(0.5) = -0.6931471805599453\nlog(1.0E-310) = -713.8013788281542\n\
+        log(1.7976931348623157E308) = 709.782712893384\nlog(0.0) = -Infinity\nlog(-0.0) = -Infinity\n\
+        log(-1.0) = NaN\nlog(Infinity) = Infinity\nlog(NaN) = NaN\nexp(0.0) = 1.0\n\
+        exp(1.0) = 2.718281828459045\nexp(-1.0) = 0.36787944117144233\nexp(0.5) = 1.6487212707001282\n\
+        exp(20.0) = 4.851651954097903E8\nexp(709.7) = 1.6549840276802644E308\nexp(710.0) = Infinity\n\
+        exp(-708.0) = 3.307553003638408E-308\nexp(-740.0) = 4.2E-322\nexp(-750.0) = 0.0\n\
+        exp(Infinity) = Infinity\nexp(-Infinity) = 0.0\nexp(NaN) = NaN\npow(2.0, 10.0) = 1024.0\n\
+        pow(2.0, 0.5) = 1.4142135623730951\npow(2.0, -3.0) = 0.125\npow(-2.0, 3.0) = -8.0\n\
+        pow(-2.0, 4.0) = 16.0\npow(-8.0, 0.3333333333333333) = NaN\npow(10.0, 308.0) = 1.0E308\n\
+        pow(10.0, 309.0) = Infinity\npow(0.5, 1000.0) = 9.332636185032189E-302\npow(0.0, -1.0) = Infinity\n\
+        pow(-0.0, -3.0) = -Infinity\npow(0.0, 0.0) = 1.0\npow(NaN, 0.0) = 1.0\npow(1.0, NaN) = NaN\n\
+        pow(Infinity, -1.0) = 0.0\npow(-Infinity, 3.0) = -Infinity\n\
+        pow(1.0000001, 1.0E9) = 2.6881038582144647E43\nsqrt(2.0) = 1.4142135623730951\nsqrt(-1.0) = NaN\n\
+        sqrt(Infinity) = Infinity\ntrue false true false false true true false true false false true\n\
+        false false true true true false false false true true true false\n\
+        false false false false false true false false false false false true\n\
+        false false false false false true false false false false false true\n\
+        false false false false false true false false false false false true\n1.0\n100.0\n1234567.0\n\
+        9999999.0\n1.0E7\n1.23456789E7\n0.001\n1.0E-4\n0.30000000000000004\n0.3333333333333333\n\
+        0.6666666666666666\n1.2345678901234568E17\n1.0E21\n4.9E-322\n2.2250738585072014E-308\n\
+        1.7976931348623157E308\n-1.5E-7\n-0.0\nNaN\n-Infinity\n");
+}
+
+#[test]
+fn test_recursion() {
+    assert_eq!(interpret_and_compile("Recursion"), "6765\n2432902008176640000\n9\ntrue true false\n194.6195068359375\n\
+        176940360785041\n167.375\n303.0\n");
+}
+
+#[test]
+fn test_spilling() {
+    // At least 17 locals are live in each loop, more than the allocatable registers
+    assert_eq!(interpret_and_compile("Spilling"), "-1323292434\n-7350027540144516287\n5207975.500971271\n\
+        6710 9273 12450 13865 11515 6751 2619 623 46 -182.732421875 -51.0087890625 12.4912109375 0.00439453125 \
+        -705.6026551745588 -321.20612113228935 249.60153073357117 109.39035606487438\n");
+}
+
+#[test]
+fn test_mandelbrot() {
+    let output = interpret_and_compile_example("Mandelbrot");
+    let rows: Vec<&str> = output.lines().collect();
+    assert_eq!(rows.len(), 51);
+    assert!(rows.iter().all(|row| row.len() == 120));
+    assert_eq!(rows[25], "::::::XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX----------......*******");
+    assert_eq!(output.matches('X').count(), 1507);
+}
+
+#[test]
+fn test_person_and_circle() {
+    assert_eq!(interpret_and_compile_example("Person"), "John Smith\nCan legally drink (in the U.S.)\nYoung Adult\nWill or Kevin?\n");
+    let circle = interpret_and_compile_example("Circle");
+    assert_eq!(circle.lines().count(), 51);
+    assert_eq!(circle.lines().nth(25).unwrap(), format!("{}{}{}", ".".repeat(29), "X".repeat(63), ".".repeat(28)));
+}
+
+#[test]
+fn test_array_index_out_of_bounds() {
+    let (output, stack_trace) = interpret_and_compile_uncaught("OutOfBounds");
+    assert_eq!(output, "6\n");
+    assert_eq!(stack_trace, "Exception in thread \"main\" java.lang.ArrayIndexOutOfBoundsException: Index 3 out of bounds for length 3\n\
+        \tat OutOfBounds.sum(OutOfBounds.java:6)\n\tat OutOfBounds.main(OutOfBounds.java:14)\n");
+}
@@ -0,0 +1,36 @@
+// Runs the jvm command as java would be, and checks the output and exit status of the programs
+use std::process::{Command, Output};
+
+fn jvm(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jvm")).current_dir(env!("CARGO_MANIFEST_DIR")).args(args).output().unwrap()
+}
+
+#[test]
+fn test_jar() {
+    // The manifest of app.jar continues its Main-Class on a second line, and has lib/greeting.jar as Class-Path,
+    // relative to the jar. The arguments of the program follow the jar
+    let output = jvm(&["-jar", "tests/jar/app.jar", "World"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello from a jar, World\n");
+}
+
+#[test]
+fn test_uncaught_exception() {
+    // main does not catch the Throwable thrown for b = 3: like with java, its stack trace gets printed on stderr and
+    // the status is 1
+    let output = jvm(&["-cp", "java", "Exceptions", "10", "3"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Exception in thread \"main\" java.lang.Throwable: \
+        C'mon! You can do better than that! (exception not caught)\n\
+        \tat Exceptions.operation(Exceptions.java:12)\n\tat Exceptions.main(Exceptions.java:27)\n");
+
+    assert!(jvm(&["-cp", "java", "Exceptions", "10", "5"]).status.success());
+}
+
+#[test]
+fn test_println_overloads() {
+    // println(int), println(long), println(float), println(double), println(char), println(boolean), println(String)
+    let output = jvm(&["-cp", "tests/java", "Overloads"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().skip(1).collect::<Vec<_>>(), ["1", "2", "2.5", "3.5", "c", "true", "s"]);
+}
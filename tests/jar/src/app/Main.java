@@ -0,0 +1,14 @@
+package app;
+
+import jarred.Greeting;
+
+// Main-Class of app.jar, whose manifest continues Main-Class on a second line and has lib/greeting.jar as Class-Path
+public class Main {
+    public static String run(String name) {
+        return Greeting.greet(name);
+    }
+
+    public static void main(String[] args) {
+        System.out.println(run(args.length > 0 ? args[0] : "nobody"));
+    }
+}
@@ -0,0 +1,8 @@
+package jarred;
+
+// In lib/greeting.jar, on the Class-Path of app.jar
+public class Greeting {
+    public static String greet(String name) {
+        return "Hello from a jar, " + name;
+    }
+}
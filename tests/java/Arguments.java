@@ -0,0 +1,31 @@
+// More arguments than the registers of the calling convention, which get passed on the stack
+class Arguments {
+    int base;
+
+    Arguments(int base) {
+        this.base = base;
+    }
+
+    static long ints(int a, int b, long c, int d, int e, int f, int g, long h, int i, int j, int k) {
+        return a - b + c * 10 - d + e - f + g * 100 + h - i + j * 1000 - k * 10000;
+    }
+
+    static double doubles(double a, double b, double c, double d, double e, double f, double g, double h, double i, double j, float k, double l) {
+        return a - b + c - d + e - f + g - h + i * 10 - j * 100 + k * 1000 - l;
+    }
+
+    static double mixed(int a, double b, long c, float d, int e, double f, int g, double h, int i, double j, int k, double l, int m, double n, int o, double p, int q, double r, int s, double t) {
+        return a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p + q + r + s + t * 3;
+    }
+
+    int sum(int a, int b, int c, int d, int e, int f, int g) {
+        return base + a + b + c + d + e + f + g * 2;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(ints(1, 2, 3L, 4, 5, 6, 7, 8L, 9, 10, 11));
+        System.out.println(doubles(1.5, 2.5, 3.5, 4.5, 5.5, 6.5, 7.5, 8.5, 9.5, 10.5, 11.5f, 12.5));
+        System.out.println(mixed(1, 2.5, 3L, 4.5f, 5, 6.5, 7, 8.5, 9, 10.5, 11, 12.5, 13, 14.5, 15, 16.5, 17, 18.5, 19, 20.5));
+        System.out.println(new Arguments(100).sum(1, 2, 3, 4, 5, 6, 7));
+    }
+}
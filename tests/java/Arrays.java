@@ -0,0 +1,38 @@
+class Arrays {
+    static long addToLong(long value) {
+        long[] la = new long[3];
+        la[1] += value;
+        return la[1] + la[0];
+    }
+
+    static double addToDouble(double value) {
+        double[] da = new double[2];
+        da[0] += value;
+        return da[0] + da[1];
+    }
+
+    static float addToFloat(float value) {
+        float[] fa = new float[2];
+        fa[1] += value;
+        return fa[1];
+    }
+
+    static int sumDefaults() {
+        boolean[] za = new boolean[1];
+        byte[] ba = new byte[1];
+        char[] ca = new char[1];
+        short[] sa = new short[1];
+        int[] ia = new int[1];
+        return (za[0] ? 1 : 0) + ba[0] + ca[0] + sa[0] + ia[0];
+    }
+
+    public static void main(String[] args) {
+        long[] la = new long[3];
+        la[1] += 5L;
+        System.out.println(la[1]);
+        System.out.println(addToLong(7L));
+        System.out.println(addToDouble(1.5));
+        System.out.println(addToFloat(2.5f));
+        System.out.println(sumDefaults());
+    }
+}
@@ -0,0 +1,21 @@
+class Conversions {
+    static int toByte(int value) {
+        return (byte) value;
+    }
+
+    static int toChar(int value) {
+        return (char) value;
+    }
+
+    static int toShort(int value) {
+        return (short) value;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(toShort(32768));
+        System.out.println(toShort(-32769));
+        System.out.println(toShort(-5));
+        System.out.println(toChar(-1));
+        System.out.println(toByte(200));
+    }
+}
@@ -0,0 +1,70 @@
+// Default methods of interfaces: inherited, overridden, more specific in a subinterface, and called with super
+interface Greeter {
+    String name();
+
+    default String greet() {
+        return "Hello " + name();
+    }
+}
+
+interface LoudGreeter extends Greeter {
+    default String greet() {
+        return Greeter.super.greet() + "!";
+    }
+}
+
+interface Polite {
+    default String thank() {
+        return "Thanks " + this.toString();
+    }
+}
+
+class PlainGreeter implements Greeter {
+    public String name() {
+        return "plain";
+    }
+}
+
+class LoudPoliteGreeter implements LoudGreeter, Polite {
+    public String name() {
+        return "loud";
+    }
+
+    public String toString() {
+        return "loud";
+    }
+}
+
+class OverridingGreeter implements Greeter {
+    public String name() {
+        return "overriding";
+    }
+
+    public String greet() {
+        return "Hi from " + Greeter.super.greet();
+    }
+}
+
+class LoudPlainGreeter extends PlainGreeter implements LoudGreeter {
+}
+
+class Defaults {
+    static String greet(Greeter greeter) {
+        return greeter.greet();
+    }
+
+    static String greetAll() {
+        return greet(new PlainGreeter()) + ", " + greet(new LoudPoliteGreeter()) + ", " + greet(new OverridingGreeter())
+            + ", " + greet(new LoudPlainGreeter());
+    }
+
+    static String thank() {
+        LoudPoliteGreeter greeter = new LoudPoliteGreeter();
+        return greeter.thank();
+    }
+
+    public static void main(String[] args) {
+        System.out.println(greetAll());
+        System.out.println(thank());
+    }
+}
@@ -0,0 +1,42 @@
+// Virtual calls whose call sites see one or several classes of objects
+abstract class Shape {
+    abstract int area();
+
+    int twice() {
+        return 2 * area();
+    }
+}
+
+class Square extends Shape {
+    int area() {
+        return 4;
+    }
+}
+
+class Triangle extends Shape {
+    int area() {
+        return 3;
+    }
+
+    int twice() {
+        return 100 + area();
+    }
+}
+
+class Dispatch {
+    static int sumTwice(Shape[] shapes) {
+        int sum = 0;
+        for (Shape shape : shapes) {
+            sum += shape.twice();
+        }
+        return sum;
+    }
+
+    static int mixed() {
+        return sumTwice(new Shape[] { new Square(), new Square(), new Triangle(), new Square() });
+    }
+
+    public static void main(String[] args) {
+        System.out.println(mixed());
+    }
+}
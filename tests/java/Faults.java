@@ -0,0 +1,62 @@
+// The exceptions thrown by the JVM itself. FaultsHolder.removed was deleted after compiling this class
+class Faults {
+    int count;
+
+    static int callOnNull() {
+        String string = null;
+        return string.length();
+    }
+
+    static int fieldOfNull() {
+        Faults faults = null;
+        return faults.count;
+    }
+
+    static int lengthOfNull() {
+        int[] array = null;
+        return array.length;
+    }
+
+    static int elementOfNull() {
+        int[] array = null;
+        return array[0];
+    }
+
+    static void throwNull() throws Exception {
+        Exception exception = null;
+        throw exception;
+    }
+
+    static int arrayIndex(int idx) {
+        int[] array = new int[2];
+        return array[idx];
+    }
+
+    static String classCast(Object object) {
+        return (String) object;
+    }
+
+    static String castInteger() {
+        return classCast(Integer.valueOf(1));
+    }
+
+    static int negativeSize(int size) {
+        int[] array = new int[size];
+        return array.length;
+    }
+
+    static int removedField() {
+        return FaultsHolder.removed;
+    }
+
+    // Faults caught in Java, with the class of their exception
+    static String caught() {
+        String result = "";
+        try { callOnNull(); } catch (NullPointerException e) { result += "NPE "; }
+        try { arrayIndex(-1); } catch (ArrayIndexOutOfBoundsException e) { result += e.getMessage() + " "; }
+        try { classCast(Integer.valueOf(1)); } catch (ClassCastException e) { result += "CCE "; }
+        try { negativeSize(-3); } catch (NegativeArraySizeException e) { result += e.getMessage() + " "; }
+        try { throwNull(); } catch (NullPointerException e) { result += "NPE"; } catch (Exception e) { result += "Exception"; }
+        return result;
+    }
+}
@@ -0,0 +1,100 @@
+// Loops which run past the JIT threshold, so that the compiled code is entered in the middle of the loop
+class HotLoops {
+    static int ints(int n) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum = sum * 31 + (i ^ (i << 3)) - (i >> 1);
+            sum &= 0xfffffff | i;
+        }
+        return sum;
+    }
+
+    // The int conversion keeps the low bits of the long
+    static long longs(int n) {
+        long product = 1;
+        int low = 0;
+        for (int i = 1; i <= n; i++) {
+            product = product * 6364136223846793005L + i;
+            low += (int) product;
+            if (product < 0) {
+                product = -product;
+            }
+        }
+        return product ^ low;
+    }
+
+    static float floats(int n) {
+        float value = 0.5f;
+        for (int i = 0; i < n; i++) {
+            value = value * 1.0001f + (float) i / 7f - value / 3f;
+        }
+        return value;
+    }
+
+    static double doubles(int n) {
+        double value = 1;
+        for (int i = 0; i < n; i++) {
+            value = value * 0.999 + Math.sqrt(i) - (double) (long) value / 1000;
+        }
+        return value;
+    }
+
+    // Every comparison with NaN is false, whether it compiles to fcmpl or fcmpg
+    static int nanCompares(int n, float zero) {
+        float nan = zero / zero;
+        double doubleNan = (double) nan;
+        int ordered = 0;
+        int unequal = 0;
+        int negative = 0;
+        for (int i = 0; i < n; i++) {
+            float value = i - n / 2;
+            if (value < nan || value > nan || value <= nan || value >= nan || value == nan) ordered++;
+            if (i < doubleNan || i > doubleNan || i == doubleNan) ordered++;
+            if (value != nan) unequal++;
+            if (value < 0) negative++;
+        }
+        return ordered * 1000000 + unequal * 1000 + negative / 10;
+    }
+
+    static int switches(int n) {
+        int result = 0;
+        for (int i = 0; i < n; i++) {
+            switch (i % 5) {
+                case 0: result += 1; break;
+                case 1: result += 20; break;
+                case 2: result -= 3; break;
+                case 3: result *= 2; result %= 100003; break;
+                default: result ^= i;
+            }
+        }
+        return result;
+    }
+
+    static int exceptions(int n) {
+        int caught = 0;
+        int quotient = 0;
+        for (int i = 0; i < n; i++) {
+            try {
+                if (i % 100 == 0) {
+                    throw new IllegalStateException("loop " + i);
+                }
+                quotient += 1000 / (i % 7);
+            } catch (IllegalStateException e) {
+                caught += 1;
+            } catch (ArithmeticException e) {
+                caught += 100;
+            }
+        }
+        return caught + quotient;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(ints(5000));
+        System.out.println(longs(5000));
+        System.out.println(floats(5000));
+        System.out.println(doubles(5000));
+        System.out.println(nanCompares(5000, 0f));
+        System.out.println(switches(5000));
+        System.out.println(exceptions(5000));
+    }
+}
@@ -0,0 +1,93 @@
+// Lazy loading and initialization of classes (JVMS section 5.3, section 5.5). InitMissing was deleted after compiling this class
+class InitBase {
+    static int base = Initialization.log("InitBase");
+}
+
+class InitSub extends InitBase {
+    static int sub = Initialization.log("InitSub");
+
+    static int twice(int value) {
+        return 2 * value;
+    }
+}
+
+class InitUnused {
+    static int unused = Initialization.log("InitUnused");
+}
+
+class InitConstant {
+    static final int CONSTANT = 42;
+    static int other = Initialization.log("InitConstant");
+}
+
+class InitBoom {
+    static int value = Initialization.boom();
+}
+
+class InitMissing {
+    static int value = 1;
+}
+
+class Initialization {
+    static String order = "";
+    static int count = 0;
+
+    static int log(String name) {
+        order += name + " ";
+        return ++count;
+    }
+
+    static int boom() {
+        throw new IllegalStateException("boom");
+    }
+
+    // Superclasses are initialized first, constants do not initialize their class, and unused classes never are
+    static String order(boolean unused) {
+        if (unused) {
+            InitUnused.unused++;
+        }
+        int twice = InitSub.twice(3);
+        int constant = InitConstant.CONSTANT;
+        return order + twice + " " + constant + " " + InitSub.sub;
+    }
+
+    // The first use of a class whose initializer failed gets an ExceptionInInitializerError, the next ones a
+    // NoClassDefFoundError
+    static String failedInitialization() {
+        String result = "";
+        try {
+            result += InitBoom.value;
+        } catch (ExceptionInInitializerError e) {
+            result += "EIIE caused by " + (e.getCause() instanceof IllegalStateException) + " " + e.getCause().getMessage() + ", ";
+        }
+        try {
+            result += InitBoom.value;
+        } catch (NoClassDefFoundError e) {
+            result += "NCDFE " + e.getMessage();
+        }
+        return result;
+    }
+
+    // Missing classes are only reported when used
+    static String missingClass(boolean used) {
+        if (used) {
+            return "" + InitMissing.value;
+        }
+        return "not loaded";
+    }
+
+    static String caughtMissingClass() {
+        try {
+            return missingClass(true);
+        } catch (NoClassDefFoundError e) {
+            return "NCDFE " + e.getMessage() + " caused by " + (e.getCause() instanceof ClassNotFoundException) + " " + e.getCause().getMessage();
+        }
+    }
+
+    public static void main(String[] args) {
+        System.out.println(order(false));
+        System.out.println(failedInitialization());
+        System.out.println(missingClass(false));
+        System.out.println(caughtMissingClass());
+    }
+}
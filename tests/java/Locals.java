@@ -0,0 +1,34 @@
+// Frames sized from max_locals, where longs and doubles take two slots
+class Locals {
+    long base;
+
+    Locals(long base) {
+        this.base = base;
+    }
+
+    static double mix(int a, long b, double c, float d, long e, int f) {
+        return a + b + c + d + e + f;
+    }
+
+    long add(long value, double scale, int count) {
+        return base + (long) (value * scale) + count;
+    }
+
+    static int manyLocals(int seed) {
+        int v0 = seed, v1 = v0 + 1, v2 = v1 + 1, v3 = v2 + 1, v4 = v3 + 1, v5 = v4 + 1, v6 = v5 + 1, v7 = v6 + 1;
+        long l8 = v7 + 1L, l10 = l8 + 1;
+        double d12 = l10 + 1.0, d14 = d12 + 1;
+        int v16 = (int) d14 + 1, v17 = v16 + 1, v18 = v17 + 1, v19 = v18 + 1, v20 = v19 + 1;
+        return v0 + v1 + v2 + v3 + v4 + v5 + v6 + v7 + (int) (l8 + l10) + (int) (d12 + d14) + v16 + v17 + v18 + v19 + v20;
+    }
+
+    static long instanceCall() {
+        return new Locals(100L).add(7L, 2.5, 3);
+    }
+
+    public static void main(String[] args) {
+        System.out.println(mix(1, 2L, 3.5, 4.5f, 5L, 6));
+        System.out.println(manyLocals(10));
+        System.out.println(instanceCall());
+    }
+}
@@ -0,0 +1,48 @@
+// log, exp, pow and sqrt of ordinary and special arguments, NaN compares and the formatting of doubles
+class MathFunctions {
+    static void print(String name, double x, double result) {
+        System.out.println(name + "(" + x + ") = " + result);
+    }
+
+    static void compare(float a, float b, double c, double d) {
+        System.out.println((a < b) + " " + (a > b) + " " + (a <= b) + " " + (a >= b) + " " + (a == b) + " " + (a != b) + " "
+            + (c < d) + " " + (c > d) + " " + (c <= d) + " " + (c >= d) + " " + (c == d) + " " + (c != d));
+    }
+
+    public static void main(String[] args) {
+        double zero = 0.0;
+        double nan = zero / zero;
+        double infinity = 1 / zero;
+        double[] logs = { 1.0, 2.718281828459045, 10.0, 0.5, 1e-310, 1.7976931348623157e308, 0.0, -0.0, -1.0, infinity, nan };
+        for (int i = 0; i < logs.length; i++) {
+            print("log", logs[i], Math.log(logs[i]));
+        }
+        double[] exps = { 0.0, 1.0, -1.0, 0.5, 20.0, 709.7, 710.0, -708.0, -740.0, -750.0, infinity, -infinity, nan };
+        for (int i = 0; i < exps.length; i++) {
+            print("exp", exps[i], Math.exp(exps[i]));
+        }
+        double[][] pows = { { 2, 10 }, { 2, 0.5 }, { 2, -3 }, { -2, 3 }, { -2, 4 }, { -8, 1.0 / 3 }, { 10, 308 }, { 10, 309 },
+            { 0.5, 1000 }, { 0, -1 }, { -0.0, -3 }, { 0, 0 }, { nan, 0 }, { 1, nan }, { infinity, -1 }, { -infinity, 3 },
+            { 1.0000001, 1e9 } };
+        for (int i = 0; i < pows.length; i++) {
+            System.out.println("pow(" + pows[i][0] + ", " + pows[i][1] + ") = " + Math.pow(pows[i][0], pows[i][1]));
+        }
+        print("sqrt", 2.0, Math.sqrt(2.0));
+        print("sqrt", -1.0, Math.sqrt(-1.0));
+        print("sqrt", infinity, Math.sqrt(infinity));
+
+        float floatNan = (float) nan;
+        compare(1, 2, 1, 2);
+        compare(2, 2, 2, 2);
+        compare(floatNan, 1, nan, 1);
+        compare(1, floatNan, 1, nan);
+        compare(floatNan, floatNan, nan, nan);
+
+        double[] formats = { 1.0, 100.0, 1234567.0, 9999999.0, 1e7, 12345678.9, 0.001, 1e-4, 0.1 + 0.2, 1.0 / 3, 2.0 / 3,
+            123456789012345680.0, 1e21, 4.9e-322, 2.2250738585072014e-308, 1.7976931348623157e308, -1.5e-7, -0.0 * 5,
+            infinity - infinity, -infinity };
+        for (int i = 0; i < formats.length; i++) {
+            System.out.println(formats[i]);
+        }
+    }
+}
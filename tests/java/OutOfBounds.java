@@ -0,0 +1,17 @@
+// An array index out of bounds, which is not caught and ends the program with status 1
+class OutOfBounds {
+    static int sum(int[] values, int count) {
+        int sum = 0;
+        for (int i = 0; i < count; i++) {
+            sum += values[i];
+        }
+        return sum;
+    }
+
+    public static void main(String[] args) {
+        int[] values = { 1, 2, 3 };
+        System.out.println(sum(values, 3));
+        System.out.println(sum(values, 4));
+        System.out.println("not printed");
+    }
+}
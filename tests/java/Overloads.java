@@ -0,0 +1,30 @@
+// Methods with the same name and different descriptors
+class Overloads {
+    static String f(int value) { return "int " + value; }
+    static String f(long value) { return "long " + value; }
+    static String f(double value) { return "double " + value; }
+    static String f(String value) { return "String " + value; }
+    static String f(Object value) { return "Object"; }
+    static String f(int a, int b) { return "int,int " + (a + b); }
+
+    String g(int value) { return "g int " + value; }
+    String g(char value) { return "g char " + value; }
+
+    static String all() {
+        // The double constant takes two entries of the constant pool, before the strings
+        Overloads overloads = new Overloads();
+        return f(1) + ", " + f(2L) + ", " + f(2.5) + ", " + f("s") + ", " + f(new Object()) + ", " + f(3, 4)
+            + ", " + overloads.g(5) + ", " + overloads.g('c');
+    }
+
+    public static void main(String[] args) {
+        System.out.println(all());
+        System.out.println(1);
+        System.out.println(2L);
+        System.out.println(2.5f);
+        System.out.println(3.5);
+        System.out.println('c');
+        System.out.println(true);
+        System.out.println("s");
+    }
+}
@@ -0,0 +1,62 @@
+// Exceptions thrown by the methods called with each invoke instruction, and by native methods
+interface Thrower {
+    int fail(int value);
+}
+
+class FailingThrower implements Thrower {
+    public int fail(int value) {
+        throw new IllegalArgumentException("interface " + value);
+    }
+}
+
+class Propagation {
+    Propagation(boolean fail) {
+        if (fail) {
+            throw new IllegalStateException("constructor");
+        }
+    }
+
+    int fail(int value) {
+        throw new RuntimeException("virtual " + value);
+    }
+
+    private int failPrivately() {
+        throw new ArithmeticException("private");
+    }
+
+    static int virtualCall() {
+        return new Propagation(false).fail(1);
+    }
+
+    static int specialCall() {
+        return new Propagation(false).failPrivately();
+    }
+
+    static Propagation constructor() {
+        return new Propagation(true);
+    }
+
+    static int interfaceCall() {
+        Thrower thrower = new FailingThrower();
+        return thrower.fail(2);
+    }
+
+    static int nativeCall() {
+        return Integer.parseInt("two");
+    }
+
+    // The code after the failed calls never runs
+    static String caught() {
+        String result = "";
+        try { virtualCall(); result += "not "; } catch (RuntimeException e) { result += e.getMessage() + ", "; }
+        try { specialCall(); result += "not "; } catch (ArithmeticException e) { result += e.getMessage() + ", "; }
+        try { constructor(); result += "not "; } catch (IllegalStateException e) { result += e.getMessage() + ", "; }
+        try { interfaceCall(); result += "not "; } catch (IllegalArgumentException e) { result += e.getMessage() + ", "; }
+        try { nativeCall(); result += "not "; } catch (NumberFormatException e) { result += "native"; }
+        return result;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(caught());
+    }
+}
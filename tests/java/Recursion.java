@@ -0,0 +1,67 @@
+// Recursive static methods, including mutual recursion and recursion with more arguments than registers
+class Recursion {
+    static int fib(int n) {
+        return n < 2 ? n : fib(n - 1) + fib(n - 2);
+    }
+
+    static long fact(int n) {
+        return n <= 1 ? 1 : n * fact(n - 1);
+    }
+
+    static int ackermann(int m, int n) {
+        if (m == 0) {
+            return n + 1;
+        }
+        return ackermann(m - 1, n == 0 ? 1 : ackermann(m, n - 1));
+    }
+
+    static boolean isEven(int n) {
+        return n == 0 || isOdd(n - 1);
+    }
+
+    static boolean isOdd(int n) {
+        return n != 0 && isEven(n - 1);
+    }
+
+    static double power(double x, int n) {
+        if (n == 0) {
+            return 1;
+        }
+        double half = power(x, n / 2);
+        return n % 2 == 0 ? half * half : half * half * x;
+    }
+
+    // The arguments rotate at each call, so every one of them is read from the stack and passed again
+    static long rotateInts(int depth, int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+        if (depth == 0) {
+            return a + 2L * b + 3L * c + 4L * d + 5L * e + 6L * f + 7L * g + 8L * h + 9L * i + 10L * j;
+        }
+        return rotateInts(depth - 1, b, c, d, e, f, g, h, i, j, a + depth) * 3 + a;
+    }
+
+    static double rotateDoubles(int depth, double a, double b, double c, double d, double e, double f, double g, double h, double i, double j) {
+        if (depth == 0) {
+            return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h + 9 * i + 10 * j;
+        }
+        return rotateDoubles(depth - 1, b, c, d, e, f, g, h, i, j, a * 0.5) + a;
+    }
+
+    static double rotateMixed(int depth, int a, double b, int c, double d, int e, double f, int g, double h, int i, double j, int k, double l,
+            int m, double n, int o, double p, int q, double r, int s, double t) {
+        if (depth == 0) {
+            return a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p + q + r + s * 2 + t * 3;
+        }
+        return rotateMixed(depth - 1, c, d, e, f, g, h, i, j, k, l, m, n, o, p, q, r, s, t, a + 1, b * 2) - depth;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(fib(20));
+        System.out.println(fact(20));
+        System.out.println(ackermann(2, 3));
+        System.out.println(isEven(100) + " " + isOdd(77) + " " + isEven(7));
+        System.out.println(power(1.5, 13));
+        System.out.println(rotateInts(23, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10));
+        System.out.println(rotateDoubles(17, 1.5, 2.5, 3.5, 4.5, 5.5, 6.5, 7.5, 8.5, 9.5, 10.5));
+        System.out.println(rotateMixed(11, 1, 2.5, 3, 4.5, 5, 6.5, 7, 8.5, 9, 10.5, 11, 12.5, 13, 14.5, 15, 16.5, 17, 18.5, 19, 20.5));
+    }
+}
@@ -0,0 +1,56 @@
+// More live locals than the registers of the allocator, which get spilled into the stack frame, also across calls
+class Spilling {
+    static int scramble(int value) {
+        return value * 31 + 7;
+    }
+
+    static int ints(int n) {
+        int a = 1, b = 2, c = 3, d = 4, e = 5, f = 6, g = 7, h = 8, i = 9, j = 10, k = 11, l = 12, m = 13, o = 14, p = 15, q = 16,
+            r = 17, s = 18, t = 19, u = 20;
+        for (int x = 0; x < n; x++) {
+            a += b; b ^= c; c -= d; d += e * 3; e = scramble(e) & 0xffff; f += g; g ^= h << 1; h -= i; i += j; j = scramble(j + k) % 1000;
+            k += l; l ^= m; m -= o; o += p >> 1; p += q; q ^= r; r -= s; s += t; t = scramble(t) % 997; u += a ^ t;
+        }
+        return a + b + c + d + e + f + g + h + i + j + k + l + m + o + p + q + r + s + t + u;
+    }
+
+    static long longs(int n) {
+        long a = 1, b = 2, c = 3, d = 4, e = 5, f = 6, g = 7, h = 8, i = 9, j = 10, k = 11, l = 12, m = 13, o = 14, p = 15, q = 16,
+            r = 17;
+        for (int x = 0; x < n; x++) {
+            a = a * 3 + b; b ^= c; c -= d; d += e; e = scramble((int) e) + f; f += g; g ^= h; h -= i; i += j; j = j * 5 + k;
+            k += l; l ^= m; m -= o; o += p; p += q; q ^= r; r -= a;
+        }
+        return a + b + c + d + e + f + g + h + i + j + k + l + m + o + p + q + r;
+    }
+
+    static double doubles(int n) {
+        double a = 0.5, b = 1.5, c = 2.5, d = 3.5, e = 4.5, f = 5.5, g = 6.5, h = 7.5, i = 8.5, j = 9.5, k = 10.5, l = 11.5,
+            m = 12.5, o = 13.5, p = 14.5, q = 15.5, r = 16.5;
+        for (int x = 0; x < n; x++) {
+            a = a * 0.5 + b; b = b * 0.75 + c * 0.125; c -= d * 0.25; d += e * 0.5; e = Math.sqrt(e * e + f * f); f = f * 0.5 + g * 0.25;
+            g -= h * 0.125; h += i * 0.0625; i = i * 0.5 + j; j = Math.sqrt(j * j + k * k); k = k * 0.5 + l * 0.25; l -= m * 0.125;
+            m += o * 0.0625; o = o * 0.5 + p; p = Math.sqrt(p * p + q * q); q = q * 0.5 + r * 0.25; r -= a * 0.125;
+        }
+        return a + b + c + d + e + f + g + h + i + j + k + l + m + o + p + q + r;
+    }
+
+    // Integers and doubles are live at the same time
+    static String mixed(int n) {
+        int a = 1, b = 2, c = 3, d = 4, e = 5, f = 6, g = 7, h = 8, i = 9;
+        double v = 1.5, w = 2.5, x = 3.5, y = 4.5, z = 5.5, s = 6.5, t = 7.5, u = 8.5;
+        for (int k = 0; k < n; k++) {
+            a += b; b += c; c += d; d += e; e += f; f += g; g += h; h += i; i = scramble(i) % 101;
+            v += w; w -= x * 0.5; x += y; y *= 0.5; z += s; s -= t * 0.5; t += u; u = Math.sqrt(u * u + a);
+        }
+        return a + " " + b + " " + c + " " + d + " " + e + " " + f + " " + g + " " + h + " " + i + " "
+            + v + " " + w + " " + x + " " + y + " " + z + " " + s + " " + t + " " + u;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(ints(100));
+        System.out.println(longs(100));
+        System.out.println(doubles(100));
+        System.out.println(mixed(10));
+    }
+}
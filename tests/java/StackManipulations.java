@@ -0,0 +1,49 @@
+class StackManipulations {
+    static long counter = 3;
+    long total;
+    int count;
+
+    static long next() {
+        return counter++;
+    }
+
+    static double half(long value) {
+        return value / 2.0;
+    }
+
+    public static void main(String[] args) {
+        // dup2 of a long and of a double
+        long a, b;
+        a = b = counter * 5L;
+        System.out.println(a + b);
+        double c, d;
+        c = d = half(a);
+        System.out.println(c + d);
+
+        // pop2 of a long and of a double
+        next();
+        half(b);
+        System.out.println(counter);
+
+        // dup2 of an array and an index, then dup2_x2 and dup_x2 of the new value
+        long[] longs = new long[3];
+        longs[1] = 7L;
+        long e = longs[1] += 5L;
+        System.out.println(e + longs[1]);
+        int[] ints = new int[2];
+        ints[0] = 4;
+        int f = ints[0] += 2;
+        System.out.println(f + ints[0]);
+
+        // dup2_x1 of a long and dup_x1 of an int, assigned to fields
+        StackManipulations object = new StackManipulations();
+        long g = object.total += 4L;
+        int h = object.count += 9;
+        System.out.println(g + object.total + h);
+
+        // dup2 of a long pushed on two paths
+        long i, j;
+        i = j = args.length == 0 ? a : next();
+        System.out.println(i - j + i);
+    }
+}
@@ -0,0 +1,32 @@
+interface StaticConstants {
+    int BASE = StaticFields.log("StaticConstants", 10);
+}
+
+class StaticBase {
+    static int shared = StaticFields.log("StaticBase", 1);
+}
+
+class StaticSub extends StaticBase implements StaticConstants {
+    static int own = StaticFields.log("StaticSub", 2);
+}
+
+class StaticFields {
+    static String order = "";
+
+    static int log(String name, int value) {
+        order += name + " ";
+        return value;
+    }
+
+    // Only the classes declaring the fields get initialized, not the one they are accessed through
+    static String inherited() {
+        int value = StaticSub.shared;
+        StaticSub.shared = value + 1;
+        int base = StaticSub.BASE;
+        return order + StaticBase.shared + " " + base;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(inherited());
+    }
+}
@@ -0,0 +1,16 @@
+// sin and cos of huge, subnormal and signed zero arguments, whose reduction modulo pi/2 needs all the bits of pi
+class Trigonometry {
+    static void print(double x) {
+        System.out.println(Math.sin(x) + " " + Math.cos(x));
+    }
+
+    public static void main(String[] args) {
+        double[] values = { 0.0, -0.0, 1e-310, -3.5e-320, 2.2250738585072014e-308, 1e-300, 0.5, -0.7853981633974483,
+            0.7853981633974483, 1.5707963267948966, -3.141592653589793, 100.0, 1e9, -2147483647.0, 1e15, -1e15,
+            1e22, -1e22, 6381956970095103.0 * 1099511627776.0, 1.0e300, 1.7976931348623157e308,
+            -1.7976931348623157e308 };
+        for (int i = 0; i < values.length; i++) {
+            print(values[i]);
+        }
+    }
+}